@@ -1,11 +1,17 @@
+use std::collections::HashSet;
+use std::fmt::Display;
 use std::net::IpAddr;
 use std::num::ParseIntError;
+use std::path::PathBuf;
 
-use clap::{AppSettings, Parser};
+use clap::{AppSettings, Parser, Subcommand};
 use once_cell::sync::Lazy;
 use thiserror::Error;
 use tracing::{error, warn};
 
+use crate::database::Sizing;
+use crate::utils::is_valid_identity;
+
 static VERSION: Lazy<String> = Lazy::new(|| {
     let mut version = format!("v{}", env!("CARGO_PKG_VERSION"));
     if let Some(hash) = option_env!("GIT_SHORT_HASH") {
@@ -20,9 +26,18 @@ static VERSION: Lazy<String> = Lazy::new(|| {
 #[clap(version = &VERSION[..], global_setting = AppSettings::DeriveDisplayOrder)]
 pub struct Args {
     // region: Global Flags
+    /// Record storage backend, one of `postgres`, `memory` or `sqlite://PATH`
+    ///
+    /// Records stored in `memory` are lost when the server exits. SQLite stores records
+    /// in a local file at PATH, for single node deployments
+    #[clap(long, default_value = "postgres", env = "WQL_STORAGE", parse(try_from_str = parse_storage))]
+    pub storage: DatabaseBackend,
+
     /// PostgreSQL connection string
+    ///
+    /// Required when using `postgres` storage
     #[clap(short = 'p', long = "psql", env = "WQL_POSTGRES_CONNECTION_STRING")]
-    pub psql_conn: String,
+    pub psql_conn: Option<String>,
 
     /// Side length of subscription region cubes
     ///
@@ -30,6 +45,24 @@ pub struct Args {
     #[clap(long, default_value = "16", env = "WQL_SUBSCRIPTION_REGION_CUBE_SIZE", parse(try_from_str = parse_non_zero_16))]
     pub sub_region_size: u16,
 
+    /// Maximum number of records sent in a single `RecordReply`
+    ///
+    /// Larger replies are split into multiple messages. A value of 0 is invalid
+    #[clap(long, default_value = "256", env = "WQL_REPLY_CHUNK_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub reply_chunk_size: usize,
+
+    /// Maximum number of records returned by a `nearest` read
+    ///
+    /// Larger counts requested by peers are lowered to this. A value of 0 is invalid
+    #[clap(long, default_value = "1024", env = "WQL_MAX_NEAREST_COUNT", parse(try_from_str = parse_non_zero_sized))]
+    pub max_nearest_count: usize,
+
+    /// Maximum distance searched by a `nearest` read
+    ///
+    /// Larger distances requested by peers are lowered to this. A value of 0 is invalid
+    #[clap(long, default_value = "4096", env = "WQL_MAX_NEAREST_DISTANCE", parse(try_from_str = parse_non_zero_32))]
+    pub max_nearest_distance: u32,
+
     /// TODO: Add arg docs
     ///
     /// A value of 0 is invalid
@@ -59,6 +92,50 @@ pub struct Args {
     /// Set to 0 to disable cache eviction
     #[clap(long, default_value = "1024", env = "WQL_DB_CACHE_SIZE", parse(try_from_str = parse_non_zero_sized))]
     pub db_cache_size: usize,
+
+    /// Maximum number of records to hold in memory while the database is unavailable
+    ///
+    /// Set to 0 to disable write buffering
+    #[clap(long, default_value = "0", env = "WQL_DB_WRITE_BUFFER_SIZE")]
+    pub db_write_buffer_size: usize,
+
+    /// Number of pooled PostgreSQL connections
+    ///
+    /// Database messages are processed concurrently by one worker per connection
+    #[clap(long, default_value = "4", env = "WQL_DB_POOL_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub db_pool_size: usize,
+
+    /// Interval between deleting expired records (seconds)
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "10", env = "WQL_DB_EXPIRY_INTERVAL_SECS", parse(try_from_str = parse_non_zero_32))]
+    pub db_expiry_interval_secs: u32,
+
+    /// Maximum number of expired records deleted at once
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "1000", env = "WQL_DB_EXPIRY_BATCH_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub db_expiry_batch_size: usize,
+
+    /// How long record history is kept for (seconds)
+    ///
+    /// Set to 0 to keep history forever
+    #[clap(long, default_value = "2592000", env = "WQL_DB_HISTORY_RETENTION_SECS")]
+    pub db_history_retention_secs: u64,
+    // endregion
+
+    // region: Peer Auth
+    /// Peer authentication tokens, as `IDENTITY=TOKEN` pairs
+    ///
+    /// Peers authenticate by sending a token as the `flex` field of their handshake.
+    /// Records are owned by the peer that created them, peers without a token can only
+    /// write records without an owner
+    #[clap(long = "peer-token", env = "WQL_PEER_TOKENS", use_delimiter = true, parse(try_from_str = parse_peer_token))]
+    pub peer_tokens: Vec<(String, String)>,
+
+    /// Peer identities allowed to write any record, for backend services
+    #[clap(long = "admin-peer", env = "WQL_ADMIN_PEERS", use_delimiter = true, parse(try_from_str = parse_identity))]
+    pub admin_peers: Vec<String>,
     // endregion
 
     // region: HTTP
@@ -72,7 +149,9 @@ pub struct Args {
     #[clap(short = 'h', long, default_value = "8081", env = "WQL_HTTP_PORT")]
     pub http_port: u16,
 
-    /// HTTP server port
+    /// HTTP server auth token
+    ///
+    /// Required to use any of the `/admin` routes
     #[cfg(feature = "http")]
     #[clap(long, env = "WQL_HTTP_AUTH_TOKEN")]
     pub http_auth_token: Option<String>,
@@ -126,6 +205,122 @@ pub struct Args {
     #[clap(short, long, parse(from_occurrences))]
     pub verbose: u8,
     // endregion
+    /// Command to run, starts the server if not set
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+// endregion
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseBackend {
+    Postgres,
+    Memory,
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+}
+
+impl Display for DatabaseBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DatabaseBackend::Postgres => "postgres",
+            DatabaseBackend::Memory => "memory",
+            #[cfg(feature = "sqlite")]
+            DatabaseBackend::Sqlite(_) => "sqlite",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// region: Subcommands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the server, the default when no command is given
+    Serve,
+
+    /// Configuration commands
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+
+    /// Database maintenance commands
+    #[clap(subcommand)]
+    Db(DbCommand),
+
+    /// Stored world management commands
+    ///
+    /// Uses the configured database backend, which must not be in memory
+    #[clap(subcommand)]
+    World(WorldCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Create the database schema and apply any pending migrations
+    ///
+    /// The server does this on startup, so this is only needed to prepare a database
+    /// ahead of time
+    Init,
+
+    /// Show the stored sizing, pending migrations and how much is stored
+    Stats,
+
+    /// List schema migrations that haven't been applied yet
+    Migrations,
+
+    /// Move all worlds to the configured region and table sizes
+    ///
+    /// The server must not be running while worlds are rebucketed
+    Rebucket,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Check the configuration is valid and the database backend can be used, without
+    /// starting the server
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WorldCommand {
+    /// List stored worlds with their table and record counts
+    List,
+
+    /// Delete a world and all of its records
+    ///
+    /// The server must not be running while worlds are dropped
+    Drop { world_name: String },
+
+    /// Copy every record in a world into a new world
+    Copy { from: String, to: String },
+
+    /// Rename a world, keeping its records as they are
+    ///
+    /// The server must not be running while worlds are renamed
+    Rename { from: String, to: String },
+
+    /// Export a world's records to a snapshot file
+    ///
+    /// Snapshots hold size-prefixed flatbuffer `Record`s, expired records are skipped
+    Export {
+        world_name: String,
+        path: PathBuf,
+
+        /// Also write how the world is bucketed, to PATH.navigation.json
+        #[clap(long)]
+        navigation: bool,
+    },
+
+    /// Import a snapshot file into a new world
+    ///
+    /// Records are rebucketed under the configured sizing, and start again at version 1.
+    /// A failed import leaves no world behind
+    Import {
+        path: PathBuf,
+
+        /// World to import into, defaults to the world the snapshot was exported from
+        #[clap(long)]
+        world_name: Option<String>,
+    },
 }
 // endregion
 
@@ -140,6 +335,15 @@ enum ParseError {
 
     #[error(transparent)]
     ParseIntError(#[from] ParseIntError),
+
+    #[error("must be in the form IDENTITY=TOKEN")]
+    PeerToken,
+
+    #[error("must only contain ASCII alphanumerics or any of `_-.@`, up to 64 chars")]
+    Identity,
+
+    #[error("must be `postgres`, `memory` or `sqlite://PATH`")]
+    Storage,
 }
 
 fn parse_non_zero_16(src: &str) -> Result<u16, ParseError> {
@@ -169,6 +373,38 @@ fn parse_non_zero_sized(src: &str) -> Result<usize, ParseError> {
     Ok(size)
 }
 
+fn parse_identity(src: &str) -> Result<String, ParseError> {
+    match is_valid_identity(src) {
+        true => Ok(src.to_owned()),
+        false => Err(ParseError::Identity),
+    }
+}
+
+fn parse_peer_token(src: &str) -> Result<(String, String), ParseError> {
+    let (identity, token) = src.split_once('=').ok_or(ParseError::PeerToken)?;
+    if token.is_empty() {
+        return Err(ParseError::PeerToken);
+    }
+
+    Ok((parse_identity(identity)?, token.to_owned()))
+}
+
+fn parse_storage(src: &str) -> Result<DatabaseBackend, ParseError> {
+    match src {
+        "postgres" => Ok(DatabaseBackend::Postgres),
+        "memory" => Ok(DatabaseBackend::Memory),
+
+        #[cfg(feature = "sqlite")]
+        _ => match src.strip_prefix("sqlite://") {
+            Some(path) if !path.is_empty() => Ok(DatabaseBackend::Sqlite(PathBuf::from(path))),
+            _ => Err(ParseError::Storage),
+        },
+
+        #[cfg(not(feature = "sqlite"))]
+        _ => Err(ParseError::Storage),
+    }
+}
+
 #[cfg(feature = "zeromq")]
 fn parse_zmq_timeout_secs(src: &str) -> Result<u8, ParseError> {
     let min = 10;
@@ -190,6 +426,11 @@ impl Args {
             warn!("Subscription region sizes less than 10 might impact lookup performance")
         }
 
+        if self.storage == DatabaseBackend::Postgres && self.psql_conn.is_none() {
+            error!("--psql is required when using postgres storage");
+            return false;
+        }
+
         // TODO: Better error messages
         let mod_x = self.db_table_size % u32::from(self.db_region_x_size);
         if mod_x != 0 {
@@ -223,5 +464,45 @@ impl Args {
 
         true
     }
+
+    /// Returns `true` if every port the server listens on is free
+    pub fn check_ports(&self) -> bool {
+        let mut used_ports = HashSet::new();
+
+        #[cfg(feature = "websocket")]
+        {
+            if !portpicker::is_free_tcp(self.ws_port) {
+                error!("WebSocket Server port {} is already in use!", self.ws_port);
+                return false;
+            }
+
+            used_ports.insert(self.ws_port);
+        }
+
+        #[cfg(feature = "zeromq")]
+        {
+            let server_inserted = used_ports.insert(self.zmq_server_port);
+            if !server_inserted || !portpicker::is_free_tcp(self.zmq_server_port) {
+                error!(
+                    "ZeroMQ Server port {} is already in use!",
+                    self.zmq_server_port
+                );
+
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns the sizing records are bucketed with.
+    pub fn sizing(&self) -> Sizing {
+        Sizing {
+            region_x_size: self.db_region_x_size,
+            region_y_size: self.db_region_y_size,
+            region_z_size: self.db_region_z_size,
+            table_size: self.db_table_size,
+        }
+    }
 }
 // endregion
@@ -0,0 +1,70 @@
+use color_eyre::Result;
+
+use super::db::connect;
+#[cfg(feature = "sqlite")]
+use super::db::open_sqlite;
+use crate::args::{Args, ConfigCommand, DatabaseBackend};
+use crate::database::{DatabaseError, Sizing};
+
+pub(super) async fn run_config_command(command: &ConfigCommand, args: &Args) -> Result<()> {
+    match command {
+        ConfigCommand::Check => check(args).await,
+    }
+}
+
+/// Run the checks the server makes on startup, without changing the database.
+async fn check(args: &Args) -> Result<()> {
+    if !args.validate() || !args.check_ports() {
+        std::process::exit(1);
+    }
+
+    match &args.storage {
+        DatabaseBackend::Postgres => {
+            let client = connect(args)?;
+            let pending = client.pending_migrations().await?;
+
+            check_sizing(client.stored_sizing().await?, args)?;
+
+            if !pending.is_empty() {
+                println!(
+                    "{} pending migrations will be applied when the server starts",
+                    pending.len()
+                );
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite(path) => match path.exists() {
+            true => {
+                let store = open_sqlite(path, args)?;
+                check_sizing(store.stored_sizing().await?, args)?;
+            }
+
+            false => println!(
+                "SQLite database {} will be created when the server starts",
+                path.display()
+            ),
+        },
+
+        DatabaseBackend::Memory => println!("Records are stored in memory and lost on exit"),
+    }
+
+    println!("Configuration is valid");
+    Ok(())
+}
+
+/// Check the sizing stored in the database matches the configured sizing.
+fn check_sizing(stored: Option<Sizing>, args: &Args) -> Result<()> {
+    let configured = args.sizing();
+    match stored {
+        Some(stored) if stored != configured => {
+            Err(DatabaseError::SizingMismatch { stored, configured }.into())
+        }
+
+        Some(_) => Ok(()),
+        None => {
+            println!("Database will be initialized when the server starts");
+            Ok(())
+        }
+    }
+}
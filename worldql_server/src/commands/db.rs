@@ -0,0 +1,188 @@
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+use std::sync::Arc;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use crate::args::{Args, DatabaseBackend, DbCommand};
+#[cfg(feature = "sqlite")]
+use crate::database::SqliteStore;
+use crate::database::{ConnectionPool, DatabaseClient, ThreadRecordStore};
+use crate::utils::{health, Health};
+
+pub(super) async fn run_db_command(command: &DbCommand, args: &Args) -> Result<()> {
+    match command {
+        DbCommand::Init => init(args).await,
+        DbCommand::Stats => stats(args).await,
+        DbCommand::Migrations => migrations(&connect(args)?).await,
+        DbCommand::Rebucket => rebucket(&connect(args)?, args).await,
+    }
+}
+
+pub(super) fn connect(args: &Args) -> Result<DatabaseClient> {
+    let psql_conn = args
+        .psql_conn
+        .as_deref()
+        .ok_or_else(|| eyre!("--psql is required for database commands"))?;
+
+    let health = Arc::new(Health::new(&[health::POSTGRES]));
+    let pool = ConnectionPool::new(psql_conn, 1, health)?;
+
+    let client = DatabaseClient::new(
+        pool,
+        args.db_region_x_size,
+        args.db_region_y_size,
+        args.db_region_z_size,
+        args.db_table_size,
+        args.db_cache_size,
+        0,
+    );
+
+    Ok(client)
+}
+
+/// Open the configured SQLite database read-only, so it is inspected without being
+/// created or migrated.
+#[cfg(feature = "sqlite")]
+pub(super) fn open_sqlite(path: &Path, args: &Args) -> Result<SqliteStore> {
+    let store = SqliteStore::open_read_only(
+        path,
+        args.db_region_x_size,
+        args.db_region_y_size,
+        args.db_region_z_size,
+        args.db_table_size,
+    )?;
+
+    Ok(store)
+}
+
+/// Open the configured database backend, as the server would.
+pub(super) async fn open_store(args: &Args) -> Result<ThreadRecordStore> {
+    match &args.storage {
+        DatabaseBackend::Postgres => {
+            let client = connect(args)?;
+            client.init_database().await?;
+
+            Ok(Arc::new(client))
+        }
+
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite(path) => {
+            let store = SqliteStore::open(
+                path,
+                args.db_region_x_size,
+                args.db_region_y_size,
+                args.db_region_z_size,
+                args.db_table_size,
+            )?;
+
+            Ok(Arc::new(store))
+        }
+
+        DatabaseBackend::Memory => Err(eyre!("in-memory storage can't be used by commands")),
+    }
+}
+
+async fn init(args: &Args) -> Result<()> {
+    if !args.validate() {
+        std::process::exit(1);
+    }
+
+    open_store(args).await?;
+    println!("Initialized {} database", args.storage);
+
+    Ok(())
+}
+
+async fn stats(args: &Args) -> Result<()> {
+    if !args.validate() {
+        std::process::exit(1);
+    }
+
+    let print_config = || {
+        println!("Backend: {}", args.storage);
+        println!("Sizing: {}", args.sizing());
+    };
+
+    // Databases are only connected to or opened read-only, so stats never change them
+    let store: ThreadRecordStore = match &args.storage {
+        DatabaseBackend::Postgres => {
+            let client = connect(args)?;
+            print_config();
+
+            let pending = client.pending_migrations().await?;
+            println!("Pending migrations: {}", pending.len());
+
+            match client.stored_sizing().await? {
+                Some(sizing) => println!("Stored sizing: {}", sizing),
+                None => {
+                    println!("Database isn't initialized");
+                    return Ok(());
+                }
+            }
+
+            Arc::new(client)
+        }
+
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite(path) => {
+            if !path.exists() {
+                return Err(eyre!("SQLite database {} doesn't exist", path.display()));
+            }
+
+            let store = open_sqlite(path, args)?;
+            print_config();
+
+            match store.stored_sizing().await? {
+                Some(sizing) => println!("Stored sizing: {}", sizing),
+                None => {
+                    println!("Database isn't initialized");
+                    return Ok(());
+                }
+            }
+
+            Arc::new(store)
+        }
+
+        DatabaseBackend::Memory => {
+            return Err(eyre!("in-memory storage can't be used by commands"))
+        }
+    };
+
+    let worlds = store.list_worlds().await?;
+    let table_count = worlds.iter().map(|world| world.table_count).sum::<u64>();
+    let record_count = worlds.iter().map(|world| world.record_count).sum::<u64>();
+
+    println!("Worlds: {}", worlds.len());
+    println!("Tables: {}", table_count);
+    println!("Records: {}", record_count);
+
+    Ok(())
+}
+
+async fn migrations(client: &DatabaseClient) -> Result<()> {
+    let pending = client.pending_migrations().await?;
+    if pending.is_empty() {
+        println!("No pending migrations");
+        return Ok(());
+    }
+
+    println!("{} pending migrations:", pending.len());
+    for migration in pending {
+        println!("  {}", migration);
+    }
+
+    Ok(())
+}
+
+async fn rebucket(client: &DatabaseClient, args: &Args) -> Result<()> {
+    if !args.validate() {
+        std::process::exit(1);
+    }
+
+    let moved = client.rebucket().await?;
+    println!("Rebucketed {} records to sizing {}", moved, client.sizing());
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use color_eyre::Result;
+
+use crate::args::{Args, Command};
+
+mod config;
+mod db;
+mod world;
+
+/// Run an admin subcommand to completion.
+///
+/// # Panics
+/// If `command` is [`Command::Serve`], which starts the server instead.
+pub async fn run_command(command: &Command, args: &Args) -> Result<()> {
+    match command {
+        Command::Serve => unreachable!("the server isn't started by commands"),
+        Command::Config(command) => config::run_config_command(command, args).await,
+        Command::Db(command) => db::run_db_command(command, args).await,
+        Command::World(command) => world::run_world_command(command, args).await,
+    }
+}
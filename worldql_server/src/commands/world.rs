@@ -0,0 +1,241 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use tracing::warn;
+
+use super::db::open_store;
+use crate::args::{Args, WorldCommand};
+use crate::database::{
+    import_record, navigation_path, read_snapshot, write_snapshot, DatabaseError, SnapshotError,
+    SnapshotNavigation, ThreadRecordStore, Writer,
+};
+use crate::structures::Record;
+use crate::utils::sanitize_world_name;
+
+/// Maximum number of records imported in a single transaction
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+pub(super) async fn run_world_command(command: &WorldCommand, args: &Args) -> Result<()> {
+    if !args.validate() {
+        std::process::exit(1);
+    }
+
+    let store = open_store(args).await?;
+
+    match command {
+        WorldCommand::List => list_worlds(&store).await,
+        WorldCommand::Drop { world_name } => drop_world(&store, world_name).await,
+        WorldCommand::Copy { from, to } => copy_world(&store, from, to).await,
+        WorldCommand::Rename { from, to } => rename_world(&store, from, to).await,
+        WorldCommand::Export {
+            world_name,
+            path,
+            navigation,
+        } => export_world(&store, args, world_name, path, *navigation).await,
+
+        WorldCommand::Import { path, world_name } => {
+            import_world(&store, args, path, world_name.as_deref()).await
+        }
+    }
+}
+
+async fn list_worlds(store: &ThreadRecordStore) -> Result<()> {
+    let worlds = store.list_worlds().await?;
+    if worlds.is_empty() {
+        println!("No stored worlds");
+        return Ok(());
+    }
+
+    println!("{} stored worlds:", worlds.len());
+    for world in worlds {
+        println!(
+            "  {} ({} tables, {} records)",
+            world.world_name, world.table_count, world.record_count
+        );
+    }
+
+    Ok(())
+}
+
+async fn drop_world(store: &ThreadRecordStore, world_name: &str) -> Result<()> {
+    warn_server_running();
+    match store.drop_world(world_name).await? {
+        true => println!("Dropped world {}", world_name),
+        false => println!("World {} doesn't exist", world_name),
+    }
+
+    Ok(())
+}
+
+async fn copy_world(store: &ThreadRecordStore, from: &str, to: &str) -> Result<()> {
+    let copied = store.copy_world(from, to).await?;
+    println!("Copied {} records from world {} to {}", copied, from, to);
+
+    Ok(())
+}
+
+async fn rename_world(store: &ThreadRecordStore, from: &str, to: &str) -> Result<()> {
+    warn_server_running();
+    store.rename_world(from, to).await?;
+    println!("Renamed world {} to {}", from, to);
+
+    Ok(())
+}
+
+async fn export_world(
+    store: &ThreadRecordStore,
+    args: &Args,
+    world_name: &str,
+    path: &Path,
+    navigation: bool,
+) -> Result<()> {
+    let world_name = sanitize_world_name(world_name)?;
+    let now = Utc::now().naive_utc();
+
+    let table_suffixes = store.get_world_tables(&world_name).await?;
+    let mut navigation = navigation.then(|| SnapshotNavigation::new(&world_name, args.sizing()));
+
+    // Written one table at a time, so only a single table is ever held in memory
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut exported = 0;
+    for table_suffix in table_suffixes {
+        let mut records = store.get_table_records(&world_name, table_suffix).await?;
+        records.retain(|record| !record.is_expired(&now));
+
+        if let Some(navigation) = &mut navigation {
+            navigation.add_records(&records);
+        }
+
+        exported += write_snapshot(&mut writer, records)?;
+    }
+
+    println!(
+        "Exported {} records from world {} to {}",
+        exported,
+        world_name,
+        path.display()
+    );
+
+    if let Some(navigation) = navigation {
+        let navigation_path = navigation_path(path);
+        fs::write(
+            &navigation_path,
+            serde_json::to_string_pretty(&navigation.to_json())?,
+        )?;
+
+        println!(
+            "Wrote navigation for {} regions to {}",
+            navigation.regions.len(),
+            navigation_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+async fn import_world(
+    store: &ThreadRecordStore,
+    args: &Args,
+    path: &Path,
+    world_name: Option<&str>,
+) -> Result<()> {
+    let mut records = read_snapshot(BufReader::new(File::open(path)?)).peekable();
+
+    let navigation_path = navigation_path(path);
+    let navigation = match navigation_path.exists() {
+        false => None,
+        true => {
+            let json = serde_json::from_str(&fs::read_to_string(&navigation_path)?)?;
+            Some(SnapshotNavigation::from_json(&json)?)
+        }
+    };
+
+    // Only the first record is read up front, to name the world after
+    let first_world_name = match records.peek() {
+        Some(Ok(record)) => Some(record.world_name.clone()),
+        _ => None,
+    };
+
+    let world_name = world_name
+        .or_else(|| navigation.as_ref().map(|n| n.world_name.as_str()))
+        .or(first_world_name.as_deref())
+        .ok_or_else(|| eyre!("snapshot is empty, --world-name is required"))?;
+
+    let world_name = sanitize_world_name(world_name)?;
+    let worlds = store.list_worlds().await?;
+    if worlds.iter().any(|world| world.world_name == world_name) {
+        return Err(DatabaseError::WorldExists(world_name).into());
+    }
+
+    let sizing = args.sizing();
+    if let Some(navigation) = navigation.filter(|n| n.sizing != sizing) {
+        println!(
+            "Rebucketing from sizing {} to {}",
+            navigation.sizing, sizing
+        );
+    }
+
+    // A failed import is dropped again, so it can simply be retried
+    let imported = import_records(store, records, &world_name).await;
+    if imported.is_err() {
+        if let Err(error) = store.drop_world(&world_name).await {
+            warn!(
+                "error dropping partially imported world {}: {}",
+                world_name, error
+            );
+        }
+    }
+
+    let imported = imported?;
+    println!("Imported {} records into world {}", imported, world_name);
+
+    Ok(())
+}
+
+/// Insert snapshot `records` into `world_name` in batches, returning the number of
+/// records inserted.
+async fn import_records(
+    store: &ThreadRecordStore,
+    records: impl Iterator<Item = Result<Record, SnapshotError>>,
+    world_name: &str,
+) -> Result<usize> {
+    let mut imported = 0;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    for record in records {
+        batch.push(import_record(record?, world_name)?);
+        if batch.len() < IMPORT_BATCH_SIZE {
+            continue;
+        }
+
+        imported += insert_batch(store, std::mem::take(&mut batch)).await?;
+    }
+
+    if !batch.is_empty() {
+        imported += insert_batch(store, batch).await?;
+    }
+
+    Ok(imported)
+}
+
+/// Insert a batch of imported records in a single transaction, returning the number of
+/// records inserted.
+async fn insert_batch(store: &ThreadRecordStore, batch: Vec<Record>) -> Result<usize> {
+    let count = batch.len();
+    let written = store.insert_records_atomic(batch, &Writer::SERVER).await;
+    match written.errors.into_iter().next() {
+        Some(error) => Err(error.into()),
+        None => Ok(count),
+    }
+}
+
+/// Warn that a running server won't see worlds dropped or renamed by commands.
+///
+/// Servers cache navigation IDs, which only the process changing a world clears, so a
+/// running server would keep writing to tables that no longer exist.
+fn warn_server_running() {
+    warn!("Make sure the server isn't running, it won't see this change until restarted");
+}
@@ -0,0 +1,162 @@
+use chrono::prelude::*;
+use deadpool_postgres::GenericClient;
+use tokio_postgres::types::ToSql;
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::versions::is_undefined_table;
+use super::world_region::navigation_bounds;
+use super::{
+    query_select_records_in_box, query_select_records_in_box_after, DataFilter,
+    QUERY_SELECT_TABLES_IN_BOUNDS, QUERY_SELECT_WORLD_TABLE_SUFFIXES,
+};
+use crate::structures::{Area, Record};
+use crate::utils::sanitize_world_name;
+
+impl DatabaseClient {
+    /// Returns a [`Vec`] containing all records positioned inside `area`, with data
+    /// matching `filter`.
+    ///
+    /// Only tables that overlap `area` are queried, and no navigation rows are created.
+    pub async fn get_records_in_area(
+        &self,
+        world_name: &str,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_records_in_area"])
+            .start_timer();
+
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+
+        let mut records = vec![];
+        for table_suffix in self.read_tables(&client, world_name, Some(&area)).await? {
+            let table_records =
+                records_in_area(&client, world_name, table_suffix, &area, after, filter).await?;
+
+            records.extend(table_records);
+        }
+
+        Ok(records)
+    }
+
+    /// Same as [`DatabaseClient::get_records_in_area`], but only queries one of the
+    /// tables returned by [`DatabaseClient::get_read_tables`].
+    pub async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_table_records_in_area"])
+            .start_timer();
+
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+
+        records_in_area(&client, world_name, table_suffix, &area, after, filter).await
+    }
+
+    /// Returns the suffixes of the tables that overlap `area`, or of every table in the
+    /// world if `area` is [`None`], so reads can be made one table at a time.
+    pub async fn get_read_tables(
+        &self,
+        world_name: &str,
+        area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+
+        self.read_tables(&client, world_name, area.as_ref()).await
+    }
+
+    /// See [`DatabaseClient::get_read_tables`], `world_name` must already be sanitized.
+    pub(super) async fn read_tables(
+        &self,
+        client: &impl GenericClient,
+        world_name: &str,
+        area: Option<&Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let rows = match area {
+            None => {
+                client
+                    .query(QUERY_SELECT_WORLD_TABLE_SUFFIXES, &[&world_name])
+                    .await?
+            }
+
+            Some(area) => {
+                let [min_x, max_x, min_y, max_y, min_z, max_z] = navigation_bounds(
+                    area,
+                    self.region_x_size(),
+                    self.region_y_size(),
+                    self.region_z_size(),
+                );
+
+                client
+                    .query(
+                        QUERY_SELECT_TABLES_IN_BOUNDS,
+                        &[&world_name, &min_x, &max_x, &min_y, &max_y, &min_z, &max_z],
+                    )
+                    .await?
+            }
+        };
+
+        let table_suffixes = rows
+            .into_iter()
+            .map(|row| row.try_get("table_suffix"))
+            .collect::<Result<Vec<i32>, _>>()?;
+
+        Ok(table_suffixes)
+    }
+}
+
+/// Returns the records positioned inside `area` from a single table, with data matching
+/// `filter`.
+async fn records_in_area(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    area: &Area,
+    after: Option<NaiveDateTime>,
+    filter: &DataFilter,
+) -> Result<Vec<Record>, DatabaseError> {
+    // Expired records may not have been deleted yet
+    let now = Utc::now().naive_utc();
+    let (min, max) = area.bounds();
+    let mut params: Vec<&(dyn ToSql + Sync)> =
+        vec![min.x(), max.x(), min.y(), max.y(), min.z(), max.z(), &now];
+
+    if let Some(after) = &after {
+        params.push(after);
+    }
+
+    let query = match after {
+        None => query_select_records_in_box(world_name, table_suffix),
+        Some(_) => query_select_records_in_box_after(world_name, table_suffix),
+    } + &filter.postgres_sql(params.len() + 1);
+    params.extend(filter.postgres_params());
+
+    let rows = match client.query(&query, &params).await {
+        // Table has navigation rows but no records yet
+        Err(error) if is_undefined_table(&error) => return Ok(vec![]),
+        result => result?,
+    };
+
+    let records = rows
+        .into_iter()
+        .map(|row| Record::from_postgres_row(row, world_name))
+        .filter(|record| match &record.position {
+            None => false,
+            Some(position) => area.contains(position),
+        })
+        .collect();
+
+    Ok(records)
+}
@@ -0,0 +1,70 @@
+use super::client::DatabaseClient;
+use super::store::{Writer, Written};
+use super::transaction::{delete_transaction, write_transaction};
+use crate::structures::Record;
+
+impl DatabaseClient {
+    /// Insert many [`Record`] structs in a single transaction, so either every record is
+    /// written or none are.
+    ///
+    /// Atomic writes are never buffered while the database is unavailable.
+    pub async fn insert_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["insert_records_atomic"])
+            .start_timer();
+
+        if records.is_empty() {
+            return Written::default();
+        }
+
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(error) => return vec![error].into(),
+        };
+
+        let records = records
+            .into_iter()
+            .map(|record| (record, writer.clone()))
+            .collect::<Vec<_>>();
+
+        let flushed = self.flush_writes(&mut client).await;
+
+        let mut errors = vec![];
+        let records = self.resolve_records(&client, records, &mut errors).await;
+        let written = write_transaction(&mut client, records, true, true, errors).await;
+
+        Written { flushed, ..written }
+    }
+
+    /// Delete many [`Record`] structs in a single transaction, so either every record is
+    /// deleted or none are.
+    pub async fn delete_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["delete_records_atomic"])
+            .start_timer();
+
+        if records.is_empty() {
+            return Written::default();
+        }
+
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(error) => return vec![error].into(),
+        };
+
+        let records = records
+            .into_iter()
+            .map(|record| (record, writer.clone()))
+            .collect::<Vec<_>>();
+
+        let flushed = self.flush_writes(&mut client).await;
+
+        let mut errors = vec![];
+        let records = self.resolve_records(&client, records, &mut errors).await;
+        let written = delete_transaction(&mut client, records, true, errors).await;
+
+        Written { flushed, ..written }
+    }
+}
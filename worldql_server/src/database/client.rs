@@ -1,43 +1,72 @@
-use ahash::AHashMap;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use ahash::{AHashMap, AHashSet};
+use async_trait::async_trait;
 use chrono::prelude::*;
-use color_eyre::Result;
+use deadpool_postgres::{GenericClient, Object, Transaction};
 use lru::LruCache;
 use thiserror::Error;
 use tokio_postgres::error::SqlState;
 use tokio_postgres::types::ToSql;
-use tokio_postgres::Client;
+use tokio_postgres::{Client, Row};
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use super::connection::ConnectionPool;
+use super::history::select_records_by_uuid;
+use super::migrations::mark_world_migrated;
+use super::nearest::find_nearest;
+use super::record_data::data_columns;
+use super::record_tables::{claim_uuids, forget_uuids, move_records};
+use super::sizing::Sizing;
+use super::store::{record_position, HistoryEntry, RecordStore, Role, WorldStats, Writer, Written};
+use super::tags::stored_tags;
+use super::transaction::{delete_transaction, write_transaction};
+use super::versions::{
+    delete_record_if_version, insert_record_if_version, record_access, RecordAccess,
+};
 use super::world_region::WorldRegion;
-use super::{query_create_world_schema, query_delete_duplictes, query_delete_record};
+use super::{
+    query_create_record_tables, query_create_world_schema, query_delete_record,
+    query_delete_record_as_peer, DataFilter,
+};
 use crate::database::{
-    query_create_world, query_create_world_index, query_insert_record, query_insert_record_many,
+    query_create_world, query_create_world_expiry_index, query_create_world_index,
+    query_create_world_tags_index, query_insert_record_many, query_insert_record_many_as_peer,
     query_select_records, query_select_records_after,
 };
-use crate::structures::{Record, Vector3};
+use crate::structures::{Area, Record, Vector3};
 use crate::utils::{sanitize_world_name, SanitizeError};
 
+/// Cloning a [`DatabaseClient`] is cheap, all clones share the same connection
+/// pool, navigation caches and write buffer.
+#[derive(Clone)]
 pub struct DatabaseClient {
-    pub(super) client: Client,
-    pub(super) table_cache: LruCache<WorldRegion, i32>,
-    pub(super) region_cache: LruCache<WorldRegion, i32>,
+    pub(super) pool: Arc<ConnectionPool>,
+    pub(super) table_cache: Arc<Mutex<LruCache<WorldRegion, i32>>>,
+    pub(super) region_cache: Arc<Mutex<LruCache<WorldRegion, i32>>>,
+    pub(super) navigation_lock: Arc<tokio::sync::Mutex<()>>,
 
     region_x_size: u16,
     region_y_size: u16,
     region_z_size: u16,
     table_size: u32,
-}
 
-pub type DedupeData = (Uuid, NaiveDateTime, String, Vector3);
+    write_buffer: Arc<Mutex<VecDeque<(Record, Writer)>>>,
+    write_buffer_size: usize,
+    flush_lock: Arc<tokio::sync::Mutex<()>>,
+}
 
 impl DatabaseClient {
     pub fn new(
-        client: Client,
+        pool: ConnectionPool,
         region_x_size: u16,
         region_y_size: u16,
         region_z_size: u16,
         table_size: u32,
         cache_size: usize,
+        write_buffer_size: usize,
     ) -> Self {
         let (table_cache, region_cache) = if cache_size == 0 {
             (LruCache::unbounded(), LruCache::unbounded())
@@ -46,14 +75,19 @@ impl DatabaseClient {
         };
 
         Self {
-            client,
-            table_cache,
-            region_cache,
+            pool: Arc::new(pool),
+            table_cache: Arc::new(Mutex::new(table_cache)),
+            region_cache: Arc::new(Mutex::new(region_cache)),
+            navigation_lock: Default::default(),
 
             region_x_size,
             region_y_size,
             region_z_size,
             table_size,
+
+            write_buffer: Default::default(),
+            write_buffer_size,
+            flush_lock: Default::default(),
         }
     }
 
@@ -82,336 +116,899 @@ impl DatabaseClient {
     // region: Methods
     /// Insert many [`Record`] structs into the database.
     ///
-    /// Batches records that map to the same table into a single `INSERT` operation.
-    pub async fn insert_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
+    /// Batches records that map to the same table into a single `INSERT` operation. The
+    /// changes are appended to the record history in the same transaction.
+    ///
+    /// If the database is unavailable and write buffering is enabled, records are held in
+    /// memory and inserted once the database is available again.
+    #[inline]
+    pub async fn insert_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.write_records(records, writer, true).await
+    }
+
+    /// Same as [`DatabaseClient::insert_records`], but only appends the changes to the
+    /// record history if `history` is set.
+    pub(super) async fn write_records(
+        &self,
+        records: Vec<Record>,
+        writer: &Writer,
+        history: bool,
+    ) -> Written {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["insert_records"])
+            .start_timer();
+
         // Early return for no records
-        if records.is_empty() {
-            return vec![];
+        if records.is_empty() && self.write_buffer.lock().unwrap().is_empty() {
+            return Written::default();
         }
 
-        type HashKey = (String, i32);
-        type HashValue = Vec<(i32, Record)>;
-        let mut table_map: AHashMap<HashKey, HashValue> = AHashMap::new();
+        let records = records
+            .into_iter()
+            .map(|record| (record, writer.clone()))
+            .collect::<Vec<_>>();
 
-        // Divide up records into table insertion operations
-        let len = records.len();
-        let mut errors = Vec::with_capacity(len);
-        for record in records {
-            // TODO: Handle records without position
-            let position = record.position.unwrap();
-            let world_name = match sanitize_world_name(&record.world_name) {
-                Ok(world_name) => world_name,
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(DatabaseError::Unavailable) => return self.buffer_writes(records).into(),
+            Err(error) => return vec![error].into(),
+        };
+
+        let flushed = self.flush_writes(&mut client).await;
+
+        let mut errors = Vec::with_capacity(records.len());
+        let records = self.resolve_records(&client, records, &mut errors).await;
+        let written = write_transaction(&mut client, records, history, false, errors).await;
+
+        Written { flushed, ..written }
+    }
+
+    /// Insert a single [`Record`] into the database.
+    #[deprecated = "use insert_records() instead"]
+    pub async fn insert_record(&self, record: &Record) -> Result<(), DatabaseError> {
+        let written = self
+            .insert_records(vec![record.clone()], &Writer::SERVER)
+            .await;
+
+        match written.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a [`Vec`] containing all records found within the region represented
+    /// by `point_inside_region`, with data matching `filter`
+    pub async fn get_records_in_region(
+        &self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_records_in_region"])
+            .start_timer();
+
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+        let (table_suffix, region_id) = self
+            .lookup_ids(&client, world_name, &point_inside_region)
+            .await?;
+
+        // Expired records may not have been deleted yet
+        let now = Utc::now().naive_utc();
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&region_id, &now];
+        let query = match &after {
+            // Send all results
+            None => query_select_records(world_name, table_suffix),
+
+            // Send only results after time
+            Some(after) => {
+                params.push(after);
+                query_select_records_after(world_name, table_suffix)
+            }
+        };
+
+        let query = query + &filter.postgres_sql(params.len() + 1);
+        params.extend(filter.postgres_params());
+
+        let result = client.query(&query, &params).await;
+
+        // Check for undefined table error and early return no records
+        if let Err(error) = result {
+            match error.as_db_error() {
+                None => return Err(error.into()),
+                Some(db_error) => {
+                    // Early return
+                    if *db_error.code() == SqlState::UNDEFINED_TABLE {
+                        return Ok(vec![]);
+                    }
+
+                    // Different error, re-throw
+                    return Err(error.into());
+                }
+            }
+        }
+
+        let records = result
+            .unwrap()
+            .into_iter()
+            .map(|row| Record::from_postgres_row(row, world_name))
+            .collect::<Vec<_>>();
+
+        Ok(records)
+    }
+
+    /// Delete many [`Record`] structs at once, appending the changes to the record history
+    /// in the same transaction.
+    pub async fn delete_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(error) => return vec![error].into(),
+        };
+
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["delete_records"])
+            .start_timer();
+
+        let flushed = self.flush_writes(&mut client).await;
+
+        let records = records
+            .into_iter()
+            .map(|record| (record, writer.clone()))
+            .collect::<Vec<_>>();
+
+        let mut errors = vec![];
+        let records = self.resolve_records(&client, records, &mut errors).await;
+        let written = delete_transaction(&mut client, records, false, errors).await;
+
+        Written { flushed, ..written }
+    }
+
+    /// Sanitize the world name of each record and look up its navigation IDs, creating
+    /// them if required.
+    pub(super) async fn resolve_records(
+        &self,
+        client: &Client,
+        records: Vec<(Record, Writer)>,
+        errors: &mut Vec<DatabaseError>,
+    ) -> Vec<ResolvedRecord> {
+        let mut resolved = Vec::with_capacity(records.len());
+        for (record, writer) in records {
+            let position = match record_position(&record) {
+                Ok(position) => position,
                 Err(error) => {
-                    errors.push(error.into());
+                    errors.push(error);
                     continue;
                 }
             };
 
-            // Lookup navigation IDs for this record
-            let (table_suffix, region_id) = match self.lookup_ids(&world_name, &position).await {
-                Ok(result) => result,
+            let world_name = match sanitize_world_name(&record.world_name) {
+                Ok(world_name) => world_name,
                 Err(error) => {
                     errors.push(error.into());
                     continue;
                 }
             };
 
-            // Get or create Vec for this table_suffix
-            let filtered_records = table_map
-                .entry((world_name, table_suffix))
-                .or_insert_with(|| Vec::with_capacity(len));
+            let (table_suffix, region_id) =
+                match self.lookup_ids(client, &world_name, &position).await {
+                    Ok(result) => result,
+                    Err(error) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                };
+
+            resolved.push(ResolvedRecord {
+                world_name,
+                table_suffix,
+                region_id,
+                record,
+                writer,
+            });
+        }
+
+        resolved
+    }
 
-            filtered_records.push((region_id, record));
+    /// Check that the database is reachable, reconnecting if required.
+    ///
+    /// Any buffered writes are flushed once the database is available.
+    pub async fn check_connection(&self) -> Written {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(error) => return vec![error].into(),
+        };
+
+        Written {
+            flushed: self.flush_writes(&mut client).await,
+            ..Default::default()
         }
+    }
+    // endregion
 
-        for ((world_name, table_suffix), records) in table_map {
-            // Destructure and map records
-            let records = records
-                .into_iter()
-                .map(|(region_id, record)| {
-                    (
-                        region_id,
-                        record.position.unwrap(),
-                        record.uuid,
-                        record.data,
-                        record.flex.map(|b| b.to_vec()),
-                    )
-                })
-                .collect::<Vec<_>>();
-
-            // Construct params array
-            let count = records.len();
-            let params = {
-                let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
-
-                for (region_id, position, uuid, data, flex) in &records {
-                    params.push(region_id);
-                    params.push(position.x());
-                    params.push(position.y());
-                    params.push(position.z());
-                    params.push(uuid);
-                    params.push(data);
-                    params.push(flex);
-                }
+    // region: Write Buffer
+    /// Write any buffered records ahead of a later change, returning them as stored.
+    ///
+    /// Every write and delete flushes first, and waits for any flush in progress, so no
+    /// later change can overtake a buffered record. Records are put back in the buffer
+    /// if the connection fails again, other failures are logged.
+    pub(super) async fn flush_writes(&self, client: &mut Object) -> Vec<Record> {
+        let _guard = self.flush_lock.lock().await;
+        let buffered = self
+            .write_buffer
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect::<Vec<_>>();
+        if buffered.is_empty() {
+            return vec![];
+        }
 
-                params
-            };
+        debug!("flushing {} buffered records", buffered.len());
 
-            // Build a bulk insertion query and execute
-            let query = query_insert_record_many(&world_name, table_suffix, count);
-            let result = self.client.execute(&query, &params).await;
+        let mut errors = vec![];
+        let records = self
+            .resolve_records(client, buffered.clone(), &mut errors)
+            .await;
 
-            // Insertion completed without errors, exit early
-            if result.is_ok() {
-                continue;
+        // Nothing is stored if the connection failed, as the transaction never commits
+        let written = write_transaction(client, records, true, false, errors).await;
+        if written.records.is_empty() && written.errors.iter().any(is_connection_error) {
+            let mut write_buffer = self.write_buffer.lock().unwrap();
+            for buffered in buffered.into_iter().rev() {
+                write_buffer.push_front(buffered);
             }
 
-            // Handle SQL errors
-            let error = result.unwrap_err();
-            let db_error = error.as_db_error();
+            return vec![];
+        }
+
+        for error in written.errors {
+            warn!("error writing buffered record: {}", error);
+        }
 
-            // If error isn't a database error, re-throw
-            if db_error.is_none() {
-                errors.push(DatabaseError::PostgresError(error));
-                continue;
-            }
+        written.records
+    }
 
-            // Check for undefined table error, if not then re-throw
-            let db_error = db_error.unwrap();
-            if *db_error.code() != SqlState::UNDEFINED_TABLE {
-                errors.push(DatabaseError::PostgresError(error));
+    /// Hold records in memory while the database is unavailable.
+    ///
+    /// Records that don't fit in the buffer, and conditional writes which can't be
+    /// checked until later, are rejected with [`DatabaseError::Unavailable`].
+    ///
+    /// Buffered records keep their [`Writer`], so are still checked against record owners.
+    fn buffer_writes(&self, records: Vec<(Record, Writer)>) -> Vec<DatabaseError> {
+        let mut write_buffer = self.write_buffer.lock().unwrap();
+        let mut errors = vec![];
+        for (record, writer) in records {
+            if record.version.is_some() || write_buffer.len() >= self.write_buffer_size {
+                errors.push(DatabaseError::Unavailable);
                 continue;
             }
 
-            // Create schema for world
-            let result = self
-                .client
-                .execute(&query_create_world_schema(&world_name), &[])
-                .await;
+            write_buffer.push_back((record, writer));
+        }
 
-            if let Err(error) = result {
-                errors.push(error.into());
-                continue;
+        if !errors.is_empty() && self.write_buffer_size > 0 {
+            warn!(
+                "write buffer full, dropped {} records while database is unavailable",
+                errors.len()
+            );
+        }
+
+        errors
+    }
+    // endregion
+}
+
+// region: ResolvedRecord Struct
+/// A record to write, along with its sanitized world name and navigation IDs.
+pub(super) struct ResolvedRecord {
+    pub world_name: String,
+    pub table_suffix: i32,
+    pub region_id: i32,
+    pub record: Record,
+    pub writer: Writer,
+}
+// endregion
+
+// region: Helper Functions
+/// Write resolved records within `transaction`, returning the changes made.
+///
+/// Stored records are only looked up as the old values of the changes if `history` is
+/// set, otherwise every change is recorded as a create.
+///
+/// Batches records that map to the same table into a single `INSERT` operation. Tables
+/// must already exist, see [`super::transaction::create_missing_tables`]. Each batch is
+/// written within a savepoint, so a failed batch doesn't abort the whole transaction.
+pub(super) async fn write_resolved(
+    transaction: &mut Transaction<'_>,
+    records: Vec<ResolvedRecord>,
+    history: bool,
+    errors: &mut Vec<DatabaseError>,
+) -> Vec<HistoryEntry> {
+    // Peer writes are batched separately, as they are checked against record owners
+    type HashKey = (String, i32, bool);
+    let mut table_map: AHashMap<HashKey, TableBatch> = AHashMap::new();
+
+    // Divide up records into table insertion operations
+    let mut entries = vec![];
+    for resolved in records {
+        let ResolvedRecord {
+            world_name,
+            table_suffix,
+            region_id,
+            record,
+            writer,
+        } = resolved;
+
+        // Conditional writes are checked and written one at a time
+        if let Some(version) = record.version {
+            let savepoint = match transaction.savepoint("write").await {
+                Ok(savepoint) => savepoint,
+                Err(error) => {
+                    errors.push(error.into());
+                    continue;
+                }
+            };
+
+            let result = insert_record_if_version(
+                &savepoint,
+                &world_name,
+                table_suffix,
+                region_id,
+                &record,
+                version,
+                &writer,
+            )
+            .await;
+
+            match release(savepoint, result).await {
+                Ok(entry) => entries.push(entry),
+                Err(error) => errors.push(error),
             }
 
-            // Create table for world region
-            let result = self
-                .client
-                .execute(&query_create_world(&world_name, table_suffix), &[])
-                .await;
+            continue;
+        }
+
+        // Get or create map for this table_suffix
+        let admin = writer.role == Role::Admin;
+        let filtered_records = table_map
+            .entry((world_name, table_suffix, admin))
+            .or_default();
+
+        // A single upsert can't affect the same row twice, so only the last
+        // write for each uuid is kept
+        filtered_records.insert(record.uuid, (region_id, record, writer));
+    }
 
-            if let Err(error) = result {
+    for ((world_name, table_suffix, admin), records) in table_map {
+        let savepoint = match transaction.savepoint("write").await {
+            Ok(savepoint) => savepoint,
+            Err(error) => {
                 errors.push(error.into());
                 continue;
             }
+        };
 
-            // Create index for new table
-            let result = self
-                .client
-                .execute(&query_create_world_index(&world_name, table_suffix), &[])
-                .await;
+        let result = write_batch(
+            &savepoint,
+            &world_name,
+            table_suffix,
+            admin,
+            records,
+            history,
+        )
+        .await;
+
+        match release(savepoint, result).await {
+            Ok((written, denied)) => {
+                entries.extend(written);
+                errors.extend(denied);
+            }
 
-            if let Err(error) = result {
-                errors.push(error.into());
-                continue;
+            Err(error) => errors.push(error),
+        }
+    }
+
+    entries
+}
+
+/// Records written to a single table, by uuid.
+///
+/// Values have the form `(region_id, record, writer)`
+type TableBatch = AHashMap<Uuid, (i32, Record, Writer)>;
+
+/// Upsert a batch of records into a single table, returning the changes made and the
+/// errors for records that peers may not write.
+///
+/// Records stored in another table of the world are checked against their stored
+/// values, then moved into this table before being written.
+async fn write_batch(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    admin: bool,
+    mut records: TableBatch,
+    history: bool,
+) -> Result<(Vec<HistoryEntry>, Vec<DatabaseError>), DatabaseError> {
+    let mut denied = vec![];
+    let uuids = records.keys().copied().collect::<Vec<_>>();
+    let elsewhere = claim_uuids(client, world_name, table_suffix, &uuids).await?;
+    for (from, mut uuids) in elsewhere {
+        for stored in select_records_by_uuid(client, world_name, from, &uuids).await? {
+            let (_, record, writer) = &records[&stored.uuid];
+            let sharing = record.writers.is_some();
+            if let Err(error) = RecordAccess::from(&stored).check(stored.uuid, writer, sharing) {
+                uuids.retain(|uuid| *uuid != stored.uuid);
+                records.remove(&stored.uuid);
+                denied.push(error);
             }
+        }
 
-            // Retry insertion
-            let result = self.client.execute(&query, &params[..]).await;
-            if let Err(error) = result {
+        move_records(client, world_name, from, table_suffix, &uuids).await?;
+    }
+
+    if records.is_empty() {
+        return Ok((vec![], denied));
+    }
+
+    // Lookup the stored records first, as the old values in the history
+    let mut old_records = AHashMap::new();
+    if history {
+        let uuids = records.keys().copied().collect::<Vec<_>>();
+        let records = select_records_by_uuid(client, world_name, table_suffix, &uuids).await?;
+        old_records.extend(records.into_iter().map(|record| (record.uuid, record)));
+    }
+
+    // Destructure and map records
+    let mut peers = AHashMap::with_capacity(records.len());
+    let records = records
+        .into_iter()
+        .map(|(uuid, (region_id, record, writer))| {
+            peers.insert(uuid, writer.peer);
+            let owner = writer.owner_of(&record, None);
+            let position = record_position(&record)?;
+            let (data, data_json) = data_columns(record.data);
+            Ok((
+                region_id,
+                position,
+                record.uuid,
+                data,
+                data_json,
+                record.flex.map(|b| b.to_vec()),
+                record.expires_at,
+                owner,
+                record.writers,
+                stored_tags(record.tags),
+            ))
+        })
+        .collect::<Result<Vec<_>, DatabaseError>>()?;
+
+    // Construct params array
+    let count = records.len();
+    let params = {
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+
+        for (region_id, position, uuid, data, data_json, flex, expires_at, owner, writers, tags) in
+            &records
+        {
+            params.push(region_id);
+            params.push(position.x());
+            params.push(position.y());
+            params.push(position.z());
+            params.push(uuid);
+            params.push(data);
+            params.push(data_json);
+            params.push(flex);
+            params.push(expires_at);
+            params.push(owner);
+            params.push(writers);
+            params.push(tags);
+        }
+
+        params
+    };
+
+    // Build a bulk insertion query and execute
+    let query = match admin {
+        true => query_insert_record_many(world_name, table_suffix, count),
+        false => query_insert_record_many_as_peer(world_name, table_suffix, count),
+    };
+
+    let rows = client.query(&query, &params).await?;
+
+    // Upserts return every record written
+    let now = Utc::now().naive_utc();
+    let mut entries = Vec::with_capacity(rows.len());
+    let mut written = AHashSet::with_capacity(rows.len());
+    for row in rows {
+        let new = Record::from_postgres_row(row, world_name);
+        written.insert(new.uuid);
+
+        let old = old_records.remove(&new.uuid);
+        let peer = peers.get(&new.uuid).copied().flatten();
+        entries.push(HistoryEntry::new(now, peer, old, Some(new)));
+    }
+
+    // Any records peers didn't write were denied
+    let rejected = records
+        .iter()
+        .filter(|(_, _, uuid, ..)| !written.contains(uuid))
+        .map(|(_, _, uuid, ..)| DatabaseError::PermissionDenied { uuid: *uuid });
+
+    denied.extend(rejected);
+
+    Ok((entries, denied))
+}
+
+/// Delete resolved records within `transaction`, returning the changes made.
+///
+/// Each record is deleted within a savepoint, so a failed delete doesn't abort the whole
+/// transaction.
+pub(super) async fn delete_resolved(
+    transaction: &mut Transaction<'_>,
+    records: Vec<ResolvedRecord>,
+    errors: &mut Vec<DatabaseError>,
+) -> Vec<HistoryEntry> {
+    let mut entries = vec![];
+    for resolved in records {
+        let savepoint = match transaction.savepoint("delete").await {
+            Ok(savepoint) => savepoint,
+            Err(error) => {
                 errors.push(error.into());
                 continue;
             }
+        };
+
+        let result = delete_record(&savepoint, resolved).await;
+        match release(savepoint, result).await {
+            Ok(entry) => entries.extend(entry),
+            Err(error) => errors.push(error),
         }
+    }
 
-        errors
+    entries
+}
+
+/// Delete a single resolved record, returning the change made if it existed.
+async fn delete_record(
+    client: &impl GenericClient,
+    resolved: ResolvedRecord,
+) -> Result<Option<HistoryEntry>, DatabaseError> {
+    let ResolvedRecord {
+        world_name,
+        table_suffix,
+        region_id,
+        record,
+        writer,
+    } = resolved;
+
+    if let Some(version) = record.version {
+        return delete_record_if_version(
+            client,
+            &world_name,
+            table_suffix,
+            record.uuid,
+            version,
+            &writer,
+        )
+        .await;
     }
 
-    /// Insert a single [`Record`] into the database.
-    #[deprecated = "use insert_records() instead"]
-    pub async fn insert_record(&mut self, record: &Record) -> Result<(), DatabaseError> {
-        // TODO: Handle records without position
-        let position = record.position.unwrap();
-        let world_name = sanitize_world_name(&record.world_name)?;
-
-        let (table_suffix, region_id) = self.lookup_ids(&world_name, &position).await?;
-        let query = query_insert_record(&world_name, table_suffix);
-
-        let result = self
-            .client
-            .execute(
-                &query,
-                &[
-                    &region_id,
-                    position.x(),
-                    position.y(),
-                    position.z(),
-                    &record.uuid,
-                    &record.data,
-                    &record.flex.as_ref().map(|b| b.to_vec()),
-                ],
-            )
-            .await;
+    let deleted = match &writer.role {
+        Role::Admin => {
+            let query = query_delete_record(&world_name, table_suffix);
+            client
+                .query_opt(&query, &[&region_id, &record.uuid])
+                .await?
+        }
 
-        // Insertion completed without errors, exit early
-        if result.is_ok() {
-            return Ok(());
+        Role::Peer(identity) => {
+            let query = query_delete_record_as_peer(&world_name, table_suffix);
+            let params: [&(dyn ToSql + Sync); 3] = [&region_id, &record.uuid, identity];
+            client.query_opt(&query, &params).await?
         }
+    };
 
-        // Handle SQL Error
-        let error = result.unwrap_err();
-        let db_error = error.as_db_error();
+    if deleted.is_some() {
+        forget_uuids(client, &world_name, &[record.uuid]).await?;
+        return Ok(deleted_entry(deleted, &world_name, &writer));
+    }
+
+    // Nothing deleted, check if the record exists and is owned by someone else
+    if let Some(access) = record_access(client, &world_name, table_suffix, &record.uuid).await? {
+        access.check(record.uuid, &writer, false)?;
+    }
+
+    Ok(None)
+}
 
-        // If error isn't a database error, re-throw
-        if db_error.is_none() {
-            return Err(DatabaseError::PostgresError(error));
+/// Release `savepoint` if `result` is ok, otherwise roll back to it.
+async fn release<T>(
+    savepoint: Transaction<'_>,
+    result: Result<T, DatabaseError>,
+) -> Result<T, DatabaseError> {
+    match result {
+        Ok(value) => {
+            savepoint.commit().await?;
+            Ok(value)
         }
 
-        // Check for undefined table error, if not then re-throw
-        let db_error = db_error.unwrap();
-        if *db_error.code() != SqlState::UNDEFINED_TABLE {
-            return Err(DatabaseError::PostgresError(error));
+        Err(error) => {
+            if let Err(error) = savepoint.rollback().await {
+                warn!("error rolling back to savepoint: {}", error);
+            }
+
+            Err(error)
         }
+    }
+}
 
-        // Create schema for world
-        self.client
-            .execute(&query_create_world_schema(&world_name), &[])
-            .await?;
+/// Returns the history entry for a record deleted by `writer`, taking the deleted row.
+fn deleted_entry(row: Option<Row>, world_name: &str, writer: &Writer) -> Option<HistoryEntry> {
+    let old = Record::from_postgres_row(row?, world_name);
+    let entry = HistoryEntry::new(Utc::now().naive_utc(), writer.peer, Some(old), None);
 
-        // Create table for world region
-        self.client
-            .execute(&query_create_world(&world_name, table_suffix), &[])
-            .await?;
+    Some(entry)
+}
 
-        // Create index for new table
-        self.client
-            .execute(&query_create_world_index(&world_name, table_suffix), &[])
-            .await?;
+/// Returns `true` if `error` means the connection failed, rather than a statement.
+fn is_connection_error(error: &DatabaseError) -> bool {
+    match error {
+        DatabaseError::Unavailable => true,
+        DatabaseError::PostgresError(error) => error.is_closed() || error.as_db_error().is_none(),
+        _ => false,
+    }
+}
 
-        // Retry insertion
-        self.client
-            .execute(
-                &query,
-                &[
-                    &region_id,
-                    position.x(),
-                    position.y(),
-                    position.z(),
-                    &record.uuid,
-                    &record.data,
-                    &record.flex.as_ref().map(|b| b.to_vec()),
-                ],
-            )
-            .await?;
+/// Create a world's schema and a table within it, along with the table's indexes.
+pub(super) async fn create_world_table(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+) -> Result<(), DatabaseError> {
+    // Create schema for world
+    client
+        .execute(&query_create_world_schema(world_name), &[])
+        .await?;
+
+    client
+        .execute(&query_create_record_tables(world_name), &[])
+        .await?;
+
+    // New tables always use the latest world schema
+    mark_world_migrated(client, world_name).await?;
+
+    // Create table for world region
+    client
+        .execute(&query_create_world(world_name, table_suffix), &[])
+        .await?;
+
+    // Create indexes for new table
+    client
+        .execute(&query_create_world_index(world_name, table_suffix), &[])
+        .await?;
+
+    client
+        .execute(
+            &query_create_world_expiry_index(world_name, table_suffix),
+            &[],
+        )
+        .await?;
+
+    client
+        .execute(
+            &query_create_world_tags_index(world_name, table_suffix),
+            &[],
+        )
+        .await?;
+
+    Ok(())
+}
+// endregion
 
-        Ok(())
+// region: RecordStore Impl
+#[async_trait]
+impl RecordStore for DatabaseClient {
+    #[inline]
+    async fn insert_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        DatabaseClient::insert_records(self, records, writer).await
     }
 
-    /// Returns a [`Vec`] containing all records found within the region represented
-    /// by `point_inside_region`
-    pub async fn get_records_in_region(
-        &mut self,
+    #[inline]
+    async fn insert_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        DatabaseClient::insert_records_atomic(self, records, writer).await
+    }
+
+    #[inline]
+    async fn get_records_in_region(
+        &self,
         world_name: &str,
         point_inside_region: Vector3,
         after: Option<NaiveDateTime>,
-    ) -> Result<Vec<(NaiveDateTime, Record)>> {
-        let (table_suffix, region_id) = self.lookup_ids(world_name, &point_inside_region).await?;
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_records_in_region(self, world_name, point_inside_region, after, filter)
+            .await
+    }
 
-        let result = match after {
-            // Send all results
-            None => {
-                let query = query_select_records(world_name, table_suffix);
-                self.client.query(&query, &[&region_id]).await
-            }
+    #[inline]
+    async fn get_records_in_area(
+        &self,
+        world_name: &str,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_records_in_area(self, world_name, area, after, filter).await
+    }
 
-            // Send only results after time
-            Some(after) => {
-                let query = query_select_records_after(world_name, table_suffix);
-                self.client.query(&query, &[&region_id, &after]).await
-            }
-        };
+    #[inline]
+    async fn get_nearest_records(
+        &self,
+        world_name: &str,
+        point: Vector3,
+        count: usize,
+        max_distance: f64,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let start_radius = f64::from(self.sizing().min_region_size());
+        find_nearest(
+            self,
+            world_name,
+            point,
+            count,
+            max_distance,
+            after,
+            filter,
+            start_radius,
+        )
+        .await
+    }
 
-        // Check for undefined table error and early return no records
-        if let Err(error) = result {
-            match error.as_db_error() {
-                None => return Err(error.into()),
-                Some(db_error) => {
-                    // Early return
-                    if *db_error.code() == SqlState::UNDEFINED_TABLE {
-                        return Ok(vec![]);
-                    }
+    #[inline]
+    async fn get_records_with_tag(
+        &self,
+        world_name: &str,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_records_with_tag(self, world_name, tag, area, after, filter).await
+    }
 
-                    // Different error, re-throw
-                    return Err(error.into());
-                }
-            }
-        }
+    #[inline]
+    async fn get_read_tables(
+        &self,
+        world_name: &str,
+        area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        DatabaseClient::get_read_tables(self, world_name, area).await
+    }
 
-        let records = result
-            .unwrap()
-            .into_iter()
-            .map(|row| {
-                let timestamp: NaiveDateTime = row.get("last_modified");
-                let record = Record::from_postgres_row(row, world_name);
+    #[inline]
+    async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_table_records_in_area(
+            self,
+            world_name,
+            table_suffix,
+            area,
+            after,
+            filter,
+        )
+        .await
+    }
 
-                (timestamp, record)
-            })
-            .collect::<Vec<_>>();
+    #[inline]
+    async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_table_records_with_tag(
+            self,
+            world_name,
+            table_suffix,
+            tag,
+            area,
+            after,
+            filter,
+        )
+        .await
+    }
 
-        Ok(records)
+    #[inline]
+    async fn delete_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        DatabaseClient::delete_records(self, records, writer).await
     }
 
-    /// Delete many [`Record`] structs at once.
-    pub async fn delete_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
-        let mut errors = vec![];
+    #[inline]
+    async fn delete_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        DatabaseClient::delete_records_atomic(self, records, writer).await
+    }
 
-        for record in records {
-            // TODO: Handle records without position
-            let position = record.position.unwrap();
-            let world_name = match sanitize_world_name(&record.world_name) {
-                Ok(world_name) => world_name,
-                Err(error) => {
-                    errors.push(error.into());
-                    continue;
-                }
-            };
+    #[inline]
+    async fn delete_expired_records(
+        &self,
+        now: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::delete_expired_records(self, now, limit).await
+    }
 
-            let (table_suffix, region_id) = match self.lookup_ids(&world_name, &position).await {
-                Ok(result) => result,
-                Err(error) => {
-                    errors.push(error.into());
-                    continue;
-                }
-            };
+    #[cfg(any(feature = "http", test))]
+    #[inline]
+    async fn get_record_history(
+        &self,
+        world_name: &str,
+        uuid: Uuid,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, DatabaseError> {
+        DatabaseClient::get_record_history(self, world_name, uuid, limit).await
+    }
 
-            let query = query_delete_record(&world_name, table_suffix);
-            let result = self
-                .client
-                .execute(&query, &[&region_id, &record.uuid])
-                .await;
+    #[inline]
+    async fn delete_history_before(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        DatabaseClient::delete_history_before(self, before).await
+    }
 
-            if let Err(error) = result {
-                errors.push(error.into())
-            }
-        }
+    #[inline]
+    async fn check_connection(&self) -> Written {
+        DatabaseClient::check_connection(self).await
+    }
 
-        errors
+    #[inline]
+    async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError> {
+        DatabaseClient::list_worlds(self).await
     }
 
-    /// Delete duplicate records based on [`Uuid`] and last modified [`NaiveDateTime`]
-    pub async fn dedupe_records(&mut self, ops: Vec<DedupeData>) -> Result<(), DatabaseError> {
-        // TODO: Run concurrently
-        for (uuid, timestamp, world_name, position) in ops {
-            let (table_suffix, _) = self.lookup_ids(&world_name, &position).await?;
-            let query = query_delete_duplictes(&world_name, table_suffix);
+    #[inline]
+    async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError> {
+        DatabaseClient::drop_world(self, world_name).await
+    }
 
-            self.client.execute(&query, &[&uuid, &timestamp]).await?;
-        }
+    #[inline]
+    async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError> {
+        DatabaseClient::copy_world(self, from, to).await
+    }
 
-        Ok(())
+    #[inline]
+    async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError> {
+        DatabaseClient::rename_world(self, from, to).await
+    }
+
+    #[inline]
+    async fn get_world_tables(&self, world_name: &str) -> Result<Vec<i32>, DatabaseError> {
+        DatabaseClient::get_world_tables(self, world_name).await
+    }
+
+    #[inline]
+    async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        DatabaseClient::get_table_records(self, world_name, table_suffix).await
     }
-    // endregion
 }
+// endregion
 
 #[derive(Debug, Error)]
 pub enum DatabaseError {
@@ -420,4 +1017,128 @@ pub enum DatabaseError {
 
     #[error(transparent)]
     PostgresError(#[from] tokio_postgres::Error),
+
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("connection pool error: {0}")]
+    PoolError(String),
+
+    #[error("database is unavailable")]
+    Unavailable,
+
+    #[error("database uses sizing {stored}, but the server is configured with {configured}")]
+    SizingMismatch { stored: Sizing, configured: Sizing },
+
+    #[error("a rebucket is in progress, finish it with `db rebucket`")]
+    RebucketInProgress,
+
+    #[error("record {uuid} is at version {version}")]
+    VersionConflict { uuid: Uuid, version: u64 },
+
+    #[error("record {uuid} is owned by another peer")]
+    PermissionDenied { uuid: Uuid },
+
+    #[error("record {uuid} has no position")]
+    MissingPosition { uuid: Uuid },
+
+    #[error("world {0} doesn't exist")]
+    WorldNotFound(String),
+
+    #[error("world {0} already exists")]
+    WorldExists(String),
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{health, Health};
+
+    fn client(psql_conn: &str) -> DatabaseClient {
+        let health = Arc::new(Health::new(&[health::POSTGRES]));
+        let pool = ConnectionPool::new(psql_conn, 1, health).unwrap();
+
+        DatabaseClient::new(pool, 16, 256, 16, 1024, 0, 16)
+    }
+
+    fn record(uuid: Uuid) -> Record {
+        Record {
+            uuid,
+            position: Some(Vector3::new(1.0, 1.0, 1.0)),
+            world_name: "write_buffer".into(),
+            data: Some("data".into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn buffered_while_unavailable() {
+        let client = client("host=127.0.0.1 port=1 connect_timeout=1");
+
+        let written = client
+            .insert_records(vec![record(Uuid::new_v4())], &Writer::SERVER)
+            .await;
+        assert!(written.errors.is_empty());
+        assert!(written.records.is_empty());
+
+        // Conditional writes can't be checked until the database is available
+        let conditional = Record {
+            version: Some(1),
+            ..record(Uuid::new_v4())
+        };
+        let written = client
+            .insert_records(vec![conditional], &Writer::SERVER)
+            .await;
+        assert!(matches!(written.errors[..], [DatabaseError::Unavailable]));
+
+        // Deletes aren't buffered, and buffered records are kept until they are written
+        let written = client
+            .delete_records(vec![record(Uuid::new_v4())], &Writer::SERVER)
+            .await;
+        assert!(matches!(written.errors[..], [DatabaseError::Unavailable]));
+        assert!(client.check_connection().await.flushed.is_empty());
+        assert_eq!(client.write_buffer.lock().unwrap().len(), 1);
+    }
+
+    /// Needs a PostgreSQL database, set `WQL_TEST_PSQL` to its connection string to run.
+    #[tokio::test]
+    async fn flushed_before_delete() {
+        let psql_conn = match std::env::var("WQL_TEST_PSQL") {
+            Ok(psql_conn) => psql_conn,
+            Err(_) => return,
+        };
+
+        let client = client(&psql_conn);
+        client.init_database().await.unwrap();
+        client.drop_world("write_buffer").await.unwrap();
+
+        // Created while the database was unavailable, then deleted once it is back
+        let uuid = Uuid::new_v4();
+        assert!(client
+            .buffer_writes(vec![(record(uuid), Writer::SERVER)])
+            .is_empty());
+
+        let written = client
+            .delete_records(vec![record(uuid)], &Writer::SERVER)
+            .await;
+        assert!(written.errors.is_empty());
+        assert_eq!(written.flushed.len(), 1);
+        assert_eq!(written.records.len(), 1);
+
+        let stored = client
+            .get_records_in_region(
+                "write_buffer",
+                Vector3::new(1.0, 1.0, 1.0),
+                None,
+                &DataFilter::default(),
+            )
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+
+        client.drop_world("write_buffer").await.unwrap();
+    }
 }
+// endregion
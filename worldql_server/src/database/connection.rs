@@ -0,0 +1,182 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use tokio_postgres::NoTls;
+use tracing::{info, warn};
+
+use super::DatabaseError;
+use crate::utils::{health, ThreadHealth};
+
+// region: Constants
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// endregion
+
+// region: ConnectionPool
+/// Pool of PostgreSQL connections.
+///
+/// Broken connections are replaced when they are next checked out. If a new connection
+/// cannot be made, further attempts are delayed using exponential backoff and
+/// [`DatabaseError::Unavailable`] is returned in the meantime.
+pub struct ConnectionPool {
+    pool: Pool,
+    backoff: Mutex<Backoff>,
+    health: ThreadHealth,
+}
+
+impl ConnectionPool {
+    pub fn new(
+        psql_conn: &str,
+        max_size: usize,
+        health: ThreadHealth,
+    ) -> Result<Self, DatabaseError> {
+        let mut config = tokio_postgres::Config::from_str(psql_conn)?;
+        if config.get_connect_timeout().is_none() {
+            config.connect_timeout(CONNECT_TIMEOUT);
+        }
+
+        let manager_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+
+        let manager = Manager::from_config(config, NoTls, manager_config);
+        let pool = Pool::builder(manager)
+            .max_size(max_size)
+            .build()
+            .map_err(|error| DatabaseError::PoolError(error.to_string()))?;
+
+        Ok(Self {
+            pool,
+            backoff: Mutex::new(Backoff::new(MIN_BACKOFF, MAX_BACKOFF)),
+            health,
+        })
+    }
+
+    /// Check out a connection from the pool.
+    ///
+    /// Returns [`DatabaseError::Unavailable`] without attempting to connect if the last
+    /// connection attempt failed and the backoff delay has not yet elapsed.
+    pub(super) async fn get(&self) -> Result<Object, DatabaseError> {
+        {
+            let backoff = self.backoff.lock().unwrap();
+            if !backoff.can_retry(Instant::now()) {
+                return Err(DatabaseError::Unavailable);
+            }
+        }
+
+        match self.pool.get().await {
+            Ok(client) => {
+                let mut backoff = self.backoff.lock().unwrap();
+                if backoff.succeeded() {
+                    info!("Reconnected to PostgreSQL");
+                    self.health.set_ready(health::POSTGRES, true);
+                }
+
+                Ok(client)
+            }
+
+            Err(error) => {
+                let mut backoff = self.backoff.lock().unwrap();
+                let delay = backoff.failed(Instant::now());
+
+                warn!("PostgreSQL unavailable, retrying in {:?}: {}", delay, error);
+
+                self.health.set_ready(health::POSTGRES, false);
+                Err(DatabaseError::Unavailable)
+            }
+        }
+    }
+}
+// endregion
+
+// region: Backoff
+/// Exponential backoff state for reconnection attempts.
+#[derive(Debug)]
+struct Backoff {
+    min: Duration,
+    max: Duration,
+
+    delay: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+
+            delay: min,
+            retry_at: None,
+        }
+    }
+
+    /// Returns `true` if another attempt can be made at the given [`Instant`].
+    fn can_retry(&self, now: Instant) -> bool {
+        match self.retry_at {
+            None => true,
+            Some(retry_at) => now >= retry_at,
+        }
+    }
+
+    /// Record a failed attempt, returning the delay until the next attempt.
+    fn failed(&mut self, now: Instant) -> Duration {
+        let delay = self.delay;
+
+        self.retry_at = Some(now + delay);
+        self.delay = (self.delay * 2).min(self.max);
+
+        delay
+    }
+
+    /// Record a successful attempt.
+    ///
+    /// Returns `true` if previous attempts had failed.
+    fn succeeded(&mut self) -> bool {
+        let recovered = self.retry_at.is_some();
+
+        self.retry_at = None;
+        self.delay = self.min;
+
+        recovered
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let min = Duration::from_secs(1);
+        let max = Duration::from_secs(5);
+        let mut backoff = Backoff::new(min, max);
+
+        let now = Instant::now();
+        assert!(backoff.can_retry(now));
+
+        // Delay doubles after each failure
+        assert_eq!(backoff.failed(now), Duration::from_secs(1));
+        assert!(!backoff.can_retry(now));
+        assert!(backoff.can_retry(now + Duration::from_secs(1)));
+
+        assert_eq!(backoff.failed(now), Duration::from_secs(2));
+        assert_eq!(backoff.failed(now), Duration::from_secs(4));
+
+        // Delay is capped at max
+        assert_eq!(backoff.failed(now), Duration::from_secs(5));
+        assert_eq!(backoff.failed(now), Duration::from_secs(5));
+
+        // Success resets delay
+        assert!(backoff.succeeded());
+        assert!(backoff.can_retry(now));
+        assert!(!backoff.succeeded());
+        assert_eq!(backoff.failed(now), Duration::from_secs(1));
+    }
+}
+// endregion
@@ -0,0 +1,400 @@
+use std::str::FromStr;
+
+use serde_json::Value;
+use thiserror::Error;
+use tokio_postgres::types::ToSql;
+
+// region: DataFilter Struct
+/// Filter on the JSON contents of [`crate::structures::Record::data`].
+///
+/// Filters are a list of `,` separated conditions that must all match, each having the
+/// form `<path><op><value>`:
+/// - `path` is a list of `.` separated object keys, made of ASCII letters, digits,
+///   `_` and `-`
+/// - `op` is one of `=`, `<`, `<=`, `>` or `>=`
+/// - `value` is `null`, `true`, `false`, a number, a double quoted JSON string, or any
+///   other text as a bare string. Values can't contain `,` or `;`
+///
+/// Ranges only match numbers, so `level>=5,level<10` selects records with a numeric
+/// `level` in that range. Records without JSON data, or without a value at `path`,
+/// never match a condition.
+///
+/// An empty filter matches every record.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DataFilter {
+    conditions: Vec<Condition>,
+}
+
+impl DataFilter {
+    /// Returns `true` if this filter matches every record.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Returns `true` if record data matches every condition.
+    pub fn matches(&self, data: Option<&str>) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let json = match data.map(serde_json::from_str::<Value>) {
+            Some(Ok(json)) => json,
+            _ => return false,
+        };
+
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(&json))
+    }
+
+    /// Returns SQL conditions on the `data_json` column to append to a `WHERE` clause,
+    /// with parameters numbered from `first_param`.
+    ///
+    /// The returned string is empty for empty filters, and otherwise starts with `AND`.
+    pub(super) fn postgres_sql(&self, first_param: usize) -> String {
+        let mut sql = String::new();
+        for (i, condition) in self.conditions.iter().enumerate() {
+            let value = condition
+                .path
+                .iter()
+                .fold(String::from("data_json"), |value, key| {
+                    format!("{} -> '{}'", value, key)
+                });
+
+            let param = first_param + i;
+            let condition = match condition.comparison {
+                Comparison::Eq => format!("({}) = ${}::text::jsonb", value, param),
+                comparison => format!(
+                    "CASE WHEN jsonb_typeof({0}) = 'number' THEN ({0})::float8 END {1} ${2}::float8",
+                    value,
+                    comparison.as_str(),
+                    param
+                ),
+            };
+
+            sql += &format!(" AND {}", condition);
+        }
+
+        sql
+    }
+
+    /// Returns the parameters for [`DataFilter::postgres_sql`], one per condition.
+    pub(super) fn postgres_params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.conditions
+            .iter()
+            .map(
+                |condition| match (&condition.comparison, &condition.value) {
+                    (Comparison::Eq, _) => &condition.json as &(dyn ToSql + Sync),
+                    (_, FilterValue::Number(number)) => number,
+
+                    // Parsing rejects ranges on anything but numbers
+                    _ => unreachable!(),
+                },
+            )
+            .collect()
+    }
+
+    /// Returns SQL conditions on the `data` column to append to a `WHERE` clause, with
+    /// parameters numbered from `first_param`.
+    ///
+    /// SQLite stores data as text, so conditions use its JSON functions and never match
+    /// data that isn't valid JSON.
+    #[cfg(feature = "sqlite")]
+    pub(super) fn sqlite_sql(&self, first_param: usize) -> String {
+        let mut sql = String::new();
+        for (i, condition) in self.conditions.iter().enumerate() {
+            let path = condition.path.iter().fold(String::from("$"), |path, key| {
+                format!("{}.\"{}\"", path, key)
+            });
+
+            let param = first_param + i;
+            let json_type = format!("json_type(data, '{}')", path);
+            let value = format!("json_extract(data, '{}')", path);
+
+            let condition = match (&condition.comparison, &condition.value) {
+                (Comparison::Eq, FilterValue::String(_)) => {
+                    format!("{} = 'text' AND {} = ?{}", json_type, value, param)
+                }
+
+                (comparison, FilterValue::Number(_)) => format!(
+                    "{} IN ('integer', 'real') AND {} {} ?{}",
+                    json_type,
+                    value,
+                    comparison.as_str(),
+                    param
+                ),
+
+                // Null and booleans are matched by type alone
+                _ => format!("{} = ?{}", json_type, param),
+            };
+
+            // JSON functions fail on invalid JSON
+            sql += &format!(" AND CASE WHEN json_valid(data) THEN {} END", condition);
+        }
+
+        sql
+    }
+
+    /// Returns the parameters for [`DataFilter::sqlite_sql`], one per condition.
+    #[cfg(feature = "sqlite")]
+    pub(super) fn sqlite_params(&self) -> Vec<rusqlite::types::Value> {
+        use rusqlite::types::Value as SqlValue;
+
+        self.conditions
+            .iter()
+            .map(|condition| match &condition.value {
+                FilterValue::Null => SqlValue::Text("null".into()),
+                FilterValue::Bool(true) => SqlValue::Text("true".into()),
+                FilterValue::Bool(false) => SqlValue::Text("false".into()),
+                FilterValue::Number(number) => SqlValue::Real(*number),
+                FilterValue::String(string) => SqlValue::Text(string.clone()),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for DataFilter {
+    type Err = DataFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let conditions = s
+            .split(',')
+            .map(Condition::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { conditions })
+    }
+}
+// endregion
+
+// region: Condition Struct
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FilterValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl FilterValue {
+    fn parse(value: &str) -> Result<Self, DataFilterError> {
+        let parsed = match value {
+            "" => return Err(DataFilterError::InvalidValue(value.into())),
+            "null" => Self::Null,
+            "true" => Self::Bool(true),
+            "false" => Self::Bool(false),
+
+            _ if value.starts_with('"') => match serde_json::from_str(value) {
+                Ok(string) => Self::String(string),
+                Err(_) => return Err(DataFilterError::InvalidValue(value.into())),
+            },
+
+            _ => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Self::Number(number),
+                _ => Self::String(value.into()),
+            },
+        };
+
+        Ok(parsed)
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Null => Value::Null,
+            Self::Bool(bool) => Value::Bool(*bool),
+            Self::Number(number) => Value::from(*number),
+            Self::String(string) => Value::String(string.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    path: Vec<String>,
+    comparison: Comparison,
+    value: FilterValue,
+
+    /// `value` serialized as JSON, to compare against `jsonb` columns
+    json: String,
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Result<Self, DataFilterError> {
+        let start = condition
+            .find(|c| matches!(c, '=' | '<' | '>'))
+            .ok_or_else(|| DataFilterError::MissingOperator(condition.trim().into()))?;
+
+        let (path, rest) = condition.split_at(start);
+        let (comparison, value) = match rest.as_bytes() {
+            [b'<', b'=', ..] => (Comparison::Le, &rest[2..]),
+            [b'>', b'=', ..] => (Comparison::Ge, &rest[2..]),
+            [b'<', ..] => (Comparison::Lt, &rest[1..]),
+            [b'>', ..] => (Comparison::Gt, &rest[1..]),
+            _ => (Comparison::Eq, &rest[1..]),
+        };
+
+        let path = path
+            .trim()
+            .split('.')
+            .map(|key| match is_valid_key(key) {
+                true => Ok(key.to_owned()),
+                false => Err(DataFilterError::InvalidPath(path.trim().into())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let value = FilterValue::parse(value.trim())?;
+        if comparison != Comparison::Eq && !matches!(value, FilterValue::Number(_)) {
+            return Err(DataFilterError::InvalidRange(condition.trim().into()));
+        }
+
+        Ok(Self {
+            path,
+            comparison,
+            json: value.to_json().to_string(),
+            value,
+        })
+    }
+
+    fn matches(&self, json: &Value) -> bool {
+        // Keys never index into arrays
+        let found = self
+            .path
+            .iter()
+            .try_fold(json, |json, key| json.as_object()?.get(key));
+
+        let found = match found {
+            Some(found) => found,
+            None => return false,
+        };
+
+        match (&self.comparison, &self.value) {
+            (Comparison::Eq, FilterValue::Null) => found.is_null(),
+            (Comparison::Eq, FilterValue::Bool(bool)) => found.as_bool() == Some(*bool),
+            (Comparison::Eq, FilterValue::String(string)) => {
+                found.as_str() == Some(string.as_str())
+            }
+
+            (comparison, FilterValue::Number(number)) => match found.as_f64() {
+                None => false,
+                Some(found) => match comparison {
+                    Comparison::Eq => found == *number,
+                    Comparison::Lt => found < *number,
+                    Comparison::Le => found <= *number,
+                    Comparison::Gt => found > *number,
+                    Comparison::Ge => found >= *number,
+                },
+            },
+
+            _ => false,
+        }
+    }
+}
+
+/// Keys are interpolated into SQL, so only allow a safe subset of characters.
+#[inline]
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+// endregion
+
+#[derive(Debug, Error)]
+pub enum DataFilterError {
+    #[error("missing comparison in filter condition: {0}")]
+    MissingOperator(String),
+
+    #[error("invalid filter path: {0}")]
+    InvalidPath(String),
+
+    #[error("invalid filter value: {0}")]
+    InvalidValue(String),
+
+    #[error("filter ranges only compare numbers: {0}")]
+    InvalidRange(String),
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(s: &str) -> DataFilter {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = filter("type=rare, loot.level >= 5,open=false,owner=null,name=\"a b\"");
+        let values = parsed
+            .conditions
+            .iter()
+            .map(|condition| (condition.comparison, condition.json.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                (Comparison::Eq, "\"rare\""),
+                (Comparison::Ge, "5.0"),
+                (Comparison::Eq, "false"),
+                (Comparison::Eq, "null"),
+                (Comparison::Eq, "\"a b\""),
+            ]
+        );
+
+        assert_eq!(parsed.conditions[1].path, vec!["loot", "level"]);
+
+        assert!("".parse::<DataFilter>().is_err());
+        assert!("type".parse::<DataFilter>().is_err());
+        assert!("type=".parse::<DataFilter>().is_err());
+        assert!("a..b=1".parse::<DataFilter>().is_err());
+        assert!("a'b=1".parse::<DataFilter>().is_err());
+        assert!("level>rare".parse::<DataFilter>().is_err());
+        assert!("name=\"open".parse::<DataFilter>().is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let data = Some(r#"{"type": "rare", "level": 7, "loot": {"gold": 2.5}, "open": true}"#);
+
+        assert!(DataFilter::default().matches(None));
+        assert!(filter("type=rare").matches(data));
+        assert!(filter("type=\"rare\"").matches(data));
+        assert!(filter("level=7.0,level>=5,level<10").matches(data));
+        assert!(filter("loot.gold>2").matches(data));
+        assert!(filter("open=true").matches(data));
+
+        assert!(!filter("type=common").matches(data));
+        assert!(!filter("level>7").matches(data));
+        assert!(!filter("level=\"7\"").matches(data));
+        assert!(!filter("missing=null").matches(data));
+        assert!(!filter("type.name=rare").matches(data));
+        assert!(!filter("type=rare").matches(Some("rare")));
+        assert!(!filter("type=rare").matches(None));
+    }
+}
+// endregion
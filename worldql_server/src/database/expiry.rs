@@ -0,0 +1,98 @@
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Object;
+use tokio_postgres::error::SqlState;
+use tracing::warn;
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::history::append_history;
+use super::record_tables::forget_uuids;
+use super::store::HistoryEntry;
+use super::{query_delete_expired_records, QUERY_SELECT_ALL_TABLES};
+use crate::structures::Record;
+
+impl DatabaseClient {
+    /// Delete up to `limit` records that expired at or before `now`, returning them.
+    ///
+    /// Tables are visited in creation order, stopping once `limit` records are deleted.
+    /// Each table's records are deleted along with their history in one transaction. If a
+    /// table fails, the records already deleted from earlier tables are returned.
+    pub async fn delete_expired_records(
+        &self,
+        now: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["delete_expired_records"])
+            .start_timer();
+
+        let mut client = self.pool.get().await?;
+        let tables = client
+            .query(QUERY_SELECT_ALL_TABLES, &[])
+            .await?
+            .into_iter()
+            .map(|row| Ok((row.try_get("world_name")?, row.try_get("table_suffix")?)))
+            .collect::<Result<Vec<(String, i32)>, tokio_postgres::Error>>()?;
+
+        let mut records = vec![];
+        for (world_name, table_suffix) in tables {
+            let remaining = (limit - records.len()) as i64;
+            if remaining == 0 {
+                break;
+            }
+
+            let result = delete_expired(&mut client, &world_name, table_suffix, now, remaining);
+            match result.await {
+                Ok(expired) => records.extend(expired),
+                Err(error) => {
+                    warn!("error deleting expired records: {}", error);
+                    break;
+                }
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+/// Delete up to `limit` records that expired at or before `now` from a single table,
+/// appending their deletion to the record history in the same transaction, and return
+/// them.
+async fn delete_expired(
+    client: &mut Object,
+    world_name: &str,
+    table_suffix: i32,
+    now: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<Record>, DatabaseError> {
+    let transaction = client.transaction().await?;
+    let query = query_delete_expired_records(world_name, table_suffix);
+    let rows = match transaction.query(&query, &[&now, &limit]).await {
+        Ok(rows) => rows,
+        Err(error) => match error.as_db_error() {
+            // Table has navigation rows but no records yet
+            Some(db_error) if *db_error.code() == SqlState::UNDEFINED_TABLE => return Ok(vec![]),
+            _ => return Err(error.into()),
+        },
+    };
+
+    let expired = rows
+        .into_iter()
+        .map(|row| Record::from_postgres_row(row, world_name))
+        .collect::<Vec<_>>();
+
+    let uuids = expired.iter().map(|record| record.uuid).collect::<Vec<_>>();
+    forget_uuids(&transaction, world_name, &uuids).await?;
+
+    // Expired records weren't deleted by any peer
+    let changed_at = Utc::now().naive_utc();
+    let entries = expired
+        .iter()
+        .map(|record| HistoryEntry::new(changed_at, None, Some(record.clone()), None))
+        .collect::<Vec<_>>();
+
+    append_history(&transaction, &entries).await?;
+    transaction.commit().await?;
+
+    Ok(expired)
+}
@@ -0,0 +1,219 @@
+use chrono::NaiveDateTime;
+use deadpool_postgres::GenericClient;
+use tokio_postgres::types::ToSql;
+use uuid::Uuid;
+#[cfg(any(feature = "http", test))]
+use {
+    super::QUERY_SELECT_RECORD_HISTORY, crate::structures::Vector3,
+    crate::utils::sanitize_world_name,
+};
+#[cfg(any(feature = "http", test))]
+use {bytes::Bytes, tokio_postgres::Row};
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::store::HistoryEntry;
+use super::tags::stored_tags;
+use super::versions::is_undefined_table;
+use super::{
+    query_insert_history_many, query_select_records_by_uuid, HISTORY_PARAMS,
+    QUERY_DELETE_HISTORY_BEFORE,
+};
+use crate::structures::Record;
+
+/// Maximum number of history entries inserted at once, PostgreSQL limits queries to
+/// 65535 parameters
+const MAX_BATCH_SIZE: usize = 1000;
+
+impl DatabaseClient {
+    /// Returns up to `limit` history entries for the record with `uuid`, newest first.
+    #[cfg(any(feature = "http", test))]
+    pub async fn get_record_history(
+        &self,
+        world_name: &str,
+        uuid: Uuid,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_record_history"])
+            .start_timer();
+
+        let world_name = sanitize_world_name(world_name)?;
+        let limit = limit as i64;
+
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(QUERY_SELECT_RECORD_HISTORY, &[&world_name, &uuid, &limit])
+            .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                let old = history_record(&row, "old_", &world_name, uuid);
+                let new = history_record(&row, "new_", &world_name, uuid);
+
+                HistoryEntry::new(row.get("changed_at"), row.get("peer"), old, new)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(entries)
+    }
+
+    /// Delete history entries for changes made before `before`, returning the number of
+    /// entries deleted.
+    pub async fn delete_history_before(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        let client = self.pool.get().await?;
+        let deleted = client
+            .execute(QUERY_DELETE_HISTORY_BEFORE, &[&before])
+            .await?;
+
+        Ok(deleted)
+    }
+}
+
+// region: Helper Functions
+/// Append entries to the record history.
+pub(super) async fn append_history(
+    client: &impl GenericClient,
+    entries: &[HistoryEntry],
+) -> Result<(), DatabaseError> {
+    for batch in entries.chunks(MAX_BATCH_SIZE) {
+        let columns = batch
+            .iter()
+            .map(|entry| {
+                (
+                    entry.operation.as_str(),
+                    HistoryColumns::new(entry.old.as_ref()),
+                    HistoryColumns::new(entry.new.as_ref()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(batch.len() * HISTORY_PARAMS);
+        for (entry, (operation, old, new)) in batch.iter().zip(&columns) {
+            params.push(&entry.changed_at);
+            params.push(&entry.world_name);
+            params.push(&entry.uuid);
+            params.push(operation);
+            params.push(&entry.peer);
+            old.push_params(&mut params);
+            new.push_params(&mut params);
+        }
+
+        let query = query_insert_history_many(batch.len());
+        client.execute(&query, &params).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the records stored in a table with any of `uuids`, to record as their old
+/// values in the history.
+pub(super) async fn select_records_by_uuid(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    uuids: &[Uuid],
+) -> Result<Vec<Record>, DatabaseError> {
+    let query = query_select_records_by_uuid(world_name, table_suffix);
+    let rows = match client.query(&query, &[&uuids]).await {
+        // No table means no records
+        Err(error) if is_undefined_table(&error) => vec![],
+        result => result?,
+    };
+
+    let records = rows
+        .into_iter()
+        .map(|row| Record::from_postgres_row(row, world_name))
+        .collect();
+
+    Ok(records)
+}
+
+/// Read the old or new record from a history row, depending on `prefix`.
+#[cfg(any(feature = "http", test))]
+fn history_record(row: &Row, prefix: &str, world_name: &str, uuid: Uuid) -> Option<Record> {
+    let column = |name: &str| format!("{}{}", prefix, name);
+
+    // Version is never null for stored records
+    let version: i64 = row.get::<_, Option<i64>>(column("version").as_str())?;
+    let x: Option<f64> = row.get(column("x").as_str());
+    let y: Option<f64> = row.get(column("y").as_str());
+    let z: Option<f64> = row.get(column("z").as_str());
+    let flex: Option<Vec<u8>> = row.get(column("flex").as_str());
+
+    let position = match (x, y, z) {
+        (Some(x), Some(y), Some(z)) => Some(Vector3::new(x, y, z)),
+        _ => None,
+    };
+
+    let record = Record {
+        uuid,
+        position,
+        world_name: world_name.to_string(),
+        data: row.get(column("data").as_str()),
+        flex: flex.map(Bytes::from),
+        expires_at: row.get(column("expires_at").as_str()),
+        version: Some(version as u64),
+        owner: row.get(column("owner").as_str()),
+        writers: row.get(column("writers").as_str()),
+        tags: row
+            .get::<_, Option<Vec<String>>>(column("tags").as_str())
+            .unwrap_or_default(),
+    };
+
+    Some(record)
+}
+// endregion
+
+// region: HistoryColumns Struct
+/// Old or new record columns of a history entry, all null if there is no record.
+#[derive(Default)]
+struct HistoryColumns {
+    x: Option<f64>,
+    y: Option<f64>,
+    z: Option<f64>,
+    data: Option<String>,
+    flex: Option<Vec<u8>>,
+    expires_at: Option<NaiveDateTime>,
+    version: Option<i64>,
+    owner: Option<String>,
+    writers: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+}
+
+impl HistoryColumns {
+    fn new(record: Option<&Record>) -> Self {
+        let record = match record {
+            Some(record) => record,
+            None => return Self::default(),
+        };
+
+        Self {
+            x: record.position.map(|position| *position.x()),
+            y: record.position.map(|position| *position.y()),
+            z: record.position.map(|position| *position.z()),
+            data: record.data.clone(),
+            flex: record.flex.as_ref().map(|flex| flex.to_vec()),
+            expires_at: record.expires_at,
+            version: Some(record.version.unwrap_or(1) as i64),
+            owner: record.owner.clone(),
+            writers: record.writers.clone(),
+            tags: stored_tags(record.tags.clone()),
+        }
+    }
+
+    fn push_params<'a>(&'a self, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        params.push(&self.x);
+        params.push(&self.y);
+        params.push(&self.z);
+        params.push(&self.data);
+        params.push(&self.flex);
+        params.push(&self.expires_at);
+        params.push(&self.version);
+        params.push(&self.owner);
+        params.push(&self.writers);
+        params.push(&self.tags);
+    }
+}
+// endregion
@@ -0,0 +1,754 @@
+use std::sync::Mutex;
+
+use ahash::{AHashMap, AHashSet};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use super::nearest::find_nearest;
+use super::store::{record_position, HistoryEntry, RecordStore, WorldStats, Writer, Written};
+use super::versions::RecordAccess;
+use super::world_region::WorldRegion;
+use super::{DataFilter, DatabaseError};
+use crate::structures::{Area, Record, Vector3};
+use crate::utils::sanitize_world_name;
+
+// region: MemoryStore Struct
+/// In-memory [`RecordStore`], using the same [`WorldRegion`] bucketing as PostgreSQL.
+///
+/// Records are lost when the server exits.
+#[derive(Debug)]
+pub struct MemoryStore {
+    worlds: Mutex<AHashMap<String, MemoryWorld>>,
+    history: Mutex<Vec<HistoryEntry>>,
+
+    region_x_size: u16,
+    region_y_size: u16,
+    region_z_size: u16,
+}
+
+#[derive(Debug, Default)]
+struct MemoryWorld {
+    records: AHashMap<Uuid, StoredRecord>,
+    regions: AHashMap<WorldRegion, AHashSet<Uuid>>,
+    tags: AHashMap<String, AHashSet<Uuid>>,
+}
+
+#[derive(Debug)]
+struct StoredRecord {
+    region: WorldRegion,
+    last_modified: NaiveDateTime,
+    record: Record,
+}
+
+impl MemoryStore {
+    pub fn new(region_x_size: u16, region_y_size: u16, region_z_size: u16) -> Self {
+        Self {
+            worlds: Default::default(),
+            history: Default::default(),
+
+            region_x_size,
+            region_y_size,
+            region_z_size,
+        }
+    }
+
+    #[inline]
+    fn world_region(&self, world_name: &str, vector: &Vector3) -> WorldRegion {
+        WorldRegion::new(
+            world_name,
+            vector,
+            self.region_x_size,
+            self.region_y_size,
+            self.region_z_size,
+        )
+    }
+
+    /// Insert or replace records, undoing every write if any record fails and `atomic`
+    /// is set.
+    fn write_records(&self, records: Vec<Record>, writer: &Writer, atomic: bool) -> Written {
+        let mut errors = vec![];
+        let mut entries = vec![];
+        let mut undo = vec![];
+        let mut worlds = self.worlds.lock().unwrap();
+        let now = Utc::now().naive_utc();
+
+        for mut record in records {
+            let position = match record_position(&record) {
+                Ok(position) => position,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            let world_name = match sanitize_world_name(&record.world_name) {
+                Ok(world_name) => world_name,
+                Err(error) => {
+                    errors.push(error.into());
+                    continue;
+                }
+            };
+
+            let region = self.world_region(&world_name, &position);
+            record.world_name = world_name.clone();
+            let world = worlds.entry(world_name.clone()).or_default();
+            let sharing = record.writers.is_some();
+            let checked = world
+                .check_access(&record, writer, sharing)
+                .and_then(|_| world.check_version(&record));
+
+            if let Err(error) = checked {
+                errors.push(error);
+                continue;
+            }
+
+            let uuid = record.uuid;
+            let (stored, new) = world.insert(region, record, writer);
+            let old = stored.as_ref().map(|stored| stored.record.clone());
+            entries.push(HistoryEntry::new(now, writer.peer, old, Some(new)));
+
+            if atomic {
+                undo.push((world_name, uuid, stored));
+            }
+        }
+
+        if atomic && !errors.is_empty() {
+            for (world_name, uuid, stored) in undo.into_iter().rev() {
+                if let Some(world) = worlds.get_mut(&world_name) {
+                    world.restore(&uuid, stored);
+                }
+            }
+
+            return errors.into();
+        }
+
+        self.history.lock().unwrap().extend(entries.iter().cloned());
+        Written::new(entries, errors)
+    }
+
+    /// Delete records, putting back every deleted record if any record fails and
+    /// `atomic` is set.
+    fn remove_records(&self, records: Vec<Record>, writer: &Writer, atomic: bool) -> Written {
+        let mut errors = vec![];
+        let mut removed = vec![];
+        let mut worlds = self.worlds.lock().unwrap();
+
+        for record in records {
+            let position = match record_position(&record) {
+                Ok(position) => position,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            let world_name = match sanitize_world_name(&record.world_name) {
+                Ok(world_name) => world_name,
+                Err(error) => {
+                    errors.push(error.into());
+                    continue;
+                }
+            };
+
+            let region = self.world_region(&world_name, &position);
+            if record.version.is_some() {
+                let world = worlds.entry(world_name.clone()).or_default();
+                let checked = world
+                    .check_access(&record, writer, false)
+                    .and_then(|_| world.check_version(&record));
+
+                match checked {
+                    Ok(_) => removed.extend(world.remove(&record.uuid).map(|s| (world_name, s))),
+                    Err(error) => errors.push(error),
+                }
+
+                continue;
+            }
+
+            if let Some(world) = worlds.get_mut(&world_name) {
+                // Only delete if the record is in the given region
+                let in_region = world
+                    .records
+                    .get(&record.uuid)
+                    .map_or(false, |stored| stored.region == region);
+
+                if !in_region {
+                    continue;
+                }
+
+                match world.check_access(&record, writer, false) {
+                    Ok(_) => removed.extend(world.remove(&record.uuid).map(|s| (world_name, s))),
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+
+        if atomic && !errors.is_empty() {
+            for (world_name, stored) in removed {
+                if let Some(world) = worlds.get_mut(&world_name) {
+                    world.put(stored);
+                }
+            }
+
+            return errors.into();
+        }
+
+        let now = Utc::now().naive_utc();
+        let entries = removed
+            .into_iter()
+            .map(|(_, stored)| HistoryEntry::new(now, writer.peer, Some(stored.record), None))
+            .collect::<Vec<_>>();
+
+        self.history.lock().unwrap().extend(entries.iter().cloned());
+        Written::new(entries, errors)
+    }
+}
+// endregion
+
+// region: MemoryWorld Methods
+impl MemoryWorld {
+    /// Insert or replace a record, returning the old stored record and the new record.
+    fn insert(
+        &mut self,
+        region: WorldRegion,
+        mut record: Record,
+        writer: &Writer,
+    ) -> (Option<StoredRecord>, Record) {
+        let uuid = record.uuid;
+        let stored = self.remove(&uuid);
+        let version = stored.as_ref().map_or(0, StoredRecord::version);
+        record.version = Some(version + 1);
+
+        let old = stored.as_ref().map(|stored| &stored.record);
+        record.owner = writer.owner_of(&record, old.map(|old| old.owner.as_deref()));
+        if record.writers.is_none() {
+            record.writers = old.and_then(|old| old.writers.clone());
+        }
+
+        self.put(StoredRecord {
+            region,
+            last_modified: Utc::now().naive_utc(),
+            record: record.clone(),
+        });
+
+        (stored, record)
+    }
+
+    /// Store a record as is, indexing it by region and tags.
+    fn put(&mut self, stored: StoredRecord) {
+        let uuid = stored.record.uuid;
+        self.regions
+            .entry(stored.region.clone())
+            .or_default()
+            .insert(uuid);
+
+        for tag in &stored.record.tags {
+            self.tags.entry(tag.clone()).or_default().insert(uuid);
+        }
+
+        self.records.insert(uuid, stored);
+    }
+
+    /// Undo a change to the record with `uuid`, putting back the stored record from
+    /// before the change.
+    fn restore(&mut self, uuid: &Uuid, stored: Option<StoredRecord>) {
+        self.remove(uuid);
+        if let Some(stored) = stored {
+            self.put(stored);
+        }
+    }
+
+    fn remove(&mut self, uuid: &Uuid) -> Option<StoredRecord> {
+        let stored = self.records.remove(uuid)?;
+        if let Some(uuids) = self.regions.get_mut(&stored.region) {
+            uuids.remove(uuid);
+            if uuids.is_empty() {
+                self.regions.remove(&stored.region);
+            }
+        }
+
+        for tag in &stored.record.tags {
+            if let Some(uuids) = self.tags.get_mut(tag) {
+                uuids.remove(uuid);
+                if uuids.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+
+        Some(stored)
+    }
+
+    /// Returns `true` if the world stores any records.
+    ///
+    /// Empty worlds are left behind by reads and deletes, so don't count as existing.
+    #[inline]
+    fn exists(&self) -> bool {
+        !self.records.is_empty()
+    }
+
+    /// Returns the version of a stored record, or 0 if it doesn't exist.
+    fn version(&self, uuid: &Uuid) -> u64 {
+        self.records.get(uuid).map_or(0, StoredRecord::version)
+    }
+
+    /// Check that `writer` may write to the stored record, see [`Writer::can_write`].
+    fn check_access(
+        &self,
+        record: &Record,
+        writer: &Writer,
+        sharing: bool,
+    ) -> Result<(), DatabaseError> {
+        match self.records.get(&record.uuid) {
+            Some(stored) => RecordAccess::from(&stored.record).check(record.uuid, writer, sharing),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a conditional write against the stored version, see [`Record::version`].
+    fn check_version(&self, record: &Record) -> Result<(), DatabaseError> {
+        let version = match record.version {
+            Some(version) => version,
+            None => return Ok(()),
+        };
+
+        let stored = self.version(&record.uuid);
+        match stored == version {
+            true => Ok(()),
+            false => Err(DatabaseError::VersionConflict {
+                uuid: record.uuid,
+                version: stored,
+            }),
+        }
+    }
+}
+
+impl StoredRecord {
+    #[inline]
+    fn version(&self) -> u64 {
+        self.record.version.unwrap_or(1)
+    }
+}
+// endregion
+
+// region: RecordStore Impl
+#[async_trait]
+impl RecordStore for MemoryStore {
+    async fn insert_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.write_records(records, writer, false)
+    }
+
+    async fn insert_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.write_records(records, writer, true)
+    }
+
+    async fn get_records_in_region(
+        &self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let region = self.world_region(&world_name, &point_inside_region);
+
+        let worlds = self.worlds.lock().unwrap();
+        let world = match worlds.get(&world_name) {
+            Some(world) => world,
+            None => return Ok(vec![]),
+        };
+
+        let uuids = match world.regions.get(&region) {
+            Some(uuids) => uuids,
+            None => return Ok(vec![]),
+        };
+
+        let now = Utc::now().naive_utc();
+        let records = uuids
+            .iter()
+            .filter_map(|uuid| world.records.get(uuid))
+            .filter(|stored| !stored.record.is_expired(&now))
+            .filter(|stored| match after {
+                None => true,
+                Some(after) => stored.last_modified > after,
+            })
+            .filter(|stored| filter.matches(stored.record.data.as_deref()))
+            .map(|stored| Record {
+                world_name: world_name.clone(),
+                ..stored.record.clone()
+            })
+            .collect::<Vec<_>>();
+
+        Ok(records)
+    }
+
+    async fn get_records_in_area(
+        &self,
+        world_name: &str,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        let worlds = self.worlds.lock().unwrap();
+        let world = match worlds.get(&world_name) {
+            Some(world) => world,
+            None => return Ok(vec![]),
+        };
+
+        let now = Utc::now().naive_utc();
+        let records = world
+            .records
+            .values()
+            .filter(|stored| !stored.record.is_expired(&now))
+            .filter(|stored| match &stored.record.position {
+                None => false,
+                Some(position) => area.contains(position),
+            })
+            .filter(|stored| match after {
+                None => true,
+                Some(after) => stored.last_modified > after,
+            })
+            .filter(|stored| filter.matches(stored.record.data.as_deref()))
+            .map(|stored| Record {
+                world_name: world_name.clone(),
+                ..stored.record.clone()
+            })
+            .collect::<Vec<_>>();
+
+        Ok(records)
+    }
+
+    async fn get_nearest_records(
+        &self,
+        world_name: &str,
+        point: Vector3,
+        count: usize,
+        max_distance: f64,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let start_radius = self
+            .region_x_size
+            .min(self.region_y_size)
+            .min(self.region_z_size);
+
+        let start_radius = f64::from(start_radius);
+        find_nearest(
+            self,
+            world_name,
+            point,
+            count,
+            max_distance,
+            after,
+            filter,
+            start_radius,
+        )
+        .await
+    }
+
+    async fn get_records_with_tag(
+        &self,
+        world_name: &str,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        let worlds = self.worlds.lock().unwrap();
+        let world = match worlds.get(&world_name) {
+            Some(world) => world,
+            None => return Ok(vec![]),
+        };
+
+        let uuids = match world.tags.get(tag) {
+            Some(uuids) => uuids,
+            None => return Ok(vec![]),
+        };
+
+        let now = Utc::now().naive_utc();
+        let records = uuids
+            .iter()
+            .filter_map(|uuid| world.records.get(uuid))
+            .filter(|stored| !stored.record.is_expired(&now))
+            .filter(|stored| match (&area, &stored.record.position) {
+                (None, _) => true,
+                (Some(area), Some(position)) => area.contains(position),
+                (Some(_), None) => false,
+            })
+            .filter(|stored| match after {
+                None => true,
+                Some(after) => stored.last_modified > after,
+            })
+            .filter(|stored| filter.matches(stored.record.data.as_deref()))
+            .map(|stored| Record {
+                world_name: world_name.clone(),
+                ..stored.record.clone()
+            })
+            .collect::<Vec<_>>();
+
+        Ok(records)
+    }
+
+    async fn get_read_tables(
+        &self,
+        world_name: &str,
+        _area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        // Worlds aren't split into tables, so are always read at once
+        let worlds = self.worlds.lock().unwrap();
+        match worlds.get(&world_name) {
+            Some(world) if world.exists() => Ok(vec![0]),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        match table_suffix {
+            0 => {
+                self.get_records_in_area(world_name, area, after, filter)
+                    .await
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        match table_suffix {
+            0 => {
+                self.get_records_with_tag(world_name, tag, area, after, filter)
+                    .await
+            }
+
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn delete_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.remove_records(records, writer, false)
+    }
+
+    async fn delete_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.remove_records(records, writer, true)
+    }
+
+    async fn delete_expired_records(
+        &self,
+        now: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let mut worlds = self.worlds.lock().unwrap();
+        let mut records = vec![];
+
+        for (world_name, world) in worlds.iter_mut() {
+            let expired = world
+                .records
+                .values()
+                .filter(|stored| stored.record.is_expired(&now))
+                .map(|stored| stored.record.uuid)
+                .take(limit - records.len())
+                .collect::<Vec<_>>();
+
+            for uuid in expired {
+                if let Some(stored) = world.remove(&uuid) {
+                    records.push(Record {
+                        world_name: world_name.clone(),
+                        ..stored.record
+                    });
+                }
+            }
+        }
+
+        // Expired records weren't deleted by any peer
+        let changed_at = Utc::now().naive_utc();
+        let entries = records
+            .iter()
+            .map(|record| HistoryEntry::new(changed_at, None, Some(record.clone()), None));
+
+        self.history.lock().unwrap().extend(entries);
+        Ok(records)
+    }
+
+    #[cfg(any(feature = "http", test))]
+    async fn get_record_history(
+        &self,
+        world_name: &str,
+        uuid: Uuid,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let history = self.history.lock().unwrap();
+        let entries = history
+            .iter()
+            .rev()
+            .filter(|entry| entry.uuid == uuid && entry.world_name == world_name)
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(entries)
+    }
+
+    async fn delete_history_before(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        let mut history = self.history.lock().unwrap();
+        let len = history.len();
+        history.retain(|entry| entry.changed_at >= before);
+
+        Ok((len - history.len()) as u64)
+    }
+
+    #[inline]
+    async fn check_connection(&self) -> Written {
+        Written::default()
+    }
+
+    async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError> {
+        let worlds = self.worlds.lock().unwrap();
+        let mut stats = worlds
+            .iter()
+            .filter(|(_, world)| world.exists())
+            .map(|(world_name, world)| WorldStats {
+                world_name: world_name.clone(),
+                table_count: 0,
+                record_count: world.records.len() as u64,
+            })
+            .collect::<Vec<_>>();
+
+        stats.sort_by(|a, b| a.world_name.cmp(&b.world_name));
+        Ok(stats)
+    }
+
+    async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let removed = self.worlds.lock().unwrap().remove(&world_name);
+
+        Ok(removed.map_or(false, |world| world.exists()))
+    }
+
+    async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+
+        let mut worlds = self.worlds.lock().unwrap();
+        if worlds.get(&to).map_or(false, MemoryWorld::exists) {
+            return Err(DatabaseError::WorldExists(to));
+        }
+
+        let records = match worlds.get(&from) {
+            Some(world) if world.exists() => world
+                .records
+                .values()
+                .map(|stored| stored.record.clone())
+                .collect::<Vec<_>>(),
+
+            _ => return Err(DatabaseError::WorldNotFound(from)),
+        };
+
+        // Checked before copying anything, so a failed copy leaves no world behind
+        let regions = records
+            .iter()
+            .map(|record| Ok(self.world_region(&to, &record_position(record)?)))
+            .collect::<Result<Vec<_>, DatabaseError>>()?;
+
+        let copied = records.len() as u64;
+        let world = worlds.entry(to.clone()).or_default();
+        for (record, region) in records.into_iter().zip(regions) {
+            let record = Record {
+                world_name: to.clone(),
+                version: None,
+                ..record
+            };
+
+            world.insert(region, record, &Writer::SERVER);
+        }
+
+        Ok(copied)
+    }
+
+    async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+
+        let mut worlds = self.worlds.lock().unwrap();
+        if worlds.get(&to).map_or(false, MemoryWorld::exists) {
+            return Err(DatabaseError::WorldExists(to));
+        }
+
+        // Regions include the world name, so every record is put back under the new one
+        let mut regions = match worlds.get(&from) {
+            Some(world) if world.exists() => world
+                .records
+                .values()
+                .map(|stored| {
+                    let position = record_position(&stored.record)?;
+                    Ok((stored.record.uuid, self.world_region(&to, &position)))
+                })
+                .collect::<Result<AHashMap<_, _>, DatabaseError>>()?,
+
+            _ => return Err(DatabaseError::WorldNotFound(from)),
+        };
+
+        let world = worlds.remove(&from).unwrap_or_default();
+        let mut renamed = MemoryWorld::default();
+        for (uuid, mut stored) in world.records {
+            stored.region = regions.remove(&uuid).unwrap_or(stored.region);
+            stored.record.world_name = to.clone();
+            renamed.put(stored);
+        }
+
+        worlds.insert(to, renamed);
+        Ok(())
+    }
+
+    async fn get_world_tables(&self, world_name: &str) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        // Worlds aren't split into tables, so are always read at once
+        let worlds = self.worlds.lock().unwrap();
+        match worlds.get(&world_name) {
+            Some(world) if world.exists() => Ok(vec![0]),
+            _ => Err(DatabaseError::WorldNotFound(world_name)),
+        }
+    }
+
+    async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        let worlds = self.worlds.lock().unwrap();
+        match worlds.get(&world_name) {
+            Some(world) if table_suffix == 0 => Ok(world
+                .records
+                .values()
+                .map(|stored| stored.record.clone())
+                .collect()),
+
+            _ => Ok(vec![]),
+        }
+    }
+}
+// endregion
@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use color_eyre::Result;
+use deadpool_postgres::GenericClient;
+use tracing::info;
+
+use super::client::DatabaseClient;
+use super::{
+    query_add_data_json_column, query_add_expiry_column, query_add_owner_columns,
+    query_add_tags_column, query_add_uuid_constraint, query_add_version_column,
+    query_create_world_expiry_index, query_create_world_tags_index, query_delete_duplicates,
+    query_delete_moved_duplicates, query_fill_record_tables, ALTER_HISTORY_ADD_TAGS,
+    CREATE_HISTORY_INDEXES, CREATE_REGION_NAVIGATION, CREATE_SCHEMA_NAVIGATION,
+    CREATE_TABLE_HISTORY, CREATE_TABLE_MIGRATIONS, CREATE_TABLE_NAVIGATION,
+    CREATE_TABLE_NAVIGATION_INDEX, CREATE_TABLE_REBUCKET, CREATE_TABLE_SIZING,
+    QUERY_INSERT_MIGRATION, QUERY_MIGRATIONS_TABLE_EXISTS, QUERY_MIGRATION_LOCK,
+    QUERY_SELECT_MIGRATIONS, QUERY_SELECT_WORLD_SCHEMAS, QUERY_SELECT_WORLD_TABLES,
+};
+
+const NAVIGATION_SCHEMA: &str = "navigation";
+
+// region: Migration List
+pub(super) enum MigrationKind {
+    /// Runs once against the `navigation` schema
+    Navigation(&'static [&'static str]),
+
+    /// Runs once for every `w_*` schema, against each of its tables.
+    ///
+    /// Called with `(world_name, table_suffix)`
+    WorldTable(fn(&str, i32) -> Vec<String>),
+}
+
+pub(super) struct Migration {
+    version: i32,
+    description: &'static str,
+    kind: MigrationKind,
+}
+
+/// All schema migrations, in the order they are applied.
+///
+/// Versions must be strictly increasing. Migrations that have been released must
+/// never be edited, add a new migration instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create navigation tables",
+        kind: MigrationKind::Navigation(&[
+            CREATE_TABLE_NAVIGATION,
+            CREATE_REGION_NAVIGATION,
+            CREATE_TABLE_NAVIGATION_INDEX,
+        ]),
+    },
+    Migration {
+        version: 2,
+        description: "unique record uuids",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![
+                query_delete_duplicates(world_name, table_suffix),
+                query_add_uuid_constraint(world_name, table_suffix),
+            ]
+        }),
+    },
+    Migration {
+        version: 3,
+        description: "store region and table sizing",
+        kind: MigrationKind::Navigation(&[CREATE_TABLE_SIZING, CREATE_TABLE_REBUCKET]),
+    },
+    Migration {
+        version: 4,
+        description: "record expiry",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![
+                query_add_expiry_column(world_name, table_suffix),
+                query_create_world_expiry_index(world_name, table_suffix),
+            ]
+        }),
+    },
+    Migration {
+        version: 5,
+        description: "record versions",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![query_add_version_column(world_name, table_suffix)]
+        }),
+    },
+    Migration {
+        version: 6,
+        description: "record ownership",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![query_add_owner_columns(world_name, table_suffix)]
+        }),
+    },
+    Migration {
+        version: 7,
+        description: "record history",
+        kind: MigrationKind::Navigation(&[CREATE_TABLE_HISTORY, CREATE_HISTORY_INDEXES]),
+    },
+    Migration {
+        version: 8,
+        description: "jsonb record data",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![query_add_data_json_column(world_name, table_suffix)]
+        }),
+    },
+    Migration {
+        version: 9,
+        description: "record tags",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![
+                query_add_tags_column(world_name, table_suffix),
+                query_create_world_tags_index(world_name, table_suffix),
+            ]
+        }),
+    },
+    Migration {
+        version: 10,
+        description: "record tags history",
+        kind: MigrationKind::Navigation(&[ALTER_HISTORY_ADD_TAGS]),
+    },
+    Migration {
+        version: 11,
+        description: "record tables",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![query_fill_record_tables(world_name, table_suffix)]
+        }),
+    },
+    Migration {
+        version: 12,
+        description: "unique record uuids per world",
+        kind: MigrationKind::WorldTable(|world_name, table_suffix| {
+            vec![query_delete_moved_duplicates(world_name, table_suffix)]
+        }),
+    },
+];
+// endregion
+
+// region: PendingMigration Struct
+#[derive(Debug)]
+pub struct PendingMigration {
+    pub schema_name: String,
+    pub version: i32,
+    pub description: &'static str,
+}
+
+impl Display for PendingMigration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} v{}: {}",
+            self.schema_name, self.version, self.description
+        )
+    }
+}
+// endregion
+
+// region: DatabaseClient Methods
+impl DatabaseClient {
+    /// Returns all migrations that haven't been applied yet, across every schema.
+    pub async fn pending_migrations(&self) -> Result<Vec<PendingMigration>> {
+        let client = self.pool.get().await?;
+        let row = client.query_one(QUERY_MIGRATIONS_TABLE_EXISTS, &[]).await?;
+
+        let exists: bool = row.try_get("exists")?;
+        let pending = match exists {
+            true => find_pending(&client).await?,
+            false => {
+                let schemas = world_schemas(&client).await?;
+                pending_from(&HashSet::new(), &schemas)
+            }
+        };
+
+        Ok(pending)
+    }
+
+    /// Apply all pending migrations inside a single transaction.
+    pub(super) async fn run_migrations(&self) -> Result<()> {
+        let mut client = self.pool.get().await?;
+        client
+            .batch_execute(&format!(
+                "{};{};",
+                CREATE_SCHEMA_NAVIGATION, CREATE_TABLE_MIGRATIONS
+            ))
+            .await?;
+
+        let transaction = client.transaction().await?;
+        transaction.execute(QUERY_MIGRATION_LOCK, &[]).await?;
+
+        let pending = find_pending(&transaction).await?;
+        for migration in &pending {
+            info!("applying migration {}", migration);
+
+            let kind = MIGRATIONS
+                .iter()
+                .find(|m| m.version == migration.version)
+                .map(|m| &m.kind)
+                .unwrap();
+
+            match kind {
+                MigrationKind::Navigation(queries) => {
+                    for query in queries.iter() {
+                        transaction.batch_execute(query).await?;
+                    }
+                }
+
+                MigrationKind::WorldTable(queries) => {
+                    let world_name = &migration.schema_name[2..];
+                    let rows = transaction
+                        .query(QUERY_SELECT_WORLD_TABLES, &[&migration.schema_name])
+                        .await?;
+
+                    for row in rows {
+                        let table_name: &str = row.try_get("tablename")?;
+                        let table_suffix = match table_name[2..].parse::<i32>() {
+                            Ok(suffix) => suffix,
+                            Err(_) => continue,
+                        };
+
+                        for query in queries(world_name, table_suffix) {
+                            transaction.batch_execute(&query).await?;
+                        }
+                    }
+                }
+            }
+
+            transaction
+                .execute(
+                    QUERY_INSERT_MIGRATION,
+                    &[&migration.schema_name, &migration.version],
+                )
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+}
+// endregion
+
+// region: Helper Functions
+/// Mark every world migration as applied for a newly created world schema.
+///
+/// New world tables are always created with the latest schema.
+pub(super) async fn mark_world_migrated(
+    client: &impl GenericClient,
+    world_name: &str,
+) -> Result<(), tokio_postgres::Error> {
+    let schema_name = format!("w_{}", world_name);
+    for migration in MIGRATIONS {
+        if let MigrationKind::WorldTable(_) = migration.kind {
+            client
+                .execute(QUERY_INSERT_MIGRATION, &[&schema_name, &migration.version])
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn world_schemas(client: &impl GenericClient) -> Result<Vec<String>> {
+    let rows = client.query(QUERY_SELECT_WORLD_SCHEMAS, &[]).await?;
+    let schemas = rows
+        .into_iter()
+        .map(|row| row.try_get("nspname"))
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(schemas)
+}
+
+async fn find_pending(client: &impl GenericClient) -> Result<Vec<PendingMigration>> {
+    let rows = client.query(QUERY_SELECT_MIGRATIONS, &[]).await?;
+    let applied = rows
+        .into_iter()
+        .map(|row| Ok((row.try_get("schema_name")?, row.try_get("version")?)))
+        .collect::<Result<HashSet<(String, i32)>, tokio_postgres::Error>>()?;
+
+    let schemas = world_schemas(client).await?;
+    Ok(pending_from(&applied, &schemas))
+}
+
+fn pending_from(
+    applied: &HashSet<(String, i32)>,
+    world_schemas: &[String],
+) -> Vec<PendingMigration> {
+    let mut pending = vec![];
+    for migration in MIGRATIONS {
+        let schemas = match migration.kind {
+            MigrationKind::Navigation(_) => vec![NAVIGATION_SCHEMA.to_owned()],
+            MigrationKind::WorldTable(_) => world_schemas.to_vec(),
+        };
+
+        for schema_name in schemas {
+            if applied.contains(&(schema_name.clone(), migration.version)) {
+                continue;
+            }
+
+            pending.push(PendingMigration {
+                schema_name,
+                version: migration.version,
+                description: migration.description,
+            });
+        }
+    }
+
+    pending
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_increase() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+    }
+
+    #[test]
+    fn pending() {
+        let schemas = vec!["w_a".to_owned(), "w_b".to_owned()];
+        let all = pending_from(&HashSet::new(), &schemas);
+        assert_eq!(all.len(), 20);
+
+        let mut applied = HashSet::new();
+        applied.insert(("navigation".to_owned(), 1));
+        applied.insert(("navigation".to_owned(), 3));
+        applied.insert(("navigation".to_owned(), 7));
+        applied.insert(("w_a".to_owned(), 2));
+        applied.insert(("w_a".to_owned(), 4));
+        applied.insert(("w_b".to_owned(), 4));
+        applied.insert(("w_a".to_owned(), 5));
+        applied.insert(("w_b".to_owned(), 5));
+        applied.insert(("w_a".to_owned(), 6));
+        applied.insert(("w_b".to_owned(), 6));
+        applied.insert(("w_a".to_owned(), 8));
+        applied.insert(("w_b".to_owned(), 8));
+        applied.insert(("w_a".to_owned(), 9));
+        applied.insert(("w_b".to_owned(), 9));
+        applied.insert(("navigation".to_owned(), 10));
+        applied.insert(("w_a".to_owned(), 11));
+        applied.insert(("w_b".to_owned(), 11));
+        applied.insert(("w_a".to_owned(), 12));
+        applied.insert(("w_b".to_owned(), 12));
+
+        let pending = pending_from(&applied, &schemas);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].schema_name, "w_b");
+        assert_eq!(pending[0].version, 2);
+    }
+}
+// endregion
@@ -1,8 +1,43 @@
+mod area_query;
+mod atomic;
 mod client;
+mod connection;
+mod data_filter;
+mod expiry;
+mod history;
 mod init;
+mod memory;
+mod migrations;
 mod navigation;
+mod nearest;
 mod query_constants;
+mod rebucket;
+mod record_data;
+mod record_tables;
+mod sizing;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+mod tags;
+mod transaction;
+mod versions;
 mod world_region;
+mod worlds;
 
-pub use client::{DatabaseClient, DedupeData};
+pub use client::{DatabaseClient, DatabaseError};
+pub use connection::ConnectionPool;
+pub use data_filter::{DataFilter, DataFilterError};
+pub use memory::MemoryStore;
+pub use nearest::truncate_nearest;
 pub(self) use query_constants::*;
+pub use sizing::Sizing;
+pub use snapshot::{
+    import_record, navigation_path, read_snapshot, write_snapshot, SnapshotError,
+    SnapshotNavigation,
+};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+#[cfg(feature = "http")]
+pub use store::{HistoryEntry, WorldStats};
+pub use store::{RecordStore, ThreadRecordStore, Writer};
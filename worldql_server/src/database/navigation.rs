@@ -1,4 +1,4 @@
-use tokio_postgres::Error;
+use tokio_postgres::{Client, Error};
 use tracing::trace;
 
 use super::world_region::WorldRegion;
@@ -13,30 +13,46 @@ impl DatabaseClient {
     ///
     /// Returned tuple has the form `(table_suffix, region_id)`
     pub(super) async fn lookup_ids(
-        &mut self,
+        &self,
+        client: &Client,
         world_name: &str,
         point: &Vector3,
     ) -> Result<(i32, i32), Error> {
         let world_region = self.world_region(world_name, point);
-        let table_suffix = self.get_table_suffix(&world_region).await?;
-        let region_id = self.get_region_id(&world_region).await?;
+        let table_suffix = self.get_table_suffix(client, &world_region).await?;
+        let region_id = self.get_region_id(client, &world_region).await?;
 
         Ok((table_suffix, region_id))
     }
 
-    async fn get_table_suffix(&mut self, region: &WorldRegion) -> Result<i32, Error> {
+    async fn get_table_suffix(&self, client: &Client, region: &WorldRegion) -> Result<i32, Error> {
         trace!("looking up table_suffix for {}", region);
 
         // Early return for cached value
-        if let Some(id) = self.table_cache.get(region) {
-            trace!("region {} has cached table_suffix = {}", region, id);
-            return Ok(*id);
+        if let Some(id) = self.cached_table_suffix(region) {
+            #[cfg(feature = "metrics")]
+            crate::metrics::DB_CACHE_LOOKUPS
+                .with_label_values(&["table", "hit"])
+                .inc();
+
+            return Ok(id);
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::DB_CACHE_LOOKUPS
+            .with_label_values(&["table", "miss"])
+            .inc();
+
+        // Only one worker may create navigation rows at a time, otherwise two
+        // workers missing the cache for the same region would both insert one
+        let _guard = self.navigation_lock.lock().await;
+        if let Some(id) = self.cached_table_suffix(region) {
+            return Ok(id);
         }
 
         // Query database for table_suffix
         trace!("querying database for {} table_suffix", region);
-        let rows = self
-            .client
+        let rows = client
             .query(
                 QUERY_LOOKUP_TABLE_SUFFIX,
                 &[region.world_name(), region.x(), region.y(), region.z()],
@@ -66,8 +82,7 @@ impl DatabaseClient {
                 let (min_z, max_z) = region.z_bounds(table_size);
 
                 // Insert new values into DB
-                let row = self
-                    .client
+                let row = client
                     .query_one(
                         QUERY_INSERT_TABLE_SUFFIX,
                         &[
@@ -94,23 +109,41 @@ impl DatabaseClient {
         };
 
         // Insert into cache and return
-        self.table_cache.put(region.clone(), table_suffix);
+        self.table_cache
+            .lock()
+            .unwrap()
+            .put(region.clone(), table_suffix);
         Ok(table_suffix)
     }
 
-    async fn get_region_id(&mut self, region: &WorldRegion) -> Result<i32, Error> {
+    async fn get_region_id(&self, client: &Client, region: &WorldRegion) -> Result<i32, Error> {
         trace!("looking up region_id for {}", region);
 
         // Early return for cached value
-        if let Some(id) = self.region_cache.get(region) {
-            trace!("region {} has cached region_id = {}", region, id);
-            return Ok(*id);
+        if let Some(id) = self.cached_region_id(region) {
+            #[cfg(feature = "metrics")]
+            crate::metrics::DB_CACHE_LOOKUPS
+                .with_label_values(&["region", "hit"])
+                .inc();
+
+            return Ok(id);
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::DB_CACHE_LOOKUPS
+            .with_label_values(&["region", "miss"])
+            .inc();
+
+        // Only one worker may create navigation rows at a time, otherwise two
+        // workers missing the cache for the same region would both insert one
+        let _guard = self.navigation_lock.lock().await;
+        if let Some(id) = self.cached_region_id(region) {
+            return Ok(id);
         }
 
         // Query database for region_id
         trace!("querying database for {} region_id", region);
-        let rows = self
-            .client
+        let rows = client
             .query(
                 QUERY_LOOKUP_REGION_ID,
                 &[region.world_name(), region.x(), region.y(), region.z()],
@@ -139,8 +172,7 @@ impl DatabaseClient {
                 let max_z = min_z + i64::from(self.region_z_size());
 
                 // Insert new values into DB
-                let row = self
-                    .client
+                let row = client
                     .query_one(
                         QUERY_INSERT_REGION_ID,
                         &[
@@ -163,7 +195,24 @@ impl DatabaseClient {
         };
 
         // Insert into cache and return
-        self.region_cache.put(region.clone(), region_id);
+        self.region_cache
+            .lock()
+            .unwrap()
+            .put(region.clone(), region_id);
         Ok(region_id)
     }
+
+    fn cached_table_suffix(&self, region: &WorldRegion) -> Option<i32> {
+        let id = self.table_cache.lock().unwrap().get(region).copied()?;
+        trace!("region {} has cached table_suffix = {}", region, id);
+
+        Some(id)
+    }
+
+    fn cached_region_id(&self, region: &WorldRegion) -> Option<i32> {
+        let id = self.region_cache.lock().unwrap().get(region).copied()?;
+        trace!("region {} has cached region_id = {}", region, id);
+
+        Some(id)
+    }
 }
@@ -0,0 +1,219 @@
+use std::cmp::Ordering;
+
+use ahash::AHashSet;
+use chrono::NaiveDateTime;
+
+use super::store::RecordStore;
+use super::{DataFilter, DatabaseError};
+use crate::structures::{Area, Record, Vector3};
+
+/// Returns up to `count` records nearest to `point`, sorted by distance.
+///
+/// Searches outward through cubes around `point`, starting at `start_radius` and
+/// doubling each step, until `count` records are found or `max_distance` is reached.
+/// Each step only reads the shell between the new cube and the previous one. Every
+/// record within a searched radius is found, so the closest `count` records are
+/// always returned.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn find_nearest<S: RecordStore + ?Sized>(
+    store: &S,
+    world_name: &str,
+    point: Vector3,
+    count: usize,
+    max_distance: f64,
+    after: Option<NaiveDateTime>,
+    filter: &DataFilter,
+    start_radius: f64,
+) -> Result<Vec<Record>, DatabaseError> {
+    if count == 0 || max_distance < 0.0 {
+        return Ok(vec![]);
+    }
+
+    let mut records = vec![];
+    let mut seen = AHashSet::new();
+
+    let mut inner = None;
+    let mut radius = start_radius.min(max_distance);
+    loop {
+        for area in shell(point, inner, radius) {
+            let found = store
+                .get_records_in_area(world_name, area, after, filter)
+                .await?;
+
+            // Shells share their faces, so records on them are read twice
+            for record in found {
+                if seen.insert(record.uuid) {
+                    records.push(record);
+                }
+            }
+        }
+
+        let within = records
+            .iter()
+            .filter(|record| distance(record, &point) <= radius * radius)
+            .count();
+
+        if within >= count || radius >= max_distance {
+            records.retain(|record| distance(record, &point) <= max_distance * max_distance);
+            truncate_nearest(&mut records, &point, count);
+            return Ok(records);
+        }
+
+        inner = Some(radius);
+        radius = (radius * 2.0).min(max_distance);
+    }
+}
+
+/// Returns the boxes covering the cube of half side `outer` around `center`, minus
+/// the cube of half side `inner`.
+fn shell(center: Vector3, inner: Option<f64>, outer: f64) -> Vec<Area> {
+    let area = |min: (f64, f64, f64), max: (f64, f64, f64)| {
+        Area::new_box(
+            center + Vector3::new(min.0, min.1, min.2),
+            center + Vector3::new(max.0, max.1, max.2),
+        )
+    };
+
+    let (i, o) = match inner {
+        None => return vec![area((-outer, -outer, -outer), (outer, outer, outer))],
+        Some(inner) => (inner, outer),
+    };
+
+    vec![
+        area((-o, -o, -o), (-i, o, o)),
+        area((i, -o, -o), (o, o, o)),
+        area((-i, -o, -o), (i, -i, o)),
+        area((-i, i, -o), (i, o, o)),
+        area((-i, -i, -o), (i, i, -i)),
+        area((-i, -i, i), (i, i, o)),
+    ]
+}
+
+#[inline]
+fn distance(record: &Record, point: &Vector3) -> f64 {
+    match &record.position {
+        None => f64::INFINITY,
+        Some(position) => position.distance_squared(point),
+    }
+}
+
+/// Sort records by distance to `point`, keeping only the nearest `count`.
+pub fn truncate_nearest(records: &mut Vec<Record>, point: &Vector3, count: usize) {
+    records.sort_by(|a, b| {
+        distance(a, point)
+            .partial_cmp(&distance(b, point))
+            .unwrap_or(Ordering::Equal)
+    });
+
+    records.truncate(count);
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::{MemoryStore, Writer};
+
+    async fn store(xs: &[f64]) -> MemoryStore {
+        let store = MemoryStore::new(16, 16, 16);
+        let records = xs
+            .iter()
+            .map(|x| Record {
+                uuid: Uuid::new_v4(),
+                position: Some(Vector3::new(*x, 0.0, 0.0)),
+                world_name: "world".into(),
+                ..Default::default()
+            })
+            .collect();
+
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        store
+    }
+
+    fn xs(records: &[Record]) -> Vec<f64> {
+        records
+            .iter()
+            .map(|record| *record.position.unwrap().x())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn sorted_by_distance() {
+        let store = store(&[500.0, -3.0, 40.0, 1.0, -90.0, 2.0]).await;
+
+        let found = find_nearest(
+            &store,
+            "world",
+            Vector3::zero(),
+            4,
+            1000.0,
+            None,
+            &DataFilter::default(),
+            16.0,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(xs(&found), vec![1.0, 2.0, -3.0, 40.0]);
+    }
+
+    #[tokio::test]
+    async fn max_distance() {
+        let store = store(&[500.0, -3.0, 40.0, 1.0]).await;
+
+        let found = find_nearest(
+            &store,
+            "world",
+            Vector3::zero(),
+            10,
+            100.0,
+            None,
+            &DataFilter::default(),
+            16.0,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(xs(&found), vec![1.0, -3.0, 40.0]);
+
+        let found = find_nearest(
+            &store,
+            "world",
+            Vector3::zero(),
+            0,
+            100.0,
+            None,
+            &DataFilter::default(),
+            16.0,
+        )
+        .await
+        .unwrap();
+
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn shell_excludes_inner_cube() {
+        let center = Vector3::new(5.0, -3.0, 8.0);
+        let boxes = shell(center, Some(4.0), 8.0);
+
+        for x in -8..=8 {
+            for y in -8..=8 {
+                for z in -8..=8 {
+                    let offset = Vector3::new(x.into(), y.into(), z.into());
+                    let inside = boxes.iter().any(|area| area.contains(&(center + offset)));
+                    let in_inner = [x, y, z].iter().all(|axis: &i32| axis.abs() < 4);
+
+                    assert_eq!(inside, !in_inner, "{}", offset);
+                }
+            }
+        }
+    }
+}
+// endregion
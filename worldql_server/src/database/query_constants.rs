@@ -36,6 +36,24 @@ pub(super) const CREATE_REGION_NAVIGATION: &str = "
         region_id  serial NOT NULL
     );
 ";
+
+pub(super) const CREATE_TABLE_SIZING: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.sizing
+    (
+        id            integer PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+        region_x_size bigint NOT NULL,
+        region_y_size bigint NOT NULL,
+        region_z_size bigint NOT NULL,
+        table_size    bigint NOT NULL
+    )
+";
+
+pub(super) const CREATE_TABLE_REBUCKET: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.rebucket
+    (
+        world_name varchar(32) PRIMARY KEY
+    )
+";
 // endregion
 
 // region: Lookups
@@ -66,6 +84,24 @@ pub(super) const QUERY_INSERT_REGION_ID: &str = "
     VALUES ($1, $2, $3, $4, $5, $6, $7)
     RETURNING region_id
 ";
+
+pub(super) const QUERY_SELECT_ALL_TABLES: &str = "
+    SELECT world_name, table_suffix FROM navigation.tables
+    ORDER BY table_suffix
+";
+
+pub(super) const QUERY_SELECT_WORLD_TABLE_SUFFIXES: &str = "
+    SELECT table_suffix FROM navigation.tables
+    WHERE world_name = $1
+";
+
+pub(super) const QUERY_SELECT_TABLES_IN_BOUNDS: &str = "
+    SELECT table_suffix FROM navigation.tables
+    WHERE world_name = $1 AND
+    max_x > $2 AND min_x <= $3 AND
+    max_y > $4 AND min_y <= $5 AND
+    max_z > $6 AND min_z <= $7
+";
 // endregion
 
 // region: Create World Table
@@ -85,10 +121,21 @@ fn table_name(world_name: &str, suffix: i32) -> String {
     format!("w_{0}.t_{1}", world_name, suffix)
 }
 
+pub(super) fn query_world_table_exists(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT to_regclass('{0}') IS NOT NULL AS exists
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
 pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        CREATE TABLE {}
+        CREATE TABLE IF NOT EXISTS {0}
         (
             last_modified timestamp NOT NULL DEFAULT NOW(),
             region_id     integer NOT NULL,
@@ -97,10 +144,18 @@ pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
             z             double precision,
             uuid          uuid NOT NULL,
             data          varchar,
-            flex          bytea
+            data_json     jsonb,
+            flex          bytea,
+            expires_at    timestamp,
+            version       bigint NOT NULL DEFAULT 1,
+            owner         varchar,
+            writers       varchar[],
+            tags          varchar[],
+            CONSTRAINT {1} UNIQUE (uuid)
         )
         ",
-        table_name(world_name, suffix)
+        table_name(world_name, suffix),
+        uuid_constraint_name(world_name, suffix)
     );
 
     query
@@ -109,7 +164,7 @@ pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
 pub(super) fn query_create_world_index(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        CREATE INDEX {0}_{1}_region_id_index
+        CREATE INDEX IF NOT EXISTS {0}_{1}_region_id_index
         ON {2} USING btree (region_id);
         ",
         world_name,
@@ -119,37 +174,609 @@ pub(super) fn query_create_world_index(world_name: &str, suffix: i32) -> String
 
     query
 }
+
+pub(super) fn query_create_world_expiry_index(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        CREATE INDEX IF NOT EXISTS {0}_{1}_expires_at_index
+        ON {2} USING btree (expires_at) WHERE expires_at IS NOT NULL
+        ",
+        world_name,
+        suffix,
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_create_world_tags_index(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        CREATE INDEX IF NOT EXISTS {0}_{1}_tags_index
+        ON {2} USING gin (tags)
+        ",
+        world_name,
+        suffix,
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Create the table mapping every record uuid in a world to the table it's stored in.
+///
+/// Record uuids are unique per world, not just per table, so writes check this table
+/// to find records stored elsewhere in the world.
+pub(super) fn query_create_record_tables(world_name: &str) -> String {
+    let query = format!(
+        "
+        CREATE TABLE IF NOT EXISTS w_{}.record_tables
+        (
+            uuid         uuid PRIMARY KEY,
+            table_suffix integer NOT NULL
+        )
+        ",
+        world_name
+    );
+
+    query
+}
+
+#[inline]
+fn uuid_constraint_name(world_name: &str, suffix: i32) -> String {
+    format!("{0}_{1}_uuid_uindex", world_name, suffix)
+}
 // endregion
 
-// region: Record Manipulation
-pub(super) fn query_insert_record(world_name: &str, suffix: i32) -> String {
+// region: Sizing
+pub(super) const QUERY_SELECT_SIZING: &str = "
+    SELECT region_x_size, region_y_size, region_z_size, table_size
+    FROM navigation.sizing
+";
+
+pub(super) const QUERY_UPSERT_SIZING: &str = "
+    INSERT INTO navigation.sizing (region_x_size, region_y_size, region_z_size, table_size)
+    VALUES ($1, $2, $3, $4)
+    ON CONFLICT (id) DO UPDATE SET
+    region_x_size = EXCLUDED.region_x_size,
+    region_y_size = EXCLUDED.region_y_size,
+    region_z_size = EXCLUDED.region_z_size,
+    table_size = EXCLUDED.table_size
+";
+
+/// Infer region sizes from navigation rows created before sizing was stored.
+pub(super) const QUERY_INFER_REGION_SIZE: &str = "
+    SELECT max_x - min_x AS x, max_y - min_y AS y, max_z - min_z AS z
+    FROM navigation.regions LIMIT 1
+";
+
+/// Infer table size from navigation rows created before sizing was stored.
+pub(super) const QUERY_INFER_TABLE_SIZE: &str = "
+    SELECT max_x - min_x AS size FROM navigation.tables LIMIT 1
+";
+// endregion
+
+// region: Rebucket
+pub(super) const QUERY_SELECT_NAVIGATION_WORLDS: &str = "
+    SELECT DISTINCT world_name FROM navigation.tables
+";
+
+pub(super) const QUERY_SELECT_REBUCKET_WORLDS: &str = "
+    SELECT world_name FROM navigation.rebucket
+";
+
+pub(super) const QUERY_INSERT_REBUCKET_WORLD: &str = "
+    INSERT INTO navigation.rebucket (world_name) VALUES ($1)
+";
+
+pub(super) const QUERY_DELETE_REBUCKET_WORLD: &str = "
+    DELETE FROM navigation.rebucket WHERE world_name = $1
+";
+
+pub(super) const QUERY_WORLD_SCHEMA_EXISTS: &str = "
+    SELECT EXISTS (
+        SELECT 1 FROM pg_namespace WHERE nspname = lower('w_' || $1)
+    ) AS exists
+";
+
+pub(super) const QUERY_DELETE_WORLD_TABLES: &str = "
+    DELETE FROM navigation.tables WHERE world_name = $1
+";
+
+pub(super) const QUERY_DELETE_WORLD_REGIONS: &str = "
+    DELETE FROM navigation.regions WHERE world_name = $1
+";
+
+/// Moves a world schema out of the way while its records are re-inserted.
+pub(super) fn query_stage_world(world_name: &str) -> String {
     let query = format!(
         "
-        INSERT INTO {}
-        (region_id, x, y, z, uuid, data, flex)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ALTER SCHEMA w_{0} RENAME TO r_{0}
+        ",
+        world_name
+    );
+
+    query
+}
+
+pub(super) fn query_select_staged_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM r_{0}.t_{1}
+        ",
+        world_name, suffix
+    );
+
+    query
+}
+
+pub(super) fn query_drop_staged_world(world_name: &str) -> String {
+    let query = format!(
+        "
+        DROP SCHEMA r_{} CASCADE
+        ",
+        world_name
+    );
+
+    query
+}
+// endregion
+
+// region: Worlds
+pub(super) const QUERY_SELECT_WORLD_TABLE_COUNTS: &str = "
+    SELECT world_name, count(*) AS table_count FROM navigation.tables
+    GROUP BY world_name
+    ORDER BY world_name
+";
+
+/// Whether a world has any navigation rows or a schema.
+pub(super) const QUERY_WORLD_EXISTS: &str = "
+    SELECT EXISTS (
+        SELECT 1 FROM navigation.tables WHERE world_name = $1
+    ) OR EXISTS (
+        SELECT 1 FROM pg_namespace WHERE nspname = lower('w_' || $1)
+    ) AS exists
+";
+
+pub(super) const QUERY_DELETE_WORLD_MIGRATIONS: &str = "
+    DELETE FROM navigation.migrations WHERE schema_name = $1
+";
+
+pub(super) const QUERY_RENAME_WORLD_TABLES: &str = "
+    UPDATE navigation.tables SET world_name = $2 WHERE world_name = $1
+";
+
+pub(super) const QUERY_RENAME_WORLD_REGIONS: &str = "
+    UPDATE navigation.regions SET world_name = $2 WHERE world_name = $1
+";
+
+pub(super) const QUERY_RENAME_WORLD_MIGRATIONS: &str = "
+    UPDATE navigation.migrations SET schema_name = $2 WHERE schema_name = $1
+";
+
+pub(super) fn query_count_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT count(*) AS count FROM {}
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_table_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {}
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_drop_world_schema(world_name: &str) -> String {
+    let query = format!(
+        "
+        DROP SCHEMA IF EXISTS w_{} CASCADE
+        ",
+        world_name
+    );
+
+    query
+}
+
+pub(super) fn query_rename_world_schema(from: &str, to: &str) -> String {
+    let query = format!(
+        "
+        ALTER SCHEMA w_{} RENAME TO w_{}
+        ",
+        from, to
+    );
+
+    query
+}
+// endregion
+
+// region: Migrations
+pub(super) const CREATE_TABLE_MIGRATIONS: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.migrations
+    (
+        schema_name varchar(34) NOT NULL,
+        version     integer NOT NULL,
+        applied_at  timestamp NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (schema_name, version)
+    )
+";
+
+pub(super) const QUERY_MIGRATIONS_TABLE_EXISTS: &str = "
+    SELECT to_regclass('navigation.migrations') IS NOT NULL AS exists
+";
+
+pub(super) const QUERY_SELECT_MIGRATIONS: &str = "
+    SELECT schema_name, version FROM navigation.migrations
+";
+
+pub(super) const QUERY_INSERT_MIGRATION: &str = "
+    INSERT INTO navigation.migrations (schema_name, version)
+    VALUES ($1, $2)
+    ON CONFLICT DO NOTHING
+";
+
+/// Serializes migrations between multiple servers sharing a database.
+pub(super) const QUERY_MIGRATION_LOCK: &str = "
+    SELECT pg_advisory_xact_lock(7157526)
+";
+
+pub(super) const QUERY_SELECT_WORLD_SCHEMAS: &str = "
+    SELECT nspname FROM pg_namespace
+    WHERE nspname LIKE 'w\\_%'
+    ORDER BY nspname
+";
+
+pub(super) const QUERY_SELECT_WORLD_TABLES: &str = "
+    SELECT tablename FROM pg_tables
+    WHERE schemaname = $1 AND tablename LIKE 't\\_%'
+";
+
+pub(super) fn query_add_uuid_constraint(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0}
+        DROP CONSTRAINT IF EXISTS {1},
+        ADD CONSTRAINT {1} UNIQUE (uuid)
+        ",
+        table_name(world_name, suffix),
+        uuid_constraint_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_add_expiry_column(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0}
+        ADD COLUMN IF NOT EXISTS expires_at timestamp
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_add_version_column(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0}
+        ADD COLUMN IF NOT EXISTS version bigint NOT NULL DEFAULT 1
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_add_owner_columns(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0}
+        ADD COLUMN IF NOT EXISTS owner varchar,
+        ADD COLUMN IF NOT EXISTS writers varchar[]
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Add the `data_json` column, and copy stored data that parses as JSON into it.
+pub(super) fn query_add_data_json_column(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0} ADD COLUMN IF NOT EXISTS data_json jsonb;
+
+        DO $$
+        DECLARE
+            stored record;
+        BEGIN
+            FOR stored IN SELECT uuid, data FROM {0} WHERE data IS NOT NULL LOOP
+                BEGIN
+                    UPDATE {0} SET data_json = stored.data::jsonb
+                    WHERE uuid = stored.uuid;
+                EXCEPTION WHEN data_exception THEN
+                    -- Not JSON, keep it as text
+                    NULL;
+                END;
+            END LOOP;
+        END
+        $$
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_add_tags_column(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        ALTER TABLE {0}
+        ADD COLUMN IF NOT EXISTS tags varchar[]
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Add the rows of a world table to its world's `record_tables`, keeping the most
+/// recently modified row for uuids stored in more than one table.
+///
+/// Tracks `last_modified` until [`query_delete_moved_duplicates`] runs.
+pub(super) fn query_fill_record_tables(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        {0};
+        ALTER TABLE w_{1}.record_tables ADD COLUMN IF NOT EXISTS last_modified timestamp;
+        INSERT INTO w_{1}.record_tables AS i (uuid, table_suffix, last_modified)
+        SELECT uuid, {2}, last_modified FROM {3}
+        ON CONFLICT (uuid) DO UPDATE SET
+        table_suffix = EXCLUDED.table_suffix,
+        last_modified = EXCLUDED.last_modified
+        WHERE EXCLUDED.last_modified > i.last_modified
+        ",
+        query_create_record_tables(world_name),
+        world_name,
+        suffix,
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Delete the rows of a world table that `record_tables` maps to another table.
+pub(super) fn query_delete_moved_duplicates(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {0} r USING w_{1}.record_tables i
+        WHERE i.uuid = r.uuid AND i.table_suffix <> {2};
+        ALTER TABLE w_{1}.record_tables DROP COLUMN IF EXISTS last_modified;
+        ",
+        table_name(world_name, suffix),
+        world_name,
+        suffix
+    );
+
+    query
+}
+
+/// Delete all but the most recently modified row for each `uuid`.
+pub(super) fn query_delete_duplicates(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {0} a USING {0} b
+        WHERE a.uuid = b.uuid AND (
+            a.last_modified < b.last_modified OR
+            (a.last_modified = b.last_modified AND a.ctid < b.ctid)
+        )
         ",
         table_name(world_name, suffix)
     );
 
     query
 }
+// endregion
+
+// region: History
+pub(super) const CREATE_TABLE_HISTORY: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.history
+    (
+        id             bigserial PRIMARY KEY,
+        changed_at     timestamp NOT NULL,
+        world_name     varchar(32) NOT NULL,
+        uuid           uuid NOT NULL,
+        operation      varchar(6) NOT NULL,
+        peer           uuid,
+        old_x          double precision,
+        old_y          double precision,
+        old_z          double precision,
+        old_data       varchar,
+        old_flex       bytea,
+        old_expires_at timestamp,
+        old_version    bigint,
+        old_owner      varchar,
+        old_writers    varchar[],
+        new_x          double precision,
+        new_y          double precision,
+        new_z          double precision,
+        new_data       varchar,
+        new_flex       bytea,
+        new_expires_at timestamp,
+        new_version    bigint,
+        new_owner      varchar,
+        new_writers    varchar[]
+    )
+";
+
+pub(super) const ALTER_HISTORY_ADD_TAGS: &str = "
+    ALTER TABLE navigation.history
+    ADD COLUMN IF NOT EXISTS old_tags varchar[],
+    ADD COLUMN IF NOT EXISTS new_tags varchar[]
+";
+
+pub(super) const CREATE_HISTORY_INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS history_record_index
+    ON navigation.history (world_name, uuid, id);
+
+    CREATE INDEX IF NOT EXISTS history_changed_at_index
+    ON navigation.history (changed_at);
+";
+
+/// Number of parameters taken by each entry in [`query_insert_history_many`]
+pub(super) const HISTORY_PARAMS: usize = 25;
+
+/// Takes `changed_at, world_name, uuid, operation, peer`, then the old and new record
+/// columns for each entry.
+pub(super) fn query_insert_history_many(count: usize) -> String {
+    let mut query = String::from(
+        "
+        INSERT INTO navigation.history
+        (
+            changed_at, world_name, uuid, operation, peer,
+            old_x, old_y, old_z, old_data, old_flex,
+            old_expires_at, old_version, old_owner, old_writers, old_tags,
+            new_x, new_y, new_z, new_data, new_flex,
+            new_expires_at, new_version, new_owner, new_writers, new_tags
+        )
+        VALUES",
+    );
+
+    for i in 0..count {
+        let i = i * HISTORY_PARAMS;
+        let params = (1..=HISTORY_PARAMS)
+            .map(|param| format!("${}", i + param))
+            .collect::<Vec<_>>();
+
+        let prefix = if i == 0 { " " } else { ", " };
+        query += &format!("{}({})", prefix, params.join(", "));
+    }
+
+    query
+}
+
+#[cfg(any(feature = "http", test))]
+pub(super) const QUERY_SELECT_RECORD_HISTORY: &str = "
+    SELECT * FROM navigation.history
+    WHERE world_name = $1 AND uuid = $2
+    ORDER BY id DESC LIMIT $3
+";
+
+pub(super) const QUERY_DELETE_HISTORY_BEFORE: &str = "
+    DELETE FROM navigation.history WHERE changed_at < $1
+";
+
+/// Returns the stored records with any of the uuids in `$1`.
+pub(super) fn query_select_records_by_uuid(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE uuid = ANY($1)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+// endregion
+
+// region: Record Manipulation
+const UPSERT_RECORD: &str = "
+        ON CONFLICT (uuid) DO UPDATE SET
+        last_modified = NOW(),
+        region_id = EXCLUDED.region_id,
+        x = EXCLUDED.x,
+        y = EXCLUDED.y,
+        z = EXCLUDED.z,
+        data = EXCLUDED.data,
+        data_json = EXCLUDED.data_json,
+        flex = EXCLUDED.flex,
+        expires_at = EXCLUDED.expires_at,
+        tags = EXCLUDED.tags,
+        owner = COALESCE(EXCLUDED.owner, r.owner),
+        writers = COALESCE(EXCLUDED.writers, r.writers),
+        version = r.version + 1
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+";
+
+/// Only updates records the peer in `owner` may write, and keeps their owner.
+///
+/// Returns every record written.
+const UPSERT_RECORD_AS_PEER: &str = "
+        ON CONFLICT (uuid) DO UPDATE SET
+        last_modified = NOW(),
+        region_id = EXCLUDED.region_id,
+        x = EXCLUDED.x,
+        y = EXCLUDED.y,
+        z = EXCLUDED.z,
+        data = EXCLUDED.data,
+        data_json = EXCLUDED.data_json,
+        flex = EXCLUDED.flex,
+        expires_at = EXCLUDED.expires_at,
+        tags = EXCLUDED.tags,
+        writers = COALESCE(EXCLUDED.writers, r.writers),
+        version = r.version + 1
+        WHERE r.owner IS NULL OR r.owner = EXCLUDED.owner OR
+        (EXCLUDED.writers IS NULL AND EXCLUDED.owner = ANY(r.writers))
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+";
+
+pub(super) fn query_insert_record(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        INSERT INTO {} AS r
+        (region_id, x, y, z, uuid, data, data_json, flex, expires_at, tags)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        {}",
+        table_name(world_name, suffix),
+        UPSERT_RECORD
+    );
+
+    query
+}
 
 pub(super) fn query_insert_record_many(world_name: &str, suffix: i32, count: usize) -> String {
+    insert_record_many(world_name, suffix, count, UPSERT_RECORD)
+}
+
+/// Takes the same parameters as [`query_insert_record_many`], with each record's owner
+/// set to the peer's identity.
+pub(super) fn query_insert_record_many_as_peer(
+    world_name: &str,
+    suffix: i32,
+    count: usize,
+) -> String {
+    insert_record_many(world_name, suffix, count, UPSERT_RECORD_AS_PEER)
+}
+
+fn insert_record_many(world_name: &str, suffix: i32, count: usize, upsert: &str) -> String {
     let mut query = format!(
         "
-        INSERT INTO {}
-        (region_id, x, y, z, uuid, data, flex)
+        INSERT INTO {} AS r
+        (region_id, x, y, z, uuid, data, data_json, flex, expires_at, owner, writers, tags)
         VALUES",
         table_name(world_name, suffix)
     );
 
     for i in 0..count {
-        let i = i * 7;
+        let i = i * 12;
         let prefix = if i == 0 { " " } else { ", " };
 
         query += &format!(
-            "{}(${}, ${}, ${}, ${}, ${}, ${}, ${})",
+            "{}(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
             prefix,
             i + 1,
             i + 2,
@@ -157,18 +784,25 @@ pub(super) fn query_insert_record_many(world_name: &str, suffix: i32, count: usi
             i + 4,
             i + 5,
             i + 6,
-            i + 7
+            i + 7,
+            i + 8,
+            i + 9,
+            i + 10,
+            i + 11,
+            i + 12
         );
     }
 
+    query += upsert;
     query
 }
 
 pub(super) fn query_select_records(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        SELECT last_modified, x, y, z, uuid, data, flex
-        FROM {} WHERE region_id = $1
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE region_id = $1 AND
+        (expires_at IS NULL OR expires_at > $2)
         ",
         table_name(world_name, suffix)
     );
@@ -179,8 +813,43 @@ pub(super) fn query_select_records(world_name: &str, suffix: i32) -> String {
 pub(super) fn query_select_records_after(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        SELECT last_modified, x, y, z, uuid, data, flex
-        FROM {} WHERE region_id = $1 AND last_modified > $2
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE region_id = $1 AND
+        (expires_at IS NULL OR expires_at > $2) AND
+        last_modified > $3
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_records_in_box(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE
+        x >= $1 AND x <= $2 AND
+        y >= $3 AND y <= $4 AND
+        z >= $5 AND z <= $6 AND
+        (expires_at IS NULL OR expires_at > $7)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_records_in_box_after(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE
+        x >= $1 AND x <= $2 AND
+        y >= $3 AND y <= $4 AND
+        z >= $5 AND z <= $6 AND
+        (expires_at IS NULL OR expires_at > $7) AND
+        last_modified > $8
         ",
         table_name(world_name, suffix)
     );
@@ -188,11 +857,171 @@ pub(super) fn query_select_records_after(world_name: &str, suffix: i32) -> Strin
     query
 }
 
+/// Returns records tagged with `$1`, using the `tags` index.
+pub(super) fn query_select_records_with_tag(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE tags @> ARRAY[$1]::varchar[] AND
+        (expires_at IS NULL OR expires_at > $2)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_records_with_tag_after(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE tags @> ARRAY[$1]::varchar[] AND
+        (expires_at IS NULL OR expires_at > $2) AND
+        last_modified > $3
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Delete up to `$2` records that expired at or before `$1`, returning them.
+pub(super) fn query_delete_expired_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {0} WHERE uuid IN (
+            SELECT uuid FROM {0} WHERE expires_at <= $1 LIMIT $2
+        )
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Insert a record only if no record with its uuid exists, returning it.
+pub(super) fn query_insert_record_if_absent(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        INSERT INTO {}
+        (region_id, x, y, z, uuid, data, data_json, flex, expires_at, owner, writers, tags)
+        VALUES ($1, $2, $3, $4, $5, $6, $11, $7, $8, $9, $10, $12)
+        ON CONFLICT (uuid) DO NOTHING
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Update a record only if it is at version `$13`, returning the updated record.
+///
+/// Takes the same parameters as [`query_insert_record_if_absent`].
+pub(super) fn query_update_record_if_version(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        UPDATE {} AS r SET
+        last_modified = NOW(),
+        region_id = $1,
+        x = $2,
+        y = $3,
+        z = $4,
+        data = $6,
+        data_json = $11,
+        flex = $7,
+        expires_at = $8,
+        tags = $12,
+        owner = COALESCE($9, r.owner),
+        writers = COALESCE($10, r.writers),
+        version = r.version + 1
+        WHERE r.uuid = $5 AND r.version = $13
+        RETURNING r.x, r.y, r.z, r.uuid, r.data, r.flex, r.expires_at, r.version, r.owner, r.writers, r.tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Same as [`query_update_record_if_version`], but only updates records the peer in
+/// `$9` may write, and keeps their owner.
+pub(super) fn query_update_record_if_version_as_peer(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        UPDATE {} AS r SET
+        last_modified = NOW(),
+        region_id = $1,
+        x = $2,
+        y = $3,
+        z = $4,
+        data = $6,
+        data_json = $11,
+        flex = $7,
+        expires_at = $8,
+        tags = $12,
+        writers = COALESCE($10, r.writers),
+        version = r.version + 1
+        WHERE r.uuid = $5 AND r.version = $13 AND
+        (r.owner IS NULL OR r.owner = $9 OR ($10::varchar[] IS NULL AND $9 = ANY(r.writers)))
+        RETURNING r.x, r.y, r.z, r.uuid, r.data, r.flex, r.expires_at, r.version, r.owner, r.writers, r.tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Returns the version, owner and writers of a record.
+pub(super) fn query_select_record_access(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT version, owner, writers FROM {} WHERE uuid = $1
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Delete a record only if it is at version `$2`, returning the deleted record.
+pub(super) fn query_delete_record_if_version(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        uuid = $1 AND version = $2
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Same as [`query_delete_record_if_version`], but only deletes records the peer in
+/// `$3` may write.
+pub(super) fn query_delete_record_if_version_as_peer(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} AS r WHERE
+        r.uuid = $1 AND r.version = $2 AND
+        (r.owner IS NULL OR r.owner = $3 OR $3 = ANY(r.writers))
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Returns the deleted record.
 pub(super) fn query_delete_record(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
         DELETE FROM {} WHERE
         region_id = $1 AND uuid = $2
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
         ",
         table_name(world_name, suffix)
     );
@@ -200,11 +1029,14 @@ pub(super) fn query_delete_record(world_name: &str, suffix: i32) -> String {
     query
 }
 
-pub(super) fn query_delete_duplictes(world_name: &str, suffix: i32) -> String {
+/// Same as [`query_delete_record`], but only deletes records the peer in `$3` may write.
+pub(super) fn query_delete_record_as_peer(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        DELETE FROM {} WHERE
-        uuid = $1 AND last_modified < $2
+        DELETE FROM {} AS r WHERE
+        r.region_id = $1 AND r.uuid = $2 AND
+        (r.owner IS NULL OR r.owner = $3 OR $3 = ANY(r.writers))
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
         ",
         table_name(world_name, suffix)
     );
@@ -212,3 +1044,95 @@ pub(super) fn query_delete_duplictes(world_name: &str, suffix: i32) -> String {
     query
 }
 // endregion
+
+// region: Record Tables
+/// Add `record_tables` rows mapping the uuids in `$1` to table `$2`, unless they're
+/// already stored in the world.
+///
+/// Waits for concurrent writes of the same uuids to finish.
+pub(super) fn query_claim_record_tables(world_name: &str) -> String {
+    let query = format!(
+        "
+        INSERT INTO w_{}.record_tables (uuid, table_suffix)
+        SELECT unnest($1::uuid[]), $2
+        ON CONFLICT (uuid) DO NOTHING
+        ",
+        world_name
+    );
+
+    query
+}
+
+/// Returns the tables storing the uuids in `$1`, locking their rows until the
+/// transaction ends.
+pub(super) fn query_lock_record_tables(world_name: &str) -> String {
+    let query = format!(
+        "
+        SELECT uuid, table_suffix FROM w_{}.record_tables
+        WHERE uuid = ANY($1)
+        ORDER BY uuid
+        FOR UPDATE
+        ",
+        world_name
+    );
+
+    query
+}
+
+/// Returns the table storing the uuid `$1`.
+pub(super) fn query_select_record_table(world_name: &str) -> String {
+    let query = format!(
+        "
+        SELECT table_suffix FROM w_{}.record_tables WHERE uuid = $1
+        ",
+        world_name
+    );
+
+    query
+}
+
+pub(super) fn query_update_record_tables(world_name: &str) -> String {
+    let query = format!(
+        "
+        UPDATE w_{}.record_tables SET table_suffix = $2 WHERE uuid = ANY($1)
+        ",
+        world_name
+    );
+
+    query
+}
+
+pub(super) fn query_delete_record_tables(world_name: &str) -> String {
+    let query = format!(
+        "
+        DELETE FROM w_{}.record_tables WHERE uuid = ANY($1)
+        ",
+        world_name
+    );
+
+    query
+}
+
+/// Move the records with the uuids in `$1` between two tables of a world, keeping
+/// every column as stored.
+pub(super) fn query_move_records(world_name: &str, from: i32, to: i32) -> String {
+    let query = format!(
+        "
+        WITH moved AS (
+            DELETE FROM {0} WHERE uuid = ANY($1)
+            RETURNING last_modified, region_id, x, y, z, uuid, data, data_json, flex,
+            expires_at, version, owner, writers, tags
+        )
+        INSERT INTO {1}
+        (last_modified, region_id, x, y, z, uuid, data, data_json, flex, expires_at, version,
+        owner, writers, tags)
+        SELECT last_modified, region_id, x, y, z, uuid, data, data_json, flex, expires_at,
+        version, owner, writers, tags FROM moved
+        ",
+        table_name(world_name, from),
+        table_name(world_name, to)
+    );
+
+    query
+}
+// endregion
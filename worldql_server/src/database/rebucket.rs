@@ -0,0 +1,164 @@
+use color_eyre::Result;
+use tracing::info;
+
+use super::client::DatabaseClient;
+use super::sizing::{store_sizing, stored_sizing};
+use super::store::Writer;
+use super::{
+    query_drop_staged_world, query_select_staged_records, query_stage_world,
+    QUERY_DELETE_REBUCKET_WORLD, QUERY_DELETE_WORLD_REGIONS, QUERY_DELETE_WORLD_TABLES,
+    QUERY_INSERT_REBUCKET_WORLD, QUERY_MIGRATION_LOCK, QUERY_SELECT_NAVIGATION_WORLDS,
+    QUERY_SELECT_REBUCKET_WORLDS, QUERY_SELECT_WORLD_TABLES, QUERY_WORLD_SCHEMA_EXISTS,
+};
+use crate::structures::Record;
+
+/// Maximum number of records re-inserted at once
+const REBUCKET_BATCH_SIZE: usize = 1000;
+
+impl DatabaseClient {
+    /// Move every world to the sizing this client is configured with.
+    ///
+    /// Worlds are first staged into `r_*` schemas, then their records are re-inserted
+    /// into freshly bucketed tables. If interrupted, running again resumes from the
+    /// worlds that are still staged.
+    ///
+    /// Returns the number of records that were moved.
+    pub async fn rebucket(&self) -> Result<u64> {
+        self.run_migrations().await?;
+        self.stage_worlds().await?;
+
+        let worlds = {
+            let client = self.pool.get().await?;
+            client
+                .query(QUERY_SELECT_REBUCKET_WORLDS, &[])
+                .await?
+                .into_iter()
+                .map(|row| row.try_get("world_name"))
+                .collect::<Result<Vec<String>, _>>()?
+        };
+
+        let mut moved = 0;
+        for world_name in worlds {
+            let world_moved = self.rebucket_world(&world_name).await?;
+
+            let client = self.pool.get().await?;
+            client
+                .batch_execute(&query_drop_staged_world(&world_name))
+                .await?;
+
+            client
+                .execute(QUERY_DELETE_REBUCKET_WORLD, &[&world_name])
+                .await?;
+
+            info!("rebucketed {} records in world {}", world_moved, world_name);
+            moved += world_moved;
+        }
+
+        Ok(moved)
+    }
+
+    /// Move all worlds into `r_*` schemas and store the new sizing.
+    ///
+    /// Does nothing if the stored sizing already matches, so an interrupted
+    /// rebucket can be resumed.
+    async fn stage_worlds(&self) -> Result<()> {
+        let mut client = self.pool.get().await?;
+        let configured = self.sizing();
+
+        let stored = stored_sizing(&client).await?;
+        if stored == Some(configured) {
+            return Ok(());
+        }
+
+        let transaction = client.transaction().await?;
+        transaction.execute(QUERY_MIGRATION_LOCK, &[]).await?;
+
+        let rows = transaction
+            .query(QUERY_SELECT_NAVIGATION_WORLDS, &[])
+            .await?;
+
+        for row in rows {
+            let world_name: &str = row.try_get("world_name")?;
+            let exists: bool = transaction
+                .query_one(QUERY_WORLD_SCHEMA_EXISTS, &[&world_name])
+                .await?
+                .try_get("exists")?;
+
+            if exists {
+                transaction
+                    .batch_execute(&query_stage_world(world_name))
+                    .await?;
+
+                transaction
+                    .execute(QUERY_INSERT_REBUCKET_WORLD, &[&world_name])
+                    .await?;
+            }
+
+            transaction
+                .execute(QUERY_DELETE_WORLD_TABLES, &[&world_name])
+                .await?;
+
+            transaction
+                .execute(QUERY_DELETE_WORLD_REGIONS, &[&world_name])
+                .await?;
+        }
+
+        store_sizing(&transaction, &configured).await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Re-insert all records from a staged world, returning the number of records moved.
+    async fn rebucket_world(&self, world_name: &str) -> Result<u64> {
+        // Unquoted identifiers are folded to lowercase by PostgreSQL
+        let schema_name = format!("r_{}", world_name).to_ascii_lowercase();
+        let table_suffixes = {
+            let client = self.pool.get().await?;
+            client
+                .query(QUERY_SELECT_WORLD_TABLES, &[&schema_name])
+                .await?
+                .into_iter()
+                .map(|row| row.try_get::<_, String>("tablename"))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter_map(|table_name| table_name[2..].parse::<i32>().ok())
+                .collect::<Vec<_>>()
+        };
+
+        let mut moved = 0;
+        for table_suffix in table_suffixes {
+            // Release the connection before inserting, the pool may only hold one
+            let records = {
+                let client = self.pool.get().await?;
+                let query = query_select_staged_records(world_name, table_suffix);
+
+                client
+                    .query(&query, &[])
+                    .await?
+                    .into_iter()
+                    .map(|row| Record::from_postgres_row(row, world_name))
+                    // Re-inserted unconditionally, so versions restart at 1
+                    .map(|record| Record {
+                        version: None,
+                        ..record
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            // Moving records doesn't change them, so isn't recorded in the history
+            for batch in records.chunks(REBUCKET_BATCH_SIZE) {
+                let written = self
+                    .write_records(batch.to_vec(), &Writer::SERVER, false)
+                    .await;
+                if let Some(error) = written.errors.into_iter().next() {
+                    return Err(error.into());
+                }
+
+                moved += batch.len() as u64;
+            }
+        }
+
+        Ok(moved)
+    }
+}
@@ -0,0 +1,84 @@
+use std::error::Error;
+
+use bytes::{BufMut, BytesMut};
+use serde_json::Value;
+use tokio_postgres::types::{to_sql_checked, IsNull, ToSql, Type};
+
+/// Version of the `jsonb` binary format, which is the JSON text itself
+const JSONB_VERSION: u8 = 1;
+
+// region: JsonText Struct
+/// JSON text stored in a `json` or `jsonb` column.
+///
+/// Sent as text so numbers never lose precision. PostgreSQL normalizes whitespace and
+/// key order of stored `jsonb` values, so they're only filtered on, never read back.
+#[derive(Debug)]
+pub struct JsonText(pub String);
+
+impl ToSql for JsonText {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        if *ty == Type::JSONB {
+            out.put_u8(JSONB_VERSION);
+        }
+
+        out.put_slice(self.0.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+
+    to_sql_checked!();
+}
+
+// endregion
+
+/// Returns the values of the `data` and `data_json` columns for record data.
+///
+/// Data is always stored as text, exactly as written. Data that parses as JSON is also
+/// copied into `data_json` as `jsonb`, so it can be filtered on.
+pub(super) fn data_columns(data: Option<String>) -> (Option<String>, Option<JsonText>) {
+    let json = data.as_deref().filter(|data| is_json(data));
+    let json = json.map(|json| JsonText(json.to_owned()));
+
+    (data, json)
+}
+
+#[inline]
+fn is_json(data: &str) -> bool {
+    // PostgreSQL can't store NUL characters in jsonb strings
+    serde_json::from_str::<Value>(data).is_ok() && !data.contains("\\u0000")
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let columns = |data: Option<&str>| {
+            let (text, json) = data_columns(data.map(Into::into));
+            (text, json.map(|json| json.0))
+        };
+
+        let both = |data: &str| (Some(data.into()), Some(data.into()));
+        let text = |data: &str| (Some(data.into()), None);
+
+        assert_eq!(columns(None), (None, None));
+        assert_eq!(columns(Some("rare")), text("rare"));
+        assert_eq!(columns(Some("{\"a\": 1")), text("{\"a\": 1"));
+        assert_eq!(
+            columns(Some("{\"b\": 1,  \"a\": 2}")),
+            both("{\"b\": 1,  \"a\": 2}")
+        );
+        assert_eq!(columns(Some("-20")), both("-20"));
+        assert_eq!(columns(Some("\"\\u0000\"")), text("\"\\u0000\""));
+    }
+}
+// endregion
@@ -0,0 +1,103 @@
+use ahash::AHashMap;
+use deadpool_postgres::GenericClient;
+use uuid::Uuid;
+
+use super::client::DatabaseError;
+use super::{
+    query_claim_record_tables, query_delete_record_tables, query_lock_record_tables,
+    query_move_records, query_select_record_table, query_update_record_tables,
+};
+
+/// Claim `uuids` for a table in a world, returning the uuids already stored in other
+/// tables of the world, by their table suffix.
+///
+/// Claimed uuids stay locked until the transaction ends, so they can't be written to
+/// another table meanwhile.
+pub(super) async fn claim_uuids(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    uuids: &[Uuid],
+) -> Result<AHashMap<i32, Vec<Uuid>>, DatabaseError> {
+    // Sorted so concurrent claims lock rows in the same order
+    let mut uuids = uuids.to_vec();
+    uuids.sort_unstable();
+
+    client
+        .execute(
+            &query_claim_record_tables(world_name),
+            &[&uuids, &table_suffix],
+        )
+        .await?;
+
+    let rows = client
+        .query(&query_lock_record_tables(world_name), &[&uuids])
+        .await?;
+
+    let mut elsewhere: AHashMap<i32, Vec<Uuid>> = AHashMap::new();
+    for row in rows {
+        let stored_suffix: i32 = row.get("table_suffix");
+        if stored_suffix != table_suffix {
+            elsewhere
+                .entry(stored_suffix)
+                .or_default()
+                .push(row.get("uuid"));
+        }
+    }
+
+    Ok(elsewhere)
+}
+
+/// Returns the suffix of the table storing `uuid` in a world, or [`None`] if no record
+/// has that uuid.
+pub(super) async fn record_table(
+    client: &impl GenericClient,
+    world_name: &str,
+    uuid: &Uuid,
+) -> Result<Option<i32>, DatabaseError> {
+    let row = client
+        .query_opt(&query_select_record_table(world_name), &[uuid])
+        .await?;
+
+    Ok(row.map(|row| row.get("table_suffix")))
+}
+
+/// Move records between two tables of a world without changing them.
+pub(super) async fn move_records(
+    client: &impl GenericClient,
+    world_name: &str,
+    from: i32,
+    to: i32,
+    uuids: &[Uuid],
+) -> Result<(), DatabaseError> {
+    if uuids.is_empty() {
+        return Ok(());
+    }
+
+    client
+        .execute(&query_move_records(world_name, from, to), &[&uuids])
+        .await?;
+
+    client
+        .execute(&query_update_record_tables(world_name), &[&uuids, &to])
+        .await?;
+
+    Ok(())
+}
+
+/// Forget the tables of deleted records.
+pub(super) async fn forget_uuids(
+    client: &impl GenericClient,
+    world_name: &str,
+    uuids: &[Uuid],
+) -> Result<(), DatabaseError> {
+    if uuids.is_empty() {
+        return Ok(());
+    }
+
+    client
+        .execute(&query_delete_record_tables(world_name), &[&uuids])
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::fmt::Display;
+
+use deadpool_postgres::GenericClient;
+use tokio_postgres::Row;
+use tracing::info;
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::versions::is_undefined_table;
+use super::{
+    QUERY_INFER_REGION_SIZE, QUERY_INFER_TABLE_SIZE, QUERY_SELECT_REBUCKET_WORLDS,
+    QUERY_SELECT_SIZING, QUERY_UPSERT_SIZING,
+};
+
+// region: Sizing Struct
+/// Parameters that decide how positions are bucketed into regions and tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizing {
+    pub region_x_size: u16,
+    pub region_y_size: u16,
+    pub region_z_size: u16,
+    pub table_size: u32,
+}
+
+impl Sizing {
+    /// Returns the length of the shortest region side.
+    #[inline]
+    pub fn min_region_size(&self) -> u16 {
+        self.region_x_size
+            .min(self.region_y_size)
+            .min(self.region_z_size)
+    }
+
+    fn from_row(row: &Row) -> Result<Self, tokio_postgres::Error> {
+        let region_x_size: i64 = row.try_get("region_x_size")?;
+        let region_y_size: i64 = row.try_get("region_y_size")?;
+        let region_z_size: i64 = row.try_get("region_z_size")?;
+        let table_size: i64 = row.try_get("table_size")?;
+
+        Ok(Self {
+            region_x_size: region_x_size as u16,
+            region_y_size: region_y_size as u16,
+            region_z_size: region_z_size as u16,
+            table_size: table_size as u32,
+        })
+    }
+}
+
+impl Display for Sizing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{ region = {}x{}x{}, table = {} }}",
+            self.region_x_size, self.region_y_size, self.region_z_size, self.table_size
+        )
+    }
+}
+// endregion
+
+// region: DatabaseClient Methods
+impl DatabaseClient {
+    /// Returns the sizing this client was configured with.
+    pub fn sizing(&self) -> Sizing {
+        Sizing {
+            region_x_size: self.region_x_size(),
+            region_y_size: self.region_y_size(),
+            region_z_size: self.region_z_size(),
+            table_size: self.table_size(),
+        }
+    }
+
+    /// Returns the sizing stored in the database, [`None`] if it isn't initialized yet.
+    pub async fn stored_sizing(&self) -> Result<Option<Sizing>, DatabaseError> {
+        let client = self.pool.get().await?;
+        match stored_sizing(&client).await {
+            Err(error) if is_undefined_table(&error) => Ok(None),
+            result => Ok(result?),
+        }
+    }
+
+    /// Check the configured sizing matches the sizing stored in the database.
+    ///
+    /// On first init the sizing is stored, inferred from any existing navigation rows
+    /// if the database predates stored sizing.
+    pub(super) async fn check_sizing(&self) -> Result<(), DatabaseError> {
+        let client = self.pool.get().await?;
+
+        let rows = client.query(QUERY_SELECT_REBUCKET_WORLDS, &[]).await?;
+        if !rows.is_empty() {
+            return Err(DatabaseError::RebucketInProgress);
+        }
+
+        let configured = self.sizing();
+        let stored = match stored_sizing(&client).await? {
+            Some(sizing) => sizing,
+            None => {
+                let sizing = infer_sizing(&client, configured).await?;
+                store_sizing(&client, &sizing).await?;
+
+                info!("stored database sizing {}", sizing);
+                sizing
+            }
+        };
+
+        if stored != configured {
+            return Err(DatabaseError::SizingMismatch { stored, configured });
+        }
+
+        Ok(())
+    }
+}
+// endregion
+
+// region: Helper Functions
+pub(super) async fn stored_sizing(
+    client: &impl GenericClient,
+) -> Result<Option<Sizing>, tokio_postgres::Error> {
+    let row = client.query_opt(QUERY_SELECT_SIZING, &[]).await?;
+    row.as_ref().map(Sizing::from_row).transpose()
+}
+
+pub(super) async fn store_sizing(
+    client: &impl GenericClient,
+    sizing: &Sizing,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            QUERY_UPSERT_SIZING,
+            &[
+                &i64::from(sizing.region_x_size),
+                &i64::from(sizing.region_y_size),
+                &i64::from(sizing.region_z_size),
+                &i64::from(sizing.table_size),
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn infer_sizing(
+    client: &impl GenericClient,
+    fallback: Sizing,
+) -> Result<Sizing, tokio_postgres::Error> {
+    let mut sizing = fallback;
+
+    if let Some(row) = client.query_opt(QUERY_INFER_REGION_SIZE, &[]).await? {
+        let x: i64 = row.try_get("x")?;
+        let y: i64 = row.try_get("y")?;
+        let z: i64 = row.try_get("z")?;
+
+        sizing.region_x_size = x as u16;
+        sizing.region_y_size = y as u16;
+        sizing.region_z_size = z as u16;
+    }
+
+    if let Some(row) = client.query_opt(QUERY_INFER_TABLE_SIZE, &[]).await? {
+        let size: i64 = row.try_get("size")?;
+        sizing.table_size = size as u32;
+    }
+
+    Ok(sizing)
+}
+// endregion
@@ -0,0 +1,469 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ahash::AHashSet;
+use serde_json::{json, Value};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::sizing::Sizing;
+use super::world_region::{clamp_table_size, WorldRegion};
+use crate::structures::{DeserializeError, Record, Vector3};
+
+/// Largest record accepted when reading a snapshot, so a corrupt length prefix can't
+/// allocate an unbounded buffer
+const MAX_RECORD_LENGTH: usize = 64 * 1024 * 1024;
+
+// region: SnapshotError Enum
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("invalid record #{index}: {error}")]
+    InvalidRecord { index: u64, error: DeserializeError },
+
+    #[error("record #{0} is truncated")]
+    Truncated(u64),
+
+    #[error("record #{index} is {length} bytes long, larger than {MAX_RECORD_LENGTH}")]
+    RecordTooLarge { index: u64, length: usize },
+
+    #[error("record {0} has no position")]
+    MissingPosition(Uuid),
+
+    #[error("invalid navigation metadata: {0}")]
+    InvalidNavigation(String),
+}
+// endregion
+
+// region: Snapshot Records
+/// Write `records` as consecutive size-prefixed flatbuffer `Record`s, returning the
+/// number of records written.
+pub fn write_snapshot(writer: &mut impl Write, records: Vec<Record>) -> io::Result<u64> {
+    let mut written = 0;
+    for record in records {
+        writer.write_all(&record.serialize_size_prefixed())?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Read records one at a time from a snapshot written by [`write_snapshot`].
+pub fn read_snapshot<R: Read>(reader: R) -> SnapshotReader<R> {
+    SnapshotReader {
+        reader,
+        index: 0,
+        done: false,
+    }
+}
+
+/// Iterator over the records in a snapshot, see [`read_snapshot`].
+///
+/// Stops after the first error, as the rest of the snapshot can't be read reliably.
+#[derive(Debug)]
+pub struct SnapshotReader<R> {
+    reader: R,
+    index: u64,
+    done: bool,
+}
+
+impl<R: Read> Iterator for SnapshotReader<R> {
+    type Item = Result<Record, SnapshotError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.read_record().transpose();
+        match result {
+            Some(Ok(_)) => self.index += 1,
+            _ => self.done = true,
+        }
+
+        result
+    }
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Read the next record, returning [`None`] if the snapshot ended cleanly instead.
+    fn read_record(&mut self) -> Result<Option<Record>, SnapshotError> {
+        let index = self.index;
+
+        let mut prefix = [0; 4];
+        if !read_prefix(&mut self.reader, &mut prefix, index)? {
+            return Ok(None);
+        }
+
+        let length = u32::from_le_bytes(prefix) as usize;
+        if length > MAX_RECORD_LENGTH {
+            return Err(SnapshotError::RecordTooLarge { index, length });
+        }
+
+        let mut buf = vec![0; length + 4];
+        buf[..4].copy_from_slice(&prefix);
+        self.reader
+            .read_exact(&mut buf[4..])
+            .map_err(|error| match error.kind() {
+                ErrorKind::UnexpectedEof => SnapshotError::Truncated(index),
+                _ => error.into(),
+            })?;
+
+        let record = Record::deserialize_size_prefixed(&buf)
+            .map_err(|error| SnapshotError::InvalidRecord { index, error })?;
+
+        Ok(Some(record))
+    }
+}
+
+/// Fill `prefix` from `reader`, returning `false` if the snapshot ended cleanly instead.
+fn read_prefix(
+    reader: &mut impl Read,
+    prefix: &mut [u8; 4],
+    index: u64,
+) -> Result<bool, SnapshotError> {
+    let mut filled = 0;
+    while filled < prefix.len() {
+        match reader.read(&mut prefix[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(SnapshotError::Truncated(index)),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Prepare a snapshot record to be imported into `world_name`.
+///
+/// Imported records are new, so start again at version 1. They are bucketed by the store
+/// they are written to, under its own sizing.
+pub fn import_record(record: Record, world_name: &str) -> Result<Record, SnapshotError> {
+    if record.position.is_none() {
+        return Err(SnapshotError::MissingPosition(record.uuid));
+    }
+
+    Ok(Record {
+        world_name: world_name.into(),
+        version: None,
+        ..record
+    })
+}
+// endregion
+
+// region: SnapshotNavigation Struct
+/// Navigation metadata describing how a world was bucketed when it was exported.
+///
+/// Stored as JSON next to the snapshot, see [`navigation_path`]. Imports always
+/// rebucket, so this is only informational.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotNavigation {
+    pub world_name: String,
+    pub sizing: Sizing,
+
+    /// Number of tables holding the world's records
+    pub table_count: u64,
+
+    /// Every region holding records, ordered by their coordinates
+    pub regions: Vec<SnapshotRegion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotRegion {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub record_count: u64,
+}
+
+impl SnapshotNavigation {
+    /// Describe an empty world, for records to be added to as they are read.
+    pub fn new(world_name: &str, sizing: Sizing) -> Self {
+        Self {
+            world_name: world_name.into(),
+            sizing,
+            table_count: 0,
+            regions: vec![],
+        }
+    }
+
+    /// Count `records` into the regions they are bucketed into.
+    pub fn add_records(&mut self, records: &[Record]) {
+        let sizing = &self.sizing;
+        for position in records.iter().filter_map(|record| record.position.as_ref()) {
+            let (x, y, z) = snapshot_region(&self.world_name, position, sizing);
+            match self
+                .regions
+                .binary_search_by_key(&(x, y, z), |region| (region.x, region.y, region.z))
+            {
+                Ok(index) => self.regions[index].record_count += 1,
+                Err(index) => self.regions.insert(
+                    index,
+                    SnapshotRegion {
+                        x,
+                        y,
+                        z,
+                        record_count: 1,
+                    },
+                ),
+            }
+        }
+
+        // Tables are bucketed by region coordinates, so can be counted from the regions
+        let table_size = i64::from(sizing.table_size);
+        let tables = self
+            .regions
+            .iter()
+            .map(|region| {
+                (
+                    clamp_table_size(region.x, table_size),
+                    clamp_table_size(region.y, table_size),
+                    clamp_table_size(region.z, table_size),
+                )
+            })
+            .collect::<AHashSet<_>>();
+
+        self.table_count = tables.len() as u64;
+    }
+
+    /// Returns the number of records in every region.
+    pub fn record_count(&self) -> u64 {
+        self.regions.iter().map(|region| region.record_count).sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let regions = self
+            .regions
+            .iter()
+            .map(|region| {
+                json!({
+                    "x": region.x,
+                    "y": region.y,
+                    "z": region.z,
+                    "record_count": region.record_count,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "world_name": self.world_name,
+            "sizing": {
+                "region_x_size": self.sizing.region_x_size,
+                "region_y_size": self.sizing.region_y_size,
+                "region_z_size": self.sizing.region_z_size,
+                "table_size": self.sizing.table_size,
+            },
+            "table_count": self.table_count,
+            "record_count": self.record_count(),
+            "regions": regions,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, SnapshotError> {
+        let field = |value: &Value, name: &str| -> Result<u64, SnapshotError> {
+            value[name]
+                .as_u64()
+                .ok_or_else(|| SnapshotError::InvalidNavigation(format!("missing {}", name)))
+        };
+
+        let int_field = |value: &Value, name: &str| -> Result<i64, SnapshotError> {
+            value[name]
+                .as_i64()
+                .ok_or_else(|| SnapshotError::InvalidNavigation(format!("missing {}", name)))
+        };
+
+        let world_name = value["world_name"]
+            .as_str()
+            .ok_or_else(|| SnapshotError::InvalidNavigation("missing world_name".into()))?;
+
+        let sizing = &value["sizing"];
+        let region_size = |name: &str| -> Result<u16, SnapshotError> {
+            match u16::try_from(field(sizing, name)?) {
+                Ok(size) if size > 0 => Ok(size),
+                _ => Err(SnapshotError::InvalidNavigation(format!(
+                    "invalid {}",
+                    name
+                ))),
+            }
+        };
+
+        let sizing = Sizing {
+            region_x_size: region_size("region_x_size")?,
+            region_y_size: region_size("region_y_size")?,
+            region_z_size: region_size("region_z_size")?,
+            table_size: u32::try_from(field(sizing, "table_size")?)
+                .map_err(|_| SnapshotError::InvalidNavigation("invalid table_size".into()))?,
+        };
+
+        let regions = value["regions"]
+            .as_array()
+            .ok_or_else(|| SnapshotError::InvalidNavigation("missing regions".into()))?
+            .iter()
+            .map(|region| {
+                Ok(SnapshotRegion {
+                    x: int_field(region, "x")?,
+                    y: int_field(region, "y")?,
+                    z: int_field(region, "z")?,
+                    record_count: field(region, "record_count")?,
+                })
+            })
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+        Ok(Self {
+            world_name: world_name.into(),
+            sizing,
+            table_count: field(value, "table_count")?,
+            regions,
+        })
+    }
+}
+
+/// Returns the region coordinates `position` is bucketed into under `sizing`.
+fn snapshot_region(world_name: &str, position: &Vector3, sizing: &Sizing) -> (i64, i64, i64) {
+    let region = WorldRegion::new(
+        world_name,
+        position,
+        sizing.region_x_size,
+        sizing.region_y_size,
+        sizing.region_z_size,
+    );
+
+    (*region.x(), *region.y(), *region.z())
+}
+
+/// Returns the path navigation metadata is stored at for the snapshot at `path`.
+pub fn navigation_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".navigation.json");
+
+    path.with_file_name(file_name)
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    const SIZING: Sizing = Sizing {
+        region_x_size: 16,
+        region_y_size: 256,
+        region_z_size: 16,
+        table_size: 1024,
+    };
+
+    fn record(x: f64, z: f64) -> Record {
+        Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(x, 10.0, z)),
+            world_name: "world".into(),
+            data: Some("data".into()),
+            flex: Some(Bytes::from_static(&[0, 1])),
+            version: Some(4),
+            owner: Some("game".into()),
+            tags: vec!["chest".into()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let records = vec![record(1.0, 1.0), record(-20.0, 5.0), record(2000.0, 1.0)];
+
+        let mut buf = vec![];
+        assert_eq!(write_snapshot(&mut buf, records.clone()).unwrap(), 3);
+
+        let read = read_snapshot(buf.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(read.len(), 3);
+        for (read, record) in read.iter().zip(&records) {
+            assert_eq!(read.uuid, record.uuid);
+            assert_eq!(read.position, record.position);
+            assert_eq!(read.version, record.version);
+            assert_eq!(read.owner, record.owner);
+            assert_eq!(read.tags, record.tags);
+        }
+
+        assert!(read_snapshot([].as_slice()).next().is_none());
+
+        // Records before the error are still read, but nothing after it
+        let mut truncated = read_snapshot(&buf[..buf.len() - 3]);
+        assert!(truncated.by_ref().take(2).all(|record| record.is_ok()));
+        assert!(matches!(
+            truncated.next(),
+            Some(Err(SnapshotError::Truncated(2)))
+        ));
+        assert!(truncated.next().is_none());
+
+        let truncated = read_snapshot(&buf[..2]).next();
+        assert!(matches!(truncated, Some(Err(SnapshotError::Truncated(0)))));
+
+        let too_large = read_snapshot([0xff; 8].as_slice()).next();
+        assert!(matches!(
+            too_large,
+            Some(Err(SnapshotError::RecordTooLarge { index: 0, .. }))
+        ));
+    }
+
+    #[test]
+    fn import() {
+        let imported = import_record(record(1.0, 1.0), "copy").unwrap();
+        assert_eq!(imported.world_name, "copy");
+        assert!(imported.version.is_none());
+
+        let unpositioned = Record {
+            position: None,
+            ..record(0.0, 0.0)
+        };
+
+        let result = import_record(unpositioned, "copy");
+        assert!(matches!(result, Err(SnapshotError::MissingPosition(_))));
+    }
+
+    #[test]
+    fn navigation() {
+        let records = vec![record(1.0, 1.0), record(5.0, 2.0), record(2000.0, 1.0)];
+        let mut navigation = SnapshotNavigation::new("world", SIZING);
+        navigation.add_records(&records);
+
+        assert_eq!(navigation.table_count, 2);
+        assert_eq!(navigation.record_count(), 3);
+        assert_eq!(
+            navigation.regions[0],
+            SnapshotRegion {
+                x: 0,
+                y: 0,
+                z: 0,
+                record_count: 2
+            }
+        );
+
+        // Adding records in batches describes the same world
+        let mut added = SnapshotNavigation::new("world", SIZING);
+        added.add_records(&records[2..]);
+        added.add_records(&records[..2]);
+        assert_eq!(added, navigation);
+
+        let parsed = SnapshotNavigation::from_json(&navigation.to_json()).unwrap();
+        assert_eq!(parsed, navigation);
+
+        let invalid = json!({ "world_name": "world", "sizing": {} });
+        assert!(SnapshotNavigation::from_json(&invalid).is_err());
+    }
+
+    #[test]
+    fn navigation_paths() {
+        let path = navigation_path(Path::new("backups/world.wqlsnap"));
+        assert_eq!(path, Path::new("backups/world.wqlsnap.navigation.json"));
+    }
+}
+// endregion
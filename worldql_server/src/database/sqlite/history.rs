@@ -0,0 +1,151 @@
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+#[cfg(any(feature = "http", test))]
+use {
+    bytes::Bytes,
+    rusqlite::{types::Type, Row},
+    uuid::Uuid,
+};
+
+use super::queries::*;
+#[cfg(any(feature = "http", test))]
+use super::{from_timestamp_micros, split_tags, split_writers};
+use super::{join_tags, timestamp_micros};
+use crate::database::store::HistoryEntry;
+use crate::structures::{Record, Vector3};
+
+/// Append entries to the record history.
+pub(super) fn append_history(
+    connection: &Connection,
+    entries: &[HistoryEntry],
+) -> Result<(), rusqlite::Error> {
+    let mut statement = connection.prepare_cached(QUERY_INSERT_HISTORY)?;
+    for entry in entries {
+        let mut values = vec![
+            Value::Integer(timestamp_micros(&entry.changed_at)),
+            Value::Text(entry.world_name.clone()),
+            Value::Blob(entry.uuid.as_bytes().to_vec()),
+            Value::Text(entry.operation.as_str().into()),
+            entry
+                .peer
+                .map_or(Value::Null, |peer| Value::Blob(peer.as_bytes().to_vec())),
+        ];
+
+        values.extend(history_values(entry.old.as_ref()));
+        values.extend(history_values(entry.new.as_ref()));
+        statement.execute(params_from_iter(values))?;
+    }
+
+    Ok(())
+}
+
+/// Old or new record columns of a history entry, all null if there is no record.
+pub(super) fn history_values(record: Option<&Record>) -> Vec<Value> {
+    let record = match record {
+        Some(record) => record,
+        None => return vec![Value::Null; 10],
+    };
+
+    let position = |axis: fn(&Vector3) -> &f64| {
+        record
+            .position
+            .as_ref()
+            .map_or(Value::Null, |position| Value::Real(*axis(position)))
+    };
+
+    vec![
+        position(Vector3::x),
+        position(Vector3::y),
+        position(Vector3::z),
+        record.data.clone().map_or(Value::Null, Value::Text),
+        record
+            .flex
+            .as_ref()
+            .map_or(Value::Null, |flex| Value::Blob(flex.to_vec())),
+        record
+            .expires_at
+            .as_ref()
+            .map_or(Value::Null, |expires_at| {
+                Value::Integer(timestamp_micros(expires_at))
+            }),
+        Value::Integer(record.version.unwrap_or(1) as i64),
+        record.owner.clone().map_or(Value::Null, Value::Text),
+        record
+            .writers
+            .as_ref()
+            .map_or(Value::Null, |writers| Value::Text(writers.join(","))),
+        join_tags(&record.tags).map_or(Value::Null, Value::Text),
+    ]
+}
+
+#[cfg(any(feature = "http", test))]
+pub(super) fn history_entry_from_row(
+    row: &Row,
+    world_name: &str,
+    uuid: Uuid,
+) -> Result<HistoryEntry, rusqlite::Error> {
+    let changed_at: i64 = row.get("changed_at")?;
+    let changed_at = from_timestamp_micros(changed_at)
+        .ok_or(rusqlite::Error::IntegralValueOutOfRange(1, changed_at))?;
+
+    let peer = match row.get::<_, Option<Vec<u8>>>("peer")? {
+        None => None,
+        Some(peer) => Some(Uuid::from_slice(&peer).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(5, Type::Blob, error.into())
+        })?),
+    };
+
+    let old = history_record(row, "old_", world_name, uuid)?;
+    let new = history_record(row, "new_", world_name, uuid)?;
+
+    Ok(HistoryEntry::new(changed_at, peer, old, new))
+}
+
+/// Read the old or new record from a history row, depending on `prefix`.
+#[cfg(any(feature = "http", test))]
+fn history_record(
+    row: &Row,
+    prefix: &str,
+    world_name: &str,
+    uuid: Uuid,
+) -> Result<Option<Record>, rusqlite::Error> {
+    let column = |name: &str| format!("{}{}", prefix, name);
+
+    // Version is never null for stored records
+    let version = match row.get::<_, Option<i64>>(column("version").as_str())? {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+
+    let x: Option<f64> = row.get(column("x").as_str())?;
+    let y: Option<f64> = row.get(column("y").as_str())?;
+    let z: Option<f64> = row.get(column("z").as_str())?;
+    let position = match (x, y, z) {
+        (Some(x), Some(y), Some(z)) => Some(Vector3::new(x, y, z)),
+        _ => None,
+    };
+
+    let expires_at = match row.get::<_, Option<i64>>(column("expires_at").as_str())? {
+        None => None,
+        Some(micros) => Some(
+            from_timestamp_micros(micros)
+                .ok_or(rusqlite::Error::IntegralValueOutOfRange(0, micros))?,
+        ),
+    };
+
+    let flex: Option<Vec<u8>> = row.get(column("flex").as_str())?;
+    let record = Record {
+        uuid,
+        position,
+        world_name: world_name.into(),
+        data: row.get(column("data").as_str())?,
+        flex: flex.map(Bytes::from),
+        expires_at,
+        version: Some(version as u64),
+        owner: row.get(column("owner").as_str())?,
+        writers: split_writers(row.get(column("writers").as_str())?),
+        tags: split_tags(row.get(column("tags").as_str())?)?,
+    };
+
+    Ok(Some(record))
+}
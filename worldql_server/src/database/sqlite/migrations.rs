@@ -0,0 +1,207 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::queries::*;
+
+/// Returns the queries adding a column to a world table, taking its unquoted name.
+type AddColumn = fn(&str) -> String;
+
+/// Columns added to world tables after they were first released.
+const ADDED_COLUMNS: &[(&str, AddColumn)] = &[
+    ("expires_at", query_add_expiry),
+    ("version", query_add_version),
+    ("owner", query_add_owner),
+    ("tags", query_add_tags),
+];
+
+/// Add any missing columns to world and history tables created by older versions.
+pub(super) fn migrate_columns(connection: &Connection) -> Result<(), rusqlite::Error> {
+    for (column, query_add_column) in ADDED_COLUMNS {
+        let tables = connection
+            .prepare(&query_select_tables_without(column))?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        for name in tables {
+            connection.execute_batch(&query_add_column(&name))?;
+        }
+    }
+
+    let history_without_tags: bool =
+        connection.query_row(QUERY_HISTORY_WITHOUT_TAGS, [], |row| row.get(0))?;
+    if history_without_tags {
+        connection.execute_batch(ALTER_HISTORY_ADD_TAGS)?;
+    }
+
+    Ok(())
+}
+
+/// Fill `navigation_records` for world tables created by older versions, which only
+/// had unique uuids per table.
+///
+/// Only the most recently modified record is kept for uuids stored in more than one
+/// table of a world.
+pub(super) fn migrate_records(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+    let transaction = connection.transaction()?;
+    let tables = transaction
+        .prepare(QUERY_SELECT_TABLES_WITHOUT_RECORDS)?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(String, i64)>, _>>()?;
+
+    for (world_name, table_suffix) in tables {
+        let uuids = transaction
+            .prepare(&query_select_uuids(&world_name, table_suffix))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(Vec<u8>, i64)>, _>>()?;
+
+        for (uuid, last_modified) in uuids {
+            let stored_suffix: Option<i64> = transaction
+                .query_row(
+                    QUERY_LOOKUP_RECORD_TABLE,
+                    params![world_name, uuid],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            // Keep whichever duplicate was modified last
+            if let Some(stored_suffix) = stored_suffix {
+                let stored_modified: i64 = transaction.query_row(
+                    &query_select_last_modified(&world_name, stored_suffix),
+                    params![uuid],
+                    |row| row.get(0),
+                )?;
+
+                let (kept, removed) = match last_modified > stored_modified {
+                    true => (table_suffix, stored_suffix),
+                    false => (stored_suffix, table_suffix),
+                };
+
+                transaction.execute(
+                    &query_delete_record_by_uuid(&world_name, removed),
+                    params![uuid],
+                )?;
+
+                if kept == stored_suffix {
+                    continue;
+                }
+            }
+
+            transaction.execute(
+                QUERY_INSERT_RECORD_TABLE,
+                params![world_name, uuid, table_suffix],
+            )?;
+        }
+
+        let name = format!("w_{}_t_{}", world_name, table_suffix);
+        transaction.execute_batch(&query_create_record_triggers(&name, table_suffix))?;
+    }
+
+    transaction.commit()
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::sqlite::versions::record_table;
+
+    #[test]
+    fn column_migration() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE \"w_world_t_1\" (uuid blob NOT NULL UNIQUE, data text);
+                CREATE TABLE \"w_world_t_2\" (uuid blob NOT NULL UNIQUE, expires_at integer);
+                CREATE TABLE history (id integer PRIMARY KEY, old_writers text, new_writers text);
+                ",
+            )
+            .unwrap();
+
+        migrate_columns(&connection).unwrap();
+        migrate_columns(&connection).unwrap();
+
+        for (column, _) in ADDED_COLUMNS {
+            let missing = connection
+                .prepare(&query_select_tables_without(column))
+                .unwrap()
+                .query_map([], |row| row.get::<_, String>(0))
+                .unwrap()
+                .count();
+
+            assert_eq!(missing, 0);
+        }
+
+        let history_without_tags: bool = connection
+            .query_row(QUERY_HISTORY_WITHOUT_TAGS, [], |row| row.get(0))
+            .unwrap();
+        assert!(!history_without_tags);
+    }
+
+    #[test]
+    fn record_migration() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(CREATE_NAVIGATION).unwrap();
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        for (table_suffix, last_modified) in [(1, 10), (2, 20)] {
+            connection
+                .execute(
+                    QUERY_INSERT_TABLE_SUFFIX,
+                    params![0, 16, 0, 16, 0, 16, "world"],
+                )
+                .unwrap();
+
+            // Tables created before uuids were unique per world
+            let name = format!("w_world_t_{}", table_suffix);
+            connection
+                .execute_batch(&query_create_world("world", table_suffix))
+                .unwrap();
+            connection
+                .execute_batch(&format!(
+                    "
+                    DROP TRIGGER \"{0}_records_insert\";
+                    DROP TRIGGER \"{0}_records_delete\";
+                    ",
+                    name
+                ))
+                .unwrap();
+
+            for uuid in [a, b].iter().take(table_suffix as usize) {
+                connection
+                    .execute(
+                        &format!(
+                            "INSERT INTO \"{}\" (last_modified, region_id, uuid) VALUES (?1, 1, ?2)",
+                            name
+                        ),
+                        params![last_modified, &uuid.as_bytes()[..]],
+                    )
+                    .unwrap();
+            }
+        }
+
+        migrate_records(&mut connection).unwrap();
+        migrate_records(&mut connection).unwrap();
+
+        // Only the most recently modified duplicate is kept
+        for (uuid, table_suffix) in [(a, 2), (b, 2)] {
+            assert_eq!(
+                record_table(&connection, "world", &uuid).unwrap(),
+                Some(table_suffix)
+            );
+        }
+
+        let count = |table_suffix| -> i64 {
+            connection
+                .query_row(&query_count_records("world", table_suffix), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        assert_eq!(count(1), 0);
+        assert_eq!(count(2), 2);
+    }
+}
+// endregion
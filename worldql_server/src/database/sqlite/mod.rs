@@ -0,0 +1,1741 @@
+mod history;
+mod migrations;
+mod navigation;
+mod queries;
+mod reads;
+mod versions;
+mod worlds;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::prelude::*;
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension, Row};
+use uuid::Uuid;
+
+use self::history::append_history;
+#[cfg(any(feature = "http", test))]
+use self::history::history_entry_from_row;
+use self::migrations::{migrate_columns, migrate_records};
+use self::navigation::{find_ids, lookup_ids};
+use self::queries::*;
+use self::reads::{read_tables, records_in_area, records_with_tag, TableRead};
+use self::versions::{delete_record_if_version, record_table, stored_record};
+use super::nearest::find_nearest;
+use super::sizing::Sizing;
+use super::store::{record_position, HistoryEntry, RecordStore, WorldStats, Writer, Written};
+use super::versions::RecordAccess;
+use super::world_region::WorldRegion;
+use super::{DataFilter, DatabaseError};
+use crate::structures::{Area, Record, Vector3};
+use crate::utils::sanitize_world_name;
+
+// region: SqliteStore Struct
+/// Embedded SQLite [`RecordStore`], for single node deployments.
+///
+/// Uses the same layout as PostgreSQL, with a table per `(world, table_suffix)` and
+/// `navigation_tables`/`navigation_regions` tables mapping positions to them. The
+/// `navigation_records` table maps record uuids to the table storing them.
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    sizing: Sizing,
+}
+
+impl SqliteStore {
+    /// Open or create a SQLite database at `path`.
+    ///
+    /// Fails if the database was created with a different sizing.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        region_x_size: u16,
+        region_y_size: u16,
+        region_z_size: u16,
+        table_size: u32,
+    ) -> Result<Self, DatabaseError> {
+        let configured = Sizing {
+            region_x_size,
+            region_y_size,
+            region_z_size,
+            table_size,
+        };
+
+        let mut connection = Connection::open(path)?;
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        connection.execute_batch(CREATE_NAVIGATION)?;
+        migrate_columns(&connection)?;
+        migrate_records(&mut connection)?;
+
+        match select_sizing(&connection)? {
+            None => {
+                connection.execute(
+                    QUERY_INSERT_SIZING,
+                    params![region_x_size, region_y_size, region_z_size, table_size],
+                )?;
+            }
+
+            Some(stored) if stored != configured => {
+                return Err(DatabaseError::SizingMismatch { stored, configured });
+            }
+
+            Some(_) => (),
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            sizing: configured,
+        })
+    }
+
+    /// Open an existing SQLite database at `path` without changing it.
+    ///
+    /// Nothing is created or migrated and the sizing isn't checked, so it can be inspected
+    /// while the server is running. See [`SqliteStore::stored_sizing`].
+    pub fn open_read_only<P: AsRef<Path>>(
+        path: P,
+        region_x_size: u16,
+        region_y_size: u16,
+        region_z_size: u16,
+        table_size: u32,
+    ) -> Result<Self, DatabaseError> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            sizing: Sizing {
+                region_x_size,
+                region_y_size,
+                region_z_size,
+                table_size,
+            },
+        })
+    }
+
+    /// Returns the sizing stored in the database, [`None`] if it isn't initialized yet.
+    pub async fn stored_sizing(&self) -> Result<Option<Sizing>, DatabaseError> {
+        self.with_connection(|connection| {
+            let initialized: bool =
+                connection.query_row(QUERY_SIZING_EXISTS, [], |row| row.get(0))?;
+            match initialized {
+                true => Ok(select_sizing(connection)?),
+                false => Ok(None),
+            }
+        })
+        .await
+    }
+
+    #[inline]
+    fn world_region(&self, world_name: &str, vector: &Vector3) -> WorldRegion {
+        WorldRegion::new(
+            world_name,
+            vector,
+            self.sizing.region_x_size,
+            self.sizing.region_y_size,
+            self.sizing.region_z_size,
+        )
+    }
+
+    /// Run a closure against the connection on the blocking thread pool.
+    async fn with_connection<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap();
+            f(&mut connection)
+        })
+        .await
+        .expect("sqlite task panicked")
+    }
+
+    /// Pair each [`Record`] with its [`WorldRegion`] and position, collecting records
+    /// without a position and invalid world names.
+    fn record_regions(
+        &self,
+        records: Vec<Record>,
+        errors: &mut Vec<DatabaseError>,
+    ) -> Vec<(WorldRegion, Vector3, Record)> {
+        let mut regions = Vec::with_capacity(records.len());
+        for record in records {
+            let position = match record_position(&record) {
+                Ok(position) => position,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            match sanitize_world_name(&record.world_name) {
+                Ok(world_name) => {
+                    let region = self.world_region(&world_name, &position);
+                    regions.push((region, position, record));
+                }
+
+                Err(error) => errors.push(error.into()),
+            }
+        }
+
+        regions
+    }
+
+    /// Insert or replace records, rolling back every write if any record fails and
+    /// `atomic` is set.
+    async fn write_records(&self, records: Vec<Record>, writer: &Writer, atomic: bool) -> Written {
+        let mut errors = vec![];
+        let regions = self.record_regions(records, &mut errors);
+        if regions.is_empty() || (atomic && !errors.is_empty()) {
+            return errors.into();
+        }
+
+        let sizing = self.sizing;
+        let writer = writer.clone();
+        let now = Utc::now().naive_utc();
+        let last_modified = timestamp_micros(&now);
+
+        let result = self
+            .with_connection(move |connection| {
+                let mut errors = vec![];
+                let mut entries = vec![];
+                let transaction = connection.transaction()?;
+
+                for (region, position, record) in regions {
+                    let insert = || -> Result<HistoryEntry, DatabaseError> {
+                        let (table_suffix, region_id) = lookup_ids(&transaction, &region, &sizing)?;
+
+                        // Checked within the transaction, so the record can't change before
+                        // it's written. Uuids are unique per world, so the record may be
+                        // stored in another table
+                        let stored_suffix =
+                            record_table(&transaction, region.world_name(), &record.uuid)?;
+                        let old = stored_record(
+                            &transaction,
+                            region.world_name(),
+                            stored_suffix,
+                            &record.uuid,
+                        )?;
+
+                        let access = old.as_ref().map(RecordAccess::from);
+
+                        if let Some(access) = &access {
+                            access.check(record.uuid, &writer, record.writers.is_some())?;
+                        }
+
+                        if let Some(version) = record.version {
+                            let stored = access.as_ref().map_or(0, |access| access.version);
+                            if stored != version {
+                                return Err(DatabaseError::VersionConflict {
+                                    uuid: record.uuid,
+                                    version: stored,
+                                });
+                            }
+                        }
+
+                        // Moved as stored, so the upsert below updates it
+                        if let Some(from) = stored_suffix.filter(|from| *from != table_suffix) {
+                            let world_name = region.world_name();
+                            let uuid = &record.uuid.as_bytes()[..];
+                            transaction.execute(
+                                &query_copy_record(world_name, from, table_suffix),
+                                params![uuid],
+                            )?;
+                            transaction.execute(
+                                &query_delete_record_by_uuid(world_name, from),
+                                params![uuid],
+                            )?;
+                        }
+
+                        let stored_owner = access.as_ref().map(|access| access.owner.as_deref());
+                        let owner = writer.owner_of(&record, stored_owner);
+                        let writers = record.writers.as_ref().map(|writers| writers.join(","));
+                        let tags = join_tags(&record.tags);
+
+                        let new = transaction.query_row(
+                            &query_insert_record(region.world_name(), table_suffix),
+                            params![
+                                last_modified,
+                                region_id,
+                                position.x(),
+                                position.y(),
+                                position.z(),
+                                &record.uuid.as_bytes()[..],
+                                record.data,
+                                record.flex.as_deref(),
+                                record.expires_at.as_ref().map(timestamp_micros),
+                                owner,
+                                writers,
+                                tags,
+                            ],
+                            |row| record_from_row(row, region.world_name()),
+                        )?;
+
+                        Ok(HistoryEntry::new(now, writer.peer, old, Some(new)))
+                    };
+
+                    match insert() {
+                        Ok(entry) => entries.push(entry),
+                        Err(error) => errors.push(error),
+                    }
+                }
+
+                if atomic && !errors.is_empty() {
+                    transaction.rollback()?;
+                    return Ok(errors.into());
+                }
+
+                append_history(&transaction, &entries)?;
+                transaction.commit()?;
+                Ok::<_, DatabaseError>(Written::new(entries, errors))
+            })
+            .await;
+
+        match result {
+            Ok(mut written) => {
+                errors.append(&mut written.errors);
+                written.errors = errors;
+                written
+            }
+
+            Err(error) => {
+                errors.push(error);
+                errors.into()
+            }
+        }
+    }
+
+    /// Delete records, rolling back every delete if any record fails and `atomic` is
+    /// set.
+    async fn remove_records(&self, records: Vec<Record>, writer: &Writer, atomic: bool) -> Written {
+        let mut errors = vec![];
+        let regions = self.record_regions(records, &mut errors);
+        if regions.is_empty() || (atomic && !errors.is_empty()) {
+            return errors.into();
+        }
+
+        let writer = writer.clone();
+        let now = Utc::now().naive_utc();
+
+        let result = self
+            .with_connection(move |connection| {
+                let mut errors = vec![];
+                let mut entries = vec![];
+                let transaction = connection.transaction()?;
+
+                for (region, _, record) in regions {
+                    let delete = || -> Result<Option<Record>, DatabaseError> {
+                        if let Some(version) = record.version {
+                            return delete_record_if_version(
+                                &transaction,
+                                &region,
+                                &record,
+                                version,
+                                &writer,
+                            );
+                        }
+
+                        let (table_suffix, region_id) = match find_ids(&transaction, &region)? {
+                            Some(ids) => ids,
+                            None => return Ok(None),
+                        };
+
+                        let stored = stored_record(
+                            &transaction,
+                            region.world_name(),
+                            Some(table_suffix),
+                            &record.uuid,
+                        )?;
+
+                        if let Some(stored) = &stored {
+                            RecordAccess::from(stored).check(record.uuid, &writer, false)?;
+                        }
+
+                        let deleted = transaction
+                            .query_row(
+                                &query_delete_record(region.world_name(), table_suffix),
+                                params![region_id, &record.uuid.as_bytes()[..]],
+                                |row| record_from_row(row, region.world_name()),
+                            )
+                            .optional()?;
+
+                        Ok(deleted)
+                    };
+
+                    match delete() {
+                        Ok(Some(old)) => {
+                            entries.push(HistoryEntry::new(now, writer.peer, Some(old), None))
+                        }
+
+                        Ok(None) => (),
+                        Err(error) => errors.push(error),
+                    }
+                }
+
+                if atomic && !errors.is_empty() {
+                    transaction.rollback()?;
+                    return Ok(errors.into());
+                }
+
+                append_history(&transaction, &entries)?;
+                transaction.commit()?;
+                Ok::<_, DatabaseError>(Written::new(entries, errors))
+            })
+            .await;
+
+        match result {
+            Ok(mut written) => {
+                errors.append(&mut written.errors);
+                written.errors = errors;
+                written
+            }
+
+            Err(error) => {
+                errors.push(error);
+                errors.into()
+            }
+        }
+    }
+}
+// endregion
+
+// region: RecordStore Impl
+#[async_trait]
+impl RecordStore for SqliteStore {
+    async fn insert_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.write_records(records, writer, false).await
+    }
+
+    async fn insert_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.write_records(records, writer, true).await
+    }
+
+    async fn get_records_in_region(
+        &self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let region = self.world_region(&world_name, &point_inside_region);
+        let after = after.as_ref().map_or(i64::MIN, timestamp_micros);
+        let now = timestamp_micros(&Utc::now().naive_utc());
+        let filter = filter.clone();
+
+        self.with_connection(move |connection| {
+            let (table_suffix, region_id) = match find_ids(connection, &region)? {
+                Some(ids) => ids,
+                None => return Ok(vec![]),
+            };
+
+            let mut values = vec![
+                Value::Integer(region_id),
+                Value::Integer(after),
+                Value::Integer(now),
+            ];
+
+            let query = query_select_records(&world_name, table_suffix)
+                + &filter.sqlite_sql(values.len() + 1);
+            values.extend(filter.sqlite_params());
+
+            let mut statement = connection.prepare(&query)?;
+            let records = statement
+                .query_map(params_from_iter(values), |row| {
+                    record_from_row(row, &world_name)
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(records)
+        })
+        .await
+    }
+
+    async fn get_records_in_area(
+        &self,
+        world_name: &str,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let bounds = self.bounds(&area);
+        let read = TableRead::new(after, filter);
+
+        self.with_connection(move |connection| {
+            let mut records = vec![];
+            for table_suffix in read_tables(connection, &world_name, Some(bounds))? {
+                let table_records =
+                    records_in_area(connection, &world_name, table_suffix, &area, &read)?;
+
+                records.extend(table_records);
+            }
+
+            Ok(records)
+        })
+        .await
+    }
+
+    async fn get_nearest_records(
+        &self,
+        world_name: &str,
+        point: Vector3,
+        count: usize,
+        max_distance: f64,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let start_radius = f64::from(self.sizing.min_region_size());
+        find_nearest(
+            self,
+            world_name,
+            point,
+            count,
+            max_distance,
+            after,
+            filter,
+            start_radius,
+        )
+        .await
+    }
+
+    async fn get_records_with_tag(
+        &self,
+        world_name: &str,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let tag = tag.to_owned();
+        let bounds = area.as_ref().map(|area| self.bounds(area));
+        let read = TableRead::new(after, filter);
+
+        self.with_connection(move |connection| {
+            let mut records = vec![];
+            for table_suffix in read_tables(connection, &world_name, bounds)? {
+                let table_records = records_with_tag(
+                    connection,
+                    &world_name,
+                    table_suffix,
+                    &tag,
+                    area.as_ref(),
+                    &read,
+                )?;
+
+                records.extend(table_records);
+            }
+
+            Ok(records)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn get_read_tables(
+        &self,
+        world_name: &str,
+        area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        SqliteStore::get_read_tables(self, world_name, area).await
+    }
+
+    #[inline]
+    async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        SqliteStore::get_table_records_in_area(self, world_name, table_suffix, area, after, filter)
+            .await
+    }
+
+    #[inline]
+    async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        SqliteStore::get_table_records_with_tag(
+            self,
+            world_name,
+            table_suffix,
+            tag,
+            area,
+            after,
+            filter,
+        )
+        .await
+    }
+
+    async fn delete_records(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.remove_records(records, writer, false).await
+    }
+
+    async fn delete_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written {
+        self.remove_records(records, writer, true).await
+    }
+
+    async fn delete_expired_records(
+        &self,
+        now: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let now = timestamp_micros(&now);
+        self.with_connection(move |connection| {
+            let tables = connection
+                .prepare(QUERY_SELECT_ALL_TABLES)?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<(String, i64)>, _>>()?;
+
+            let mut records = vec![];
+            for (world_name, table_suffix) in tables {
+                let remaining = (limit - records.len()) as i64;
+                if remaining == 0 {
+                    break;
+                }
+
+                let mut statement =
+                    connection.prepare(&query_delete_expired_records(&world_name, table_suffix))?;
+
+                let rows = statement.query_map(params![now, remaining], |row| {
+                    record_from_row(row, &world_name)
+                })?;
+
+                for record in rows {
+                    records.push(record?);
+                }
+            }
+
+            // Expired records weren't deleted by any peer
+            let changed_at = Utc::now().naive_utc();
+            let entries = records
+                .iter()
+                .map(|record| HistoryEntry::new(changed_at, None, Some(record.clone()), None))
+                .collect::<Vec<_>>();
+
+            append_history(connection, &entries)?;
+            Ok(records)
+        })
+        .await
+    }
+
+    #[cfg(any(feature = "http", test))]
+    async fn get_record_history(
+        &self,
+        world_name: &str,
+        uuid: Uuid,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let limit = limit as i64;
+
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(QUERY_SELECT_RECORD_HISTORY)?;
+            let entries = statement
+                .query_map(params![world_name, &uuid.as_bytes()[..], limit], |row| {
+                    history_entry_from_row(row, &world_name, uuid)
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(entries)
+        })
+        .await
+    }
+
+    async fn delete_history_before(&self, before: NaiveDateTime) -> Result<u64, DatabaseError> {
+        let before = timestamp_micros(&before);
+        self.with_connection(move |connection| {
+            let deleted = connection.execute(QUERY_DELETE_HISTORY_BEFORE, params![before])?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn check_connection(&self) -> Written {
+        let result = self
+            .with_connection(|connection| connection.execute_batch("SELECT 1"))
+            .await;
+
+        match result {
+            Ok(_) => Written::default(),
+            Err(error) => vec![error.into()].into(),
+        }
+    }
+
+    #[inline]
+    async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError> {
+        SqliteStore::list_worlds(self).await
+    }
+
+    #[inline]
+    async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError> {
+        SqliteStore::drop_world(self, world_name).await
+    }
+
+    #[inline]
+    async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError> {
+        SqliteStore::copy_world(self, from, to).await
+    }
+
+    #[inline]
+    async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError> {
+        SqliteStore::rename_world(self, from, to).await
+    }
+
+    #[inline]
+    async fn get_world_tables(&self, world_name: &str) -> Result<Vec<i32>, DatabaseError> {
+        SqliteStore::get_world_tables(self, world_name).await
+    }
+
+    #[inline]
+    async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        SqliteStore::get_table_records(self, world_name, table_suffix).await
+    }
+}
+// endregion
+
+// region: Helper Functions
+/// Returns the sizing stored in `navigation_sizing`, [`None`] if none was stored yet.
+fn select_sizing(connection: &Connection) -> Result<Option<Sizing>, rusqlite::Error> {
+    connection
+        .query_row(QUERY_SELECT_SIZING, [], |row| {
+            Ok(Sizing {
+                region_x_size: row.get("region_x_size")?,
+                region_y_size: row.get("region_y_size")?,
+                region_z_size: row.get("region_z_size")?,
+                table_size: row.get("table_size")?,
+            })
+        })
+        .optional()
+}
+
+#[inline]
+fn timestamp_micros(time: &NaiveDateTime) -> i64 {
+    time.timestamp() * 1_000_000 + i64::from(time.timestamp_subsec_micros())
+}
+
+#[inline]
+fn from_timestamp_micros(micros: i64) -> Option<NaiveDateTime> {
+    let secs = micros.div_euclid(1_000_000);
+    let nsecs = micros.rem_euclid(1_000_000) * 1000;
+
+    NaiveDateTime::from_timestamp_opt(secs, nsecs as u32)
+}
+
+fn record_from_row(row: &Row, world_name: &str) -> Result<Record, rusqlite::Error> {
+    let uuid: Vec<u8> = row.get("uuid")?;
+    let uuid = Uuid::from_slice(&uuid)
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(3, Type::Blob, error.into()))?;
+
+    let expires_at = match row.get::<_, Option<i64>>("expires_at")? {
+        None => None,
+        Some(micros) => Some(
+            from_timestamp_micros(micros)
+                .ok_or_else(|| rusqlite::Error::IntegralValueOutOfRange(6, micros))?,
+        ),
+    };
+
+    let flex: Option<Vec<u8>> = row.get("flex")?;
+    let record = Record {
+        uuid,
+        position: Some(Vector3::new(row.get("x")?, row.get("y")?, row.get("z")?)),
+        world_name: world_name.into(),
+        data: row.get("data")?,
+        flex: flex.map(Bytes::from),
+        expires_at,
+        version: Some(row.get::<_, i64>("version")? as u64),
+        owner: row.get("owner")?,
+        writers: split_writers(row.get("writers")?),
+        tags: split_tags(row.get("tags")?)?,
+    };
+
+    Ok(record)
+}
+
+/// Writers are stored as a comma separated list, identities never contain commas.
+#[inline]
+fn split_writers(writers: Option<String>) -> Option<Vec<String>> {
+    let writers = writers?;
+    let writers = writers
+        .split(',')
+        .filter(|writer| !writer.is_empty())
+        .map(Into::into)
+        .collect();
+
+    Some(writers)
+}
+
+/// Tags are stored as a JSON array so triggers can keep the tags table in sync, or
+/// `NULL` for records without any.
+#[inline]
+fn join_tags(tags: &[String]) -> Option<String> {
+    match tags.is_empty() {
+        true => None,
+        false => serde_json::to_string(tags).ok(),
+    }
+}
+
+#[inline]
+fn split_tags(tags: Option<String>) -> Result<Vec<String>, rusqlite::Error> {
+    match tags {
+        None => Ok(vec![]),
+        Some(tags) => serde_json::from_str(&tags).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, error.into())
+        }),
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::store::Role;
+
+    fn store() -> SqliteStore {
+        SqliteStore::open(":memory:", 16, 16, 16, 256).unwrap()
+    }
+
+    fn peer(identity: Option<&str>) -> Writer {
+        Writer {
+            peer: Some(Uuid::new_v4()),
+            role: Role::Peer(identity.map(Into::into)),
+        }
+    }
+
+    async fn world_records(
+        store: &SqliteStore,
+        world_name: &str,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let mut records = vec![];
+        for table_suffix in store.get_world_tables(world_name).await? {
+            records.extend(store.get_table_records(world_name, table_suffix).await?);
+        }
+
+        Ok(records)
+    }
+
+    fn record(uuid: Uuid, position: Vector3, data: &str) -> Record {
+        Record {
+            uuid,
+            position: Some(position),
+            world_name: "world".into(),
+            data: Some(data.into()),
+            flex: Some(Bytes::from_static(b"flex")),
+            expires_at: None,
+            version: None,
+            owner: None,
+            writers: None,
+            tags: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn insert_read_delete() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+        let records = vec![
+            record(uuid, Vector3::new(1.0, 2.0, 3.0), "a"),
+            record(Uuid::new_v4(), Vector3::new(-1.0, 2.0, 3.0), "b"),
+        ];
+
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let found = store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uuid, uuid);
+        assert_eq!(found[0].data.as_deref(), Some("a"));
+        assert_eq!(found[0].flex.as_deref(), Some(&b"flex"[..]));
+        assert_eq!(found[0].position, Some(Vector3::new(1.0, 2.0, 3.0)));
+
+        let deleted = record(uuid, Vector3::new(2.0, 2.0, 2.0), "a");
+        assert!(store
+            .delete_records(vec![deleted], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let found = store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap();
+
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_position() {
+        let store = store();
+        let position = Vector3::new(1.0, 1.0, 1.0);
+        let unpositioned = Record {
+            position: None,
+            ..record(Uuid::new_v4(), position, "a")
+        };
+
+        let records = vec![unpositioned.clone(), record(Uuid::new_v4(), position, "b")];
+        let written = store.insert_records(records, &Writer::SERVER).await;
+        assert!(matches!(
+            written.errors[..],
+            [DatabaseError::MissingPosition { .. }]
+        ));
+        assert_eq!(written.records.len(), 1);
+
+        let errors = store
+            .delete_records(vec![unpositioned], &Writer::SERVER)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::MissingPosition { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn upsert_and_after() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+
+        let first = record(uuid, Vector3::new(1.0, 1.0, 1.0), "first");
+        assert!(store
+            .insert_records(vec![first], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let before_second = Utc::now().naive_utc();
+        let second = record(uuid, Vector3::new(2.0, 2.0, 2.0), "second");
+        assert!(store
+            .insert_records(vec![second], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let found = store
+            .get_records_in_region(
+                "world",
+                Vector3::zero(),
+                Some(before_second),
+                &DataFilter::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data.as_deref(), Some("second"));
+
+        let found = store
+            .get_records_in_region(
+                "world",
+                Vector3::zero(),
+                Some(Utc::now().naive_utc()),
+                &DataFilter::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn area() {
+        let store = store();
+        let records = [-16.0, -1.0, 0.0, 17.0, 300.0]
+            .iter()
+            .map(|x| record(Uuid::new_v4(), Vector3::new(*x, 1.0, 1.0), "a"))
+            .collect();
+
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let area = Area::new_box(Vector3::new(-16.0, 0.0, 0.0), Vector3::new(17.0, 1.0, 1.0));
+        let found = store
+            .get_records_in_area("world", area, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 4);
+
+        let area = Area::new_sphere(Vector3::new(300.0, 1.0, 1.0), 1.0);
+        let found = store
+            .get_records_in_area("world", area, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].position, Some(Vector3::new(300.0, 1.0, 1.0)));
+
+        // Reading one table at a time finds the same records
+        let area = Area::new_box(Vector3::new(-16.0, 0.0, 0.0), Vector3::new(300.0, 1.0, 1.0));
+        let tables = store.get_read_tables("world", Some(area)).await.unwrap();
+        assert!(tables.len() > 1);
+
+        let mut found = 0;
+        for table_suffix in tables {
+            found += store
+                .get_table_records_in_area(
+                    "world",
+                    table_suffix,
+                    area,
+                    None,
+                    &DataFilter::default(),
+                )
+                .await
+                .unwrap()
+                .len();
+        }
+
+        assert_eq!(found, 5);
+        assert!(store
+            .get_read_tables("missing", None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn data_filter() {
+        let store = store();
+        let data = [
+            r#"{"type": "rare", "level": 7}"#,
+            r#"{"type": "rare", "level": 2.5, "open": true}"#,
+            r#"{"type": "common", "level": 7, "loot": {"gold": null}}"#,
+            r#"{"type": ["rare"], "level": "7"}"#,
+            "type=rare",
+        ];
+
+        let records = data
+            .iter()
+            .map(|data| record(Uuid::new_v4(), Vector3::new(1.0, 1.0, 1.0), data))
+            .collect();
+
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let filtered = |filter: &str| {
+            let filter = filter.parse::<DataFilter>().unwrap();
+            let store = &store;
+            async move {
+                let mut found = store
+                    .get_records_in_region("world", Vector3::zero(), None, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|record| record.data.unwrap())
+                    .collect::<Vec<_>>();
+
+                let area = Area::new_sphere(Vector3::new(1.0, 1.0, 1.0), 1.0);
+                let mut in_area = store
+                    .get_records_in_area("world", area, None, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|record| record.data.unwrap())
+                    .collect::<Vec<_>>();
+
+                found.sort();
+                in_area.sort();
+                assert_eq!(found, in_area);
+
+                let mut indexes = found
+                    .iter()
+                    .map(|found| data.iter().position(|data| data == found).unwrap())
+                    .collect::<Vec<_>>();
+
+                indexes.sort_unstable();
+                indexes
+            }
+        };
+
+        assert_eq!(filtered("type=rare").await, vec![0, 1]);
+        assert_eq!(filtered("type=rare,level>=5").await, vec![0]);
+        assert_eq!(filtered("level=7").await, vec![0, 2]);
+        assert_eq!(filtered("level>2,level<7").await, vec![1]);
+        assert_eq!(filtered("level=\"7\"").await, vec![3]);
+        assert_eq!(filtered("open=true").await, vec![1]);
+        assert_eq!(filtered("loot.gold=null").await, vec![2]);
+        assert!(filtered("missing=null").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tags() {
+        let store = store();
+        let tagged = |x: f64, tags: &[&str]| Record {
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..record(Uuid::new_v4(), Vector3::new(x, 1.0, 1.0), &x.to_string())
+        };
+
+        let records = vec![
+            tagged(1.0, &["chest", "spawn_point"]),
+            tagged(100.0, &["spawn_point"]),
+            tagged(-500.0, &["spawn_point"]),
+            tagged(2.0, &["chest"]),
+            tagged(3.0, &[]),
+        ];
+
+        let moved = records[0].clone();
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let xs = |tag: &'static str, area: Option<Area>| {
+            let store = &store;
+            async move {
+                let mut xs = store
+                    .get_records_with_tag("world", tag, area, None, &DataFilter::default())
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|record| *record.position.unwrap().x())
+                    .collect::<Vec<_>>();
+
+                xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                xs
+            }
+        };
+
+        assert_eq!(xs("spawn_point", None).await, vec![-500.0, 1.0, 100.0]);
+        assert_eq!(xs("chest", None).await, vec![1.0, 2.0]);
+        assert!(xs("missing", None).await.is_empty());
+
+        let area = Area::new_sphere(Vector3::new(1.0, 1.0, 1.0), 200.0);
+        assert_eq!(xs("spawn_point", Some(area)).await, vec![1.0, 100.0]);
+
+        let found = store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap();
+        let found = found.iter().find(|r| r.uuid == moved.uuid).unwrap();
+        assert_eq!(found.tags, vec!["chest", "spawn_point"]);
+
+        // Tags are replaced on every write, and moving records keeps them
+        let retagged = Record {
+            position: Some(Vector3::new(5.0, 1.0, 1.0)),
+            tags: vec!["chest".into()],
+            ..moved.clone()
+        };
+
+        store
+            .insert_records(vec![retagged.clone()], &Writer::SERVER)
+            .await;
+        assert_eq!(xs("spawn_point", None).await, vec![-500.0, 100.0]);
+        assert_eq!(xs("chest", None).await, vec![2.0, 5.0]);
+
+        store.delete_records(vec![retagged], &Writer::SERVER).await;
+        assert_eq!(xs("chest", None).await, vec![2.0]);
+
+        let history = store
+            .get_record_history("world", moved.uuid, 10)
+            .await
+            .unwrap();
+        assert_eq!(history[1].old.as_ref().unwrap().tags, moved.tags);
+    }
+
+    #[tokio::test]
+    async fn expiry() {
+        let store = store();
+        let now = Utc::now().naive_utc();
+
+        let mut expired = record(Uuid::new_v4(), Vector3::new(1.0, 1.0, 1.0), "expired");
+        expired.expires_at = Some(now - chrono::Duration::seconds(10));
+        let mut later = record(Uuid::new_v4(), Vector3::new(2.0, 1.0, 1.0), "later");
+        later.expires_at = Some(now + chrono::Duration::seconds(3600));
+        let forever = record(Uuid::new_v4(), Vector3::new(3.0, 1.0, 1.0), "forever");
+
+        let records = vec![expired, later.clone(), forever];
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        // Expired records are hidden before they are deleted
+        let mut found = store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap();
+
+        found.sort_by(|a, b| a.data.cmp(&b.data));
+        assert_eq!(found.len(), 2);
+        // Stored with microsecond precision
+        assert_eq!(
+            found[1].expires_at.as_ref().map(timestamp_micros),
+            later.expires_at.as_ref().map(timestamp_micros)
+        );
+
+        let area = Area::new_sphere(Vector3::zero(), 10.0);
+        let found = store
+            .get_records_in_area("world", area, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let deleted = store.delete_expired_records(now, 10).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].data.as_deref(), Some("expired"));
+        assert_eq!(deleted[0].world_name, "world");
+
+        assert!(store
+            .delete_expired_records(now, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn versions() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+        let position = Vector3::new(1.0, 1.0, 1.0);
+        let versioned = |version: Option<u64>, data: &str| Record {
+            version,
+            ..record(uuid, position, data)
+        };
+
+        let stored_version = || async {
+            let found = store
+                .get_records_in_region("world", position, None, &DataFilter::default())
+                .await
+                .unwrap();
+
+            found.first().and_then(|record| record.version)
+        };
+
+        // Version 0 only creates new records
+        assert!(store
+            .insert_records(vec![versioned(Some(0), "a")], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert_eq!(stored_version().await, Some(1));
+
+        let errors = store
+            .insert_records(vec![versioned(Some(0), "b")], &Writer::SERVER)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 1, .. }]
+        ));
+
+        // Unconditional writes still increment the version
+        assert!(store
+            .insert_records(vec![versioned(None, "b")], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert!(store
+            .insert_records(vec![versioned(Some(2), "c")], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert_eq!(stored_version().await, Some(3));
+
+        let errors = store
+            .delete_records(vec![versioned(Some(2), "c")], &Writer::SERVER)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 3, .. }]
+        ));
+
+        assert!(store
+            .delete_records(vec![versioned(Some(3), "c")], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert_eq!(stored_version().await, None);
+
+        let errors = store
+            .insert_records(vec![versioned(Some(3), "d")], &Writer::SERVER)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 0, .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn ownership() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+        let position = Vector3::new(1.0, 1.0, 1.0);
+        let game = peer(Some("game"));
+        let other = peer(Some("other"));
+
+        let stored = || async {
+            let found = store
+                .get_records_in_region("world", position, None, &DataFilter::default())
+                .await
+                .unwrap();
+
+            found.into_iter().next()
+        };
+
+        let mut shared = record(uuid, position, "a");
+        shared.writers = Some(vec!["other".into(), "editor".into()]);
+        assert!(store
+            .insert_records(vec![shared], &game)
+            .await
+            .errors
+            .is_empty());
+
+        let found = stored().await.unwrap();
+        assert_eq!(found.owner.as_deref(), Some("game"));
+        assert_eq!(found.writers.as_deref().map(<[_]>::len), Some(2));
+
+        // Shared writers can write, but not change writers or delete with a stale version
+        let updated = record(uuid, position, "b");
+        assert!(store
+            .insert_records(vec![updated], &other)
+            .await
+            .errors
+            .is_empty());
+
+        let mut unshared = record(uuid, position, "c");
+        unshared.writers = Some(vec![]);
+        let errors = store.insert_records(vec![unshared], &other).await.errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::PermissionDenied { .. }]
+        ));
+
+        let errors = store
+            .delete_records(vec![record(uuid, position, "b")], &peer(None))
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::PermissionDenied { .. }]
+        ));
+
+        let mut stale = record(uuid, position, "b");
+        stale.version = Some(1);
+        let errors = store.delete_records(vec![stale], &other).await.errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 2, .. }]
+        ));
+
+        let found = stored().await.unwrap();
+        assert_eq!(found.data.as_deref(), Some("b"));
+        assert_eq!(found.owner.as_deref(), Some("game"));
+
+        let deleted = record(uuid, position, "b");
+        assert!(store
+            .delete_records(vec![deleted], &other)
+            .await
+            .errors
+            .is_empty());
+        assert!(stored().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn moved_records() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+        let near = Vector3::new(1.0, 1.0, 1.0);
+        let far = Vector3::new(400.0, 1.0, 1.0);
+        let game = peer(Some("game"));
+        let other = peer(Some("other"));
+
+        let stored = |position: Vector3| {
+            let store = &store;
+            async move {
+                store
+                    .get_records_in_region("world", position, None, &DataFilter::default())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        assert!(store
+            .insert_records(vec![record(uuid, near, "a")], &game)
+            .await
+            .errors
+            .is_empty());
+
+        // Owners and versions are checked wherever the uuid is stored in the world
+        let errors = store
+            .insert_records(vec![record(uuid, far, "b")], &other)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::PermissionDenied { .. }]
+        ));
+
+        let stale = Record {
+            version: Some(0),
+            ..record(uuid, far, "b")
+        };
+
+        let errors = store.insert_records(vec![stale], &game).await.errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 1, .. }]
+        ));
+        assert!(stored(far).await.is_empty());
+
+        // Allowed writes move the record rather than duplicating it
+        let moved = Record {
+            version: Some(1),
+            ..record(uuid, far, "b")
+        };
+
+        assert!(store
+            .insert_records(vec![moved], &game)
+            .await
+            .errors
+            .is_empty());
+        assert!(stored(near).await.is_empty());
+
+        let found = stored(far).await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].version, Some(2));
+        assert_eq!(found[0].owner.as_deref(), Some("game"));
+        assert_eq!(world_records(&store, "world").await.unwrap().len(), 1);
+
+        let history = store.get_record_history("world", uuid, 10).await.unwrap();
+        assert_eq!(history[0].old.as_ref().unwrap().position, Some(near));
+
+        // Conditional deletes find the record in its new table
+        let deleted = Record {
+            version: Some(2),
+            ..record(uuid, near, "b")
+        };
+
+        assert!(store
+            .delete_records(vec![deleted], &game)
+            .await
+            .errors
+            .is_empty());
+        assert!(stored(far).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn atomic() {
+        let store = store();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let near = Vector3::new(1.0, 1.0, 1.0);
+        let far = Vector3::new(40.0, 1.0, 1.0);
+
+        let stored = |position: Vector3| {
+            let store = &store;
+            async move {
+                store
+                    .get_records_in_region("world", position, None, &DataFilter::default())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // A conflict in one table rolls back writes to every table
+        let conflicting = Record {
+            version: Some(2),
+            ..record(b, far, "b")
+        };
+
+        let records = vec![record(a, near, "a"), conflicting];
+        let errors = store
+            .insert_records_atomic(records, &Writer::SERVER)
+            .await
+            .errors;
+        assert!(matches!(
+            errors[..],
+            [DatabaseError::VersionConflict { version: 0, .. }]
+        ));
+        assert!(stored(near).await.is_empty());
+        assert!(store
+            .get_record_history("world", a, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let records = vec![record(a, near, "a"), record(b, far, "b")];
+        assert!(store
+            .insert_records_atomic(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert_eq!(stored(near).await.len(), 1);
+        assert_eq!(stored(far).await.len(), 1);
+
+        let conflicting = Record {
+            version: Some(5),
+            ..record(b, far, "b")
+        };
+
+        let records = vec![record(a, near, "a"), conflicting];
+        let errors = store
+            .delete_records_atomic(records, &Writer::SERVER)
+            .await
+            .errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(stored(near).await.len(), 1);
+
+        let records = vec![record(a, near, "a"), record(b, far, "b")];
+        assert!(store
+            .delete_records_atomic(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+        assert!(stored(near).await.is_empty());
+        assert!(stored(far).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn worlds() {
+        let store = store();
+        let in_world = |world_name: &str, x: f64, data: &str| Record {
+            world_name: world_name.into(),
+            tags: vec!["chest".into()],
+            ..record(Uuid::new_v4(), Vector3::new(x, 1.0, 1.0), data)
+        };
+
+        let records = vec![
+            in_world("world", 1.0, "a"),
+            in_world("world", 5000.0, "b"),
+            in_world("other", 1.0, "c"),
+        ];
+
+        let game = peer(Some("game"));
+        assert!(store.insert_records(records, &game).await.errors.is_empty());
+
+        let stats = |world_name: &str, table_count, record_count| WorldStats {
+            world_name: world_name.into(),
+            table_count,
+            record_count,
+        };
+
+        let worlds = store.list_worlds().await.unwrap();
+        assert_eq!(worlds, vec![stats("other", 1, 1), stats("world", 2, 2)]);
+
+        // Copies are new records in the target world
+        assert_eq!(store.copy_world("world", "copy").await.unwrap(), 2);
+        let copied = store
+            .get_records_with_tag("copy", "chest", None, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(copied.len(), 2);
+        assert!(copied.iter().all(|record| record.world_name == "copy"));
+        assert!(copied.iter().all(|record| record.version == Some(1)));
+        assert!(copied
+            .iter()
+            .all(|record| record.owner.as_deref() == Some("game")));
+
+        assert!(matches!(
+            store.copy_world("world", "other").await,
+            Err(DatabaseError::WorldExists(_))
+        ));
+        assert!(matches!(
+            store.copy_world("missing", "new").await,
+            Err(DatabaseError::WorldNotFound(_))
+        ));
+
+        // Renamed worlds keep their records, and tag lookups keep working
+        store.rename_world("copy", "renamed").await.unwrap();
+        let renamed = store
+            .get_records_with_tag("renamed", "chest", None, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(renamed.len(), 2);
+
+        let mut moved = renamed[0].clone();
+        moved.tags = vec!["moved".into()];
+        assert!(store
+            .insert_records(vec![moved], &game)
+            .await
+            .errors
+            .is_empty());
+        let found = store
+            .get_records_with_tag("renamed", "moved", None, None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(found[0].version, Some(2));
+
+        assert!(matches!(
+            store.rename_world("renamed", "world").await,
+            Err(DatabaseError::WorldExists(_))
+        ));
+
+        let mut stored = world_records(&store, "renamed").await.unwrap();
+        stored.sort_by_key(|record| record.version);
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[1].version, Some(2));
+        assert!(stored.iter().all(|record| record.world_name == "renamed"));
+        assert!(matches!(
+            world_records(&store, "missing").await,
+            Err(DatabaseError::WorldNotFound(_))
+        ));
+
+        assert!(store.drop_world("world").await.unwrap());
+        assert!(!store.drop_world("world").await.unwrap());
+        assert!(store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let worlds = store.list_worlds().await.unwrap();
+        assert_eq!(worlds, vec![stats("other", 1, 1), stats("renamed", 2, 2)]);
+
+        // Dropped world names can be used again
+        let records = vec![in_world("world", 1.0, "d")];
+        assert!(store.insert_records(records, &game).await.errors.is_empty());
+        assert!(store.copy_world("world", "copy").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let store = store();
+        let uuid = Uuid::new_v4();
+        let position = Vector3::new(1.0, 1.0, 1.0);
+        let game = peer(Some("game"));
+
+        let mut created = record(uuid, position, "a");
+        created.expires_at = Some(Utc::now().naive_utc() + chrono::Duration::hours(1));
+        assert!(store
+            .insert_records(vec![created], &game)
+            .await
+            .errors
+            .is_empty());
+
+        let mut updated = record(uuid, position, "b");
+        updated.writers = Some(vec!["editor".into()]);
+        assert!(store
+            .insert_records(vec![updated], &game)
+            .await
+            .errors
+            .is_empty());
+
+        // Rejected writes aren't recorded
+        let errors = store
+            .delete_records(vec![record(uuid, position, "b")], &peer(None))
+            .await
+            .errors;
+        assert_eq!(errors.len(), 1);
+
+        let deleted = record(uuid, position, "b");
+        assert!(store
+            .delete_records(vec![deleted], &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        let history = store.get_record_history("world", uuid, 10).await.unwrap();
+        let operations = history
+            .iter()
+            .map(|entry| entry.operation.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(operations, vec!["delete", "update", "create"]);
+
+        let (deleted, updated, created) = (&history[0], &history[1], &history[2]);
+        assert_eq!(created.peer, game.peer);
+        assert!(created.old.is_none());
+        let new = created.new.as_ref().unwrap();
+        assert_eq!(new.data.as_deref(), Some("a"));
+        assert_eq!(new.owner.as_deref(), Some("game"));
+        assert!(new.expires_at.is_some());
+
+        let old = updated.old.as_ref().unwrap();
+        let new = updated.new.as_ref().unwrap();
+        assert_eq!(old.data.as_deref(), Some("a"));
+        assert_eq!(new.data.as_deref(), Some("b"));
+        assert_eq!(new.version, Some(2));
+        assert_eq!(new.writers.as_deref().map(<[_]>::len), Some(1));
+        assert_eq!(new.position, Some(position));
+
+        assert_eq!(deleted.peer, None);
+        assert_eq!(
+            deleted.old.as_ref().unwrap().flex.as_deref(),
+            Some(&b"flex"[..])
+        );
+        assert!(deleted.new.is_none());
+
+        assert_eq!(
+            store
+                .get_record_history("world", uuid, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let pruned = store
+            .delete_history_before(Utc::now().naive_utc())
+            .await
+            .unwrap();
+        assert_eq!(pruned, 3);
+        assert!(store
+            .get_record_history("world", uuid, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn sizing_mismatch() {
+        let path = std::env::temp_dir().join(format!("worldql-{}.db", Uuid::new_v4()));
+        let _guard = scopeguard::guard((), |_| {
+            let _ = std::fs::remove_file(&path);
+        });
+
+        assert!(SqliteStore::open(&path, 16, 16, 16, 256).is_ok());
+        assert!(SqliteStore::open(&path, 16, 16, 16, 256).is_ok());
+
+        let result = SqliteStore::open(&path, 32, 16, 16, 256);
+        assert!(matches!(result, Err(DatabaseError::SizingMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn read_only() {
+        let path = std::env::temp_dir().join(format!("worldql-{}.db", Uuid::new_v4()));
+        let _guard = scopeguard::guard((), |_| {
+            let _ = std::fs::remove_file(&path);
+        });
+
+        Connection::open(&path).unwrap();
+        let store = SqliteStore::open_read_only(&path, 16, 16, 16, 256).unwrap();
+        assert_eq!(store.stored_sizing().await.unwrap(), None);
+
+        SqliteStore::open(&path, 16, 16, 16, 256).unwrap();
+        let store = SqliteStore::open_read_only(&path, 32, 16, 16, 256).unwrap();
+        let stored = store.stored_sizing().await.unwrap().unwrap();
+        assert_eq!(stored.region_x_size, 16);
+
+        let record = record(Uuid::new_v4(), Vector3::zero(), "data");
+        let written = store.insert_records(vec![record], &Writer::SERVER).await;
+        assert_eq!(written.errors.len(), 1);
+    }
+}
+// endregion
@@ -0,0 +1,125 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::trace;
+
+use super::queries::*;
+use crate::database::sizing::Sizing;
+use crate::database::world_region::WorldRegion;
+
+/// Lookup both `table_suffix` and `region_id`, creating them and the world table if
+/// they don't exist yet.
+///
+/// Returned tuple has the form `(table_suffix, region_id)`
+pub(super) fn lookup_ids(
+    connection: &Connection,
+    region: &WorldRegion,
+    sizing: &Sizing,
+) -> Result<(i64, i64), rusqlite::Error> {
+    let ids = (
+        lookup(connection, QUERY_LOOKUP_TABLE_SUFFIX, region)?,
+        lookup(connection, QUERY_LOOKUP_REGION_ID, region)?,
+    );
+
+    let table_suffix = match ids.0 {
+        Some(table_suffix) => table_suffix,
+        None => {
+            trace!("table_suffix for {} not found in db, creating", region);
+
+            let table_size = i64::from(sizing.table_size);
+            let (min_x, max_x) = region.x_bounds(table_size);
+            let (min_y, max_y) = region.y_bounds(table_size);
+            let (min_z, max_z) = region.z_bounds(table_size);
+
+            connection.execute(
+                QUERY_INSERT_TABLE_SUFFIX,
+                params![
+                    min_x,
+                    max_x,
+                    min_y,
+                    max_y,
+                    min_z,
+                    max_z,
+                    region.world_name()
+                ],
+            )?;
+
+            let table_suffix = connection.last_insert_rowid();
+            connection.execute_batch(&query_create_world(region.world_name(), table_suffix))?;
+
+            table_suffix
+        }
+    };
+
+    let region_id = match ids.1 {
+        Some(region_id) => region_id,
+        None => {
+            trace!("region_id for {} not found in db, creating", region);
+
+            let min_x = *region.x();
+            let min_y = *region.y();
+            let min_z = *region.z();
+
+            let max_x = min_x + i64::from(sizing.region_x_size);
+            let max_y = min_y + i64::from(sizing.region_y_size);
+            let max_z = min_z + i64::from(sizing.region_z_size);
+
+            connection.execute(
+                QUERY_INSERT_REGION_ID,
+                params![
+                    min_x,
+                    max_x,
+                    min_y,
+                    max_y,
+                    min_z,
+                    max_z,
+                    region.world_name()
+                ],
+            )?;
+
+            connection.last_insert_rowid()
+        }
+    };
+
+    Ok((table_suffix, region_id))
+}
+
+/// Returns the suffixes of every table in a world.
+pub(super) fn world_tables(
+    connection: &Connection,
+    world_name: &str,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    connection
+        .prepare(QUERY_SELECT_WORLD_TABLES)?
+        .query_map(params![world_name], |row| row.get(0))?
+        .collect()
+}
+
+/// Lookup both `table_suffix` and `region_id` without creating them.
+pub(super) fn find_ids(
+    connection: &Connection,
+    region: &WorldRegion,
+) -> Result<Option<(i64, i64)>, rusqlite::Error> {
+    let table_suffix = match lookup(connection, QUERY_LOOKUP_TABLE_SUFFIX, region)? {
+        Some(table_suffix) => table_suffix,
+        None => return Ok(None),
+    };
+
+    let ids = lookup(connection, QUERY_LOOKUP_REGION_ID, region)?
+        .map(|region_id| (table_suffix, region_id));
+
+    Ok(ids)
+}
+
+#[inline]
+pub(super) fn lookup(
+    connection: &Connection,
+    query: &str,
+    region: &WorldRegion,
+) -> Result<Option<i64>, rusqlite::Error> {
+    connection
+        .query_row(
+            query,
+            params![region.world_name(), region.x(), region.y(), region.z()],
+            |row| row.get(0),
+        )
+        .optional()
+}
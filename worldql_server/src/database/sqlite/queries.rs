@@ -0,0 +1,660 @@
+pub(super) const CREATE_NAVIGATION: &str = "
+    CREATE TABLE IF NOT EXISTS navigation_tables
+    (
+        min_x        integer NOT NULL,
+        max_x        integer NOT NULL,
+        min_y        integer NOT NULL,
+        max_y        integer NOT NULL,
+        min_z        integer NOT NULL,
+        max_z        integer NOT NULL,
+        world_name   text NOT NULL,
+        table_suffix integer PRIMARY KEY AUTOINCREMENT
+    );
+
+    CREATE INDEX IF NOT EXISTS navigation_tables_world_name_index
+    ON navigation_tables (world_name, min_x, min_y, min_z);
+
+    CREATE TABLE IF NOT EXISTS navigation_regions
+    (
+        min_x      integer NOT NULL,
+        max_x      integer NOT NULL,
+        min_y      integer NOT NULL,
+        max_y      integer NOT NULL,
+        min_z      integer NOT NULL,
+        max_z      integer NOT NULL,
+        world_name text NOT NULL,
+        region_id  integer PRIMARY KEY AUTOINCREMENT
+    );
+
+    CREATE INDEX IF NOT EXISTS navigation_regions_world_name_index
+    ON navigation_regions (world_name, min_x, min_y, min_z);
+
+    CREATE TABLE IF NOT EXISTS navigation_records
+    (
+        world_name   text NOT NULL,
+        uuid         blob NOT NULL,
+        table_suffix integer NOT NULL,
+        PRIMARY KEY (world_name, uuid)
+    ) WITHOUT ROWID;
+
+    CREATE TABLE IF NOT EXISTS navigation_sizing
+    (
+        id            integer PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+        region_x_size integer NOT NULL,
+        region_y_size integer NOT NULL,
+        region_z_size integer NOT NULL,
+        table_size    integer NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS history
+    (
+        id             integer PRIMARY KEY AUTOINCREMENT,
+        changed_at     integer NOT NULL,
+        world_name     text NOT NULL,
+        uuid           blob NOT NULL,
+        operation      text NOT NULL,
+        peer           blob,
+        old_x          real,
+        old_y          real,
+        old_z          real,
+        old_data       text,
+        old_flex       blob,
+        old_expires_at integer,
+        old_version    integer,
+        old_owner      text,
+        old_writers    text,
+        old_tags       text,
+        new_x          real,
+        new_y          real,
+        new_z          real,
+        new_data       text,
+        new_flex       blob,
+        new_expires_at integer,
+        new_version    integer,
+        new_owner      text,
+        new_writers    text,
+        new_tags       text
+    );
+
+    CREATE INDEX IF NOT EXISTS history_record_index
+    ON history (world_name, uuid, id);
+
+    CREATE INDEX IF NOT EXISTS history_changed_at_index
+    ON history (changed_at);
+";
+
+pub(super) const QUERY_SELECT_SIZING: &str = "
+    SELECT region_x_size, region_y_size, region_z_size, table_size
+    FROM navigation_sizing
+";
+
+pub(super) const QUERY_SIZING_EXISTS: &str = "
+    SELECT EXISTS (
+        SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'navigation_sizing'
+    )
+";
+
+pub(super) const QUERY_INSERT_SIZING: &str = "
+    INSERT INTO navigation_sizing (region_x_size, region_y_size, region_z_size, table_size)
+    VALUES (?1, ?2, ?3, ?4)
+";
+
+pub(super) const QUERY_LOOKUP_TABLE_SUFFIX: &str = "
+    SELECT table_suffix FROM navigation_tables
+    WHERE world_name = ?1 AND
+    ?2 >= min_x AND ?2 < max_x AND
+    ?3 >= min_y AND ?3 < max_y AND
+    ?4 >= min_z AND ?4 < max_z
+";
+
+pub(super) const QUERY_INSERT_TABLE_SUFFIX: &str = "
+    INSERT INTO navigation_tables (min_x, max_x, min_y, max_y, min_z, max_z, world_name)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+pub(super) const QUERY_LOOKUP_REGION_ID: &str = "
+    SELECT region_id FROM navigation_regions
+    WHERE world_name = ?1 AND
+    ?2 >= min_x AND ?2 < max_x AND
+    ?3 >= min_y AND ?3 < max_y AND
+    ?4 >= min_z AND ?4 < max_z
+";
+
+pub(super) const QUERY_INSERT_REGION_ID: &str = "
+    INSERT INTO navigation_regions (min_x, max_x, min_y, max_y, min_z, max_z, world_name)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+";
+
+pub(super) const QUERY_SELECT_ALL_TABLES: &str = "
+    SELECT world_name, table_suffix FROM navigation_tables
+    ORDER BY table_suffix
+";
+
+pub(super) const QUERY_SELECT_WORLD_TABLES: &str = "
+    SELECT table_suffix FROM navigation_tables
+    WHERE world_name = ?1
+";
+
+pub(super) const QUERY_SELECT_TABLES_IN_BOUNDS: &str = "
+    SELECT table_suffix FROM navigation_tables
+    WHERE world_name = ?1 AND
+    max_x > ?2 AND min_x <= ?3 AND
+    max_y > ?4 AND min_y <= ?5 AND
+    max_z > ?6 AND min_z <= ?7
+";
+
+pub(super) const QUERY_SELECT_WORLD_TABLE_COUNTS: &str = "
+    SELECT world_name, count(*) AS table_count FROM navigation_tables
+    GROUP BY world_name
+    ORDER BY world_name
+";
+
+pub(super) const QUERY_DELETE_WORLD_TABLES: &str = "
+    DELETE FROM navigation_tables WHERE world_name = ?1
+";
+
+pub(super) const QUERY_DELETE_WORLD_REGIONS: &str = "
+    DELETE FROM navigation_regions WHERE world_name = ?1
+";
+
+pub(super) const QUERY_RENAME_WORLD_TABLES: &str = "
+    UPDATE navigation_tables SET world_name = ?2 WHERE world_name = ?1
+";
+
+pub(super) const QUERY_RENAME_WORLD_REGIONS: &str = "
+    UPDATE navigation_regions SET world_name = ?2 WHERE world_name = ?1
+";
+
+pub(super) const QUERY_LOOKUP_RECORD_TABLE: &str = "
+    SELECT table_suffix FROM navigation_records
+    WHERE world_name = ?1 AND uuid = ?2
+";
+
+pub(super) const QUERY_INSERT_RECORD_TABLE: &str = "
+    INSERT OR REPLACE INTO navigation_records (world_name, uuid, table_suffix)
+    VALUES (?1, ?2, ?3)
+";
+
+/// World tables without `navigation_records` triggers, created by older versions.
+pub(super) const QUERY_SELECT_TABLES_WITHOUT_RECORDS: &str = "
+    SELECT n.world_name, n.table_suffix FROM navigation_tables n
+    WHERE EXISTS (
+        SELECT 1 FROM sqlite_master
+        WHERE type = 'table' AND name = 'w_' || n.world_name || '_t_' || n.table_suffix
+    ) AND NOT EXISTS (
+        SELECT 1 FROM sqlite_master
+        WHERE type = 'trigger' AND
+        name = 'w_' || n.world_name || '_t_' || n.table_suffix || '_records_insert'
+    )
+    ORDER BY n.table_suffix
+";
+
+pub(super) const QUERY_DELETE_WORLD_RECORDS: &str = "
+    DELETE FROM navigation_records WHERE world_name = ?1
+";
+
+pub(super) const QUERY_RENAME_WORLD_RECORDS: &str = "
+    UPDATE navigation_records SET world_name = ?2 WHERE world_name = ?1
+";
+
+/// Takes `changed_at, world_name, uuid, operation, peer`, then the old and new record
+/// columns.
+pub(super) const QUERY_INSERT_HISTORY: &str = "
+    INSERT INTO history
+    (
+        changed_at, world_name, uuid, operation, peer,
+        old_x, old_y, old_z, old_data, old_flex,
+        old_expires_at, old_version, old_owner, old_writers, old_tags,
+        new_x, new_y, new_z, new_data, new_flex,
+        new_expires_at, new_version, new_owner, new_writers, new_tags
+    )
+    VALUES (
+        ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+        ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25
+    )
+";
+
+/// Whether the history table was created before tags were added.
+pub(super) const QUERY_HISTORY_WITHOUT_TAGS: &str = "
+    SELECT EXISTS (
+        SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'history'
+    ) AND NOT EXISTS (
+        SELECT 1 FROM pragma_table_info('history') WHERE name = 'new_tags'
+    )
+";
+
+pub(super) const ALTER_HISTORY_ADD_TAGS: &str = "
+    ALTER TABLE history ADD COLUMN old_tags text;
+    ALTER TABLE history ADD COLUMN new_tags text;
+";
+
+#[cfg(any(feature = "http", test))]
+pub(super) const QUERY_SELECT_RECORD_HISTORY: &str = "
+    SELECT * FROM history
+    WHERE world_name = ?1 AND uuid = ?2
+    ORDER BY id DESC LIMIT ?3
+";
+
+pub(super) const QUERY_DELETE_HISTORY_BEFORE: &str = "
+    DELETE FROM history WHERE changed_at < ?1
+";
+
+#[inline]
+pub(super) fn table_name(world_name: &str, suffix: i64) -> String {
+    format!("\"w_{0}_t_{1}\"", world_name, suffix)
+}
+
+pub(super) fn query_create_world(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        CREATE TABLE IF NOT EXISTS {0}
+        (
+            last_modified integer NOT NULL,
+            region_id     integer NOT NULL,
+            x             real,
+            y             real,
+            z             real,
+            uuid          blob NOT NULL UNIQUE,
+            data          text,
+            flex          blob,
+            expires_at    integer,
+            version       integer NOT NULL DEFAULT 1,
+            owner         text,
+            writers       text,
+            tags          text
+        );
+
+        CREATE INDEX IF NOT EXISTS \"w_{1}_t_{2}_region_id_index\"
+        ON {0} (region_id, last_modified);
+
+        CREATE INDEX IF NOT EXISTS \"w_{1}_t_{2}_expires_at_index\"
+        ON {0} (expires_at) WHERE expires_at IS NOT NULL;
+        ",
+        table_name(world_name, suffix),
+        world_name,
+        suffix
+    );
+
+    let name = format!("w_{}_t_{}", world_name, suffix);
+    query + &query_create_tags(&name) + &query_create_record_triggers(&name, suffix)
+}
+
+/// Create the tags table for a world table, taking its unquoted name.
+///
+/// Tags are stored in the world table as a JSON array, and kept in sync with the tags
+/// table by triggers so tag lookups can use its primary key.
+pub(super) fn query_create_tags(name: &str) -> String {
+    let query = format!(
+        "
+        CREATE TABLE IF NOT EXISTS \"{0}_tags\"
+        (
+            tag  text NOT NULL,
+            uuid blob NOT NULL,
+            PRIMARY KEY (tag, uuid)
+        ) WITHOUT ROWID;
+
+        CREATE TRIGGER IF NOT EXISTS \"{0}_tags_insert\"
+        AFTER INSERT ON \"{0}\" WHEN new.tags IS NOT NULL
+        BEGIN
+            INSERT OR IGNORE INTO \"{0}_tags\" (tag, uuid)
+            SELECT value, new.uuid FROM json_each(new.tags);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS \"{0}_tags_update\"
+        AFTER UPDATE OF tags ON \"{0}\"
+        BEGIN
+            DELETE FROM \"{0}_tags\" WHERE uuid = old.uuid;
+            INSERT OR IGNORE INTO \"{0}_tags\" (tag, uuid)
+            SELECT value, new.uuid FROM json_each(COALESCE(new.tags, '[]'));
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS \"{0}_tags_delete\"
+        AFTER DELETE ON \"{0}\"
+        BEGIN
+            DELETE FROM \"{0}_tags\" WHERE uuid = old.uuid;
+        END;
+        ",
+        name
+    );
+
+    query
+}
+
+/// Create the triggers keeping `navigation_records` in sync with a world table, taking
+/// its unquoted name.
+///
+/// The world name is looked up from `navigation_tables`, so renamed worlds keep working.
+pub(super) fn query_create_record_triggers(name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        CREATE TRIGGER IF NOT EXISTS \"{0}_records_insert\"
+        AFTER INSERT ON \"{0}\"
+        BEGIN
+            INSERT OR REPLACE INTO navigation_records (world_name, uuid, table_suffix)
+            SELECT world_name, new.uuid, table_suffix FROM navigation_tables
+            WHERE table_suffix = {1};
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS \"{0}_records_delete\"
+        AFTER DELETE ON \"{0}\"
+        BEGIN
+            DELETE FROM navigation_records WHERE
+            world_name = (SELECT world_name FROM navigation_tables WHERE table_suffix = {1}) AND
+            uuid = old.uuid AND table_suffix = {1};
+        END;
+        ",
+        name, suffix
+    );
+
+    query
+}
+
+/// Drop a world table along with its tags table, which also drops their indexes and
+/// triggers.
+pub(super) fn query_drop_world(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        DROP TABLE IF EXISTS \"w_{0}_t_{1}_tags\";
+        DROP TABLE IF EXISTS \"w_{0}_t_{1}\";
+        ",
+        world_name, suffix
+    );
+
+    query
+}
+
+/// Rename a world table and its tags table, recreating their triggers under the new
+/// name.
+///
+/// Index names are left as is, table suffixes are never reused so they can't clash.
+pub(super) fn query_rename_world(from: &str, to: &str, suffix: i64) -> String {
+    let (from, to) = (
+        format!("w_{}_t_{}", from, suffix),
+        format!("w_{}_t_{}", to, suffix),
+    );
+
+    let query = format!(
+        "
+        DROP TRIGGER IF EXISTS \"{0}_tags_insert\";
+        DROP TRIGGER IF EXISTS \"{0}_tags_update\";
+        DROP TRIGGER IF EXISTS \"{0}_tags_delete\";
+        DROP TRIGGER IF EXISTS \"{0}_records_insert\";
+        DROP TRIGGER IF EXISTS \"{0}_records_delete\";
+        ALTER TABLE \"{0}\" RENAME TO \"{1}\";
+        ALTER TABLE \"{0}_tags\" RENAME TO \"{1}_tags\";
+        ",
+        from, to
+    );
+
+    query + &query_create_tags(&to) + &query_create_record_triggers(&to, suffix)
+}
+
+/// World tables created before `column` was added, as unquoted names.
+pub(super) fn query_select_tables_without(column: &str) -> String {
+    let query = format!(
+        "
+        SELECT m.name FROM sqlite_master m
+        WHERE m.type = 'table' AND m.name LIKE 'w\\_%' ESCAPE '\\' AND
+        m.name NOT LIKE '%\\_tags' ESCAPE '\\' AND NOT EXISTS (
+            SELECT 1 FROM pragma_table_info(m.name) p WHERE p.name = '{}'
+        )
+        ",
+        column
+    );
+
+    query
+}
+
+/// Add the `expires_at` column to a world table, taking its unquoted name.
+pub(super) fn query_add_expiry(name: &str) -> String {
+    let query = format!(
+        "
+        ALTER TABLE \"{0}\" ADD COLUMN expires_at integer;
+
+        CREATE INDEX IF NOT EXISTS \"{0}_expires_at_index\"
+        ON \"{0}\" (expires_at) WHERE expires_at IS NOT NULL;
+        ",
+        name
+    );
+
+    query
+}
+
+/// Add the `version` column to a world table, taking its unquoted name.
+pub(super) fn query_add_version(name: &str) -> String {
+    let query = format!(
+        "
+        ALTER TABLE \"{}\" ADD COLUMN version integer NOT NULL DEFAULT 1;
+        ",
+        name
+    );
+
+    query
+}
+
+/// Add the `owner` and `writers` columns to a world table, taking its unquoted name.
+pub(super) fn query_add_owner(name: &str) -> String {
+    let query = format!(
+        "
+        ALTER TABLE \"{0}\" ADD COLUMN owner text;
+        ALTER TABLE \"{0}\" ADD COLUMN writers text;
+        ",
+        name
+    );
+
+    query
+}
+
+/// Add the `tags` column and its tags table to a world table, taking its unquoted name.
+pub(super) fn query_add_tags(name: &str) -> String {
+    let query = format!(
+        "
+        ALTER TABLE \"{}\" ADD COLUMN tags text;
+        ",
+        name
+    );
+
+    query + &query_create_tags(name)
+}
+
+/// Takes the record's owner, its writers as a comma separated list, and its tags as a
+/// JSON array.
+pub(super) fn query_insert_record(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        INSERT INTO {}
+        (last_modified, region_id, x, y, z, uuid, data, flex, expires_at, owner, writers, tags)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+        ON CONFLICT (uuid) DO UPDATE SET
+        last_modified = excluded.last_modified,
+        region_id = excluded.region_id,
+        x = excluded.x,
+        y = excluded.y,
+        z = excluded.z,
+        data = excluded.data,
+        flex = excluded.flex,
+        expires_at = excluded.expires_at,
+        owner = excluded.owner,
+        writers = COALESCE(excluded.writers, writers),
+        tags = excluded.tags,
+        version = version + 1
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_records(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE region_id = ?1 AND last_modified > ?2 AND
+        (expires_at IS NULL OR expires_at > ?3)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_table_records(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {}
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_uuids(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT uuid, last_modified FROM {}
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_last_modified(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT last_modified FROM {} WHERE uuid = ?1
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_count_records(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT count(*) FROM {}
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_records_in_box(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE
+        x >= ?1 AND x <= ?2 AND
+        y >= ?3 AND y <= ?4 AND
+        z >= ?5 AND z <= ?6 AND
+        last_modified > ?7 AND
+        (expires_at IS NULL OR expires_at > ?8)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Returns records tagged with `?1`, using the tags table.
+pub(super) fn query_select_records_with_tag(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE uuid IN (
+            SELECT uuid FROM \"w_{}_t_{}_tags\" WHERE tag = ?1
+        ) AND
+        last_modified > ?2 AND
+        (expires_at IS NULL OR expires_at > ?3)
+        ",
+        table_name(world_name, suffix),
+        world_name,
+        suffix
+    );
+
+    query
+}
+
+pub(super) fn query_delete_expired_records(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        DELETE FROM {0} WHERE uuid IN (
+            SELECT uuid FROM {0} WHERE expires_at <= ?1 LIMIT ?2
+        )
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_record(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        SELECT x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        FROM {} WHERE uuid = ?1
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_delete_record_if_version(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        uuid = ?1 AND version = ?2
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Copy a record into another table of the same world, keeping every column as stored.
+pub(super) fn query_copy_record(world_name: &str, from: i64, to: i64) -> String {
+    let query = format!(
+        "
+        INSERT INTO {1}
+        (last_modified, region_id, x, y, z, uuid, data, flex, expires_at, version, owner,
+        writers, tags)
+        SELECT last_modified, region_id, x, y, z, uuid, data, flex, expires_at, version,
+        owner, writers, tags FROM {0} WHERE uuid = ?1
+        ",
+        table_name(world_name, from),
+        table_name(world_name, to)
+    );
+
+    query
+}
+
+pub(super) fn query_delete_record_by_uuid(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE uuid = ?1
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_delete_record(world_name: &str, suffix: i64) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        region_id = ?1 AND uuid = ?2
+        RETURNING x, y, z, uuid, data, flex, expires_at, version, owner, writers, tags
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
@@ -0,0 +1,227 @@
+use chrono::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+
+use super::navigation::world_tables;
+use super::queries::*;
+use super::{record_from_row, timestamp_micros, SqliteStore};
+use crate::database::world_region::navigation_bounds;
+use crate::database::{DataFilter, DatabaseError};
+use crate::structures::{Area, Record};
+use crate::utils::sanitize_world_name;
+
+impl SqliteStore {
+    /// Returns the suffixes of the tables that may hold records inside `area`, or of
+    /// every table in the world if `area` is [`None`].
+    pub(super) async fn get_read_tables(
+        &self,
+        world_name: &str,
+        area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let bounds = area.map(|area| self.bounds(&area));
+
+        self.with_connection(move |connection| {
+            let table_suffixes = read_tables(connection, &world_name, bounds)?
+                .into_iter()
+                .map(|suffix| suffix as i32)
+                .collect();
+
+            Ok(table_suffixes)
+        })
+        .await
+    }
+
+    /// Returns the records positioned inside `area` from a single table, with data
+    /// matching `filter`.
+    pub(super) async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let read = TableRead::new(after, filter);
+
+        self.with_connection(move |connection| {
+            let table_suffix = i64::from(table_suffix);
+            let records = records_in_area(connection, &world_name, table_suffix, &area, &read)?;
+
+            Ok(records)
+        })
+        .await
+    }
+
+    /// Returns the records tagged with `tag` from a single table, with data matching
+    /// `filter`. If `area` is set, only records positioned inside it are returned.
+    pub(super) async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        let tag = tag.to_owned();
+        let read = TableRead::new(after, filter);
+
+        self.with_connection(move |connection| {
+            let table_suffix = i64::from(table_suffix);
+            let records = records_with_tag(
+                connection,
+                &world_name,
+                table_suffix,
+                &tag,
+                area.as_ref(),
+                &read,
+            )?;
+
+            Ok(records)
+        })
+        .await
+    }
+
+    /// Returns the navigation bounds that may hold records positioned inside `area`.
+    #[inline]
+    pub(super) fn bounds(&self, area: &Area) -> [i64; 6] {
+        navigation_bounds(
+            area,
+            self.sizing.region_x_size,
+            self.sizing.region_y_size,
+            self.sizing.region_z_size,
+        )
+    }
+}
+
+// region: TableRead Struct
+/// Conditions shared by every table of a read.
+pub(super) struct TableRead {
+    /// Only records modified after this time, in microseconds
+    after: i64,
+
+    /// Expired records may not have been deleted yet
+    now: i64,
+
+    filter: DataFilter,
+}
+
+impl TableRead {
+    pub(super) fn new(after: Option<NaiveDateTime>, filter: &DataFilter) -> Self {
+        Self {
+            after: after.as_ref().map_or(i64::MIN, timestamp_micros),
+            now: timestamp_micros(&Utc::now().naive_utc()),
+            filter: filter.clone(),
+        }
+    }
+}
+// endregion
+
+// region: Helper Functions
+/// Returns the suffixes of the tables within `bounds`, or of every table in the world
+/// if `bounds` is [`None`], see [`SqliteStore::bounds`].
+pub(super) fn read_tables(
+    connection: &Connection,
+    world_name: &str,
+    bounds: Option<[i64; 6]>,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    match bounds {
+        None => world_tables(connection, world_name),
+        Some([min_x, max_x, min_y, max_y, min_z, max_z]) => connection
+            .prepare(QUERY_SELECT_TABLES_IN_BOUNDS)?
+            .query_map(
+                params![world_name, min_x, max_x, min_y, max_y, min_z, max_z],
+                |row| row.get(0),
+            )?
+            .collect(),
+    }
+}
+
+/// Returns the records positioned inside `area` from a single table.
+pub(super) fn records_in_area(
+    connection: &Connection,
+    world_name: &str,
+    table_suffix: i64,
+    area: &Area,
+    read: &TableRead,
+) -> Result<Vec<Record>, rusqlite::Error> {
+    let (min, max) = area.bounds();
+    let mut values = vec![
+        Value::Real(*min.x()),
+        Value::Real(*max.x()),
+        Value::Real(*min.y()),
+        Value::Real(*max.y()),
+        Value::Real(*min.z()),
+        Value::Real(*max.z()),
+        Value::Integer(read.after),
+        Value::Integer(read.now),
+    ];
+
+    let query = query_select_records_in_box(world_name, table_suffix)
+        + &read.filter.sqlite_sql(values.len() + 1);
+    values.extend(read.filter.sqlite_params());
+
+    let mut statement = connection.prepare(&query)?;
+    let rows = statement.query_map(params_from_iter(values), |row| {
+        record_from_row(row, world_name)
+    })?;
+
+    let mut records = vec![];
+    for record in rows {
+        let record = record?;
+        if record
+            .position
+            .map_or(false, |position| area.contains(&position))
+        {
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+
+/// Returns the records tagged with `tag` from a single table. If `area` is set, only
+/// records positioned inside it are returned.
+pub(super) fn records_with_tag(
+    connection: &Connection,
+    world_name: &str,
+    table_suffix: i64,
+    tag: &str,
+    area: Option<&Area>,
+    read: &TableRead,
+) -> Result<Vec<Record>, rusqlite::Error> {
+    let mut values = vec![
+        Value::Text(tag.to_owned()),
+        Value::Integer(read.after),
+        Value::Integer(read.now),
+    ];
+
+    let query = query_select_records_with_tag(world_name, table_suffix)
+        + &read.filter.sqlite_sql(values.len() + 1);
+    values.extend(read.filter.sqlite_params());
+
+    let mut statement = connection.prepare(&query)?;
+    let rows = statement.query_map(params_from_iter(values), |row| {
+        record_from_row(row, world_name)
+    })?;
+
+    let mut records = vec![];
+    for record in rows {
+        let record = record?;
+        let inside = match (area, &record.position) {
+            (None, _) => true,
+            (Some(area), Some(position)) => area.contains(position),
+            (Some(_), None) => false,
+        };
+
+        if inside {
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+// endregion
@@ -0,0 +1,84 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+use super::queries::*;
+use super::record_from_row;
+use crate::database::store::Writer;
+use crate::database::versions::RecordAccess;
+use crate::database::world_region::WorldRegion;
+use crate::database::DatabaseError;
+use crate::structures::Record;
+
+/// Delete a record only if the stored record is at `version`, returning the deleted
+/// record.
+///
+/// Deleting at version 0 succeeds without doing anything if the record doesn't exist.
+/// The record is deleted from whichever table of the world stores it.
+pub(super) fn delete_record_if_version(
+    connection: &Connection,
+    region: &WorldRegion,
+    record: &Record,
+    version: u64,
+    writer: &Writer,
+) -> Result<Option<Record>, DatabaseError> {
+    let table_suffix = record_table(connection, region.world_name(), &record.uuid)?;
+    let stored = stored_record(connection, region.world_name(), table_suffix, &record.uuid)?;
+    let access = stored.as_ref().map(RecordAccess::from);
+    if let Some(access) = &access {
+        access.check(record.uuid, writer, false)?;
+    }
+
+    let stored_version = access.map_or(0, |access| access.version);
+    if stored_version != version {
+        return Err(DatabaseError::VersionConflict {
+            uuid: record.uuid,
+            version: stored_version,
+        });
+    }
+
+    if let Some(table_suffix) = table_suffix {
+        connection.execute(
+            &query_delete_record_if_version(region.world_name(), table_suffix),
+            params![&record.uuid.as_bytes()[..], version as i64],
+        )?;
+    }
+
+    Ok(stored)
+}
+
+/// Returns the suffix of the table storing `uuid` in a world, or [`None`] if no record
+/// has that uuid.
+pub(super) fn record_table(
+    connection: &Connection,
+    world_name: &str,
+    uuid: &Uuid,
+) -> Result<Option<i64>, rusqlite::Error> {
+    connection
+        .query_row(
+            QUERY_LOOKUP_RECORD_TABLE,
+            params![world_name, &uuid.as_bytes()[..]],
+            |row| row.get(0),
+        )
+        .optional()
+}
+
+/// Returns a stored record, or [`None`] if it doesn't exist.
+pub(super) fn stored_record(
+    connection: &Connection,
+    world_name: &str,
+    table_suffix: Option<i64>,
+    uuid: &Uuid,
+) -> Result<Option<Record>, rusqlite::Error> {
+    let table_suffix = match table_suffix {
+        Some(table_suffix) => table_suffix,
+        None => return Ok(None),
+    };
+
+    connection
+        .query_row(
+            &query_select_record(world_name, table_suffix),
+            params![&uuid.as_bytes()[..]],
+            |row| record_from_row(row, world_name),
+        )
+        .optional()
+}
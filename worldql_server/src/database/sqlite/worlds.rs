@@ -0,0 +1,216 @@
+use chrono::Utc;
+use rusqlite::{params, TransactionBehavior};
+
+use super::navigation::{lookup_ids, world_tables};
+use super::queries::*;
+use super::{join_tags, record_from_row, timestamp_micros, SqliteStore};
+use crate::database::store::{record_position, WorldStats};
+use crate::database::world_region::WorldRegion;
+use crate::database::DatabaseError;
+use crate::structures::Record;
+use crate::utils::sanitize_world_name;
+
+impl SqliteStore {
+    /// Returns every world in `navigation_tables` with its table and record counts.
+    pub(super) async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError> {
+        self.with_connection(|connection| {
+            let mut worlds = connection
+                .prepare(QUERY_SELECT_WORLD_TABLE_COUNTS)?
+                .query_map([], |row| {
+                    Ok(WorldStats {
+                        world_name: row.get("world_name")?,
+                        table_count: row.get("table_count")?,
+                        record_count: 0,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let tables = connection
+                .prepare(QUERY_SELECT_ALL_TABLES)?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<(String, i64)>, _>>()?;
+
+            for (world_name, table_suffix) in tables {
+                let query = query_count_records(&world_name, table_suffix);
+                let count: u64 = connection.query_row(&query, [], |row| row.get(0))?;
+
+                if let Some(world) = worlds.iter_mut().find(|w| w.world_name == world_name) {
+                    world.record_count += count;
+                }
+            }
+
+            Ok(worlds)
+        })
+        .await
+    }
+
+    /// Drop every table in a world along with its navigation rows, returning `false` if
+    /// it doesn't exist.
+    pub(super) async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            let table_suffixes = world_tables(&transaction, &world_name)?;
+            if table_suffixes.is_empty() {
+                return Ok(false);
+            }
+
+            for table_suffix in table_suffixes {
+                transaction.execute_batch(&query_drop_world(&world_name, table_suffix))?;
+            }
+
+            transaction.execute(QUERY_DELETE_WORLD_TABLES, params![world_name])?;
+            transaction.execute(QUERY_DELETE_WORLD_REGIONS, params![world_name])?;
+            transaction.execute(QUERY_DELETE_WORLD_RECORDS, params![world_name])?;
+            transaction.commit()?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Copy every record in world `from` into the new world `to`, returning the number
+    /// of records copied.
+    pub(super) async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+        let sizing = self.sizing;
+        let last_modified = timestamp_micros(&Utc::now().naive_utc());
+
+        self.with_connection(move |connection| {
+            // Take the write lock before checking `to`, so no other process creates it
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            if !world_tables(&transaction, &to)?.is_empty() {
+                return Err(DatabaseError::WorldExists(to));
+            }
+
+            let table_suffixes = world_tables(&transaction, &from)?;
+            if table_suffixes.is_empty() {
+                return Err(DatabaseError::WorldNotFound(from));
+            }
+
+            let mut copied = 0;
+            for table_suffix in table_suffixes {
+                let records = transaction
+                    .prepare(&query_select_table_records(&from, table_suffix))?
+                    .query_map([], |row| record_from_row(row, &to))?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for record in records {
+                    let position = record_position(&record)?;
+                    let region = WorldRegion::new(
+                        &to,
+                        &position,
+                        sizing.region_x_size,
+                        sizing.region_y_size,
+                        sizing.region_z_size,
+                    );
+
+                    // Copies are new records, so start again at version 1
+                    let (table_suffix, region_id) = lookup_ids(&transaction, &region, &sizing)?;
+                    transaction.query_row(
+                        &query_insert_record(&to, table_suffix),
+                        params![
+                            last_modified,
+                            region_id,
+                            position.x(),
+                            position.y(),
+                            position.z(),
+                            &record.uuid.as_bytes()[..],
+                            record.data,
+                            record.flex.as_deref(),
+                            record.expires_at.as_ref().map(timestamp_micros),
+                            record.owner,
+                            record.writers.as_ref().map(|writers| writers.join(",")),
+                            join_tags(&record.tags),
+                        ],
+                        |_| Ok(()),
+                    )?;
+
+                    copied += 1;
+                }
+            }
+
+            transaction.commit()?;
+            Ok(copied)
+        })
+        .await
+    }
+
+    /// Rename world `from` to `to`, moving its tables and navigation rows.
+    pub(super) async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+
+        self.with_connection(move |connection| {
+            // Take the write lock before checking `to`, so no other process creates it
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            if !world_tables(&transaction, &to)?.is_empty() {
+                return Err(DatabaseError::WorldExists(to));
+            }
+
+            let table_suffixes = world_tables(&transaction, &from)?;
+            if table_suffixes.is_empty() {
+                return Err(DatabaseError::WorldNotFound(from));
+            }
+
+            for table_suffix in table_suffixes {
+                transaction.execute_batch(&query_rename_world(&from, &to, table_suffix))?;
+            }
+
+            transaction.execute(QUERY_RENAME_WORLD_TABLES, params![from, to])?;
+            transaction.execute(QUERY_RENAME_WORLD_REGIONS, params![from, to])?;
+            transaction.execute(QUERY_RENAME_WORLD_RECORDS, params![from, to])?;
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the suffixes of every table in `navigation_tables` for a world.
+    pub(super) async fn get_world_tables(
+        &self,
+        world_name: &str,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        self.with_connection(move |connection| {
+            let table_suffixes = world_tables(connection, &world_name)?;
+            if table_suffixes.is_empty() {
+                return Err(DatabaseError::WorldNotFound(world_name));
+            }
+
+            Ok(table_suffixes
+                .into_iter()
+                .map(|suffix| suffix as i32)
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns every stored record in one of a world's tables, including expired records
+    /// that are yet to be deleted.
+    pub(super) async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        self.with_connection(move |connection| {
+            let records = connection
+                .prepare(&query_select_table_records(
+                    &world_name,
+                    i64::from(table_suffix),
+                ))?
+                .query_map([], |row| record_from_row(row, &world_name))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(records)
+        })
+        .await
+    }
+}
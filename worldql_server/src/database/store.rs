@@ -0,0 +1,544 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use super::{DataFilter, DatabaseError};
+use crate::structures::{Area, Record, Vector3};
+use crate::utils::PeerIdentity;
+
+pub type ThreadRecordStore = Arc<dyn RecordStore>;
+
+/// Storage backend for [`Record`] structs.
+///
+/// Records are bucketed into regions by their world and position, and are unique by
+/// [`uuid::Uuid`] within a world. Inserting a record that already exists replaces it,
+/// moving it to the region of its new position, so no deduplication is ever required
+/// when reading.
+///
+/// Every write increments a record's version. Records with [`Record::version`] set are
+/// only written or deleted if it matches the stored version, otherwise
+/// [`DatabaseError::VersionConflict`] is returned for them.
+///
+/// Records are owned by the [`Writer`] that created them. Writes and deletes to owned
+/// records by anyone other than the owner or its [`Record::writers`] are rejected with
+/// [`DatabaseError::PermissionDenied`].
+///
+/// Every write and delete, including deleting expired records, is appended to the
+/// record history along with the peer that made it, see [`HistoryEntry`].
+#[async_trait]
+pub trait RecordStore: Send + Sync {
+    /// Insert or replace many [`Record`] structs on behalf of `writer`, returning the
+    /// records as stored, see [`Written`].
+    async fn insert_records(&self, records: Vec<Record>, writer: &Writer) -> Written;
+
+    /// Same as [`RecordStore::insert_records`], but either every record is written or
+    /// none are.
+    ///
+    /// If any record can't be written, nothing is changed and the errors for every
+    /// failed record are returned.
+    async fn insert_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written;
+
+    /// Returns all records found within the region represented by `point_inside_region`.
+    ///
+    /// If `after` is set, only records modified after that time are returned. Only
+    /// records with data matching `filter` are returned.
+    async fn get_records_in_region(
+        &self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Returns all records positioned inside `area`.
+    ///
+    /// If `after` is set, only records modified after that time are returned. Only
+    /// records with data matching `filter` are returned.
+    async fn get_records_in_area(
+        &self,
+        world_name: &str,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Returns up to `count` records nearest to `point`, sorted by distance.
+    ///
+    /// Records further than `max_distance` from `point` are never returned. If `after`
+    /// is set, only records modified after that time are returned. Only records with
+    /// data matching `filter` are returned.
+    async fn get_nearest_records(
+        &self,
+        world_name: &str,
+        point: Vector3,
+        count: usize,
+        max_distance: f64,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Returns all records tagged with `tag`, see [`Record::tags`].
+    ///
+    /// If `area` is set, only records positioned inside it are returned, otherwise
+    /// records anywhere in the world are. If `after` is set, only records modified after
+    /// that time are returned. Only records with data matching `filter` are returned.
+    async fn get_records_with_tag(
+        &self,
+        world_name: &str,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Returns the suffixes of the tables that may hold records inside `area`, or of
+    /// every table in the world if `area` is [`None`], so large reads can be made one
+    /// table at a time.
+    ///
+    /// Worlds that don't exist have no tables.
+    async fn get_read_tables(
+        &self,
+        world_name: &str,
+        area: Option<Area>,
+    ) -> Result<Vec<i32>, DatabaseError>;
+
+    /// Same as [`RecordStore::get_records_in_area`], but only reads one of the tables
+    /// returned by [`RecordStore::get_read_tables`].
+    async fn get_table_records_in_area(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        area: Area,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Same as [`RecordStore::get_records_with_tag`], but only reads one of the tables
+    /// returned by [`RecordStore::get_read_tables`].
+    async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Delete many [`Record`] structs at once on behalf of `writer`, returning the
+    /// records as they were stored before being deleted, see [`Written`].
+    ///
+    /// Records are only deleted from the region of their [`Record::position`], unless
+    /// [`Record::version`] is set.
+    async fn delete_records(&self, records: Vec<Record>, writer: &Writer) -> Written;
+
+    /// Same as [`RecordStore::delete_records`], but either every record is deleted or
+    /// none are.
+    ///
+    /// If any record can't be deleted, nothing is changed and the errors for every
+    /// failed record are returned.
+    async fn delete_records_atomic(&self, records: Vec<Record>, writer: &Writer) -> Written;
+
+    /// Delete up to `limit` records that expired at or before `now`, returning the
+    /// deleted records.
+    ///
+    /// Expired records are never returned by reads, even before they are deleted.
+    async fn delete_expired_records(
+        &self,
+        now: NaiveDateTime,
+        limit: usize,
+    ) -> Result<Vec<Record>, DatabaseError>;
+
+    /// Returns up to `limit` history entries for the record with `uuid`, newest first.
+    #[cfg(any(feature = "http", test))]
+    async fn get_record_history(
+        &self,
+        world_name: &str,
+        uuid: Uuid,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, DatabaseError>;
+
+    /// Delete history entries for changes made before `before`, returning the number of
+    /// entries deleted.
+    async fn delete_history_before(&self, before: NaiveDateTime) -> Result<u64, DatabaseError>;
+
+    /// Check that the backend is reachable, flushing any buffered writes.
+    ///
+    /// Flushed records are returned in [`Written::flushed`], so subscribers can be
+    /// notified of them.
+    async fn check_connection(&self) -> Written;
+
+    /// Returns every stored world along with how much it stores, sorted by name.
+    async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError>;
+
+    /// Delete a world and all of its records, returning `false` if it doesn't exist.
+    ///
+    /// The world's record history is kept until it is pruned.
+    async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError>;
+
+    /// Copy every record in world `from` into the new world `to`, returning the number
+    /// of records copied.
+    ///
+    /// Copies keep their uuid, owner and writers, but start again at version 1 and
+    /// aren't recorded in the history. Fails with [`DatabaseError::WorldExists`] if `to`
+    /// already exists.
+    async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError>;
+
+    /// Move every record in world `from` to the new world `to`, keeping their versions.
+    ///
+    /// Fails with [`DatabaseError::WorldExists`] if `to` already exists. The record
+    /// history stays under the old world name.
+    async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError>;
+
+    /// Returns the suffixes of every table holding a world's records, so they can be
+    /// read one table at a time with [`RecordStore::get_table_records`].
+    ///
+    /// Fails with [`DatabaseError::WorldNotFound`] if the world doesn't exist.
+    async fn get_world_tables(&self, world_name: &str) -> Result<Vec<i32>, DatabaseError>;
+
+    /// Returns every stored record in one of a world's tables, including expired records
+    /// that are yet to be deleted.
+    async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError>;
+}
+
+// region: Writer Struct
+/// Who a write is made on behalf of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Writer {
+    /// Peer that sent the write, or [`None`] for writes made by the server itself.
+    ///
+    /// Only used for the record history, see [`HistoryEntry::peer`]
+    pub peer: Option<Uuid>,
+    pub role: Role,
+}
+
+/// What a [`Writer`] may write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Role {
+    /// Admin peers and the server itself, which may write any record.
+    ///
+    /// Records written by admins keep their existing owner unless
+    /// [`Record::owner`] is set.
+    Admin,
+
+    /// A peer identity, or [`None`] for anonymous peers.
+    ///
+    /// Records created by peers are owned by their identity, [`Record::owner`] is ignored.
+    Peer(Option<String>),
+}
+
+impl Writer {
+    /// The server itself, which may write any record.
+    pub const SERVER: Writer = Writer {
+        peer: None,
+        role: Role::Admin,
+    };
+
+    /// Returns the [`Writer`] for the peer `uuid` that authenticated as `identity`.
+    pub fn from_identity(uuid: Uuid, identity: Option<&PeerIdentity>) -> Self {
+        let role = match identity {
+            Some(identity) if identity.admin => Role::Admin,
+            Some(identity) => Role::Peer(Some(identity.name.clone())),
+            None => Role::Peer(None),
+        };
+
+        Self {
+            peer: Some(uuid),
+            role,
+        }
+    }
+
+    /// Returns `true` if this writer may write to a stored record with the given owner
+    /// and writers.
+    ///
+    /// Anyone may write records without an owner. Changing the writers of an owned
+    /// record, set with `sharing`, is only allowed for its owner.
+    pub fn can_write(&self, owner: Option<&str>, writers: &[String], sharing: bool) -> bool {
+        let identity = match &self.role {
+            Role::Admin => return true,
+            Role::Peer(identity) => identity.as_deref(),
+        };
+
+        match (owner, identity) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(owner), Some(identity)) => {
+                owner == identity || (!sharing && writers.iter().any(|w| w == identity))
+            }
+        }
+    }
+
+    /// Returns the owner to store for `record` when written by this writer, given the
+    /// owner of the stored record if it exists.
+    pub fn owner_of(&self, record: &Record, stored: Option<Option<&str>>) -> Option<String> {
+        match (&self.role, stored) {
+            (Role::Admin, Some(owner)) => record.owner.as_deref().or(owner).map(Into::into),
+            (Role::Admin, None) => record.owner.clone(),
+            (Role::Peer(_), Some(owner)) => owner.map(Into::into),
+            (Role::Peer(identity), None) => identity.clone(),
+        }
+    }
+}
+// endregion
+
+// region: Written Struct
+/// Outcome of writing or deleting many records at once.
+#[derive(Debug, Default)]
+pub struct Written {
+    /// Records as stored after being written, or before being deleted
+    ///
+    /// Records that weren't changed, such as deletes of records that don't exist or
+    /// writes held in the write buffer while the database is unavailable, aren't included
+    pub records: Vec<Record>,
+
+    /// Errors for the records that couldn't be written
+    pub errors: Vec<DatabaseError>,
+
+    /// Records held in the write buffer while the database was unavailable, as stored
+    /// when they were written ahead of this change
+    pub flushed: Vec<Record>,
+
+    /// Records as stored before this change moved them to another position
+    pub moved: Vec<Record>,
+}
+
+impl Written {
+    /// Returns the outcome of making the changes in `entries`, along with the `errors`
+    /// for records that weren't changed.
+    pub fn new(entries: Vec<HistoryEntry>, errors: Vec<DatabaseError>) -> Self {
+        let mut records = Vec::with_capacity(entries.len());
+        let mut moved = vec![];
+        for entry in entries {
+            match (entry.old, entry.new) {
+                (Some(old), Some(new)) => {
+                    if old.position != new.position {
+                        moved.push(old);
+                    }
+
+                    records.push(new);
+                }
+                (old, new) => records.extend(new.or(old)),
+            }
+        }
+
+        Self {
+            records,
+            errors,
+            flushed: vec![],
+            moved,
+        }
+    }
+}
+
+impl From<Vec<DatabaseError>> for Written {
+    fn from(errors: Vec<DatabaseError>) -> Self {
+        Self {
+            records: vec![],
+            errors,
+            flushed: vec![],
+            moved: vec![],
+        }
+    }
+}
+// endregion
+
+// region: WorldStats Struct
+/// A stored world, as returned by [`RecordStore::list_worlds`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldStats {
+    pub world_name: String,
+
+    /// Number of tables holding the world's records, always 0 for in-memory storage
+    pub table_count: u64,
+
+    /// Number of stored records, including expired records that are yet to be deleted
+    pub record_count: u64,
+}
+// endregion
+
+// region: HistoryEntry Struct
+/// Kind of change recorded by a [`HistoryEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Create => "create",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+/// A single change to a record, as stored in the append-only record history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub changed_at: NaiveDateTime,
+    pub world_name: String,
+    pub uuid: Uuid,
+    pub operation: Operation,
+
+    /// Peer that made the change, or [`None`] for changes made by the server itself,
+    /// such as deleting expired records
+    pub peer: Option<Uuid>,
+
+    /// Stored record before the change, [`None`] if it was created
+    pub old: Option<Record>,
+
+    /// Stored record after the change, [`None`] if it was deleted
+    pub new: Option<Record>,
+}
+
+impl HistoryEntry {
+    /// Create an entry for a change from `old` to `new`.
+    ///
+    /// # Panics
+    /// Panics if both `old` and `new` are [`None`]
+    pub fn new(
+        changed_at: NaiveDateTime,
+        peer: Option<Uuid>,
+        old: Option<Record>,
+        new: Option<Record>,
+    ) -> Self {
+        let operation = match (&old, &new) {
+            (None, _) => Operation::Create,
+            (Some(_), Some(_)) => Operation::Update,
+            (Some(_), None) => Operation::Delete,
+        };
+
+        let record = new
+            .as_ref()
+            .or(old.as_ref())
+            .expect("history entry without a record");
+
+        Self {
+            changed_at,
+            world_name: record.world_name.clone(),
+            uuid: record.uuid,
+            operation,
+            peer,
+            old,
+            new,
+        }
+    }
+}
+// endregion
+
+// region: Helper Functions
+/// Returns the position of a record to store, [`DatabaseError::MissingPosition`] if it
+/// has none.
+pub(super) fn record_position(record: &Record) -> Result<Vector3, DatabaseError> {
+    record
+        .position
+        .ok_or(DatabaseError::MissingPosition { uuid: record.uuid })
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(identity: Option<&str>) -> Writer {
+        Writer {
+            peer: None,
+            role: Role::Peer(identity.map(Into::into)),
+        }
+    }
+
+    #[test]
+    fn permissions() {
+        let writers = vec!["editor".to_owned()];
+        let owner = peer(Some("game"));
+        let editor = peer(Some("editor"));
+        let other = peer(Some("other"));
+        let anonymous = peer(None);
+
+        for writer in [&owner, &editor, &other, &anonymous, &Writer::SERVER] {
+            assert!(writer.can_write(None, &[], true));
+        }
+
+        assert!(owner.can_write(Some("game"), &writers, true));
+        assert!(editor.can_write(Some("game"), &writers, false));
+        assert!(!editor.can_write(Some("game"), &writers, true));
+        assert!(!other.can_write(Some("game"), &writers, false));
+        assert!(!anonymous.can_write(Some("game"), &writers, false));
+        assert!(Writer::SERVER.can_write(Some("game"), &writers, true));
+    }
+
+    #[test]
+    fn owners() {
+        let record = Record {
+            owner: Some("spoofed".into()),
+            ..Default::default()
+        };
+
+        let game = peer(Some("game"));
+        assert_eq!(game.owner_of(&record, None).as_deref(), Some("game"));
+        assert_eq!(
+            game.owner_of(&record, Some(Some("other"))).as_deref(),
+            Some("other")
+        );
+        assert_eq!(game.owner_of(&record, Some(None)), None);
+        assert_eq!(peer(None).owner_of(&record, None), None);
+
+        let admin = Writer::SERVER;
+        assert_eq!(admin.owner_of(&record, None).as_deref(), Some("spoofed"));
+        assert_eq!(
+            admin
+                .owner_of(&Record::default(), Some(Some("game")))
+                .as_deref(),
+            Some("game")
+        );
+    }
+
+    #[test]
+    fn history_operations() {
+        let now = chrono::Utc::now().naive_utc();
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "world".into(),
+            ..Default::default()
+        };
+
+        let created = HistoryEntry::new(now, None, None, Some(record.clone()));
+        assert_eq!(created.operation, Operation::Create);
+        assert_eq!(created.uuid, record.uuid);
+        assert_eq!(created.world_name, "world");
+
+        let updated = HistoryEntry::new(now, None, Some(record.clone()), Some(record.clone()));
+        assert_eq!(updated.operation, Operation::Update);
+
+        let deleted = HistoryEntry::new(now, None, Some(record), None);
+        assert_eq!(deleted.operation, Operation::Delete);
+        assert!(deleted.new.is_none());
+    }
+
+    #[test]
+    fn from_identity() {
+        let identity = PeerIdentity {
+            name: "backend".into(),
+            admin: true,
+        };
+
+        let uuid = Uuid::new_v4();
+        let writer = Writer::from_identity(uuid, Some(&identity));
+        assert_eq!(writer.peer, Some(uuid));
+        assert_eq!(writer.role, Role::Admin);
+
+        let writer = Writer::from_identity(uuid, None);
+        assert_eq!(writer.role, Role::Peer(None));
+    }
+}
+// endregion
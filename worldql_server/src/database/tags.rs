@@ -0,0 +1,120 @@
+use chrono::prelude::*;
+use deadpool_postgres::GenericClient;
+use tokio_postgres::types::ToSql;
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::versions::is_undefined_table;
+use super::{query_select_records_with_tag, query_select_records_with_tag_after, DataFilter};
+use crate::structures::{Area, Record};
+use crate::utils::sanitize_world_name;
+
+impl DatabaseClient {
+    /// Returns a [`Vec`] containing all records tagged with `tag`, with data matching
+    /// `filter`.
+    ///
+    /// If `area` is set, only records positioned inside it are returned and only tables
+    /// that overlap it are queried, otherwise every table in the world is queried.
+    pub async fn get_records_with_tag(
+        &self,
+        world_name: &str,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_records_with_tag"])
+            .start_timer();
+
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+        let area = area.as_ref();
+
+        let mut records = vec![];
+        for table_suffix in self.read_tables(&client, world_name, area).await? {
+            let table_records =
+                records_with_tag(&client, world_name, table_suffix, tag, area, after, filter)
+                    .await?;
+
+            records.extend(table_records);
+        }
+
+        Ok(records)
+    }
+
+    /// Same as [`DatabaseClient::get_records_with_tag`], but only queries one of the
+    /// tables returned by [`DatabaseClient::get_read_tables`].
+    pub async fn get_table_records_with_tag(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+        tag: &str,
+        area: Option<Area>,
+        after: Option<NaiveDateTime>,
+        filter: &DataFilter,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let _timer = crate::metrics::DB_QUERY_DURATION
+            .with_label_values(&["get_table_records_with_tag"])
+            .start_timer();
+
+        let world_name = &sanitize_world_name(world_name)?;
+        let client = self.pool.get().await?;
+        let area = area.as_ref();
+
+        records_with_tag(&client, world_name, table_suffix, tag, area, after, filter).await
+    }
+}
+
+/// Returns the records tagged with `tag` from a single table, with data matching
+/// `filter`. If `area` is set, only records positioned inside it are returned.
+async fn records_with_tag(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    tag: &str,
+    area: Option<&Area>,
+    after: Option<NaiveDateTime>,
+    filter: &DataFilter,
+) -> Result<Vec<Record>, DatabaseError> {
+    // Expired records may not have been deleted yet
+    let now = Utc::now().naive_utc();
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&tag, &now];
+    if let Some(after) = &after {
+        params.push(after);
+    }
+
+    let query = match after {
+        None => query_select_records_with_tag(world_name, table_suffix),
+        Some(_) => query_select_records_with_tag_after(world_name, table_suffix),
+    } + &filter.postgres_sql(params.len() + 1);
+    params.extend(filter.postgres_params());
+
+    let rows = match client.query(&query, &params).await {
+        // Table has navigation rows but no records yet
+        Err(error) if is_undefined_table(&error) => return Ok(vec![]),
+        result => result?,
+    };
+
+    let records = rows
+        .into_iter()
+        .map(|row| Record::from_postgres_row(row, world_name))
+        .filter(|record| match (area, &record.position) {
+            (None, _) => true,
+            (Some(area), Some(position)) => area.contains(position),
+            (Some(_), None) => false,
+        })
+        .collect();
+
+    Ok(records)
+}
+
+/// Tags are stored as `NULL` for records without any.
+#[inline]
+pub(super) fn stored_tags(tags: Vec<String>) -> Option<Vec<String>> {
+    match tags.is_empty() {
+        true => None,
+        false => Some(tags),
+    }
+}
@@ -0,0 +1,159 @@
+use ahash::AHashSet;
+use deadpool_postgres::{GenericClient, Object, Transaction};
+use tracing::warn;
+
+use super::client::{
+    create_world_table, delete_resolved, write_resolved, DatabaseError, ResolvedRecord,
+};
+use super::history::append_history;
+use super::query_world_table_exists;
+use super::store::{HistoryEntry, Written};
+
+/// Write resolved records and append the changes to the record history in a single
+/// transaction, so no change is ever stored without its history.
+///
+/// Records that fail are left unwritten, unless `atomic` is set, in which case nothing is
+/// written if any record fails. Tables are created before the transaction starts, as a
+/// failed statement would abort the whole transaction.
+pub(super) async fn write_transaction(
+    client: &mut Object,
+    records: Vec<ResolvedRecord>,
+    history: bool,
+    atomic: bool,
+    mut errors: Vec<DatabaseError>,
+) -> Written {
+    if records.is_empty() || (atomic && !errors.is_empty()) {
+        return errors.into();
+    }
+
+    for (world_name, table_suffix) in missing_tables(&*client, &records).await {
+        if let Err(error) = create_world_table(&*client, &world_name, table_suffix).await {
+            errors.push(error);
+            return errors.into();
+        }
+    }
+
+    let mut transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            errors.push(error.into());
+            return errors.into();
+        }
+    };
+
+    let entries = write_resolved(&mut transaction, records, history, &mut errors).await;
+    commit(transaction, entries, errors, history, atomic).await
+}
+
+/// Delete resolved records and append the changes to the record history in a single
+/// transaction, see [`write_transaction`].
+pub(super) async fn delete_transaction(
+    client: &mut Object,
+    mut records: Vec<ResolvedRecord>,
+    atomic: bool,
+    mut errors: Vec<DatabaseError>,
+) -> Written {
+    // Records in tables that don't exist yet were never written, so there is nothing
+    // to delete unless a stored version was expected
+    let missing = missing_tables(&*client, &records).await;
+    records.retain(|resolved| {
+        let key = (resolved.world_name.clone(), resolved.table_suffix);
+        if !missing.contains(&key) {
+            return true;
+        }
+
+        match resolved.record.version {
+            Some(version) if version != 0 => {
+                errors.push(DatabaseError::VersionConflict {
+                    uuid: resolved.record.uuid,
+                    version: 0,
+                });
+            }
+
+            _ => (),
+        }
+
+        false
+    });
+
+    if records.is_empty() || (atomic && !errors.is_empty()) {
+        return errors.into();
+    }
+
+    let mut transaction = match client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            errors.push(error.into());
+            return errors.into();
+        }
+    };
+
+    let entries = delete_resolved(&mut transaction, records, &mut errors).await;
+    commit(transaction, entries, errors, true, atomic).await
+}
+
+// region: Helper Functions
+/// Returns the tables targeted by `records` that don't exist yet.
+///
+/// Tables that can't be checked are assumed to exist, any error is then returned by the
+/// write itself.
+async fn missing_tables(
+    client: &impl GenericClient,
+    records: &[ResolvedRecord],
+) -> AHashSet<(String, i32)> {
+    let tables = records
+        .iter()
+        .map(|resolved| (resolved.world_name.clone(), resolved.table_suffix))
+        .collect::<AHashSet<_>>();
+
+    let mut missing = AHashSet::new();
+    for (world_name, table_suffix) in tables {
+        let query = query_world_table_exists(&world_name, table_suffix);
+        match client.query_one(&query, &[]).await {
+            Ok(row) if !row.get::<_, bool>("exists") => {
+                missing.insert((world_name, table_suffix));
+            }
+
+            Ok(_) => (),
+            Err(error) => warn!("error checking world table exists: {}", error),
+        }
+    }
+
+    missing
+}
+
+/// Append `entries` to the record history if `history` is set and commit.
+///
+/// If `atomic` is set, rolls back instead if there are any `errors`.
+async fn commit(
+    transaction: Transaction<'_>,
+    entries: Vec<HistoryEntry>,
+    mut errors: Vec<DatabaseError>,
+    history: bool,
+    atomic: bool,
+) -> Written {
+    if atomic && !errors.is_empty() {
+        if let Err(error) = transaction.rollback().await {
+            warn!("error rolling back atomic write: {}", error);
+        }
+
+        return errors.into();
+    }
+
+    // History is part of the transaction, so a failed append aborts the write too
+    if history {
+        if let Err(error) = append_history(&transaction, &entries).await {
+            errors.push(error);
+            return errors.into();
+        }
+    }
+
+    match transaction.commit().await {
+        Ok(()) => Written::new(entries, errors),
+        Err(error) => {
+            errors.push(error.into());
+            errors.into()
+        }
+    }
+}
+// endregion
@@ -0,0 +1,252 @@
+use chrono::Utc;
+use deadpool_postgres::GenericClient;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+use super::client::DatabaseError;
+use super::history::select_records_by_uuid;
+use super::record_data::data_columns;
+use super::record_tables::{claim_uuids, forget_uuids, move_records, record_table};
+use super::store::{record_position, HistoryEntry, Role, Writer};
+use super::tags::stored_tags;
+use super::{
+    query_delete_record_if_version, query_delete_record_if_version_as_peer,
+    query_insert_record_if_absent, query_select_record_access, query_update_record_if_version,
+    query_update_record_if_version_as_peer,
+};
+use crate::structures::Record;
+
+// region: RecordAccess Struct
+/// Version and permissions of a stored record.
+pub(super) struct RecordAccess {
+    pub version: u64,
+    pub owner: Option<String>,
+    pub writers: Vec<String>,
+}
+
+impl From<&Record> for RecordAccess {
+    fn from(record: &Record) -> Self {
+        Self {
+            version: record.version.unwrap_or(1),
+            owner: record.owner.clone(),
+            writers: record.writers.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl RecordAccess {
+    /// Returns [`DatabaseError::PermissionDenied`] if `writer` may not write this record.
+    pub fn check(&self, uuid: Uuid, writer: &Writer, sharing: bool) -> Result<(), DatabaseError> {
+        match writer.can_write(self.owner.as_deref(), &self.writers, sharing) {
+            true => Ok(()),
+            false => Err(DatabaseError::PermissionDenied { uuid }),
+        }
+    }
+}
+// endregion
+
+/// Insert or update a [`Record`] only if the stored record is at `version`, returning
+/// the change to record in the history.
+///
+/// Version 0 only inserts records that don't exist yet, the table must already exist. On
+/// a mismatch, returns
+/// [`DatabaseError::VersionConflict`] with the stored version, unless `writer` may not
+/// write the record at all.
+///
+/// A record stored in another table of the world is checked against its stored value,
+/// then moved into this table before being written.
+pub(super) async fn insert_record_if_version(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    region_id: i32,
+    record: &Record,
+    version: u64,
+    writer: &Writer,
+) -> Result<HistoryEntry, DatabaseError> {
+    let position = record_position(record)?;
+    let flex = record.flex.as_ref().map(|b| b.to_vec());
+    let owner = writer.owner_of(record, None);
+    let (data, data_json) = data_columns(record.data.clone());
+    let tags = stored_tags(record.tags.clone());
+    let expected = version as i64;
+
+    let uuid = record.uuid;
+    let elsewhere = claim_uuids(client, world_name, table_suffix, &[uuid]).await?;
+    for (from, uuids) in elsewhere {
+        let stored = select_records_by_uuid(client, world_name, from, &uuids).await?;
+        if let Some(access) = stored.first().map(RecordAccess::from) {
+            access.check(uuid, writer, record.writers.is_some())?;
+            if access.version != version {
+                return Err(DatabaseError::VersionConflict {
+                    uuid,
+                    version: access.version,
+                });
+            }
+        }
+
+        move_records(client, world_name, from, table_suffix, &uuids).await?;
+    }
+
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![
+        &region_id,
+        position.x(),
+        position.y(),
+        position.z(),
+        &record.uuid,
+        &data,
+        &flex,
+        &record.expires_at,
+        &owner,
+        &record.writers,
+        &data_json,
+        &tags,
+    ];
+
+    let mut old = None;
+    let result = match version {
+        0 => {
+            let query = query_insert_record_if_absent(world_name, table_suffix);
+            client.query_opt(&query, &params).await
+        }
+
+        _ => {
+            old = select_records_by_uuid(client, world_name, table_suffix, &[record.uuid])
+                .await?
+                .pop();
+
+            params.push(&expected);
+            let query = match writer.role {
+                Role::Admin => query_update_record_if_version(world_name, table_suffix),
+                Role::Peer(_) => query_update_record_if_version_as_peer(world_name, table_suffix),
+            };
+
+            match client.query_opt(&query, &params).await {
+                // No table means no records to update
+                Err(error) if is_undefined_table(&error) => Ok(None),
+                result => result,
+            }
+        }
+    };
+
+    if let Some(row) = result? {
+        let new = Record::from_postgres_row(row, world_name);
+        let entry = HistoryEntry::new(Utc::now().naive_utc(), writer.peer, old, Some(new));
+
+        return Ok(entry);
+    }
+
+    let stored = match record_access(client, world_name, table_suffix, &uuid).await? {
+        None => 0,
+        Some(access) => {
+            access.check(uuid, writer, record.writers.is_some())?;
+            access.version
+        }
+    };
+
+    Err(DatabaseError::VersionConflict {
+        uuid,
+        version: stored,
+    })
+}
+
+/// Delete a record only if the stored record is at `version`, returning the change to
+/// record in the history.
+///
+/// Deleting at version 0 succeeds without doing anything if the record doesn't exist.
+/// On a mismatch, returns [`DatabaseError::VersionConflict`] with the stored version,
+/// unless `writer` may not delete the record at all.
+///
+/// The record is deleted from whichever table of the world stores it, falling back to
+/// `table_suffix` if it doesn't exist.
+pub(super) async fn delete_record_if_version(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    uuid: Uuid,
+    version: u64,
+    writer: &Writer,
+) -> Result<Option<HistoryEntry>, DatabaseError> {
+    let table_suffix = record_table(client, world_name, &uuid)
+        .await?
+        .unwrap_or(table_suffix);
+
+    let expected = version as i64;
+    let result = match &writer.role {
+        Role::Admin => {
+            let query = query_delete_record_if_version(world_name, table_suffix);
+            client.query_opt(&query, &[&uuid, &expected]).await
+        }
+
+        Role::Peer(identity) => {
+            let query = query_delete_record_if_version_as_peer(world_name, table_suffix);
+            client
+                .query_opt(&query, &[&uuid, &expected, identity])
+                .await
+        }
+    };
+
+    let deleted = match result {
+        Err(error) if is_undefined_table(&error) => None,
+        result => result?,
+    };
+
+    if let Some(row) = deleted {
+        forget_uuids(client, world_name, &[uuid]).await?;
+        let old = Record::from_postgres_row(row, world_name);
+        let entry = HistoryEntry::new(Utc::now().naive_utc(), writer.peer, Some(old), None);
+
+        return Ok(Some(entry));
+    }
+
+    let stored = match record_access(client, world_name, table_suffix, &uuid).await? {
+        None => 0,
+        Some(access) => {
+            access.check(uuid, writer, false)?;
+            access.version
+        }
+    };
+
+    match stored == version {
+        true => Ok(None),
+        false => Err(DatabaseError::VersionConflict {
+            uuid,
+            version: stored,
+        }),
+    }
+}
+
+/// Returns the version and permissions of a stored record, or [`None`] if it doesn't
+/// exist.
+pub(super) async fn record_access(
+    client: &impl GenericClient,
+    world_name: &str,
+    table_suffix: i32,
+    uuid: &Uuid,
+) -> Result<Option<RecordAccess>, DatabaseError> {
+    let query = query_select_record_access(world_name, table_suffix);
+    let row = match client.query_opt(&query, &[uuid]).await {
+        Err(error) if is_undefined_table(&error) => None,
+        result => result?,
+    };
+
+    let access = row.map(|row| RecordAccess {
+        version: row.get::<_, i64>("version") as u64,
+        owner: row.get("owner"),
+        writers: row
+            .get::<_, Option<Vec<String>>>("writers")
+            .unwrap_or_default(),
+    });
+
+    Ok(access)
+}
+
+#[inline]
+pub(super) fn is_undefined_table(error: &Error) -> bool {
+    match error.as_db_error() {
+        Some(db_error) => *db_error.code() == SqlState::UNDEFINED_TABLE,
+        None => false,
+    }
+}
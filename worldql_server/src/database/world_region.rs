@@ -3,7 +3,7 @@ use std::fmt::Display;
 use derive_getters::Getters;
 
 use super::DatabaseClient;
-use crate::structures::Vector3;
+use crate::structures::{Area, Vector3};
 
 // region: WorldRegion Struct
 #[derive(Debug, Getters, Clone, PartialEq, Eq, Hash)]
@@ -86,6 +86,36 @@ impl DatabaseClient {
 }
 // endregion
 
+// region: Area Bounds
+/// Returns the navigation bounds that may hold records positioned inside `area`.
+///
+/// Bounds are widened by one region on each side, as negative coordinates on a region
+/// border are bucketed into the region below.
+///
+/// Returned array has the form `[min_x, max_x, min_y, max_y, min_z, max_z]`
+pub(super) fn navigation_bounds(
+    area: &Area,
+    region_x_size: u16,
+    region_y_size: u16,
+    region_z_size: u16,
+) -> [i64; 6] {
+    let (min, max) = area.bounds();
+    let widen = |min: f64, max: f64, region_size: u16| {
+        let region_size = i64::from(region_size);
+        (
+            min.floor() as i64 - region_size,
+            max.ceil() as i64 + region_size,
+        )
+    };
+
+    let (min_x, max_x) = widen(*min.x(), *max.x(), region_x_size);
+    let (min_y, max_y) = widen(*min.y(), *max.y(), region_y_size);
+    let (min_z, max_z) = widen(*min.z(), *max.z(), region_z_size);
+
+    [min_x, max_x, min_y, max_y, min_z, max_z]
+}
+// endregion
+
 // region: Coordinate Clamp Functions
 /// Define region coords by their lowest possible value.
 ///
@@ -109,7 +139,7 @@ fn clamp_region_coord(c: f64, region_size: u16) -> i64 {
     }
 }
 
-fn clamp_table_size(c: i64, table_size: i64) -> i64 {
+pub(super) fn clamp_table_size(c: i64, table_size: i64) -> i64 {
     // On a table border, return
     if c % table_size == 0 {
         return c;
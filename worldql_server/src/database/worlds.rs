@@ -0,0 +1,303 @@
+use deadpool_postgres::GenericClient;
+use tracing::{info, warn};
+
+use super::client::{DatabaseClient, DatabaseError};
+use super::store::{WorldStats, Writer};
+use super::versions::is_undefined_table;
+use super::{
+    query_count_records, query_create_world_schema, query_drop_world_schema,
+    query_rename_world_schema, query_select_table_records, QUERY_DELETE_WORLD_MIGRATIONS,
+    QUERY_DELETE_WORLD_REGIONS, QUERY_DELETE_WORLD_TABLES, QUERY_MIGRATION_LOCK,
+    QUERY_RENAME_WORLD_MIGRATIONS, QUERY_RENAME_WORLD_REGIONS, QUERY_RENAME_WORLD_TABLES,
+    QUERY_SELECT_ALL_TABLES, QUERY_SELECT_WORLD_TABLE_COUNTS, QUERY_SELECT_WORLD_TABLE_SUFFIXES,
+    QUERY_WORLD_EXISTS, QUERY_WORLD_SCHEMA_EXISTS,
+};
+use crate::structures::Record;
+use crate::utils::sanitize_world_name;
+
+/// Maximum number of records copied at once
+const COPY_BATCH_SIZE: usize = 1000;
+
+impl DatabaseClient {
+    /// Returns every world in `navigation.tables` with its table and record counts.
+    ///
+    /// Counts every table, so may be slow for large worlds.
+    pub async fn list_worlds(&self) -> Result<Vec<WorldStats>, DatabaseError> {
+        let client = self.pool.get().await?;
+        let mut worlds = client
+            .query(QUERY_SELECT_WORLD_TABLE_COUNTS, &[])
+            .await?
+            .into_iter()
+            .map(|row| WorldStats {
+                world_name: row.get("world_name"),
+                table_count: row.get::<_, i64>("table_count") as u64,
+                record_count: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let tables = client.query(QUERY_SELECT_ALL_TABLES, &[]).await?;
+        for row in tables {
+            let world_name: &str = row.get("world_name");
+            let table_suffix: i32 = row.get("table_suffix");
+
+            // Tables are only created once a record is written to them
+            let query = query_count_records(world_name, table_suffix);
+            let count = match client.query_one(&query, &[]).await {
+                Err(error) if is_undefined_table(&error) => continue,
+                result => result?.get::<_, i64>("count"),
+            };
+
+            if let Some(world) = worlds.iter_mut().find(|w| w.world_name == world_name) {
+                world.record_count += count as u64;
+            }
+        }
+
+        Ok(worlds)
+    }
+
+    /// Drop a world's schema along with its navigation rows, returning `false` if it
+    /// doesn't exist.
+    pub async fn drop_world(&self, world_name: &str) -> Result<bool, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        // Hold the navigation lock so no new tables are created for the world meanwhile
+        let _lock = self.navigation_lock.lock().await;
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(QUERY_MIGRATION_LOCK, &[]).await?;
+
+        if !world_exists(&transaction, &world_name).await? {
+            return Ok(false);
+        }
+
+        let schema_name = format!("w_{}", world_name);
+        transaction
+            .batch_execute(&query_drop_world_schema(&world_name))
+            .await?;
+
+        transaction
+            .execute(QUERY_DELETE_WORLD_TABLES, &[&world_name])
+            .await?;
+
+        transaction
+            .execute(QUERY_DELETE_WORLD_REGIONS, &[&world_name])
+            .await?;
+
+        transaction
+            .execute(QUERY_DELETE_WORLD_MIGRATIONS, &[&schema_name])
+            .await?;
+
+        transaction.commit().await?;
+        self.clear_navigation_caches();
+
+        info!("dropped world {}", world_name);
+        Ok(true)
+    }
+
+    /// Copy every record in world `from` into the new world `to`, returning the number
+    /// of records copied.
+    ///
+    /// Records are re-inserted one table at a time, so are bucketed into `to` under this
+    /// client's sizing. If the copy fails partway, whatever was copied into `to` is
+    /// dropped again.
+    pub async fn copy_world(&self, from: &str, to: &str) -> Result<u64, DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+
+        // Reserve `to` by creating its schema under the migration lock, so concurrent
+        // copies or renames into it fail instead of mixing records
+        let table_suffixes = {
+            let mut client = self.pool.get().await?;
+            let transaction = client.transaction().await?;
+            transaction.execute(QUERY_MIGRATION_LOCK, &[]).await?;
+
+            if world_exists(&transaction, &to).await? {
+                return Err(DatabaseError::WorldExists(to));
+            }
+
+            let table_suffixes = transaction
+                .query(QUERY_SELECT_WORLD_TABLE_SUFFIXES, &[&from])
+                .await?
+                .into_iter()
+                .map(|row| row.get::<_, i32>("table_suffix"))
+                .collect::<Vec<_>>();
+
+            if table_suffixes.is_empty() {
+                return Err(DatabaseError::WorldNotFound(from));
+            }
+
+            transaction
+                .execute(&query_create_world_schema(&to), &[])
+                .await?;
+
+            transaction.commit().await?;
+            table_suffixes
+        };
+
+        // Drop the reserved schema again if the copy fails or nothing was copied
+        let copied = self.copy_tables(&from, &to, table_suffixes).await;
+        if !matches!(copied, Ok(copied) if copied > 0) {
+            if let Err(error) = self.drop_world(&to).await {
+                warn!("error dropping partially copied world {}: {}", to, error);
+            }
+        }
+
+        let copied = copied?;
+
+        info!("copied {} records from world {} to {}", copied, from, to);
+        Ok(copied)
+    }
+
+    /// Rename world `from` to `to`, moving its schema and navigation rows.
+    pub async fn rename_world(&self, from: &str, to: &str) -> Result<(), DatabaseError> {
+        let from = sanitize_world_name(from)?;
+        let to = sanitize_world_name(to)?;
+
+        let _lock = self.navigation_lock.lock().await;
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(QUERY_MIGRATION_LOCK, &[]).await?;
+
+        if !world_exists(&transaction, &from).await? {
+            return Err(DatabaseError::WorldNotFound(from));
+        }
+
+        if world_exists(&transaction, &to).await? {
+            return Err(DatabaseError::WorldExists(to));
+        }
+
+        let schema_exists: bool = transaction
+            .query_one(QUERY_WORLD_SCHEMA_EXISTS, &[&from])
+            .await?
+            .get("exists");
+
+        if schema_exists {
+            transaction
+                .batch_execute(&query_rename_world_schema(&from, &to))
+                .await?;
+        }
+
+        transaction
+            .execute(QUERY_RENAME_WORLD_TABLES, &[&from, &to])
+            .await?;
+
+        transaction
+            .execute(QUERY_RENAME_WORLD_REGIONS, &[&from, &to])
+            .await?;
+
+        let schema_names = (format!("w_{}", from), format!("w_{}", to));
+        transaction
+            .execute(
+                QUERY_RENAME_WORLD_MIGRATIONS,
+                &[&schema_names.0, &schema_names.1],
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.clear_navigation_caches();
+
+        info!("renamed world {} to {}", from, to);
+        Ok(())
+    }
+
+    /// Returns the suffixes of every table in `navigation.tables` for a world.
+    pub async fn get_world_tables(&self, world_name: &str) -> Result<Vec<i32>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        let client = self.pool.get().await?;
+        let table_suffixes = client
+            .query(QUERY_SELECT_WORLD_TABLE_SUFFIXES, &[&world_name])
+            .await?
+            .into_iter()
+            .map(|row| row.get::<_, i32>("table_suffix"))
+            .collect::<Vec<_>>();
+
+        if table_suffixes.is_empty() {
+            return Err(DatabaseError::WorldNotFound(world_name));
+        }
+
+        Ok(table_suffixes)
+    }
+
+    /// Returns every stored record in one of a world's tables, including expired records
+    /// that are yet to be deleted.
+    pub async fn get_table_records(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> Result<Vec<Record>, DatabaseError> {
+        let world_name = sanitize_world_name(world_name)?;
+
+        // Tables are only created once a record is written to them
+        let client = self.pool.get().await?;
+        let query = query_select_table_records(&world_name, table_suffix);
+        let rows = match client.query(&query, &[]).await {
+            Err(error) if is_undefined_table(&error) => return Ok(vec![]),
+            result => result?,
+        };
+
+        let records = rows
+            .into_iter()
+            .map(|row| Record::from_postgres_row(row, &world_name))
+            .collect();
+
+        Ok(records)
+    }
+
+    /// Copy the records in each of `from`'s tables into world `to`, returning the number
+    /// of records copied.
+    async fn copy_tables(
+        &self,
+        from: &str,
+        to: &str,
+        table_suffixes: Vec<i32>,
+    ) -> Result<u64, DatabaseError> {
+        let mut copied = 0;
+        for table_suffix in table_suffixes {
+            let records = self
+                .get_table_records(from, table_suffix)
+                .await?
+                .into_iter()
+                .map(|record| Record {
+                    world_name: to.into(),
+                    version: None,
+                    ..record
+                })
+                .collect::<Vec<_>>();
+
+            for batch in records.chunks(COPY_BATCH_SIZE) {
+                let written = self
+                    .write_records(batch.to_vec(), &Writer::SERVER, false)
+                    .await;
+                if let Some(error) = written.errors.into_iter().next() {
+                    return Err(error);
+                }
+
+                copied += batch.len() as u64;
+            }
+        }
+
+        Ok(copied)
+    }
+
+    /// Forget every cached navigation ID, after navigation rows were changed.
+    fn clear_navigation_caches(&self) {
+        self.table_cache.lock().unwrap().clear();
+        self.region_cache.lock().unwrap().clear();
+    }
+}
+
+// region: Helper Functions
+/// Returns `true` if a world has any navigation rows or a schema.
+async fn world_exists(
+    client: &impl GenericClient,
+    world_name: &str,
+) -> Result<bool, DatabaseError> {
+    let exists = client
+        .query_one(QUERY_WORLD_EXISTS, &[&world_name])
+        .await?
+        .get("exists");
+
+    Ok(exists)
+}
+// endregion
@@ -450,6 +450,11 @@ impl<'a> Record<'a> {
         _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
         args: &'args RecordArgs<'args>) -> flatbuffers::WIPOffset<Record<'bldr>> {
       let mut builder = RecordBuilder::new(_fbb);
+      if let Some(x) = args.version { builder.add_version(x); }
+      if let Some(x) = args.expires_at { builder.add_expires_at(x); }
+      if let Some(x) = args.tags { builder.add_tags(x); }
+      if let Some(x) = args.writers { builder.add_writers(x); }
+      if let Some(x) = args.owner { builder.add_owner(x); }
       if let Some(x) = args.flex { builder.add_flex(x); }
       if let Some(x) = args.data { builder.add_data(x); }
       if let Some(x) = args.world_name { builder.add_world_name(x); }
@@ -474,12 +479,28 @@ impl<'a> Record<'a> {
       let flex = self.flex().map(|x| {
         x.to_vec()
       });
+      let expires_at = self.expires_at();
+      let version = self.version();
+      let owner = self.owner().map(|x| {
+        x.to_string()
+      });
+      let writers = self.writers().map(|x| {
+        x.iter().map(str::to_string).collect()
+      });
+      let tags = self.tags().map(|x| {
+        x.iter().map(str::to_string).collect()
+      });
       RecordT {
         uuid,
         position,
         world_name,
         data,
         flex,
+        expires_at,
+        version,
+        owner,
+        writers,
+        tags,
       }
     }
     pub const VT_UUID: flatbuffers::VOffsetT = 4;
@@ -487,6 +508,11 @@ impl<'a> Record<'a> {
     pub const VT_WORLD_NAME: flatbuffers::VOffsetT = 8;
     pub const VT_DATA: flatbuffers::VOffsetT = 10;
     pub const VT_FLEX: flatbuffers::VOffsetT = 12;
+    pub const VT_EXPIRES_AT: flatbuffers::VOffsetT = 14;
+    pub const VT_VERSION: flatbuffers::VOffsetT = 16;
+    pub const VT_OWNER: flatbuffers::VOffsetT = 18;
+    pub const VT_WRITERS: flatbuffers::VOffsetT = 20;
+    pub const VT_TAGS: flatbuffers::VOffsetT = 22;
 
   #[inline]
   pub fn uuid(&self) -> Option<&'a str> {
@@ -508,6 +534,26 @@ impl<'a> Record<'a> {
   pub fn flex(&self) -> Option<&'a [u8]> {
     self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Record::VT_FLEX, None).map(|v| v.safe_slice())
   }
+  #[inline]
+  pub fn expires_at(&self) -> Option<u64> {
+    self._tab.get::<u64>(Record::VT_EXPIRES_AT, None)
+  }
+  #[inline]
+  pub fn version(&self) -> Option<u64> {
+    self._tab.get::<u64>(Record::VT_VERSION, None)
+  }
+  #[inline]
+  pub fn owner(&self) -> Option<&'a str> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<&str>>(Record::VT_OWNER, None)
+  }
+  #[inline]
+  pub fn writers(&self) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>>>(Record::VT_WRITERS, None)
+  }
+  #[inline]
+  pub fn tags(&self) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>>>(Record::VT_TAGS, None)
+  }
 }
 
 impl flatbuffers::Verifiable for Record<'_> {
@@ -522,6 +568,11 @@ impl flatbuffers::Verifiable for Record<'_> {
      .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"world_name", Self::VT_WORLD_NAME, false)?
      .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"data", Self::VT_DATA, false)?
      .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u8>>>(&"flex", Self::VT_FLEX, false)?
+     .visit_field::<u64>(&"expires_at", Self::VT_EXPIRES_AT, false)?
+     .visit_field::<u64>(&"version", Self::VT_VERSION, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<&str>>(&"owner", Self::VT_OWNER, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&'_ str>>>>(&"writers", Self::VT_WRITERS, false)?
+     .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&'_ str>>>>(&"tags", Self::VT_TAGS, false)?
      .finish();
     Ok(())
   }
@@ -532,6 +583,11 @@ pub struct RecordArgs<'a> {
     pub world_name: Option<flatbuffers::WIPOffset<&'a str>>,
     pub data: Option<flatbuffers::WIPOffset<&'a str>>,
     pub flex: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u8>>>,
+    pub expires_at: Option<u64>,
+    pub version: Option<u64>,
+    pub owner: Option<flatbuffers::WIPOffset<&'a str>>,
+    pub writers: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>>>,
+    pub tags: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>>>,
 }
 impl<'a> Default for RecordArgs<'a> {
     #[inline]
@@ -542,6 +598,11 @@ impl<'a> Default for RecordArgs<'a> {
             world_name: None,
             data: None,
             flex: None,
+            expires_at: None,
+            version: None,
+            owner: None,
+            writers: None,
+            tags: None,
         }
     }
 }
@@ -571,6 +632,26 @@ impl<'a: 'b, 'b> RecordBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Record::VT_FLEX, flex);
   }
   #[inline]
+  pub fn add_expires_at(&mut self, expires_at: u64) {
+    self.fbb_.push_slot_always::<u64>(Record::VT_EXPIRES_AT, expires_at);
+  }
+  #[inline]
+  pub fn add_version(&mut self, version: u64) {
+    self.fbb_.push_slot_always::<u64>(Record::VT_VERSION, version);
+  }
+  #[inline]
+  pub fn add_owner(&mut self, owner: flatbuffers::WIPOffset<&'b  str>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Record::VT_OWNER, owner);
+  }
+  #[inline]
+  pub fn add_writers(&mut self, writers: flatbuffers::WIPOffset<flatbuffers::Vector<'b , flatbuffers::ForwardsUOffset<&'b  str>>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Record::VT_WRITERS, writers);
+  }
+  #[inline]
+  pub fn add_tags(&mut self, tags: flatbuffers::WIPOffset<flatbuffers::Vector<'b , flatbuffers::ForwardsUOffset<&'b  str>>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Record::VT_TAGS, tags);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> RecordBuilder<'a, 'b> {
     let start = _fbb.start_table();
     RecordBuilder {
@@ -593,6 +674,11 @@ impl std::fmt::Debug for Record<'_> {
       ds.field("world_name", &self.world_name());
       ds.field("data", &self.data());
       ds.field("flex", &self.flex());
+      ds.field("expires_at", &self.expires_at());
+      ds.field("version", &self.version());
+      ds.field("owner", &self.owner());
+      ds.field("writers", &self.writers());
+      ds.field("tags", &self.tags());
       ds.finish()
   }
 }
@@ -604,6 +690,11 @@ pub struct RecordT {
   pub world_name: Option<String>,
   pub data: Option<String>,
   pub flex: Option<Vec<u8>>,
+  pub expires_at: Option<u64>,
+  pub version: Option<u64>,
+  pub owner: Option<String>,
+  pub writers: Option<Vec<String>>,
+  pub tags: Option<Vec<String>>,
 }
 impl Default for RecordT {
   fn default() -> Self {
@@ -613,6 +704,11 @@ impl Default for RecordT {
       world_name: None,
       data: None,
       flex: None,
+      expires_at: None,
+      version: None,
+      owner: None,
+      writers: None,
+      tags: None,
     }
   }
 }
@@ -635,12 +731,28 @@ impl RecordT {
     let flex = self.flex.as_ref().map(|x|{
       _fbb.create_vector(x)
     });
+    let expires_at = self.expires_at;
+    let version = self.version;
+    let owner = self.owner.as_ref().map(|x|{
+      _fbb.create_string(x)
+    });
+    let writers = self.writers.as_ref().map(|x|{
+      let w: Vec<_> = x.iter().map(String::as_str).collect();_fbb.create_vector_of_strings(&w)
+    });
+    let tags = self.tags.as_ref().map(|x|{
+      let w: Vec<_> = x.iter().map(String::as_str).collect();_fbb.create_vector_of_strings(&w)
+    });
     Record::create(_fbb, &RecordArgs{
       uuid,
       position,
       world_name,
       data,
       flex,
+      expires_at,
+      version,
+      owner,
+      writers,
+      tags,
     })
   }
 }
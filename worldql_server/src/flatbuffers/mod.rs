@@ -1,3 +1,4 @@
+// Generated by flatc from WorldQLFB.fbs, never edit by hand
 #[allow(dead_code, unused_imports, clippy::all)]
 #[path = "./WorldQLFB_generated.rs"]
 mod generated;
@@ -6,19 +6,20 @@
     clippy::redundant_closure_for_method_calls
 )]
 
-use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use color_eyre::Result;
 use dotenv::dotenv;
 use tokio::sync::RwLock;
-use tokio_postgres::NoTls;
-use tracing::{debug, error, info, warn};
+use tracing::{error, info, warn};
 
-use crate::args::Args;
-use crate::database::DatabaseClient;
-use crate::processing::start_processing_thread;
+use crate::args::{Args, Command, DatabaseBackend};
+#[cfg(feature = "sqlite")]
+use crate::database::SqliteStore;
+use crate::database::{ConnectionPool, DatabaseClient, MemoryStore, ThreadRecordStore};
+use crate::processing::{start_processing_thread, NearestLimits};
 #[cfg(feature = "http")]
 use crate::transport::start_http_server;
 #[cfg(feature = "websocket")]
@@ -26,10 +27,14 @@ use crate::transport::start_websocket_server;
 #[cfg(feature = "zeromq")]
 use crate::transport::{start_zeromq_incoming, start_zeromq_outgoing};
 use crate::transport::{PeerMap, ThreadPeerMap};
+use crate::utils::{health, Health, PeerAuth, ThreadHealth, ThreadPeerAuth};
 
 mod args;
+mod commands;
 mod database;
 mod flatbuffers;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod processing;
 mod structures;
 mod subscriptions;
@@ -69,32 +74,15 @@ async fn main() -> Result<()> {
         .with_env_filter(filter)
         .init();
 
-    // Check for port clashes
-    {
-        let mut used_ports = HashSet::new();
-
-        #[cfg(feature = "websocket")]
-        {
-            if !portpicker::is_free_tcp(args.ws_port) {
-                error!("WebSocket Server port {} is already in use!", args.ws_port);
-                std::process::exit(1);
-            }
-
-            used_ports.insert(args.ws_port);
-        }
-
-        #[cfg(feature = "zeromq")]
-        {
-            let server_inserted = used_ports.insert(args.zmq_server_port);
-            if !server_inserted || !portpicker::is_free_tcp(args.zmq_server_port) {
-                error!(
-                    "ZeroMQ Server port {} is already in use!",
-                    args.zmq_server_port
-                );
+    // Run admin commands instead of the server
+    match &args.command {
+        None | Some(Command::Serve) => (),
+        Some(command) => return commands::run_command(command, &args).await,
+    }
 
-                std::process::exit(1);
-            }
-        }
+    // Check for port clashes
+    if !args.check_ports() {
+        std::process::exit(1);
     }
 
     // Validate args
@@ -103,48 +91,105 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    let psql_result = tokio_postgres::connect(&args.psql_conn, NoTls).await;
-    if let Err(err) = psql_result {
-        error!("PostgreSQL Error: {}", err);
-        std::process::exit(1);
-    }
+    let health: ThreadHealth = Arc::new(Health::new(&[
+        #[cfg(feature = "websocket")]
+        health::WEBSOCKET,
+        #[cfg(feature = "zeromq")]
+        health::ZEROMQ,
+        #[cfg(feature = "zeromq")]
+        health::ZEROMQ_OUTGOING,
+    ]));
+
+    let store: ThreadRecordStore = match &args.storage {
+        DatabaseBackend::Postgres => {
+            health.set_ready(health::POSTGRES, false);
+
+            // Validated by Args::validate()
+            let psql_conn = args.psql_conn.as_deref().unwrap();
+            let pool = match ConnectionPool::new(psql_conn, args.db_pool_size, health.clone()) {
+                Ok(pool) => pool,
+                Err(err) => {
+                    error!("PostgreSQL Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+
+            let client = DatabaseClient::new(
+                pool,
+                args.db_region_x_size,
+                args.db_region_y_size,
+                args.db_region_z_size,
+                args.db_table_size,
+                args.db_cache_size,
+                args.db_write_buffer_size,
+            );
+
+            // Init database
+            if let Err(error) = client.init_database().await {
+                error!("Failed to initialize database!");
+                error!("{}", error);
+
+                std::process::exit(1);
+            };
+
+            info!("Connected to PostgreSQL");
+            health.set_ready(health::POSTGRES, true);
 
-    let (client, psql_conn) = psql_result.unwrap();
-    tokio::spawn(async move {
-        debug!("spawned postgres read thread");
-        if let Err(e) = psql_conn.await {
-            error!("PostgreSQL Connection Error: {}", e);
+            Arc::new(client)
         }
-    });
-
-    info!("Connected to PostgreSQL");
-    let client = DatabaseClient::new(
-        client,
-        args.db_region_x_size,
-        args.db_region_y_size,
-        args.db_region_z_size,
-        args.db_table_size,
-        args.db_cache_size,
-    );
-
-    // Init database
-    if let Err(error) = client.init_database().await {
-        error!("Failed to create database tables!");
-        error!("{}", error);
 
-        std::process::exit(1);
+        DatabaseBackend::Memory => {
+            warn!("Using in-memory record storage, records will be lost on exit!");
+
+            Arc::new(MemoryStore::new(
+                args.db_region_x_size,
+                args.db_region_y_size,
+                args.db_region_z_size,
+            ))
+        }
+
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite(path) => {
+            let store = match SqliteStore::open(
+                path,
+                args.db_region_x_size,
+                args.db_region_y_size,
+                args.db_region_z_size,
+                args.db_table_size,
+            ) {
+                Ok(store) => store,
+                Err(error) => {
+                    error!("Failed to initialize database!");
+                    error!("{}", error);
+
+                    std::process::exit(1);
+                }
+            };
+
+            info!("Opened SQLite database {}", path.display());
+            Arc::new(store)
+        }
     };
 
     let (msg_tx, msg_rx) = flume::unbounded();
     let (remove_tx, remove_rx) = flume::unbounded();
+    #[cfg(feature = "http")]
+    let (sub_query_tx, sub_query_rx) = flume::unbounded();
+    #[cfg(not(feature = "http"))]
+    let (_, sub_query_rx) = flume::unbounded();
 
     let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+    let peer_auth: ThreadPeerAuth = Arc::new(PeerAuth::new(args.peer_tokens, args.admin_peers));
     let mut handles = vec![];
 
     #[cfg(feature = "http")]
     {
         let http_handle = tokio::spawn(start_http_server(
+            peer_map.clone(),
+            store.clone(),
             msg_tx.clone(),
+            sub_query_tx,
+            health.clone(),
             args.http_host,
             args.http_port,
             args.http_auth_token,
@@ -158,6 +203,8 @@ async fn main() -> Result<()> {
         let ws_handle = tokio::spawn(start_websocket_server(
             peer_map.clone(),
             msg_tx.clone(),
+            peer_auth.clone(),
+            health.clone(),
             args.ws_host,
             args.ws_port,
         ));
@@ -175,6 +222,7 @@ async fn main() -> Result<()> {
             peer_map.clone(),
             msg_tx,
             zmq_handshake_tx,
+            health.clone(),
             args.zmq_server_host,
             args.zmq_server_port,
             ctx.clone(),
@@ -185,6 +233,8 @@ async fn main() -> Result<()> {
             zmq_msg_tx,
             zmq_msg_rx,
             zmq_handshake_rx,
+            peer_auth.clone(),
+            health.clone(),
             ctx,
             args.zmq_timeout_secs,
         ));
@@ -194,11 +244,24 @@ async fn main() -> Result<()> {
     }
 
     let proc_handle = tokio::spawn(start_processing_thread(
-        client,
+        store,
         peer_map,
         msg_rx,
         remove_rx,
+        sub_query_rx,
         args.sub_region_size,
+        args.db_pool_size,
+        args.reply_chunk_size,
+        NearestLimits {
+            max_count: args.max_nearest_count,
+            max_distance: args.max_nearest_distance.into(),
+        },
+        Duration::from_secs(args.db_expiry_interval_secs.into()),
+        args.db_expiry_batch_size,
+        match args.db_history_retention_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
     ));
 
     handles.push(proc_handle);
@@ -0,0 +1,72 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+};
+
+pub(super) static REGISTRY: Lazy<Registry> =
+    Lazy::new(|| Registry::new_custom(Some("worldql".into()), None).unwrap());
+
+macro_rules! register {
+    ($collector: expr) => {{
+        let collector = $collector.unwrap();
+        REGISTRY.register(Box::new(collector.clone())).unwrap();
+
+        collector
+    }};
+}
+
+// region: Transport
+/// Number of connected peers, labelled by transport.
+pub static PEERS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let opts = Opts::new("peers", "Number of connected peers");
+    register!(IntGaugeVec::new(opts, &["transport"]))
+});
+
+/// Number of peers each broadcast was sent to.
+pub static BROADCAST_FANOUT: Lazy<Histogram> = Lazy::new(|| {
+    let opts =
+        HistogramOpts::new("broadcast_fanout", "Number of peers per broadcast").buckets(vec![
+            0.0, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0,
+        ]);
+
+    register!(Histogram::with_opts(opts))
+});
+// endregion
+
+// region: Processing
+/// Number of messages received by the processing thread, labelled by instruction.
+pub static MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new("messages_total", "Number of messages received");
+    register!(IntCounterVec::new(opts, &["instruction"]))
+});
+
+/// Number of messages waiting in each internal queue.
+pub static QUEUE_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "queue_depth",
+        "Number of messages waiting in internal queues",
+    );
+    register!(IntGaugeVec::new(opts, &["queue"]))
+});
+// endregion
+
+// region: Database
+/// Database operation latency, labelled by operation.
+pub static DB_QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "db_query_duration_seconds",
+        "Latency of database operations",
+    );
+
+    register!(HistogramVec::new(opts, &["operation"]))
+});
+
+/// Navigation cache lookups, labelled by cache and result (`hit` or `miss`).
+pub static DB_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "db_cache_lookups_total",
+        "Number of navigation cache lookups",
+    );
+    register!(IntCounterVec::new(opts, &["cache", "result"]))
+});
+// endregion
@@ -0,0 +1,17 @@
+use prometheus::{Encoder, TextEncoder};
+
+use super::collectors::REGISTRY;
+
+/// Encode all registered metrics using the Prometheus text format.
+///
+/// Returns a tuple of the form `(content_type, body)`
+pub fn encode_metrics() -> (String, Vec<u8>) {
+    let encoder = TextEncoder::new();
+    let families = REGISTRY.gather();
+
+    let mut buf = vec![];
+    // Writing to a Vec is infallible
+    let _ = encoder.encode(&families, &mut buf);
+
+    (encoder.format_type().to_string(), buf)
+}
@@ -0,0 +1,5 @@
+mod collectors;
+mod encode;
+
+pub use collectors::*;
+pub use encode::encode_metrics;
@@ -1,16 +1,24 @@
 use color_eyre::Result;
+use flume::Sender;
 use tracing::{debug, warn};
 
+use super::area_sync::SYNC_PARAMETER;
 use crate::structures::Message;
 use crate::subscriptions::WorldMap;
 use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
 use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
 
-pub(super) fn handle_area_subscribe(
+/// Subscribes the peer to the area containing `position`.
+///
+/// If the parameter is [`SYNC_PARAMETER`], the DB task is also asked to send the peer
+/// every record currently in the area. Changes to the area are held back from the
+/// peer until then.
+pub(super) async fn handle_area_subscribe(
     message: Message,
     peer_map: &ThreadPeerMap,
     world_map: &mut WorldMap,
+    db_tx: &Sender<Message>,
 ) -> Result<()> {
     trace_packet!("{}", &message);
 
@@ -48,5 +56,16 @@ pub(super) fn handle_area_subscribe(
     let area_map = world_map.get_mut(&world_name);
     area_map.add_subscription(uuid, cube);
 
+    if message.parameter.as_deref() == Some(SYNC_PARAMETER) {
+        area_map.add_pending_sync(uuid, cube);
+
+        let message = Message {
+            world_name,
+            ..message
+        };
+
+        db_tx.send_async(message).await?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,283 @@
+use color_eyre::Result;
+use flume::Sender;
+use tracing::warn;
+
+use super::record_reply::chunk_replies;
+use crate::database::{DataFilter, RecordStore};
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::{ToCubeArea, WorldMap};
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+
+/// `AreaSubscribe` parameter opting into an initial sync of the subscribed area.
+///
+/// The peer is sent `RecordReply` messages with this parameter containing every record
+/// in the area, followed by any later changes. Replies are chunked by [`chunk_replies`],
+/// so the last one is always sent, even if the area is empty.
+pub(super) const SYNC_PARAMETER: &str = "sync";
+
+/// Read every record in a newly subscribed area, and send them to the subscription
+/// task to be forwarded to the peer.
+///
+/// Must only be called once every earlier record change has been stored and sent to
+/// the subscription task, and before any later change is stored. This way changes
+/// are either included in the sync or sent after it, never both.
+///
+/// If the read fails, the peer is unsubscribed from the area instead.
+pub(super) async fn handle_area_sync(
+    message: Message,
+    store: &dyn RecordStore,
+    sub_tx: &Sender<Message>,
+    cube_size: u16,
+) -> Result<()> {
+    trace_packet!("{}", &message);
+
+    let uuid = message.sender_uuid;
+    let position = match message.position {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let cube = position.to_cube_area(cube_size);
+    let result = store
+        .get_records_in_area(
+            &message.world_name,
+            cube.to_area(cube_size),
+            None,
+            &DataFilter::default(),
+        )
+        .await;
+
+    let reply = match result {
+        Ok(mut records) => {
+            records.retain(|record| match record.position {
+                Some(position) => position.to_cube_area(cube_size) == cube,
+                None => false,
+            });
+
+            Message {
+                instruction: Instruction::RecordReply,
+                parameter: Some(SYNC_PARAMETER.into()),
+                sender_uuid: uuid,
+                world_name: message.world_name,
+                records,
+                position: Some(position),
+                ..Default::default()
+            }
+        }
+
+        Err(error) => {
+            warn!("error syncing area for {}: {}", uuid, error);
+            Message {
+                instruction: Instruction::AreaUnsubscribe,
+                ..message
+            }
+        }
+    };
+
+    sub_tx.send_async(reply).await?;
+    Ok(())
+}
+
+/// Forward an initial sync read by [`handle_area_sync`] to the subscribed peer.
+///
+/// Syncs for areas the peer has since unsubscribed from are dropped.
+pub(super) async fn handle_area_sync_reply(
+    mut message: Message,
+    peer_map: &ThreadPeerMap,
+    world_map: &mut WorldMap,
+    chunk_size: usize,
+) -> Result<()> {
+    let uuid = message.sender_uuid;
+    let position = match message.position {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let area_map = world_map.get_mut(&message.world_name);
+    if !area_map.finish_pending_sync(&uuid, position) {
+        return Ok(());
+    }
+
+    let records = std::mem::take(&mut message.records);
+    let reply = Message {
+        sender_uuid: Default::default(),
+        ..message
+    };
+
+    let mut map = peer_map.write().await;
+    if let Some(peer) = map.get_mut(&uuid) {
+        for reply in chunk_replies(reply, records, chunk_size) {
+            if peer.send(reply).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::{MemoryStore, Writer};
+    use crate::processing::area_subscribe::handle_area_subscribe;
+    use crate::processing::area_unsubscribe::handle_area_unsubscribe;
+    use crate::structures::{Record, Vector3};
+    use crate::transport::{Peer, PeerMap};
+
+    struct Harness {
+        store: MemoryStore,
+        peer_map: ThreadPeerMap,
+        world_map: WorldMap,
+        uuid: Uuid,
+        rx: flume::Receiver<Bytes>,
+    }
+
+    impl Harness {
+        async fn new() -> Self {
+            let (remove_tx, _) = flume::unbounded();
+            let peer_map = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+            let uuid = Uuid::new_v4();
+            let (peer, rx) = Peer::new_test(uuid);
+            peer_map.write().await.insert(uuid, peer).await;
+
+            let store = MemoryStore::new(16, 16, 16);
+            let records = [-20.0, -1.0, 0.0, 5.0, 16.0, 16.5, 40.0]
+                .iter()
+                .map(|x| Record {
+                    uuid: Uuid::new_v4(),
+                    position: Some(Vector3::new(*x, 1.0, 1.0)),
+                    world_name: "world".into(),
+                    ..Default::default()
+                })
+                .collect();
+
+            assert!(store
+                .insert_records(records, &Writer::SERVER)
+                .await
+                .errors
+                .is_empty());
+
+            Self {
+                store,
+                peer_map,
+                world_map: WorldMap::new(16),
+                uuid,
+                rx,
+            }
+        }
+
+        fn message(&self, instruction: Instruction, parameter: Option<&str>) -> Message {
+            Message {
+                instruction,
+                parameter: parameter.map(Into::into),
+                sender_uuid: self.uuid,
+                world_name: "world".into(),
+                position: Some(Vector3::new(1.0, 1.0, 1.0)),
+                ..Default::default()
+            }
+        }
+
+        /// Subscribe, returning the sync request sent to the DB task
+        async fn subscribe(&mut self, parameter: Option<&str>) -> Option<Message> {
+            let (db_tx, db_rx) = flume::unbounded();
+            let message = self.message(Instruction::AreaSubscribe, parameter);
+            handle_area_subscribe(message, &self.peer_map, &mut self.world_map, &db_tx)
+                .await
+                .unwrap();
+
+            db_rx.try_recv().ok()
+        }
+
+        /// Read the sync and forward it to the peer
+        async fn sync(&mut self, request: Message) {
+            let (sub_tx, sub_rx) = flume::unbounded();
+            handle_area_sync(request, &self.store, &sub_tx, 16)
+                .await
+                .unwrap();
+
+            let reply = sub_rx.try_recv().unwrap();
+            assert_eq!(reply.instruction, Instruction::RecordReply);
+
+            handle_area_sync_reply(reply, &self.peer_map, &mut self.world_map, 2)
+                .await
+                .unwrap();
+        }
+
+        fn received(&self) -> Vec<Message> {
+            self.rx
+                .try_iter()
+                .map(|bytes| Message::deserialize(&bytes).unwrap())
+                .filter(|message| message.instruction != Instruction::PeerConnect)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn opt_in() {
+        let mut harness = Harness::new().await;
+        assert!(harness.subscribe(None).await.is_none());
+        assert!(harness.subscribe(Some("other")).await.is_none());
+
+        let area_map = harness.world_map.get("world").unwrap();
+        assert!(!area_map.is_sync_pending(&harness.uuid, Vector3::new(1.0, 1.0, 1.0)));
+    }
+
+    #[tokio::test]
+    async fn sync_subscribed_area() {
+        let mut harness = Harness::new().await;
+        let request = harness.subscribe(Some(SYNC_PARAMETER)).await.unwrap();
+
+        let position = Vector3::new(1.0, 1.0, 1.0);
+        let area_map = harness.world_map.get("world").unwrap();
+        assert!(area_map.is_peer_subscribed(&harness.uuid, position));
+        assert!(area_map.is_sync_pending(&harness.uuid, position));
+
+        harness.sync(request).await;
+
+        let area_map = harness.world_map.get("world").unwrap();
+        assert!(!area_map.is_sync_pending(&harness.uuid, position));
+
+        let messages = harness.received();
+        let parameters = messages
+            .iter()
+            .map(|message| message.parameter.as_deref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(parameters, vec![Some("sync"), Some("sync;end")]);
+        assert!(messages
+            .iter()
+            .all(|message| message.instruction == Instruction::RecordReply));
+
+        let mut xs = messages
+            .iter()
+            .flat_map(|message| &message.records)
+            .map(|record| *record.position.unwrap().x())
+            .collect::<Vec<_>>();
+
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(xs, vec![0.0, 5.0, 16.0]);
+    }
+
+    #[tokio::test]
+    async fn unsubscribed_before_sync() {
+        let mut harness = Harness::new().await;
+        let request = harness.subscribe(Some(SYNC_PARAMETER)).await.unwrap();
+
+        let message = harness.message(Instruction::AreaUnsubscribe, None);
+        handle_area_unsubscribe(message, &harness.peer_map, &mut harness.world_map).unwrap();
+
+        harness.sync(request).await;
+        assert!(harness.received().is_empty());
+    }
+}
+// endregion
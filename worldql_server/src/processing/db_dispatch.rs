@@ -0,0 +1,281 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ahash::{AHashMap, AHashSet};
+use uuid::Uuid;
+
+use super::record_atomic::is_atomic;
+use crate::structures::{Instruction, Message};
+
+/// Split a database [`Message`] into per-worker messages.
+///
+/// Records are assigned to workers by `(world_name, uuid)`, so every operation on a
+/// single record is always handled by the same worker, in the order it was received.
+/// Reads are assigned by `(world_name, sender_uuid)` instead, keeping each peer's
+/// reads ordered relative to each other. Reads and writes from the same peer may land
+/// on different workers, see [`PeerOrdering`] for how they are kept in order.
+///
+/// Atomic writes are never split, so they are committed in a single transaction. They
+/// are assigned by their first record, and must be fenced by the caller to stay ordered
+/// with changes to their other records.
+///
+/// Returned tuples have the form `(worker_index, message)`
+pub(super) fn shard_message(message: Message, workers: usize) -> Vec<(usize, Message)> {
+    if workers <= 1 {
+        return vec![(0, message)];
+    }
+
+    if message.instruction == Instruction::RecordRead {
+        let idx = shard_index(&message.world_name, &message.sender_uuid, workers);
+        return vec![(idx, message)];
+    }
+
+    if is_atomic(&message) {
+        let idx = match message.records.first() {
+            Some(record) => shard_index(&record.world_name, &record.uuid, workers),
+            None => shard_index(&message.world_name, &message.sender_uuid, workers),
+        };
+
+        return vec![(idx, message)];
+    }
+
+    let mut shards: Vec<Vec<_>> = vec![vec![]; workers];
+    let mut message = message;
+    for record in message.records.drain(..) {
+        let idx = shard_index(&record.world_name, &record.uuid, workers);
+        shards[idx].push(record);
+    }
+
+    shards
+        .into_iter()
+        .enumerate()
+        .filter(|(_, records)| !records.is_empty())
+        .map(|(idx, records)| {
+            let message = Message {
+                records,
+                ..message.clone()
+            };
+
+            (idx, message)
+        })
+        .collect()
+}
+
+// region: PeerOrdering Struct
+/// Tracks which workers were sent each peer's reads and writes, so a peer's reads are
+/// ordered with its writes even when handled by different workers.
+///
+/// A read waits for every worker holding the peer's earlier writes, so it sees them.
+/// A write waits for every worker holding the peer's earlier reads, so they don't see
+/// it. Writes aren't ordered with each other here, as each record is always handled by
+/// the same worker.
+#[derive(Debug, Default)]
+pub(super) struct PeerOrdering {
+    peers: AHashMap<Uuid, PeerTasks>,
+}
+
+#[derive(Debug, Default)]
+struct PeerTasks {
+    reads: AHashSet<usize>,
+    writes: AHashSet<usize>,
+}
+
+impl PeerOrdering {
+    /// Record `message` as dispatched to the workers in `shards`, returning the workers
+    /// each shard must wait for first.
+    pub(super) fn dispatch(&mut self, message: &Message, shards: &[usize]) -> Vec<Vec<usize>> {
+        let tasks = self.peers.entry(message.sender_uuid).or_default();
+        let (waited, dispatched) = match message.instruction {
+            Instruction::RecordRead => (&mut tasks.writes, &mut tasks.reads),
+            _ => (&mut tasks.reads, &mut tasks.writes),
+        };
+
+        let dependencies = shards
+            .iter()
+            .map(|idx| waited.iter().copied().filter(|dep| dep != idx).collect())
+            .collect();
+
+        // Shards that waited now stand in for the workers they waited for, so later tasks
+        // still wait for everything dispatched before them
+        if !waited.is_empty() {
+            *waited = shards.iter().copied().collect();
+        }
+
+        dispatched.extend(shards);
+
+        dependencies
+    }
+
+    /// Forget every dispatched task, once all workers have handled them.
+    pub(super) fn clear(&mut self) {
+        self.peers.clear();
+    }
+}
+// endregion
+
+#[inline]
+fn shard_index(world_name: &str, uuid: &Uuid, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    world_name.hash(&mut hasher);
+    uuid.hash(&mut hasher);
+
+    (hasher.finish() % workers as u64) as usize
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::Record;
+
+    fn record(world_name: &str, uuid: Uuid) -> Record {
+        Record {
+            uuid,
+            world_name: world_name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn create(records: Vec<Record>) -> Message {
+        Message {
+            instruction: Instruction::RecordCreate,
+            world_name: "world".into(),
+            records,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_worker() {
+        let records = (0..8).map(|_| record("world", Uuid::new_v4())).collect();
+        let shards = shard_message(create(records), 1);
+
+        assert_eq!(shards.len(), 1);
+        assert_eq!(shards[0].0, 0);
+        assert_eq!(shards[0].1.records.len(), 8);
+    }
+
+    #[test]
+    fn same_record_same_worker() {
+        let uuid = Uuid::new_v4();
+        let expected = shard_index("world", &uuid, 8);
+
+        for _ in 0..4 {
+            let shards = shard_message(create(vec![record("world", uuid)]), 8);
+            assert_eq!(shards.len(), 1);
+            assert_eq!(shards[0].0, expected);
+        }
+    }
+
+    #[test]
+    fn records_are_preserved() {
+        let records = (0..64)
+            .map(|_| record("world", Uuid::new_v4()))
+            .collect::<Vec<_>>();
+
+        let shards = shard_message(create(records.clone()), 4);
+        assert!(shards.len() > 1);
+
+        let mut total = 0;
+        for (idx, message) in shards {
+            assert_eq!(message.instruction, Instruction::RecordCreate);
+            for record in message.records {
+                assert_eq!(shard_index(&record.world_name, &record.uuid, 4), idx);
+                total += 1;
+            }
+        }
+
+        assert_eq!(total, records.len());
+    }
+
+    #[test]
+    fn atomic_never_split() {
+        let records = (0..64)
+            .map(|_| record("world", Uuid::new_v4()))
+            .collect::<Vec<_>>();
+
+        for instruction in [
+            Instruction::RecordCreate,
+            Instruction::RecordUpdate,
+            Instruction::RecordDelete,
+        ] {
+            let message = Message {
+                instruction,
+                parameter: Some("atomic".into()),
+                ..create(records.clone())
+            };
+
+            let shards = shard_message(message, 4);
+            assert_eq!(shards.len(), 1);
+            assert_eq!(shards[0].0, shard_index("world", &records[0].uuid, 4));
+            assert_eq!(shards[0].1.records.len(), records.len());
+        }
+    }
+
+    #[test]
+    fn peer_ordering() {
+        let sender_uuid = Uuid::new_v4();
+        let read = Message {
+            instruction: Instruction::RecordRead,
+            sender_uuid,
+            ..Default::default()
+        };
+
+        let write = Message {
+            sender_uuid,
+            ..create(vec![])
+        };
+
+        let none = Vec::<usize>::new;
+        let mut ordering = PeerOrdering::default();
+
+        // Writes don't wait for other writes
+        assert_eq!(ordering.dispatch(&write, &[0, 1]), vec![none(), none()]);
+        assert_eq!(ordering.dispatch(&write, &[2]), vec![none()]);
+
+        // Reads wait for every worker holding earlier writes, except their own
+        let mut deps = ordering.dispatch(&read, &[1]);
+        deps[0].sort_unstable();
+        assert_eq!(deps, vec![vec![0, 2]]);
+
+        // Later reads wait for the earlier read, which waited for the writes
+        assert_eq!(ordering.dispatch(&read, &[3]), vec![vec![1]]);
+
+        // Writes wait for workers holding earlier reads
+        let mut deps = ordering.dispatch(&write, &[1, 2]);
+        deps[0].sort_unstable();
+        deps[1].sort_unstable();
+        assert_eq!(deps, vec![vec![3], vec![1, 3]]);
+
+        // Later writes wait for the earlier write, which waited for the reads
+        let mut deps = ordering.dispatch(&write, &[0]);
+        deps[0].sort_unstable();
+        assert_eq!(deps, vec![vec![1, 2]]);
+
+        // Other peers are independent
+        let other = Message {
+            sender_uuid: Uuid::new_v4(),
+            ..read.clone()
+        };
+        assert_eq!(ordering.dispatch(&other, &[0]), vec![none()]);
+
+        ordering.clear();
+        assert_eq!(ordering.dispatch(&read, &[0]), vec![none()]);
+    }
+
+    #[test]
+    fn reads_by_sender() {
+        let sender_uuid = Uuid::new_v4();
+        let message = Message {
+            instruction: Instruction::RecordRead,
+            world_name: "world".into(),
+            sender_uuid,
+            ..Default::default()
+        };
+
+        let shards = shard_message(message, 4);
+        assert_eq!(shards.len(), 1);
+        assert_eq!(shards[0].0, shard_index("world", &sender_uuid, 4));
+    }
+}
+// endregion
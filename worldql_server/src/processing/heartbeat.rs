@@ -30,7 +30,6 @@ pub(super) async fn handle_heartbeat(message: Message, peer_map: &ThreadPeerMap)
     };
 
     // Update last received time
-    #[cfg(feature = "zeromq")]
     peer.update_last_heartbeat();
 
     // Echo back heartbeat
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{debug, warn};
+
+use crate::database::{DatabaseError, RecordStore};
+
+/// Delete record history entries older than `retention`.
+pub(super) async fn handle_history_prune(store: &dyn RecordStore, retention: Duration) {
+    let now = Utc::now().naive_utc();
+    let before = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| now.checked_sub_signed(retention));
+
+    // Retention is longer than history could possibly be
+    let before = match before {
+        Some(before) => before,
+        None => return,
+    };
+
+    match store.delete_history_before(before).await {
+        Ok(0) => (),
+        Ok(deleted) => debug!("deleted {} history entries", deleted),
+
+        // Unavailable errors are already logged by the connection pool
+        Err(DatabaseError::Unavailable) => (),
+        Err(error) => warn!("error deleting record history: {}", error),
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::{MemoryStore, Writer};
+    use crate::structures::{Record, Vector3};
+
+    #[tokio::test]
+    async fn prune() {
+        let store = MemoryStore::new(16, 16, 16);
+        let uuid = Uuid::new_v4();
+        let record = Record {
+            uuid,
+            position: Some(Vector3::new(1.0, 1.0, 1.0)),
+            world_name: "world".into(),
+            ..Default::default()
+        };
+
+        let writer = Writer {
+            peer: Some(Uuid::new_v4()),
+            ..Writer::SERVER
+        };
+
+        assert!(store
+            .insert_records(vec![record.clone()], &writer)
+            .await
+            .errors
+            .is_empty());
+        assert!(store
+            .delete_records(vec![record], &writer)
+            .await
+            .errors
+            .is_empty());
+
+        let history = store.get_record_history("world", uuid, 10).await.unwrap();
+        let operations = history
+            .iter()
+            .map(|entry| entry.operation.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(operations, vec!["delete", "create"]);
+        assert!(history.iter().all(|entry| entry.peer == writer.peer));
+
+        // Entries newer than the retention period are kept
+        handle_history_prune(&store, Duration::from_secs(3600)).await;
+        let history = store.get_record_history("world", uuid, 10).await.unwrap();
+        assert_eq!(history.len(), 2);
+
+        handle_history_prune(&store, Duration::ZERO).await;
+        let history = store.get_record_history("world", uuid, 10).await.unwrap();
+        assert!(history.is_empty());
+    }
+}
+// endregion
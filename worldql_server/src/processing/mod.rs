@@ -1,11 +1,22 @@
 mod area_subscribe;
+mod area_sync;
 mod area_unsubscribe;
+mod db_dispatch;
 mod global_message;
 mod heartbeat;
+mod history_prune;
 mod local_message;
+mod read_query;
+mod record_atomic;
 mod record_create;
 mod record_delete;
+mod record_expire;
+mod record_notify;
 mod record_read;
+mod record_reject;
+mod record_reply;
+mod subscription_query;
 mod thread;
 
+pub use read_query::NearestLimits;
 pub use thread::start_processing_thread;
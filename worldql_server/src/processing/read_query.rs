@@ -0,0 +1,360 @@
+use std::num::{ParseFloatError, ParseIntError};
+
+use chrono::NaiveDateTime;
+use thiserror::Error;
+
+use crate::database::{DataFilter, DataFilterError};
+use crate::structures::{Area, Vector3};
+use crate::utils::{is_valid_tag, parse_epoch_millis, ParseEpochError};
+
+/// Which records a [`crate::structures::Instruction::RecordRead`] selects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum ReadMode {
+    /// The single database region containing a position
+    Region(Vector3),
+
+    /// Every record inside an [`Area`]
+    Area(Area),
+
+    /// Up to `count` records nearest to `point`, sorted by distance
+    Nearest {
+        point: Vector3,
+        count: usize,
+        max_distance: f64,
+    },
+
+    /// Every record in the world, only used by tag reads
+    World,
+}
+
+/// Max distance used by nearest reads that don't specify one
+const DEFAULT_NEAREST_DISTANCE: f64 = 1024.0;
+
+/// Server-side limits applied to [`ReadMode::Nearest`] reads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearestLimits {
+    pub max_count: usize,
+    pub max_distance: f64,
+}
+
+impl NearestLimits {
+    /// Lower the count and max distance of a nearest read to these limits.
+    pub(super) fn clamp(&self, mode: &mut ReadMode) {
+        if let ReadMode::Nearest {
+            count,
+            max_distance,
+            ..
+        } = mode
+        {
+            *count = (*count).min(self.max_count);
+            *max_distance = max_distance.min(self.max_distance);
+        }
+    }
+}
+
+/// A parsed `RecordRead` parameter.
+///
+/// The parameter is a list of `;` separated segments, each being one of:
+/// - `box:<x>,<y>,<z>` selects the box between `position` and the given corner
+/// - `sphere:<radius>` selects the sphere centered on `position`
+/// - `nearest:<count>[,<max distance>]` selects the records nearest to `position`
+/// - `filter:<conditions>` only selects records with data matching a [`DataFilter`]
+/// - `tag:<tag>` only selects records tagged with `tag`
+/// - `<epoch millis>` only selects records modified after that time
+///
+/// Without a mode segment, the region containing `position` is read. Tag reads without
+/// a mode segment read the whole world instead, and are the only reads that don't
+/// require a `position`.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ReadQuery {
+    pub mode: ReadMode,
+    pub after: Option<NaiveDateTime>,
+    pub filter: DataFilter,
+    pub tag: Option<String>,
+}
+
+impl ReadQuery {
+    pub fn parse(
+        parameter: Option<&str>,
+        position: Option<Vector3>,
+    ) -> Result<Self, ReadQueryError> {
+        let mut query = Self {
+            mode: ReadMode::World,
+            after: None,
+            filter: DataFilter::default(),
+            tag: None,
+        };
+
+        let segments = parameter
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty());
+
+        for segment in segments {
+            let (mode, args) = match segment.split_once(':') {
+                Some(pair) => pair,
+                None => {
+                    query.after = Some(parse_epoch_millis(segment)?);
+                    continue;
+                }
+            };
+
+            if mode.trim() == "filter" {
+                if !query.filter.is_empty() {
+                    return Err(ReadQueryError::DuplicateFilter);
+                }
+
+                query.filter = args.parse()?;
+                continue;
+            }
+
+            if mode.trim() == "tag" {
+                if query.tag.is_some() {
+                    return Err(ReadQueryError::DuplicateTag);
+                }
+
+                let tag = args.trim();
+                if !is_valid_tag(tag) {
+                    return Err(ReadQueryError::InvalidTag(tag.into()));
+                }
+
+                query.tag = Some(tag.into());
+                continue;
+            }
+
+            if query.mode != ReadMode::World {
+                return Err(ReadQueryError::DuplicateMode);
+            }
+
+            let position = position.ok_or(ReadQueryError::MissingPosition)?;
+            query.mode = match mode.trim() {
+                "box" => match parse_floats(args)?[..] {
+                    [x, y, z] => ReadMode::Area(Area::new_box(position, Vector3::new(x, y, z))),
+                    _ => return Err(ReadQueryError::InvalidArgs(segment.into())),
+                },
+
+                "sphere" => match parse_floats(args)?[..] {
+                    [radius] => ReadMode::Area(Area::new_sphere(position, radius)),
+                    _ => return Err(ReadQueryError::InvalidArgs(segment.into())),
+                },
+
+                "nearest" => {
+                    let (count, max_distance) = match args.split_once(',') {
+                        None => (args, DEFAULT_NEAREST_DISTANCE),
+                        Some((count, max_distance)) => match parse_floats(max_distance)?[..] {
+                            [max_distance] if max_distance >= 0.0 => (count, max_distance),
+                            _ => return Err(ReadQueryError::InvalidArgs(segment.into())),
+                        },
+                    };
+
+                    let count = count.trim().parse::<usize>()?;
+                    if count == 0 {
+                        return Err(ReadQueryError::InvalidArgs(segment.into()));
+                    }
+
+                    ReadMode::Nearest {
+                        point: position,
+                        count,
+                        max_distance,
+                    }
+                }
+
+                _ => return Err(ReadQueryError::UnknownMode(mode.into())),
+            };
+        }
+
+        if query.mode == ReadMode::World && query.tag.is_none() {
+            let position = position.ok_or(ReadQueryError::MissingPosition)?;
+            query.mode = ReadMode::Region(position);
+        }
+
+        Ok(query)
+    }
+}
+
+#[inline]
+fn parse_floats(args: &str) -> Result<Vec<f64>, ReadQueryError> {
+    let floats = args
+        .split(',')
+        .map(|arg| arg.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if floats.iter().any(|float| !float.is_finite()) {
+        return Err(ReadQueryError::InvalidArgs(args.into()));
+    }
+
+    Ok(floats)
+}
+
+#[derive(Debug, Error)]
+pub(super) enum ReadQueryError {
+    #[error("unknown read mode: {0}")]
+    UnknownMode(String),
+
+    #[error("invalid read mode arguments: {0}")]
+    InvalidArgs(String),
+
+    #[error("only one read mode may be given")]
+    DuplicateMode,
+
+    #[error("only one filter may be given")]
+    DuplicateFilter,
+
+    #[error("only one tag may be given")]
+    DuplicateTag,
+
+    #[error("invalid tag: {0}")]
+    InvalidTag(String),
+
+    #[error("read requires a position")]
+    MissingPosition,
+
+    #[error(transparent)]
+    InvalidFilter(#[from] DataFilterError),
+
+    #[error(transparent)]
+    InvalidNumber(#[from] ParseFloatError),
+
+    #[error(transparent)]
+    InvalidCount(#[from] ParseIntError),
+
+    #[error(transparent)]
+    InvalidTimestamp(#[from] ParseEpochError),
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! parse {
+        ($parameter:expr) => {
+            ReadQuery::parse($parameter, Some(Vector3::new(1.0, 2.0, 3.0)))
+        };
+    }
+
+    #[test]
+    fn region() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let query = parse!(None).unwrap();
+        assert_eq!(query.mode, ReadMode::Region(position));
+        assert_eq!(query.after, None);
+
+        let query = parse!(Some("1000")).unwrap();
+        assert_eq!(query.mode, ReadMode::Region(position));
+        assert_eq!(query.after, Some(NaiveDateTime::from_timestamp(1, 0)));
+    }
+
+    #[test]
+    fn areas() {
+        let query = parse!(Some("box:-1, 5, 3")).unwrap();
+        let expected = Area::new_box(Vector3::new(1.0, 2.0, 3.0), Vector3::new(-1.0, 5.0, 3.0));
+        assert_eq!(query.mode, ReadMode::Area(expected));
+
+        let query = parse!(Some("sphere:2.5;1000")).unwrap();
+        let expected = Area::new_sphere(Vector3::new(1.0, 2.0, 3.0), 2.5);
+        assert_eq!(query.mode, ReadMode::Area(expected));
+        assert!(query.after.is_some());
+    }
+
+    #[test]
+    fn nearest() {
+        let query = parse!(Some("nearest:5")).unwrap();
+        let expected = ReadMode::Nearest {
+            point: Vector3::new(1.0, 2.0, 3.0),
+            count: 5,
+            max_distance: DEFAULT_NEAREST_DISTANCE,
+        };
+
+        assert_eq!(query.mode, expected);
+
+        let query = parse!(Some("nearest:3, 64.5")).unwrap();
+        let expected = ReadMode::Nearest {
+            point: Vector3::new(1.0, 2.0, 3.0),
+            count: 3,
+            max_distance: 64.5,
+        };
+
+        assert_eq!(query.mode, expected);
+
+        assert!(parse!(Some("nearest:0")).is_err());
+        assert!(parse!(Some("nearest:-1")).is_err());
+        assert!(parse!(Some("nearest:2,-5")).is_err());
+        assert!(parse!(Some("nearest:2.5")).is_err());
+    }
+
+    #[test]
+    fn nearest_limits() {
+        let limits = NearestLimits {
+            max_count: 10,
+            max_distance: 100.0,
+        };
+
+        let mut mode = parse!(Some("nearest:1000000, 1e300")).unwrap().mode;
+        limits.clamp(&mut mode);
+        let expected = ReadMode::Nearest {
+            point: Vector3::new(1.0, 2.0, 3.0),
+            count: 10,
+            max_distance: 100.0,
+        };
+
+        assert_eq!(mode, expected);
+
+        let mut mode = parse!(Some("nearest:3, 64.5")).unwrap().mode;
+        limits.clamp(&mut mode);
+        let expected = ReadMode::Nearest {
+            point: Vector3::new(1.0, 2.0, 3.0),
+            count: 3,
+            max_distance: 64.5,
+        };
+
+        assert_eq!(mode, expected);
+    }
+
+    #[test]
+    fn filter() {
+        let query = parse!(Some("filter:type=rare,level>=5;sphere:8;1000")).unwrap();
+        assert_eq!(query.filter, "type=rare,level>=5".parse().unwrap());
+        assert!(matches!(query.mode, ReadMode::Area(_)));
+        assert!(query.after.is_some());
+
+        let query = parse!(Some("1000")).unwrap();
+        assert!(query.filter.is_empty());
+
+        assert!(parse!(Some("filter:")).is_err());
+        assert!(parse!(Some("filter:level>high")).is_err());
+        assert!(parse!(Some("filter:a=1;filter:b=2")).is_err());
+    }
+
+    #[test]
+    fn tag() {
+        let query = parse!(Some("tag:spawn_point")).unwrap();
+        assert_eq!(query.tag.as_deref(), Some("spawn_point"));
+        assert_eq!(query.mode, ReadMode::World);
+
+        let query = parse!(Some("sphere:8;tag: chest ;1000")).unwrap();
+        assert_eq!(query.tag.as_deref(), Some("chest"));
+        assert!(matches!(query.mode, ReadMode::Area(_)));
+
+        // Only tag reads may omit a position
+        let query = ReadQuery::parse(Some("tag:spawn_point"), None).unwrap();
+        assert_eq!(query.mode, ReadMode::World);
+        assert!(ReadQuery::parse(None, None).is_err());
+        assert!(ReadQuery::parse(Some("tag:a;sphere:1"), None).is_err());
+
+        assert!(parse!(Some("tag:")).is_err());
+        assert!(parse!(Some("tag:a,b")).is_err());
+        assert!(parse!(Some("tag:a;tag:b")).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse!(Some("cube:1")).is_err());
+        assert!(parse!(Some("box:1,2")).is_err());
+        assert!(parse!(Some("sphere:inf")).is_err());
+        assert!(parse!(Some("sphere:1;sphere:2")).is_err());
+        assert!(parse!(Some("yesterday")).is_err());
+    }
+}
+// endregion
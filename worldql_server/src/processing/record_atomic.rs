@@ -0,0 +1,44 @@
+use crate::structures::{Instruction, Message};
+use crate::transport::ThreadPeerMap;
+
+/// `RecordCreate`, `RecordUpdate` and `RecordDelete` parameter to write every record in
+/// the message atomically, so either every record is written or none are.
+pub(super) const ATOMIC_PARAMETER: &str = "atomic";
+
+/// `RecordReply` parameter for atomic writes where every record was written.
+///
+/// Replies contain each record as it was sent.
+pub(super) const COMMITTED_PARAMETER: &str = "committed";
+
+/// `RecordReply` parameter for atomic writes where no records were written.
+///
+/// Replies contain each record as it was sent. Records rejected by a version conflict or
+/// owned by another peer are also sent back first, see [`super::record_reject`].
+pub(super) const ABORTED_PARAMETER: &str = "aborted";
+
+/// Returns `true` if the records in `message` should be written atomically.
+#[inline]
+pub(super) fn is_atomic(message: &Message) -> bool {
+    message.parameter.as_deref() == Some(ATOMIC_PARAMETER)
+}
+
+/// Reply to the sender of an atomic write with whether it was committed.
+pub(super) async fn reply_outcome(message: &Message, committed: bool, peer_map: &ThreadPeerMap) {
+    let parameter = match committed {
+        true => COMMITTED_PARAMETER,
+        false => ABORTED_PARAMETER,
+    };
+
+    let reply = Message {
+        instruction: Instruction::RecordReply,
+        parameter: Some(parameter.into()),
+        world_name: message.world_name.clone(),
+        records: message.records.clone(),
+        ..Default::default()
+    };
+
+    let mut map = peer_map.write().await;
+    if let Some(peer) = map.get_mut(&message.sender_uuid) {
+        let _ = peer.send(reply).await;
+    }
+}
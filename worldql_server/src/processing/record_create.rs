@@ -1,14 +1,36 @@
+use ahash::AHashMap;
 use color_eyre::Result;
+use flume::Sender;
 use tracing::warn;
 
-use crate::structures::Message;
+use super::record_atomic::{is_atomic, reply_outcome};
+use super::record_notify::moved_out;
+use super::record_reject::{peer_writer, Rejections};
+use crate::database::RecordStore;
+use crate::structures::{Instruction, Message, Record};
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
 use crate::utils::GLOBAL_WORLD;
-use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
 
+/// Handles both `RecordCreate` and `RecordUpdate`, as inserting a record that already
+/// exists replaces it.
+///
+/// Records with a version are only written if it matches the stored version, and records
+/// owned by other peers are never written. Any rejected records are sent back to the
+/// peer, see [`super::record_reject`].
+///
+/// Messages with the `atomic` parameter either write every record or none, and the
+/// outcome is sent back to the peer, see [`super::record_atomic`].
+///
+/// Area subscribers are only notified of the records that were stored, as they were
+/// stored. Records moved into another cube are also deleted for subscribers of the cube
+/// they left, see [`super::record_notify::moved_out`].
 pub(super) async fn handle_record_create(
     message: Message,
-    database_client: &mut DatabaseClient,
+    store: &dyn RecordStore,
     peer_map: &ThreadPeerMap,
+    sub_tx: &Sender<Message>,
+    cube_size: u16,
 ) -> Result<()> {
     trace_packet!("{}", &message);
 
@@ -18,10 +40,83 @@ pub(super) async fn handle_record_create(
     }
 
     let uuid = message.sender_uuid;
-    let errors = database_client.insert_records(message.records).await;
+    let writer = peer_writer(&message, peer_map).await;
+    let atomic = is_atomic(&message);
+    let written = match atomic {
+        true => {
+            store
+                .insert_records_atomic(message.records.clone(), &writer)
+                .await
+        }
+        false => store.insert_records(message.records.clone(), &writer).await,
+    };
+
+    // Buffered records were written first, so subscribers hear of them first too
+    notify_flushed(written.flushed, sub_tx).await?;
+
+    let (rejections, errors) = Rejections::split(written.errors);
+    rejections.reply(&message, peer_map).await;
+
+    if atomic {
+        let committed = rejections.is_empty() && errors.is_empty();
+        reply_outcome(&message, committed, peer_map).await;
+    }
+
     for error in errors {
         warn!("peer {} record create error: {}", uuid, error);
     }
 
+    // Aborted atomic writes and buffered writes didn't store any records
+    if written.records.is_empty() {
+        return Ok(());
+    }
+
+    // Subscribers of the cubes records left are told first, as some may see them arrive
+    let moved = moved_out(written.moved, &written.records, cube_size);
+    if !moved.is_empty() {
+        let removed = Message {
+            instruction: Instruction::RecordDelete,
+            sender_uuid: uuid,
+            world_name: message.world_name.clone(),
+            replication: message.replication.clone(),
+            records: moved,
+            ..Default::default()
+        };
+
+        sub_tx.send_async(removed).await?;
+    }
+
+    // Notify area subscribers once all records are stored
+    let records = written.records;
+    sub_tx.send_async(Message { records, ..message }).await?;
+
+    Ok(())
+}
+
+/// Notify area subscribers of buffered records written once the database was available
+/// again, see [`crate::database::Written::flushed`].
+///
+/// The messages that buffered them are long gone, so the records are sent on behalf of
+/// the server, in one message per world.
+pub(super) async fn notify_flushed(records: Vec<Record>, sub_tx: &Sender<Message>) -> Result<()> {
+    let mut worlds: AHashMap<String, Vec<Record>> = AHashMap::new();
+    for record in records {
+        worlds
+            .entry(record.world_name.clone())
+            .or_default()
+            .push(record);
+    }
+
+    for (world_name, records) in worlds {
+        let message = Message {
+            instruction: Instruction::RecordCreate,
+            world_name,
+            records,
+            ..Default::default()
+        };
+
+        sub_tx.send_async(message).await?;
+    }
+
     Ok(())
 }
@@ -1,14 +1,30 @@
 use color_eyre::Result;
+use flume::Sender;
 use tracing::warn;
 
+use super::record_atomic::{is_atomic, reply_outcome};
+use super::record_create::notify_flushed;
+use super::record_reject::{peer_writer, Rejections};
+use crate::database::RecordStore;
 use crate::structures::Message;
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
 use crate::utils::GLOBAL_WORLD;
-use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
 
+/// Records with a version are only deleted if it matches the stored version, and records
+/// owned by other peers are never deleted. Any rejected records are sent back to the
+/// peer, see [`super::record_reject`].
+///
+/// Messages with the `atomic` parameter either delete every record or none, and the
+/// outcome is sent back to the peer, see [`super::record_atomic`].
+///
+/// Area subscribers are only notified of the records that were deleted, as they were
+/// stored.
 pub(super) async fn handle_record_delete(
     message: Message,
-    database_client: &mut DatabaseClient,
+    store: &dyn RecordStore,
     peer_map: &ThreadPeerMap,
+    sub_tx: &Sender<Message>,
 ) -> Result<()> {
     trace_packet!("{}", &message);
 
@@ -18,10 +34,40 @@ pub(super) async fn handle_record_delete(
     }
 
     let uuid = message.sender_uuid;
-    let errors = database_client.delete_records(message.records).await;
+    let writer = peer_writer(&message, peer_map).await;
+    let atomic = is_atomic(&message);
+    let written = match atomic {
+        true => {
+            store
+                .delete_records_atomic(message.records.clone(), &writer)
+                .await
+        }
+        false => store.delete_records(message.records.clone(), &writer).await,
+    };
+
+    // Buffered records were written first, so subscribers hear of them first too
+    notify_flushed(written.flushed, sub_tx).await?;
+
+    let (rejections, errors) = Rejections::split(written.errors);
+    rejections.reply(&message, peer_map).await;
+
+    if atomic {
+        let committed = rejections.is_empty() && errors.is_empty();
+        reply_outcome(&message, committed, peer_map).await;
+    }
+
     for error in errors {
         warn!("peer {} record remove error: {}", uuid, error);
     }
 
+    // Aborted atomic deletes didn't delete any records
+    if written.records.is_empty() {
+        return Ok(());
+    }
+
+    // Notify area subscribers once all records are deleted
+    let records = written.records;
+    sub_tx.send_async(Message { records, ..message }).await?;
+
     Ok(())
 }
@@ -0,0 +1,134 @@
+use ahash::AHashMap;
+use chrono::Utc;
+use color_eyre::Result;
+use flume::Sender;
+use tracing::{debug, warn};
+
+use crate::database::{DatabaseError, RecordStore};
+use crate::structures::{Instruction, Message, Record, Replication};
+
+/// Delete up to `batch_size` expired records, then send a `RecordDelete` for them to
+/// the subscription task so subscribers of their areas are notified.
+///
+/// Must be ordered with other record changes the same way as
+/// [`super::area_sync::handle_area_sync`].
+pub(super) async fn handle_record_expire(
+    store: &dyn RecordStore,
+    sub_tx: &Sender<Message>,
+    batch_size: usize,
+) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    let records = match store.delete_expired_records(now, batch_size).await {
+        Ok(records) => records,
+
+        // Unavailable errors are already logged by the connection pool
+        Err(DatabaseError::Unavailable) => return Ok(()),
+        Err(error) => {
+            warn!("error deleting expired records: {}", error);
+            return Ok(());
+        }
+    };
+
+    // Early return to avoid notifying subscribers
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    debug!("deleted {} expired records", records.len());
+
+    // Messages only have a single world name
+    let mut worlds: AHashMap<String, Vec<Record>> = AHashMap::new();
+    for record in records {
+        worlds
+            .entry(record.world_name.clone())
+            .or_default()
+            .push(record);
+    }
+
+    for (world_name, records) in worlds {
+        let message = Message {
+            instruction: Instruction::RecordDelete,
+            world_name,
+            // Expiry wasn't sent by any peer, so every subscriber is notified
+            replication: Replication::IncludingSelf,
+            records,
+            ..Default::default()
+        };
+
+        sub_tx.send_async(message).await?;
+    }
+
+    Ok(())
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::{DataFilter, MemoryStore, Writer};
+    use crate::structures::Vector3;
+
+    fn record(world_name: &str, x: f64, expires_in: Option<i64>) -> Record {
+        Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(x, 1.0, 1.0)),
+            world_name: world_name.into(),
+            expires_at: expires_in.map(|secs| Utc::now().naive_utc() + Duration::seconds(secs)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_records() {
+        let store = MemoryStore::new(16, 16, 16);
+        let records = vec![
+            record("world", 1.0, None),
+            record("world", 2.0, Some(-10)),
+            record("world", 3.0, Some(3600)),
+            record("other", 4.0, Some(-10)),
+            record("other", 40.0, Some(-10)),
+        ];
+
+        assert!(store
+            .insert_records(records, &Writer::SERVER)
+            .await
+            .errors
+            .is_empty());
+
+        // Expired records are hidden before they are deleted
+        let found = store
+            .get_records_in_region("world", Vector3::zero(), None, &DataFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let (sub_tx, sub_rx) = flume::unbounded();
+        handle_record_expire(&store, &sub_tx, 2).await.unwrap();
+        handle_record_expire(&store, &sub_tx, 2).await.unwrap();
+
+        let mut deleted = sub_rx
+            .try_iter()
+            .inspect(|message| {
+                assert_eq!(message.instruction, Instruction::RecordDelete);
+                assert!(message.records.len() <= 2);
+                assert!(message
+                    .records
+                    .iter()
+                    .all(|record| record.world_name == message.world_name));
+            })
+            .flat_map(|message| message.records)
+            .map(|record| *record.position.unwrap().x())
+            .collect::<Vec<_>>();
+
+        deleted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(deleted, vec![2.0, 4.0, 40.0]);
+
+        // Nothing left to delete
+        handle_record_expire(&store, &sub_tx, 2).await.unwrap();
+        assert!(sub_rx.is_empty());
+    }
+}
+// endregion
@@ -0,0 +1,289 @@
+use ahash::AHashMap;
+use color_eyre::Result;
+use uuid::Uuid;
+
+use crate::structures::{Message, Record, Replication};
+use crate::subscriptions::{ToCubeArea, WorldMap};
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+use crate::utils::sanitize_world_name;
+
+/// Push a stored record change to every peer subscribed to the area containing each
+/// record, respecting the change's [`Replication`].
+///
+/// Each peer receives a single message with the original instruction, containing only
+/// the records inside its subscribed areas.
+pub(super) async fn handle_record_notify(
+    message: Message,
+    peer_map: &ThreadPeerMap,
+    world_map: &WorldMap,
+) -> Result<()> {
+    trace_packet!("{}", &message);
+
+    let sender = message.sender_uuid;
+    let replicate_to = |peer: &Uuid| match message.replication {
+        Replication::ExceptSelf => *peer != sender,
+        Replication::IncludingSelf => true,
+        Replication::OnlySelf => *peer == sender,
+    };
+
+    let mut peer_records: AHashMap<Uuid, Vec<Record>> = AHashMap::new();
+    for record in &message.records {
+        let position = match record.position {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let area_map = match sanitize_world_name(&record.world_name) {
+            Err(_) => continue,
+            Ok(world_name) => match world_map.get(&world_name) {
+                None => continue,
+                Some(area_map) => area_map,
+            },
+        };
+
+        for peer in area_map.get_subscribed_peers(position) {
+            // Changes before a pending sync finishes are already included in it
+            if replicate_to(&peer) && !area_map.is_sync_pending(&peer, position) {
+                peer_records.entry(peer).or_default().push(record.clone());
+            }
+        }
+    }
+
+    // Early return to avoid locking the peer map
+    if peer_records.is_empty() {
+        return Ok(());
+    }
+
+    let mut map = peer_map.write().await;
+    for (uuid, records) in peer_records {
+        let notification = Message {
+            instruction: message.instruction.clone(),
+            sender_uuid: sender,
+            world_name: message.world_name.clone(),
+            replication: message.replication.clone(),
+            records,
+            ..Default::default()
+        };
+
+        if let Some(peer) = map.get_mut(&uuid) {
+            let _ = peer.send(notification).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the `moved` records, as stored before they were moved, that are no longer in
+/// the same cube as their stored `records`.
+///
+/// Subscribers of the cube a record left won't hear of it again, so they should be
+/// notified of these as deleted.
+pub(super) fn moved_out(moved: Vec<Record>, records: &[Record], cube_size: u16) -> Vec<Record> {
+    if moved.is_empty() {
+        return moved;
+    }
+
+    let cubes = records
+        .iter()
+        .filter_map(|record| {
+            let key = (record.world_name.as_str(), record.uuid);
+            let cube = record.position?.to_cube_area(cube_size);
+            Some((key, cube))
+        })
+        .collect::<AHashMap<_, _>>();
+
+    moved
+        .into_iter()
+        .filter(|old| {
+            let cube = match old.position {
+                Some(position) => position.to_cube_area(cube_size),
+                None => return false,
+            };
+
+            let key = (old.world_name.as_str(), old.uuid);
+            cubes.get(&key) != Some(&cube)
+        })
+        .collect()
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::structures::{Instruction, Vector3};
+    use crate::transport::{Peer, PeerMap};
+
+    async fn peers(
+        count: usize,
+        world_map: &mut WorldMap,
+    ) -> (ThreadPeerMap, Vec<(Uuid, flume::Receiver<Bytes>)>) {
+        let (remove_tx, _) = flume::unbounded();
+        let peer_map = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+        let mut peers = vec![];
+        for _ in 0..count {
+            let uuid = Uuid::new_v4();
+            let (peer, rx) = Peer::new_test(uuid);
+
+            peer_map.write().await.insert(uuid, peer).await;
+            world_map
+                .get_mut("world")
+                .add_subscription(uuid, Vector3::zero());
+            peers.push((uuid, rx));
+        }
+
+        (peer_map, peers)
+    }
+
+    fn received(rx: &flume::Receiver<Bytes>) -> Vec<Message> {
+        rx.try_iter()
+            .map(|bytes| Message::deserialize(&bytes).unwrap())
+            .filter(|message| message.instruction != Instruction::PeerConnect)
+            .collect()
+    }
+
+    fn change(sender_uuid: Uuid, replication: Replication) -> Message {
+        let record = |x: f64| Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(x, 1.0, 1.0)),
+            world_name: "world".into(),
+            ..Default::default()
+        };
+
+        Message {
+            instruction: Instruction::RecordCreate,
+            sender_uuid,
+            world_name: "world".into(),
+            replication,
+            records: vec![record(1.0), record(2.0), record(100.0)],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribed_areas_only() {
+        let mut world_map = WorldMap::new(16);
+        let (peer_map, peers) = peers(2, &mut world_map).await;
+        let (sender, sender_rx) = &peers[0];
+        let (_, other_rx) = &peers[1];
+
+        let message = change(*sender, Replication::ExceptSelf);
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        assert!(received(sender_rx).is_empty());
+
+        let messages = received(other_rx);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].instruction, Instruction::RecordCreate);
+        assert_eq!(messages[0].sender_uuid, *sender);
+        assert_eq!(messages[0].records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn replication() {
+        let mut world_map = WorldMap::new(16);
+        let (peer_map, peers) = peers(2, &mut world_map).await;
+        let (sender, sender_rx) = &peers[0];
+        let (_, other_rx) = &peers[1];
+
+        let message = change(*sender, Replication::IncludingSelf);
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        assert_eq!(received(sender_rx).len(), 1);
+        assert_eq!(received(other_rx).len(), 1);
+
+        let message = change(*sender, Replication::OnlySelf);
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        assert_eq!(received(sender_rx).len(), 1);
+        assert!(received(other_rx).is_empty());
+    }
+    #[tokio::test]
+    async fn pending_sync() {
+        let mut world_map = WorldMap::new(16);
+        let (peer_map, peers) = peers(2, &mut world_map).await;
+        let (sender, _) = &peers[0];
+        let (other, other_rx) = &peers[1];
+
+        // Changes are held back until the sync finishes
+        world_map
+            .get_mut("world")
+            .add_pending_sync(*other, Vector3::zero());
+
+        let message = change(*sender, Replication::ExceptSelf);
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        assert!(received(other_rx).is_empty());
+
+        world_map
+            .get_mut("world")
+            .finish_pending_sync(other, Vector3::zero());
+
+        let message = change(*sender, Replication::ExceptSelf);
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        assert_eq!(received(other_rx).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn moved_to_another_cube() {
+        let mut world_map = WorldMap::new(16);
+        let (peer_map, peers) = peers(2, &mut world_map).await;
+        let (sender, _) = &peers[0];
+        let (_, other_rx) = &peers[1];
+
+        let old = change(*sender, Replication::ExceptSelf).records;
+        let records = old
+            .iter()
+            .map(|record| Record {
+                position: Some(Vector3::new(50.0, 1.0, 1.0)),
+                ..record.clone()
+            })
+            .collect::<Vec<_>>();
+
+        // Records moved within the same cube only notify it of the change
+        let mut moved = old.clone();
+        moved[0].position = Some(Vector3::new(60.0, 1.0, 1.0));
+
+        let moved = moved_out(moved, &records, 16);
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0].uuid, old[1].uuid);
+        assert_eq!(moved[1].uuid, old[2].uuid);
+
+        let message = Message {
+            instruction: Instruction::RecordDelete,
+            sender_uuid: *sender,
+            world_name: "world".into(),
+            records: moved,
+            ..Default::default()
+        };
+
+        handle_record_notify(message, &peer_map, &world_map)
+            .await
+            .unwrap();
+
+        // Only the record that left the subscribed cube is deleted
+        let messages = received(other_rx);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].instruction, Instruction::RecordDelete);
+        assert_eq!(messages[0].records.len(), 1);
+        assert_eq!(messages[0].records[0].uuid, old[1].uuid);
+    }
+}
+// endregion
@@ -1,17 +1,27 @@
-use std::collections::HashMap;
-
+use chrono::NaiveDateTime;
 use color_eyre::Result;
 use tracing::warn;
+use uuid::Uuid;
 
-use crate::database::DedupeData;
-use crate::structures::{Instruction, Message};
+use super::read_query::{NearestLimits, ReadMode, ReadQuery};
+use super::record_reply::{chunk_replies, ERROR_PARAMETER};
+use crate::database::{truncate_nearest, DataFilter, DatabaseError, RecordStore};
+use crate::structures::{Area, Instruction, Message, Record};
 use crate::utils::GLOBAL_WORLD;
-use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
+use crate::{trace_packet, ThreadPeerMap};
 
+/// Replies are split into `RecordReply` messages of at most `chunk_size` records,
+/// see [`chunk_replies`]. Nearest reads are clamped to `limits`.
+///
+/// Area and tag reads are made one table at a time, and each table's records are sent
+/// as soon as they're read. The last reply is always sent, with [`ERROR_PARAMETER`] if
+/// the read failed, so peers never wait on a read that won't finish.
 pub(super) async fn handle_record_read(
     message: Message,
-    database_client: &mut DatabaseClient,
+    store: &dyn RecordStore,
     peer_map: &ThreadPeerMap,
+    chunk_size: usize,
+    limits: NearestLimits,
 ) -> Result<()> {
     trace_packet!("{}", &message);
 
@@ -21,119 +31,952 @@ pub(super) async fn handle_record_read(
     }
 
     let uuid = message.sender_uuid;
-    match message.position {
-        // Handle messages with position
-        Some(position) => {
-            // Extract parameter
-            let after = match message.parameter {
-                None => None,
-                Some(parameter) => {
-                    let ts = match crate::utils::parse_epoch_millis(&parameter) {
-                        Ok(ts) => ts,
-                        Err(error) => {
-                            warn!("error parsing timestamp for {}: {}", uuid, error);
-                            return Ok(());
-                        }
-                    };
-
-                    Some(ts)
-                }
-            };
+    let mut replies = ReplyStream::new(peer_map, uuid, &message.world_name, chunk_size);
+
+    let mut query = match ReadQuery::parse(message.parameter.as_deref(), message.position) {
+        Ok(query) => query,
+        Err(error) => {
+            warn!("error parsing read parameter for {}: {}", uuid, error);
+            replies.end(true).await;
+
+            return Ok(());
+        }
+    };
+
+    limits.clamp(&mut query.mode);
+    let failed = match read_records(store, &message.world_name, query, &mut replies).await {
+        Ok(()) => false,
+        Err(error) => {
+            warn!("error getting records for {}: {}", uuid, error);
+            true
+        }
+    };
+
+    replies.end(failed).await;
+    Ok(())
+}
+
+async fn read_records(
+    store: &dyn RecordStore,
+    world_name: &str,
+    query: ReadQuery,
+    replies: &mut ReplyStream<'_>,
+) -> Result<(), DatabaseError> {
+    let after = query.after;
+    let filter = &query.filter;
+
+    match (query.tag.as_deref(), query.mode) {
+        (None, ReadMode::Region(position)) => {
+            let records = store
+                .get_records_in_region(world_name, position, after, filter)
+                .await?;
+
+            replies.send(records).await;
+        }
 
-            let result = database_client
-                .get_records_in_region(&message.world_name, position, after)
-                .await;
+        (None, ReadMode::Area(area)) => {
+            for table_suffix in store.get_read_tables(world_name, Some(area)).await? {
+                let records = store
+                    .get_table_records_in_area(world_name, table_suffix, area, after, filter)
+                    .await?;
 
-            let records = match result {
-                Ok(records) => records,
-                Err(error) => {
-                    warn!("error getting records for {}: {}", uuid, error);
-                    return Ok(());
+                if !replies.send(records).await {
+                    break;
                 }
+            }
+        }
+
+        (
+            None,
+            ReadMode::Nearest {
+                point,
+                count,
+                max_distance,
+            },
+        ) => {
+            let records = store
+                .get_nearest_records(world_name, point, count, max_distance, after, filter)
+                .await?;
+
+            replies.send(records).await;
+        }
+
+        // Parsing only returns world reads for tag reads
+        (None, ReadMode::World) => (),
+
+        // Tag reads without an area ignore the read position
+        (Some(tag), ReadMode::Region(_) | ReadMode::World) => {
+            read_tagged(store, world_name, tag, None, after, filter, replies).await?
+        }
+
+        (Some(tag), ReadMode::Area(area)) => {
+            read_tagged(store, world_name, tag, Some(area), after, filter, replies).await?
+        }
+
+        // Tagged records are few, so they're read at once rather than searching outward
+        (
+            Some(tag),
+            ReadMode::Nearest {
+                point,
+                count,
+                max_distance,
+            },
+        ) => {
+            let area = Area::new_sphere(point, max_distance);
+            let mut records = store
+                .get_records_with_tag(world_name, tag, Some(area), after, filter)
+                .await?;
+
+            truncate_nearest(&mut records, &point, count);
+            replies.send(records).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the records tagged with `tag` one table at a time, sending each table's records
+/// once read.
+async fn read_tagged(
+    store: &dyn RecordStore,
+    world_name: &str,
+    tag: &str,
+    area: Option<Area>,
+    after: Option<NaiveDateTime>,
+    filter: &DataFilter,
+    replies: &mut ReplyStream<'_>,
+) -> Result<(), DatabaseError> {
+    for table_suffix in store.get_read_tables(world_name, area).await? {
+        let records = store
+            .get_table_records_with_tag(world_name, table_suffix, tag, area, after, filter)
+            .await?;
+
+        if !replies.send(records).await {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// region: ReplyStream Struct
+/// `RecordReply` messages sent to a peer as records are read.
+///
+/// Records are held back until a whole chunk can be sent, so the last reply always
+/// has some records unless the read found none.
+struct ReplyStream<'a> {
+    peer_map: &'a ThreadPeerMap,
+    uuid: Uuid,
+    reply: Message,
+    chunk_size: usize,
+    pending: Vec<Record>,
+    closed: bool,
+}
+
+impl<'a> ReplyStream<'a> {
+    fn new(peer_map: &'a ThreadPeerMap, uuid: Uuid, world_name: &str, chunk_size: usize) -> Self {
+        let reply = Message {
+            instruction: Instruction::RecordReply,
+            world_name: world_name.to_owned(),
+            ..Default::default()
+        };
+
+        Self {
+            peer_map,
+            uuid,
+            reply,
+            chunk_size: chunk_size.max(1),
+            pending: vec![],
+            closed: false,
+        }
+    }
+
+    /// Send every whole chunk of `records` after those already pending.
+    ///
+    /// Returns `false` once the peer can no longer be sent to, so the read can stop.
+    async fn send(&mut self, records: Vec<Record>) -> bool {
+        self.pending.extend(records);
+        while !self.closed && self.pending.len() > self.chunk_size {
+            let records = self.pending.drain(..self.chunk_size).collect();
+            let reply = Message {
+                records,
+                ..self.reply.clone()
             };
 
-            // Early return to avoid locking the peer map
-            if records.is_empty() {
-                return Ok(());
+            self.send_reply(reply).await;
+        }
+
+        !self.closed
+    }
+
+    /// Send the remaining records in the last reply, marked with [`ERROR_PARAMETER`] if
+    /// the read `failed`.
+    async fn end(mut self, failed: bool) {
+        let mut reply = std::mem::take(&mut self.reply);
+        if failed {
+            reply.parameter = Some(ERROR_PARAMETER.to_owned());
+        }
+
+        let records = std::mem::take(&mut self.pending);
+        for reply in chunk_replies(reply, records, self.chunk_size) {
+            self.send_reply(reply).await;
+        }
+    }
+
+    async fn send_reply(&mut self, reply: Message) {
+        if self.closed {
+            return;
+        }
+
+        // Peer map is only locked for each reply, so large reads don't hold up others
+        let mut map = self.peer_map.write().await;
+        let sent = match map.get_mut(&self.uuid) {
+            Some(peer) => peer.send(reply).await.is_ok(),
+            None => {
+                warn!("Missing peer {} for RecordReply send!", &self.uuid);
+                false
             }
+        };
 
-            // Deduplicate records
-            let deduped = {
-                let mut map = HashMap::new();
-                for (ts, record) in records {
-                    match map.get(&record.uuid) {
-                        // Not seen before, insert
-                        None => {
-                            map.insert(record.uuid, (ts, record));
-                        }
-
-                        // Seen before, check timestamp
-                        Some((existing_ts, _)) => {
-                            // Only insert if timestamp is later
-                            if &ts >= existing_ts {
-                                map.insert(record.uuid, (ts, record));
-                            }
-                        }
-                    }
-                }
+        self.closed = !sent;
+    }
+}
+// endregion
 
-                map.into_values().collect::<Vec<_>>()
-            };
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-            // Extract dedupe command information from list
-            let dedupe_ops = deduped
-                .iter()
-                .map(|(ts, record)| {
-                    let data: DedupeData = (
-                        record.uuid,
-                        *ts,
-                        record.world_name.clone(),
-                        // TODO: Handle records without position
-                        record.position.unwrap(),
-                    );
-
-                    data
-                })
-                .collect::<Vec<DedupeData>>();
-
-            // Extract only records from deduplicated list
-            let records = deduped
-                .into_iter()
-                .map(|(_, record)| record)
-                .collect::<Vec<_>>();
+    use bytes::Bytes;
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
 
-            let reply = Message {
-                instruction: Instruction::RecordReply,
-                world_name: message.world_name,
+    use super::*;
+    use crate::database::MemoryStore;
+    use crate::processing::record_atomic::{
+        ABORTED_PARAMETER, ATOMIC_PARAMETER, COMMITTED_PARAMETER,
+    };
+    use crate::processing::record_create::handle_record_create;
+    use crate::processing::record_delete::handle_record_delete;
+    use crate::processing::record_reject::{CONFLICT_PARAMETER, DENIED_PARAMETER};
+    use crate::processing::record_reply::END_PARAMETER;
+    use crate::structures::{Record, Vector3};
+    use crate::transport::{Peer, PeerMap};
+    use crate::utils::PeerIdentity;
+
+    /// Small enough for most reads to be split into several replies
+    const CHUNK_SIZE: usize = 2;
+    const LIMITS: NearestLimits = NearestLimits {
+        max_count: 64,
+        max_distance: 200.0,
+    };
+
+    struct Harness {
+        store: MemoryStore,
+        peer_map: ThreadPeerMap,
+        uuid: Uuid,
+        rx: flume::Receiver<Bytes>,
+
+        sub_tx: flume::Sender<Message>,
+        sub_rx: flume::Receiver<Message>,
+    }
+
+    impl Harness {
+        async fn new() -> Self {
+            let (remove_tx, _) = flume::unbounded();
+            let peer_map = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+            let uuid = Uuid::new_v4();
+            let (peer, rx) = Peer::new_test(uuid);
+            peer_map.write().await.insert(uuid, peer).await;
+
+            let (sub_tx, sub_rx) = flume::unbounded();
+            Self {
+                store: MemoryStore::new(16, 16, 16),
+                peer_map,
+                uuid,
+                rx,
+
+                sub_tx,
+                sub_rx,
+            }
+        }
+
+        async fn send(&self, instruction: Instruction, records: Vec<Record>) {
+            self.send_with(instruction, None, records).await;
+        }
+
+        async fn send_with(
+            &self,
+            instruction: Instruction,
+            parameter: Option<&str>,
+            records: Vec<Record>,
+        ) {
+            let message = Message {
+                instruction,
+                parameter: parameter.map(Into::into),
+                sender_uuid: self.uuid,
+                world_name: "world".into(),
                 records,
                 ..Default::default()
             };
 
-            // Lock peer map for only this section
-            {
-                let mut map = peer_map.write().await;
-                let peer = map.get_mut(&uuid);
-                if peer.is_none() {
-                    warn!("Missing peer {} for GlobalMessage send!", &uuid);
-                    return Ok(());
+            let result = match message.instruction {
+                Instruction::RecordCreate => {
+                    handle_record_create(message, &self.store, &self.peer_map, &self.sub_tx, 16)
+                        .await
                 }
+                Instruction::RecordDelete => {
+                    handle_record_delete(message, &self.store, &self.peer_map, &self.sub_tx).await
+                }
+                _ => unreachable!(),
+            };
 
-                let peer = peer.unwrap();
-                let _ = peer.send(reply).await;
-            }
+            result.unwrap();
+        }
+
+        async fn identify(&self, name: Option<&str>, admin: bool) {
+            let identity = name.map(|name| PeerIdentity {
+                name: name.into(),
+                admin,
+            });
+
+            let mut map = self.peer_map.write().await;
+            map.get_mut(&self.uuid).unwrap().set_identity(identity);
+        }
+
+        async fn read(&self, position: Vector3) -> Vec<Record> {
+            self.query(position, None).await
+        }
+
+        async fn query(&self, position: Vector3, parameter: Option<&str>) -> Vec<Record> {
+            let message = Message {
+                instruction: Instruction::RecordRead,
+                parameter: parameter.map(Into::into),
+                sender_uuid: self.uuid,
+                world_name: "world".into(),
+                position: Some(position),
+                ..Default::default()
+            };
+
+            handle_record_read(message, &self.store, &self.peer_map, CHUNK_SIZE, LIMITS)
+                .await
+                .unwrap();
+
+            // Collect every chunk, failed reads are ended without any records
+            let mut records = vec![];
+            for bytes in self.rx.try_iter() {
+                let reply = Message::deserialize(&bytes).unwrap();
+                assert_eq!(reply.instruction, Instruction::RecordReply);
+                assert!(reply.records.len() <= CHUNK_SIZE);
 
-            // Deduplicate records in background
-            let result = database_client.dedupe_records(dedupe_ops).await;
-            if let Err(error) = result {
-                warn!("error deduping records for {}: {}", uuid, error);
-                return Ok(());
+                records.extend(reply.records);
+                match reply.parameter.as_deref() {
+                    Some(END_PARAMETER) => return records,
+                    Some(parameter) if parameter == ended_with_error() => {
+                        assert!(records.is_empty());
+                        return records;
+                    }
+
+                    _ => (),
+                }
             }
+
+            panic!("read wasn't ended");
         }
+    }
 
-        // Handle messages without position
-        // TODO
-        None => todo!(),
+    fn ended_with_error() -> String {
+        format!("{};{}", ERROR_PARAMETER, END_PARAMETER)
     }
 
-    Ok(())
+    fn record(uuid: Uuid, position: Vector3, data: &str) -> Record {
+        Record {
+            uuid,
+            position: Some(position),
+            world_name: "world".into(),
+            data: Some(data.into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn create_then_read() {
+        let harness = Harness::new().await;
+        let records = vec![
+            record(Uuid::new_v4(), Vector3::new(1.0, 1.0, 1.0), "a"),
+            record(Uuid::new_v4(), Vector3::new(15.0, 2.0, 3.0), "b"),
+            record(Uuid::new_v4(), Vector3::new(17.0, 1.0, 1.0), "c"),
+        ];
+
+        harness.send(Instruction::RecordCreate, records).await;
+
+        let mut found = harness.read(Vector3::new(8.0, 8.0, 8.0)).await;
+        found.sort_by(|a, b| a.data.cmp(&b.data));
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].data.as_deref(), Some("a"));
+        assert_eq!(found[1].data.as_deref(), Some("b"));
+        assert_eq!(found[0].world_name, "world");
+    }
+
+    #[tokio::test]
+    async fn create_replaces() {
+        let harness = Harness::new().await;
+        let uuid = Uuid::new_v4();
+
+        let first = record(uuid, Vector3::new(1.0, 1.0, 1.0), "first");
+        harness.send(Instruction::RecordCreate, vec![first]).await;
+
+        let second = record(uuid, Vector3::new(2.0, 2.0, 2.0), "second");
+        harness.send(Instruction::RecordCreate, vec![second]).await;
+
+        let found = harness.read(Vector3::zero()).await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uuid, uuid);
+        assert_eq!(found[0].data.as_deref(), Some("second"));
+
+        // Moving a record removes it from its old region
+        let moved = record(uuid, Vector3::new(40.0, 1.0, 1.0), "moved");
+        harness.send(Instruction::RecordCreate, vec![moved]).await;
+
+        assert!(harness.read(Vector3::zero()).await.is_empty());
+        assert_eq!(harness.read(Vector3::new(40.0, 0.0, 0.0)).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let harness = Harness::new().await;
+        let uuid = Uuid::new_v4();
+        let created = record(uuid, Vector3::new(1.0, 1.0, 1.0), "a");
+        harness.send(Instruction::RecordCreate, vec![created]).await;
+
+        // Deleting from another region does nothing
+        let elsewhere = record(uuid, Vector3::new(40.0, 1.0, 1.0), "a");
+        harness
+            .send(Instruction::RecordDelete, vec![elsewhere])
+            .await;
+        assert_eq!(harness.read(Vector3::zero()).await.len(), 1);
+
+        let deleted = record(uuid, Vector3::new(2.0, 2.0, 2.0), "a");
+        harness.send(Instruction::RecordDelete, vec![deleted]).await;
+        assert!(harness.read(Vector3::zero()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn changes_forwarded() {
+        let harness = Harness::new().await;
+        let uuid = Uuid::new_v4();
+
+        let created = record(uuid, Vector3::new(1.0, 1.0, 1.0), "a");
+        harness.send(Instruction::RecordCreate, vec![created]).await;
+
+        let deleted = record(uuid, Vector3::new(1.0, 1.0, 1.0), "a");
+        harness.send(Instruction::RecordDelete, vec![deleted]).await;
+
+        let instructions = harness
+            .sub_rx
+            .try_iter()
+            .map(|message| message.instruction)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            instructions,
+            vec![Instruction::RecordCreate, Instruction::RecordDelete]
+        );
+
+        // Failed changes are not forwarded, but the rest of the batch is
+        let mut invalid = record(uuid, Vector3::zero(), "a");
+        invalid.world_name = "1nvalid".into();
+        harness
+            .send(Instruction::RecordCreate, vec![invalid.clone()])
+            .await;
+        assert!(harness.sub_rx.is_empty());
+
+        let valid = record(uuid, Vector3::zero(), "b");
+        harness
+            .send(Instruction::RecordCreate, vec![invalid, valid])
+            .await;
+
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.records.len(), 1);
+        assert_eq!(changed.records[0].data.as_deref(), Some("b"));
+        assert_eq!(changed.records[0].version, Some(1));
+
+        // Deleting records that don't exist changes nothing
+        let missing = record(Uuid::new_v4(), Vector3::zero(), "a");
+        harness.send(Instruction::RecordDelete, vec![missing]).await;
+        assert!(harness.sub_rx.is_empty());
+
+        // Moving to another cube deletes the record from the cube it left
+        let moved = record(uuid, Vector3::new(40.0, 1.0, 1.0), "b");
+        harness.send(Instruction::RecordCreate, vec![moved]).await;
+
+        let removed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(removed.instruction, Instruction::RecordDelete);
+        assert_eq!(removed.records[0].position, Some(Vector3::zero()));
+
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.instruction, Instruction::RecordCreate);
+        assert_eq!(
+            changed.records[0].position,
+            Some(Vector3::new(40.0, 1.0, 1.0))
+        );
+
+        // Moving within the same cube only changes it
+        let moved = record(uuid, Vector3::new(41.0, 1.0, 1.0), "b");
+        harness.send(Instruction::RecordCreate, vec![moved]).await;
+
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.instruction, Instruction::RecordCreate);
+        assert!(harness.sub_rx.is_empty());
+    }
+
+    #[tokio::test]
+    async fn area_queries() {
+        let harness = Harness::new().await;
+        let records = [-20.0, -16.0, -1.0, 0.0, 5.0, 15.0, 40.0]
+            .iter()
+            .map(|x| record(Uuid::new_v4(), Vector3::new(*x, 1.0, 1.0), &x.to_string()))
+            .collect();
+
+        harness.send(Instruction::RecordCreate, records).await;
+
+        let xs = |records: Vec<Record>| {
+            let mut xs = records
+                .iter()
+                .map(|record| *record.position.unwrap().x())
+                .collect::<Vec<_>>();
+
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            xs
+        };
+
+        let found = harness
+            .query(Vector3::new(-16.0, 0.0, 0.0), Some("box:15,2,2"))
+            .await;
+        assert_eq!(xs(found), vec![-16.0, -1.0, 0.0, 5.0, 15.0]);
+
+        let found = harness
+            .query(Vector3::new(0.0, 1.0, 1.0), Some("sphere:5"))
+            .await;
+        assert_eq!(xs(found), vec![-1.0, 0.0, 5.0]);
+
+        // Invalid parameters are ignored
+        assert!(harness
+            .query(Vector3::zero(), Some("box:1"))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn nearest_query() {
+        let harness = Harness::new().await;
+        let records = [90.0, -20.0, 3.0, -1.0, 300.0]
+            .iter()
+            .map(|x| record(Uuid::new_v4(), Vector3::new(*x, 1.0, 1.0), &x.to_string()))
+            .collect();
+
+        harness.send(Instruction::RecordCreate, records).await;
+
+        let found = harness
+            .query(Vector3::new(0.0, 1.0, 1.0), Some("nearest:3"))
+            .await;
+
+        let data = found
+            .iter()
+            .map(|record| record.data.as_deref().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(data, vec!["-1", "3", "-20"]);
+
+        let found = harness
+            .query(Vector3::new(0.0, 1.0, 1.0), Some("nearest:10,100"))
+            .await;
+
+        assert_eq!(found.len(), 4);
+
+        // Distances past the server limit are clamped
+        let found = harness
+            .query(Vector3::new(0.0, 1.0, 1.0), Some("nearest:10,1000"))
+            .await;
+
+        assert_eq!(found.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn filter_query() {
+        let harness = Harness::new().await;
+        let records = vec![
+            record(
+                Uuid::new_v4(),
+                Vector3::new(1.0, 1.0, 1.0),
+                r#"{"type": "rare", "level": 7}"#,
+            ),
+            record(
+                Uuid::new_v4(),
+                Vector3::new(2.0, 1.0, 1.0),
+                r#"{"type": "rare", "level": 2}"#,
+            ),
+            record(
+                Uuid::new_v4(),
+                Vector3::new(3.0, 1.0, 1.0),
+                r#"{"type": "common"}"#,
+            ),
+            record(Uuid::new_v4(), Vector3::new(4.0, 1.0, 1.0), "rare"),
+        ];
+
+        harness.send(Instruction::RecordCreate, records).await;
+
+        let xs = |records: Vec<Record>| {
+            let mut xs = records
+                .iter()
+                .map(|record| *record.position.unwrap().x())
+                .collect::<Vec<_>>();
+
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            xs
+        };
+
+        let found = harness
+            .query(Vector3::zero(), Some("filter:type=rare"))
+            .await;
+        assert_eq!(xs(found), vec![1.0, 2.0]);
+
+        let found = harness
+            .query(
+                Vector3::new(1.5, 1.0, 1.0),
+                Some("sphere:1;filter:type=rare,level>=5"),
+            )
+            .await;
+        assert_eq!(xs(found), vec![1.0]);
+
+        let found = harness
+            .query(Vector3::zero(), Some("nearest:5;filter:level<10"))
+            .await;
+        assert_eq!(xs(found), vec![1.0, 2.0]);
+
+        // Invalid filters are ignored
+        assert!(harness
+            .query(Vector3::zero(), Some("filter:level>rare"))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn tag_query() {
+        let harness = Harness::new().await;
+        let tagged = |x: f64, tags: &[&str]| Record {
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..record(Uuid::new_v4(), Vector3::new(x, 1.0, 1.0), &x.to_string())
+        };
+
+        let records = vec![
+            tagged(1.0, &["spawn_point"]),
+            tagged(-40.0, &["spawn_point", "chest"]),
+            tagged(500.0, &["spawn_point"]),
+            tagged(2.0, &["chest"]),
+        ];
+
+        harness.send(Instruction::RecordCreate, records).await;
+
+        let xs = |records: Vec<Record>| {
+            let mut xs = records
+                .iter()
+                .map(|record| *record.position.unwrap().x())
+                .collect::<Vec<_>>();
+
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            xs
+        };
+
+        // Tag reads without an area read the whole world
+        let found = harness
+            .query(Vector3::zero(), Some("tag:spawn_point"))
+            .await;
+        assert_eq!(xs(found), vec![-40.0, 1.0, 500.0]);
+
+        let found = harness
+            .query(Vector3::zero(), Some("sphere:50;tag:spawn_point"))
+            .await;
+        assert_eq!(xs(found), vec![-40.0, 1.0]);
+
+        let found = harness
+            .query(Vector3::new(-40.0, 1.0, 1.0), Some("nearest:1;tag:chest"))
+            .await;
+        assert_eq!(xs(found), vec![-40.0]);
+
+        // Only tag reads may omit a position
+        let read = |parameter: &str| Message {
+            instruction: Instruction::RecordRead,
+            parameter: Some(parameter.into()),
+            sender_uuid: harness.uuid,
+            world_name: "world".into(),
+            position: None,
+            ..Default::default()
+        };
+
+        handle_record_read(
+            read("tag:chest"),
+            &harness.store,
+            &harness.peer_map,
+            10,
+            LIMITS,
+        )
+        .await
+        .unwrap();
+        let reply = Message::deserialize(&harness.rx.try_recv().unwrap()).unwrap();
+        assert_eq!(xs(reply.records), vec![-40.0, 2.0]);
+
+        // Invalid reads are still ended, so peers don't wait for them
+        handle_record_read(read("1000"), &harness.store, &harness.peer_map, 10, LIMITS)
+            .await
+            .unwrap();
+        let reply = Message::deserialize(&harness.rx.try_recv().unwrap()).unwrap();
+        assert_eq!(reply.parameter, Some(ended_with_error()));
+        assert!(reply.records.is_empty());
+        assert!(harness.rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn conditional_writes() {
+        let harness = Harness::new().await;
+        let uuid = Uuid::new_v4();
+        let versioned = |version: Option<u64>, data: &str| Record {
+            version,
+            ..record(uuid, Vector3::zero(), data)
+        };
+
+        let conflicts = || {
+            harness
+                .rx
+                .try_iter()
+                .map(|bytes| Message::deserialize(&bytes).unwrap())
+                .filter(|reply| reply.parameter.as_deref() == Some(CONFLICT_PARAMETER))
+                .flat_map(|reply| reply.records)
+                .map(|record| (record.uuid, record.version))
+                .collect::<Vec<_>>()
+        };
+
+        harness
+            .send(Instruction::RecordCreate, vec![versioned(Some(0), "a")])
+            .await;
+        assert!(conflicts().is_empty());
+
+        let found = harness.read(Vector3::zero()).await;
+        assert_eq!(found[0].version, Some(1));
+
+        // Changes are forwarded with their new version
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.records[0].version, Some(1));
+
+        let other = record(Uuid::new_v4(), Vector3::zero(), "other");
+        let records = vec![versioned(Some(0), "b"), other.clone()];
+        harness.send(Instruction::RecordCreate, records).await;
+        assert_eq!(conflicts(), vec![(uuid, Some(1))]);
+
+        // Only records that were written are forwarded
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.records.len(), 1);
+        assert_eq!(changed.records[0].uuid, other.uuid);
+
+        harness
+            .send(Instruction::RecordDelete, vec![versioned(Some(2), "a")])
+            .await;
+        assert_eq!(conflicts(), vec![(uuid, Some(1))]);
+        assert!(harness.sub_rx.try_recv().is_err());
+
+        harness
+            .send(Instruction::RecordDelete, vec![versioned(Some(1), "a")])
+            .await;
+        assert!(conflicts().is_empty());
+        assert_eq!(harness.read(Vector3::zero()).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn atomic_writes() {
+        let harness = Harness::new().await;
+        let atomic = Some(ATOMIC_PARAMETER);
+        let a = record(Uuid::new_v4(), Vector3::new(1.0, 1.0, 1.0), "a");
+        let b = record(Uuid::new_v4(), Vector3::new(40.0, 1.0, 1.0), "b");
+
+        let replies = || {
+            harness
+                .rx
+                .try_iter()
+                .map(|bytes| Message::deserialize(&bytes).unwrap())
+                .map(|reply| (reply.parameter.unwrap(), reply.records.len()))
+                .collect::<Vec<_>>()
+        };
+
+        // A conflict on one record aborts the whole write
+        let conflicting = Record {
+            version: Some(3),
+            ..b.clone()
+        };
+
+        let records = vec![a.clone(), conflicting];
+        harness
+            .send_with(Instruction::RecordCreate, atomic, records)
+            .await;
+
+        let expected = vec![
+            (CONFLICT_PARAMETER.into(), 1),
+            (ABORTED_PARAMETER.into(), 2),
+        ];
+        assert_eq!(replies(), expected);
+        assert!(harness.read(Vector3::zero()).await.is_empty());
+        assert!(harness.sub_rx.try_recv().is_err());
+
+        let records = vec![a.clone(), b.clone()];
+        harness
+            .send_with(Instruction::RecordCreate, atomic, records)
+            .await;
+
+        assert_eq!(replies(), vec![(COMMITTED_PARAMETER.into(), 2)]);
+        assert_eq!(harness.read(Vector3::zero()).await.len(), 1);
+        assert_eq!(harness.read(Vector3::new(40.0, 0.0, 0.0)).await.len(), 1);
+        assert_eq!(harness.sub_rx.try_recv().unwrap().records.len(), 2);
+
+        // Records deleted before the failure are put back
+        let conflicting = Record {
+            version: Some(7),
+            ..b.clone()
+        };
+
+        let records = vec![a.clone(), conflicting];
+        harness
+            .send_with(Instruction::RecordDelete, atomic, records)
+            .await;
+
+        assert_eq!(replies(), expected);
+        assert_eq!(harness.read(Vector3::zero()).await.len(), 1);
+        assert!(harness.sub_rx.try_recv().is_err());
+
+        let records = vec![a, b];
+        harness
+            .send_with(Instruction::RecordDelete, atomic, records)
+            .await;
+
+        assert_eq!(replies(), vec![(COMMITTED_PARAMETER.into(), 2)]);
+        assert!(harness.read(Vector3::zero()).await.is_empty());
+        assert!(harness.read(Vector3::new(40.0, 0.0, 0.0)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ownership() {
+        let harness = Harness::new().await;
+        let uuid = Uuid::new_v4();
+        let owned = |data: &str| record(uuid, Vector3::zero(), data);
+
+        let denied = || {
+            harness
+                .rx
+                .try_iter()
+                .map(|bytes| Message::deserialize(&bytes).unwrap())
+                .filter(|reply| reply.parameter.as_deref() == Some(DENIED_PARAMETER))
+                .flat_map(|reply| reply.records)
+                .map(|record| record.uuid)
+                .collect::<Vec<_>>()
+        };
+
+        let stored = || async { harness.read(Vector3::zero()).await.pop().unwrap() };
+
+        // Owners sent by peers are ignored, and changes are forwarded with the stored owner
+        harness.identify(Some("game"), false).await;
+        let mut created = owned("a");
+        created.owner = Some("other".into());
+        harness.send(Instruction::RecordCreate, vec![created]).await;
+        assert_eq!(stored().await.owner.as_deref(), Some("game"));
+        let changed = harness.sub_rx.try_recv().unwrap();
+        assert_eq!(changed.records[0].owner.as_deref(), Some("game"));
+
+        harness.identify(Some("other"), false).await;
+        harness
+            .send(Instruction::RecordCreate, vec![owned("b")])
+            .await;
+        harness
+            .send(Instruction::RecordDelete, vec![owned("b")])
+            .await;
+        assert_eq!(denied(), vec![uuid, uuid]);
+        assert_eq!(stored().await.data.as_deref(), Some("a"));
+        assert!(harness.sub_rx.try_recv().is_err());
+
+        // Owners can share records with other writers
+        harness.identify(Some("game"), false).await;
+        let mut shared = owned("b");
+        shared.writers = Some(vec!["other".into()]);
+        harness.send(Instruction::RecordCreate, vec![shared]).await;
+
+        harness.identify(Some("other"), false).await;
+        harness
+            .send(Instruction::RecordCreate, vec![owned("c")])
+            .await;
+        assert!(denied().is_empty());
+
+        let found = stored().await;
+        assert_eq!(found.data.as_deref(), Some("c"));
+        assert_eq!(found.owner.as_deref(), Some("game"));
+        assert_eq!(found.writers, Some(vec!["other".to_owned()]));
+
+        // Only owners can change writers
+        let mut shared = owned("d");
+        shared.writers = Some(vec![]);
+        harness.send(Instruction::RecordCreate, vec![shared]).await;
+        assert_eq!(denied(), vec![uuid]);
+
+        harness.identify(None, false).await;
+        harness
+            .send(Instruction::RecordDelete, vec![owned("c")])
+            .await;
+        assert_eq!(denied(), vec![uuid]);
+
+        // Admins can write any record
+        harness.identify(Some("backend"), true).await;
+        harness
+            .send(Instruction::RecordDelete, vec![owned("c")])
+            .await;
+        assert!(denied().is_empty());
+        assert!(harness.read(Vector3::zero()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn global_world_ignored() {
+        let harness = Harness::new().await;
+        let mut created = record(Uuid::new_v4(), Vector3::zero(), "a");
+        created.world_name = GLOBAL_WORLD.into();
+
+        let message = Message {
+            instruction: Instruction::RecordCreate,
+            world_name: GLOBAL_WORLD.into(),
+            records: vec![created],
+            ..Default::default()
+        };
+
+        handle_record_create(
+            message,
+            &harness.store,
+            &harness.peer_map,
+            &harness.sub_tx,
+            16,
+        )
+        .await
+        .unwrap();
+
+        let read = Message {
+            instruction: Instruction::RecordRead,
+            sender_uuid: harness.uuid,
+            world_name: GLOBAL_WORLD.into(),
+            position: Some(Vector3::zero()),
+            ..Default::default()
+        };
+
+        handle_record_read(read, &harness.store, &harness.peer_map, CHUNK_SIZE, LIMITS)
+            .await
+            .unwrap();
+
+        assert!(harness.rx.try_recv().is_err());
+    }
 }
+// endregion
@@ -0,0 +1,117 @@
+use ahash::{AHashMap, AHashSet};
+use uuid::Uuid;
+
+use crate::database::{DatabaseError, Writer};
+use crate::structures::{Instruction, Message, Record};
+use crate::transport::ThreadPeerMap;
+
+/// `RecordReply` parameter for conditional writes rejected by a version mismatch.
+///
+/// Replies contain each rejected record, with [`Record::version`] set to the stored
+/// version, or 0 if the record doesn't exist.
+pub(super) const CONFLICT_PARAMETER: &str = "conflict";
+
+/// `RecordReply` parameter for writes rejected because the record is owned by another
+/// peer.
+///
+/// Replies contain each rejected record as it was sent.
+pub(super) const DENIED_PARAMETER: &str = "denied";
+
+// region: Rejections Struct
+/// Records a write was rejected for, by reason.
+#[derive(Debug, Default)]
+pub(super) struct Rejections {
+    /// Stored versions of records with a version conflict
+    pub conflicts: AHashMap<Uuid, u64>,
+
+    /// Records owned by another peer
+    pub denied: AHashSet<Uuid>,
+}
+
+impl Rejections {
+    /// Split rejected records from other errors.
+    ///
+    /// Returned tuple has the form `(rejections, errors)`
+    pub fn split(errors: Vec<DatabaseError>) -> (Self, Vec<DatabaseError>) {
+        let mut rejections = Self::default();
+        let mut other = vec![];
+
+        for error in errors {
+            match error {
+                DatabaseError::VersionConflict { uuid, version } => {
+                    rejections.conflicts.insert(uuid, version);
+                }
+
+                DatabaseError::PermissionDenied { uuid } => {
+                    rejections.denied.insert(uuid);
+                }
+
+                error => other.push(error),
+            }
+        }
+
+        (rejections, other)
+    }
+
+    /// Returns `true` if no records were rejected.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty() && self.denied.is_empty()
+    }
+
+    /// Reply to the sender of `message` with the records that were rejected, one reply
+    /// per reason.
+    pub async fn reply(&self, message: &Message, peer_map: &ThreadPeerMap) {
+        let conflicts = message
+            .records
+            .iter()
+            .filter_map(|record| {
+                let version = self.conflicts.get(&record.uuid)?;
+                Some(Record {
+                    version: Some(*version),
+                    ..record.clone()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let denied = message
+            .records
+            .iter()
+            .filter(|record| self.denied.contains(&record.uuid))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let replies = [(CONFLICT_PARAMETER, conflicts), (DENIED_PARAMETER, denied)];
+        for (parameter, records) in replies {
+            if records.is_empty() {
+                continue;
+            }
+
+            let reply = Message {
+                instruction: Instruction::RecordReply,
+                parameter: Some(parameter.into()),
+                world_name: message.world_name.clone(),
+                records,
+                ..Default::default()
+            };
+
+            let mut map = peer_map.write().await;
+            if let Some(peer) = map.get_mut(&message.sender_uuid) {
+                let _ = peer.send(reply).await;
+            }
+        }
+    }
+}
+// endregion
+
+/// Returns the [`Writer`] for the sender of `message`.
+///
+/// Peers that have already disconnected are treated as anonymous.
+pub(super) async fn peer_writer(message: &Message, peer_map: &ThreadPeerMap) -> Writer {
+    let map = peer_map.read().await;
+    let identity = map
+        .get(&message.sender_uuid)
+        .and_then(|peer| peer.identity().as_ref());
+
+    Writer::from_identity(message.sender_uuid, identity)
+}
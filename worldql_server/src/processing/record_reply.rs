@@ -0,0 +1,109 @@
+use crate::structures::{Message, Record};
+
+/// `RecordReply` parameter segment marking the last reply to a request
+pub(super) const END_PARAMETER: &str = "end";
+
+/// `RecordReply` parameter for the last reply to a read that failed, followed by
+/// [`END_PARAMETER`].
+///
+/// Records sent before it are still valid, but the read may have missed others.
+pub(super) const ERROR_PARAMETER: &str = "error";
+
+/// Split records into `RecordReply` messages of at most `chunk_size` records each.
+///
+/// Every reply is a copy of `reply` with its own records. The last reply has
+/// [`END_PARAMETER`] appended to its parameter as a `;` separated segment, and is
+/// returned even without any records so peers know when a request has finished.
+pub(super) fn chunk_replies(
+    reply: Message,
+    records: Vec<Record>,
+    chunk_size: usize,
+) -> Vec<Message> {
+    let chunk_size = chunk_size.max(1);
+    let end_parameter = match &reply.parameter {
+        None => END_PARAMETER.to_owned(),
+        Some(parameter) => format!("{};{}", parameter, END_PARAMETER),
+    };
+
+    let mut replies = Vec::with_capacity(records.len() / chunk_size + 1);
+    let mut records = records.into_iter().peekable();
+    loop {
+        let chunk = records.by_ref().take(chunk_size).collect::<Vec<_>>();
+        if records.peek().is_none() {
+            replies.push(Message {
+                parameter: Some(end_parameter),
+                records: chunk,
+                ..reply
+            });
+
+            return replies;
+        }
+
+        replies.push(Message {
+            records: chunk,
+            ..reply.clone()
+        });
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::Instruction;
+
+    fn reply(parameter: Option<&str>) -> Message {
+        Message {
+            instruction: Instruction::RecordReply,
+            parameter: parameter.map(Into::into),
+            world_name: "world".into(),
+            ..Default::default()
+        }
+    }
+
+    macro_rules! test_chunks {
+        ($records: expr, $chunk_size: expr, $expected: expr) => {
+            let records = (0..$records).map(|_| Record::default()).collect();
+            let replies = chunk_replies(reply(None), records, $chunk_size);
+
+            let sizes = replies
+                .iter()
+                .map(|reply| reply.records.len())
+                .collect::<Vec<_>>();
+
+            assert_eq!(sizes, $expected);
+        };
+    }
+
+    #[test]
+    fn chunk_sizes() {
+        test_chunks!(0, 4, vec![0]);
+        test_chunks!(3, 4, vec![3]);
+        test_chunks!(4, 4, vec![4]);
+        test_chunks!(9, 4, vec![4, 4, 1]);
+        test_chunks!(8, 4, vec![4, 4]);
+    }
+
+    #[test]
+    fn end_marker() {
+        let records = vec![Record::default(); 5];
+        let replies = chunk_replies(reply(None), records.clone(), 2);
+
+        let parameters = replies
+            .iter()
+            .map(|reply| reply.parameter.as_deref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(parameters, vec![None, None, Some("end")]);
+        assert!(replies.iter().all(|r| r.world_name == "world"));
+
+        let replies = chunk_replies(reply(Some("sync")), records, 4);
+        let parameters = replies
+            .iter()
+            .map(|reply| reply.parameter.as_deref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(parameters, vec![Some("sync"), Some("sync;end")]);
+    }
+}
+// endregion
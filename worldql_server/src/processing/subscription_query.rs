@@ -0,0 +1,38 @@
+#[cfg(feature = "http")]
+use crate::subscriptions::WorldSummary;
+use crate::subscriptions::{SubscriptionQuery, WorldMap};
+
+#[cfg_attr(not(feature = "http"), allow(unused_variables))]
+pub(super) fn handle_subscription_query(query: SubscriptionQuery, world_map: &WorldMap) {
+    match query {
+        #[cfg(feature = "http")]
+        SubscriptionQuery::Worlds(reply_tx) => {
+            let worlds = world_map
+                .iter()
+                .map(|area_map| WorldSummary {
+                    world_name: area_map.world_name().to_string(),
+                    area_count: area_map.area_count(),
+                    peer_count: area_map.peer_count(),
+                })
+                .collect::<Vec<_>>();
+
+            // Receiver may have timed out, ignore errors
+            let _ = reply_tx.send(worlds);
+        }
+
+        #[cfg(feature = "http")]
+        SubscriptionQuery::PeerSubscriptions(uuid, reply_tx) => {
+            let subscriptions = world_map
+                .iter()
+                .filter(|area_map| area_map.is_peer_subscribed_any(&uuid))
+                .map(|area_map| {
+                    let areas = area_map.get_peer_areas(&uuid).collect::<Vec<_>>();
+                    (area_map.world_name().to_string(), areas)
+                })
+                .collect::<Vec<_>>();
+
+            // Receiver may have timed out, ignore errors
+            let _ = reply_tx.send(subscriptions);
+        }
+    }
+}
@@ -1,43 +1,90 @@
+use std::time::Duration;
+
 use color_eyre::Result;
 use flume::{Receiver, Sender};
+use futures_util::future::try_join_all;
+use tokio::time;
 use tracing::{info, warn};
 use uuid::Uuid;
 
 use super::area_subscribe::handle_area_subscribe as area_subscribe;
+use super::area_sync::{handle_area_sync as area_sync, handle_area_sync_reply as area_sync_reply};
 use super::area_unsubscribe::handle_area_unsubscribe as area_unsubscribe;
+use super::db_dispatch::{shard_message, PeerOrdering};
 use super::global_message::handle_global_message as global_message;
 use super::heartbeat::handle_heartbeat as heartbeat;
+use super::history_prune::handle_history_prune as history_prune;
 use super::local_message::handle_local_message as local_message;
-use super::record_create::handle_record_create as record_create;
+use super::read_query::NearestLimits;
+use super::record_atomic::is_atomic;
+use super::record_create::{handle_record_create as record_create, notify_flushed};
 use super::record_delete::handle_record_delete as record_delete;
+use super::record_expire::handle_record_expire as record_expire;
+use super::record_notify::handle_record_notify as record_notify;
 use super::record_read::handle_record_read as record_read;
+use super::subscription_query::handle_subscription_query as subscription_query;
+use crate::database::{DatabaseError, ThreadRecordStore};
 use crate::structures::{Instruction, Message};
-use crate::subscriptions::WorldMap;
+use crate::subscriptions::{SubscriptionQuery, WorldMap};
+use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
-use crate::{trace_packet, DatabaseClient};
 
+/// Interval between database connection checks
+const DB_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval between deleting old record history
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn start_processing_thread(
-    database_client: DatabaseClient,
+    store: ThreadRecordStore,
     peer_map: ThreadPeerMap,
     msg_rx: Receiver<Message>,
     remove_rx: Receiver<Uuid>,
+    sub_query_rx: Receiver<SubscriptionQuery>,
     cube_size: u16,
+    db_workers: usize,
+    reply_chunk_size: usize,
+    nearest_limits: NearestLimits,
+    expiry_interval: Duration,
+    expiry_batch_size: usize,
+    history_retention: Option<Duration>,
 ) -> Result<()> {
     let (sub_tx, sub_rx) = flume::unbounded();
     let (db_tx, db_rx) = flume::unbounded();
 
-    let mut db = tokio::spawn(handle_db_messages(db_rx, peer_map.clone(), database_client));
+    let mut db = tokio::spawn(handle_db_messages(
+        db_rx,
+        sub_tx.clone(),
+        peer_map.clone(),
+        store,
+        cube_size,
+        db_workers,
+        reply_chunk_size,
+        nearest_limits,
+        expiry_interval,
+        expiry_batch_size,
+        history_retention,
+    ));
     let mut sub = tokio::spawn(handle_sub_messages(
         sub_rx,
+        db_tx.clone(),
         remove_rx,
+        sub_query_rx,
         peer_map.clone(),
         cube_size,
+        reply_chunk_size,
     ));
 
     loop {
         tokio::select! {
             // Handle incoming messages
             Ok(message) = msg_rx.recv_async() => {
+                #[cfg(feature = "metrics")]
+                crate::metrics::QUEUE_DEPTH
+                    .with_label_values(&["messages"])
+                    .set(msg_rx.len() as i64);
+
                 handle_message(&sub_tx, &db_tx, &peer_map, message).await?;
             },
 
@@ -69,6 +116,11 @@ async fn handle_message(
     peer_map: &ThreadPeerMap,
     message: Message,
 ) -> Result<()> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::MESSAGES
+        .with_label_values(&[&message.instruction.to_string()])
+        .inc();
+
     match message.instruction {
         // Panic on handshakes, they should never be sent to this thread.
         Instruction::Handshake => panic!("recieved handshake instruction on processing thread"),
@@ -112,9 +164,12 @@ async fn handle_message(
 
 async fn handle_sub_messages(
     msg_rx: Receiver<Message>,
+    db_tx: Sender<Message>,
     remove_rx: Receiver<Uuid>,
+    query_rx: Receiver<SubscriptionQuery>,
     peer_map: ThreadPeerMap,
     cube_size: u16,
+    chunk_size: usize,
 ) -> Result<()> {
     let mut world_map = WorldMap::new(cube_size);
 
@@ -125,14 +180,32 @@ async fn handle_sub_messages(
                 world_map.remove_peer(&peer);
             },
 
+            // Handle read-only queries from other threads
+            Ok(query) = query_rx.recv_async() => {
+                subscription_query(query, &world_map);
+            },
+
             // Handle incoming messages
             Ok(message) = msg_rx.recv_async() => {
+                #[cfg(feature = "metrics")]
+                crate::metrics::QUEUE_DEPTH
+                    .with_label_values(&["subscriptions"])
+                    .set(msg_rx.len() as i64);
+
                 match message.instruction {
-                    Instruction::AreaSubscribe => area_subscribe(message, &peer_map, &mut world_map)?,
+                    Instruction::AreaSubscribe => area_subscribe(message, &peer_map, &mut world_map, &db_tx).await?,
                     Instruction::AreaUnsubscribe => area_unsubscribe(message, &peer_map, &mut world_map)?,
                     Instruction::LocalMessage => local_message(message, &peer_map, &world_map).await?,
                     Instruction::GlobalMessage => global_message(message, &peer_map, &world_map).await?,
 
+                    // Record changes are only sent here by DB workers, once stored
+                    Instruction::RecordCreate
+                    | Instruction::RecordUpdate
+                    | Instruction::RecordDelete => record_notify(message, &peer_map, &world_map).await?,
+
+                    // Initial area syncs are only sent here by the DB task
+                    Instruction::RecordReply => area_sync_reply(message, &peer_map, &mut world_map, chunk_size).await?,
+
                     _ => panic!("invalid message type"),
                 }
             },
@@ -148,31 +221,324 @@ async fn handle_sub_messages(
     Ok(())
 }
 
+/// Work sent to a single DB worker
+enum WorkerTask {
+    Message(Message),
+
+    /// Acknowledged once every earlier task has been handled
+    Barrier(Sender<()>),
+
+    /// Wait for this many acknowledgements from barriers sent to other workers
+    Wait(Receiver<()>, usize),
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_db_messages(
     msg_rx: Receiver<Message>,
+    sub_tx: Sender<Message>,
     peer_map: ThreadPeerMap,
-    mut database_client: DatabaseClient,
+    store: ThreadRecordStore,
+    cube_size: u16,
+    workers: usize,
+    chunk_size: usize,
+    nearest_limits: NearestLimits,
+    expiry_interval: Duration,
+    expiry_batch_size: usize,
+    history_retention: Option<Duration>,
 ) -> Result<()> {
+    let mut worker_txs = Vec::with_capacity(workers);
+    let mut worker_handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let (tx, rx) = flume::unbounded();
+        let handle = tokio::spawn(handle_db_worker(
+            rx,
+            sub_tx.clone(),
+            peer_map.clone(),
+            store.clone(),
+            cube_size,
+            chunk_size,
+            nearest_limits,
+        ));
+
+        worker_txs.push(tx);
+        worker_handles.push(async move { handle.await? });
+    }
+
+    let mut worker_handles = Box::pin(try_join_all(worker_handles));
+    let mut interval = time::interval(DB_CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    let mut expiry = time::interval(expiry_interval);
+    expiry.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+    let mut history = time::interval(HISTORY_PRUNE_INTERVAL);
+    history.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    let mut ordering = PeerOrdering::default();
+
     loop {
-        let message = msg_rx.recv_async().await?;
-        match message.instruction {
-            Instruction::RecordCreate => {
-                record_create(message, &mut database_client, &peer_map).await?
+        let message = tokio::select! {
+            message = msg_rx.recv_async() => message?,
+
+            // Exit early if any worker errors
+            result = &mut worker_handles => {
+                result?;
+                return Ok(());
+            },
+
+            // Periodically reconnect and flush buffered writes
+            _ = interval.tick() => {
+                // Flushed records are written ahead of any later change
+                worker_barrier(&worker_txs).await?;
+                ordering.clear();
+
+                let checked = store.check_connection().await;
+                notify_flushed(checked.flushed, &sub_tx).await?;
+
+                for error in checked.errors {
+                    // Unavailable errors are already logged by the connection pool
+                    if !matches!(error, DatabaseError::Unavailable) {
+                        warn!("database connection check error: {}", error);
+                    }
+                }
+
+                continue;
+            },
+
+            // Delete a batch of expired records, ordered with other record changes
+            _ = expiry.tick() => {
+                worker_barrier(&worker_txs).await?;
+                ordering.clear();
+                record_expire(&*store, &sub_tx, expiry_batch_size).await?;
+
+                continue;
+            },
+
+            // Delete history older than the retention period, if set
+            _ = history.tick(), if history_retention.is_some() => {
+                history_prune(&*store, history_retention.unwrap()).await;
+
+                continue;
+            },
+        };
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::QUEUE_DEPTH
+            .with_label_values(&["database"])
+            .set(msg_rx.len() as i64);
+
+        // Initial area syncs are only sent here by the sub task.
+        // Wait for every earlier change to be stored and notified, and read the area
+        // before dispatching any later change.
+        if message.instruction == Instruction::AreaSubscribe {
+            worker_barrier(&worker_txs).await?;
+            ordering.clear();
+            area_sync(message, &*store, &sub_tx, cube_size).await?;
+            continue;
+        }
+
+        // Atomic writes span records handled by other workers, so wait for every
+        // earlier change and for the write itself before dispatching any later change
+        let atomic = is_atomic(&message);
+        if atomic {
+            worker_barrier(&worker_txs).await?;
+        }
+
+        // Keep each peer's reads and writes in order without blocking other peers,
+        // workers wait for the ones holding the peer's earlier tasks instead
+        let shards = shard_message(message, workers);
+        let targets = shards.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
+        let dependencies = match shards.first() {
+            Some((_, message)) if !atomic => ordering.dispatch(message, &targets),
+            _ => vec![vec![]; shards.len()],
+        };
+
+        for ((idx, message), dependencies) in shards.into_iter().zip(dependencies) {
+            worker_wait(&worker_txs, idx, &dependencies).await?;
+            worker_txs[idx]
+                .send_async(WorkerTask::Message(message))
+                .await?;
+        }
+
+        if atomic {
+            worker_barrier(&worker_txs).await?;
+            ordering.clear();
+        }
+    }
+}
+
+/// Wait until every DB worker has handled all of its earlier tasks.
+async fn worker_barrier(worker_txs: &[Sender<WorkerTask>]) -> Result<()> {
+    let (ack_tx, ack_rx) = flume::bounded(worker_txs.len());
+    for tx in worker_txs {
+        tx.send_async(WorkerTask::Barrier(ack_tx.clone())).await?;
+    }
+
+    for _ in worker_txs {
+        ack_rx.recv_async().await?;
+    }
+
+    Ok(())
+}
+
+/// Make worker `idx` wait until the workers in `dependencies` have handled all of their
+/// earlier tasks, without blocking the caller.
+///
+/// Barriers are always queued before the wait that depends on them, so workers can't
+/// end up waiting on each other.
+async fn worker_wait(
+    worker_txs: &[Sender<WorkerTask>],
+    idx: usize,
+    dependencies: &[usize],
+) -> Result<()> {
+    if dependencies.is_empty() {
+        return Ok(());
+    }
+
+    let (ack_tx, ack_rx) = flume::bounded(dependencies.len());
+    for dep in dependencies {
+        worker_txs[*dep]
+            .send_async(WorkerTask::Barrier(ack_tx.clone()))
+            .await?;
+    }
+
+    worker_txs[idx]
+        .send_async(WorkerTask::Wait(ack_rx, dependencies.len()))
+        .await?;
+
+    Ok(())
+}
+
+async fn handle_db_worker(
+    task_rx: Receiver<WorkerTask>,
+    sub_tx: Sender<Message>,
+    peer_map: ThreadPeerMap,
+    store: ThreadRecordStore,
+    cube_size: u16,
+    chunk_size: usize,
+    nearest_limits: NearestLimits,
+) -> Result<()> {
+    while let Ok(task) = task_rx.recv_async().await {
+        let message = match task {
+            WorkerTask::Message(message) => message,
+            WorkerTask::Barrier(ack_tx) => {
+                ack_tx.send_async(()).await?;
+                continue;
             }
 
-            Instruction::RecordRead => {
-                record_read(message, &mut database_client, &peer_map).await?
+            WorkerTask::Wait(ack_rx, count) => {
+                for _ in 0..count {
+                    ack_rx.recv_async().await?;
+                }
+
+                continue;
             }
+        };
 
-            Instruction::RecordUpdate => {
-                todo!()
+        match message.instruction {
+            Instruction::RecordCreate | Instruction::RecordUpdate => {
+                record_create(message, &*store, &peer_map, &sub_tx, cube_size).await?
+            }
+
+            Instruction::RecordRead => {
+                record_read(message, &*store, &peer_map, chunk_size, nearest_limits).await?
             }
 
             Instruction::RecordDelete => {
-                record_delete(message, &mut database_client, &peer_map).await?;
+                record_delete(message, &*store, &peer_map, &sub_tx).await?;
             }
 
             _ => panic!("invalid message type"),
         }
     }
+
+    Ok(())
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::database::MemoryStore;
+    use crate::processing::record_reply::END_PARAMETER;
+    use crate::structures::{Record, Vector3};
+    use crate::transport::{Peer, PeerMap};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn reads_see_earlier_writes() {
+        let (remove_tx, _) = flume::unbounded();
+        let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+        let sender_uuid = Uuid::new_v4();
+        let (peer, rx) = Peer::new_test(sender_uuid);
+        peer_map.write().await.insert(sender_uuid, peer).await;
+
+        let (msg_tx, msg_rx) = flume::unbounded();
+        let (sub_tx, _sub_rx) = flume::unbounded();
+        let store: ThreadRecordStore = Arc::new(MemoryStore::new(16, 16, 16));
+        tokio::spawn(handle_db_messages(
+            msg_rx,
+            sub_tx,
+            peer_map,
+            store,
+            16,
+            4,
+            1000,
+            NearestLimits {
+                max_count: 1000,
+                max_distance: 1000.0,
+            },
+            Duration::from_secs(3600),
+            100,
+            None,
+        ));
+
+        for round in 0..20 {
+            let position = Vector3::new(f64::from(round) * 16.0 + 1.0, 1.0, 1.0);
+            let records = (0..32)
+                .map(|_| Record {
+                    uuid: Uuid::new_v4(),
+                    position: Some(position),
+                    world_name: "world".into(),
+                    ..Default::default()
+                })
+                .collect();
+
+            let create = Message {
+                instruction: Instruction::RecordCreate,
+                sender_uuid,
+                world_name: "world".into(),
+                records,
+                ..Default::default()
+            };
+
+            let read = Message {
+                instruction: Instruction::RecordRead,
+                sender_uuid,
+                world_name: "world".into(),
+                position: Some(position),
+                ..Default::default()
+            };
+
+            msg_tx.send_async(create).await.unwrap();
+            msg_tx.send_async(read).await.unwrap();
+
+            let mut read = 0;
+            loop {
+                let reply = Message::deserialize(&rx.recv_async().await.unwrap()).unwrap();
+                read += reply.records.len();
+
+                if reply.parameter.as_deref() == Some(END_PARAMETER) {
+                    break;
+                }
+            }
+
+            assert_eq!(read, 32, "round {}", round);
+        }
+    }
 }
+// endregion
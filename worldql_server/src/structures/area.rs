@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+use super::Vector3;
+
+/// A region of space used to select records by their exact position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    /// Axis-aligned box, including points on its faces
+    Box { min: Vector3, max: Vector3 },
+
+    /// Sphere, including points on its surface
+    Sphere { center: Vector3, radius: f64 },
+}
+
+impl Area {
+    /// Create a box between two opposite corners, in any order.
+    pub fn new_box(a: Vector3, b: Vector3) -> Self {
+        let min = Vector3::new(a.x().min(*b.x()), a.y().min(*b.y()), a.z().min(*b.z()));
+        let max = Vector3::new(a.x().max(*b.x()), a.y().max(*b.y()), a.z().max(*b.z()));
+
+        Self::Box { min, max }
+    }
+
+    #[inline]
+    pub fn new_sphere(center: Vector3, radius: f64) -> Self {
+        Self::Sphere {
+            center,
+            radius: radius.abs(),
+        }
+    }
+
+    /// Returns the smallest axis-aligned box containing this area.
+    ///
+    /// Returned tuple has the form `(min, max)`
+    pub fn bounds(&self) -> (Vector3, Vector3) {
+        match *self {
+            Self::Box { min, max } => (min, max),
+            Self::Sphere { center, radius } => {
+                let radius = Vector3::new(radius, radius, radius);
+                (center - radius, center + radius)
+            }
+        }
+    }
+
+    /// Returns `true` if `point` is inside this area.
+    pub fn contains(&self, point: &Vector3) -> bool {
+        match self {
+            Self::Box { min, max } => {
+                point.x() >= min.x()
+                    && point.x() <= max.x()
+                    && point.y() >= min.y()
+                    && point.y() <= max.y()
+                    && point.z() >= min.z()
+                    && point.z() <= max.z()
+            }
+
+            Self::Sphere { center, radius } => point.distance_squared(center) <= radius * radius,
+        }
+    }
+}
+
+impl Display for Area {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Box { min, max } => write!(f, "Box {{ min = {}, max = {} }}", min, max),
+            Self::Sphere { center, radius } => {
+                write!(
+                    f,
+                    "Sphere {{ center = {}, radius = {:.4} }}",
+                    center, radius
+                )
+            }
+        }
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_corners_any_order() {
+        let area = Area::new_box(Vector3::new(10.0, -5.0, 3.0), Vector3::new(-10.0, 5.0, 1.0));
+        let expected = Area::Box {
+            min: Vector3::new(-10.0, -5.0, 1.0),
+            max: Vector3::new(10.0, 5.0, 3.0),
+        };
+
+        assert_eq!(area, expected);
+    }
+
+    #[test]
+    fn box_contains() {
+        let area = Area::new_box(Vector3::zero(), Vector3::new(10.0, 10.0, 10.0));
+
+        assert!(area.contains(&Vector3::new(5.0, 5.0, 5.0)));
+        assert!(area.contains(&Vector3::new(10.0, 0.0, 10.0)));
+        assert!(!area.contains(&Vector3::new(10.1, 5.0, 5.0)));
+        assert!(!area.contains(&Vector3::new(5.0, -0.1, 5.0)));
+    }
+
+    #[test]
+    fn sphere_contains() {
+        let area = Area::new_sphere(Vector3::new(1.0, 1.0, 1.0), 2.0);
+
+        assert!(area.contains(&Vector3::new(1.0, 1.0, 1.0)));
+        assert!(area.contains(&Vector3::new(3.0, 1.0, 1.0)));
+        assert!(!area.contains(&Vector3::new(2.5, 2.5, 1.0)));
+        assert_eq!(
+            area.bounds(),
+            (Vector3::new(-1.0, -1.0, -1.0), Vector3::new(3.0, 3.0, 3.0))
+        );
+    }
+}
+// endregion
@@ -17,4 +17,13 @@ pub enum DecodeError {
 
     #[error(transparent)]
     InvalidUuid(#[from] uuid::Error),
+
+    #[error("timestamp out of range: {0}")]
+    InvalidTimestamp(u64),
+
+    #[error("invalid peer identity: {0}")]
+    InvalidIdentity(String),
+
+    #[error("invalid record tag: {0}")]
+    InvalidTag(String),
 }
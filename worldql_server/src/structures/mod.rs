@@ -1,3 +1,4 @@
+mod area;
 mod codec;
 mod entity;
 mod instruction;
@@ -6,11 +7,12 @@ mod record;
 mod replication;
 mod vector3;
 
+pub use area::Area;
 pub use codec::DecodeError;
 pub(self) use codec::{Decode, Encode};
 pub use entity::Entity;
 pub use instruction::Instruction;
-pub use message::Message;
+pub use message::{DeserializeError, Message};
 pub use record::Record;
 pub use replication::Replication;
 pub use vector3::Vector3;
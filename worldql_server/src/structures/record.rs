@@ -1,9 +1,12 @@
 use bytes::Bytes;
+use chrono::NaiveDateTime;
+use flatbuffers::FlatBufferBuilder;
 use tokio_postgres::Row;
 use uuid::Uuid;
 
-use super::{Decode, DecodeError, Encode, Vector3};
-use crate::flatbuffers::RecordT;
+use super::{Decode, DecodeError, DeserializeError, Encode, Vector3};
+use crate::flatbuffers::{Record as RecordFB, RecordT};
+use crate::utils::{epoch_millis, is_valid_identity, is_valid_tag, to_epoch_millis};
 
 #[derive(Debug, Default, Clone)]
 pub struct Record {
@@ -12,6 +15,30 @@ pub struct Record {
     pub world_name: String,
     pub data: Option<String>,
     pub flex: Option<Bytes>,
+
+    /// Records are hidden from reads once expired, and deleted soon after
+    pub expires_at: Option<NaiveDateTime>,
+
+    /// Stored records start at version 1, incremented on every write.
+    ///
+    /// When set on a record being written or deleted, the write only succeeds if it
+    /// matches the stored version. Version 0 matches records that don't exist yet.
+    pub version: Option<u64>,
+
+    /// Identity of the peer that created this record, [`None`] if created anonymously.
+    ///
+    /// Only the owner, its writers, and admin peers can write to owned records.
+    pub owner: Option<String>,
+
+    /// Identities allowed to write to this record besides its owner.
+    ///
+    /// Left unchanged when writing a record without writers.
+    pub writers: Option<Vec<String>>,
+
+    /// Sorted set of tags, for reading records by tag regardless of position.
+    ///
+    /// Replaced on every write, like [`Record::data`].
+    pub tags: Vec<String>,
 }
 
 impl Encode<RecordT> for Record {
@@ -22,6 +49,14 @@ impl Encode<RecordT> for Record {
             world_name: Some(self.world_name),
             data: self.data,
             flex: self.flex.map(|flex| flex.to_vec()),
+            expires_at: self.expires_at.as_ref().map(to_epoch_millis),
+            version: self.version,
+            owner: self.owner,
+            writers: self.writers,
+            tags: match self.tags.is_empty() {
+                true => None,
+                false => Some(self.tags),
+            },
         }
     }
 }
@@ -41,12 +76,37 @@ impl Decode<RecordT> for Record {
             .world_name
             .ok_or_else(|| DecodeError::MissingRequiredField("world_name".into()))?;
 
+        let expires_at = match encoded.expires_at {
+            None => None,
+            Some(ts) => Some(epoch_millis(ts).ok_or(DecodeError::InvalidTimestamp(ts))?),
+        };
+
+        let identities = encoded.owner.iter().chain(encoded.writers.iter().flatten());
+        for identity in identities {
+            if !is_valid_identity(identity) {
+                return Err(DecodeError::InvalidIdentity(identity.clone()));
+            }
+        }
+
+        let mut tags = encoded.tags.unwrap_or_default();
+        if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+            return Err(DecodeError::InvalidTag(tag.clone()));
+        }
+
+        tags.sort_unstable();
+        tags.dedup();
+
         let record = Record {
             uuid: Uuid::parse_str(&uuid)?,
             position,
             world_name,
             data: encoded.data,
             flex: encoded.flex.map(Bytes::from),
+            expires_at,
+            version: encoded.version,
+            owner: encoded.owner,
+            writers: encoded.writers,
+            tags,
         };
 
         Ok(record)
@@ -59,6 +119,7 @@ impl Record {
         let y: f64 = row.get("y");
         let z: f64 = row.get("z");
         let flex: Option<Vec<u8>> = row.get("flex");
+        let version: i64 = row.get("version");
 
         Self {
             uuid: row.get("uuid"),
@@ -66,6 +127,163 @@ impl Record {
             world_name: world_name.to_string(),
             data: row.get("data"),
             flex: flex.map(Bytes::from),
+            expires_at: row.get("expires_at"),
+            version: Some(version as u64),
+            owner: row.get("owner"),
+            writers: row.get("writers"),
+            tags: row
+                .get::<_, Option<Vec<String>>>("tags")
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns `true` if this record has expired at the given time.
+    #[inline]
+    pub fn is_expired(&self, now: &NaiveDateTime) -> bool {
+        match &self.expires_at {
+            None => false,
+            Some(expires_at) => expires_at <= now,
+        }
+    }
+
+    /// Serialize into a standalone flatbuffer `Record`, prefixed with its length as a
+    /// little-endian `u32`.
+    pub fn serialize_size_prefixed(self) -> Bytes {
+        let encoded = self.encode();
+
+        let mut builder = FlatBufferBuilder::new();
+        let offset = encoded.pack(&mut builder);
+        builder.finish_size_prefixed(offset, None);
+
+        Bytes::copy_from_slice(builder.finished_data())
+    }
+
+    /// Deserialize a flatbuffer `Record` written by [`Record::serialize_size_prefixed`].
+    pub fn deserialize_size_prefixed(buf: &[u8]) -> Result<Self, DeserializeError> {
+        let raw = flatbuffers::size_prefixed_root::<RecordFB>(buf)?;
+        let record = Record::decode(raw.unpack())?;
+
+        Ok(record)
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn expiry_codec() {
+        let expires_at = NaiveDateTime::from_timestamp(1_650_000_000, 123_000_000);
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "world".into(),
+            expires_at: Some(expires_at),
+            ..Default::default()
+        };
+
+        let encoded = record.encode();
+        assert_eq!(encoded.expires_at, Some(1_650_000_000_123));
+
+        let decoded = Record::decode(encoded).unwrap();
+        assert_eq!(decoded.expires_at, Some(expires_at));
+        assert!(decoded.is_expired(&(expires_at + Duration::milliseconds(1))));
+        assert!(decoded.is_expired(&expires_at));
+        assert!(!decoded.is_expired(&(expires_at - Duration::milliseconds(1))));
+    }
+
+    #[test]
+    fn version_codec() {
+        for version in [None, Some(0), Some(42)] {
+            let record = Record {
+                uuid: Uuid::new_v4(),
+                world_name: "world".into(),
+                version,
+                ..Default::default()
+            };
+
+            let decoded = Record::decode(record.encode()).unwrap();
+            assert_eq!(decoded.version, version);
         }
     }
+
+    #[test]
+    fn owner_codec() {
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "world".into(),
+            owner: Some("game".into()),
+            writers: Some(vec!["editor".into(), "backend".into()]),
+            ..Default::default()
+        };
+
+        let decoded = Record::decode(record.clone().encode()).unwrap();
+        assert_eq!(decoded.owner, record.owner);
+        assert_eq!(decoded.writers, record.writers);
+
+        let invalid = Record {
+            writers: Some(vec!["a,b".into()]),
+            ..record
+        };
+
+        let result = Record::decode(invalid.encode());
+        assert!(matches!(result, Err(DecodeError::InvalidIdentity(_))));
+    }
+
+    #[test]
+    fn tags_codec() {
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "world".into(),
+            ..Default::default()
+        };
+
+        let encoded = record.clone().encode();
+        assert_eq!(encoded.tags, None);
+        assert!(Record::decode(encoded).unwrap().tags.is_empty());
+
+        let tagged = Record {
+            tags: vec!["spawn_point".into(), "chest".into(), "spawn_point".into()],
+            ..record.clone()
+        };
+
+        let decoded = Record::decode(tagged.encode()).unwrap();
+        assert_eq!(decoded.tags, vec!["chest", "spawn_point"]);
+
+        let invalid = Record {
+            tags: vec!["a;b".into()],
+            ..record
+        };
+
+        let result = Record::decode(invalid.encode());
+        assert!(matches!(result, Err(DecodeError::InvalidTag(_))));
+    }
+
+    #[test]
+    fn size_prefixed() {
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(1.0, -2.0, 3.5)),
+            world_name: "world".into(),
+            data: Some("data".into()),
+            flex: Some(Bytes::from_static(&[1, 2, 3])),
+            version: Some(3),
+            tags: vec!["chest".into()],
+            ..Default::default()
+        };
+
+        let buf = record.clone().serialize_size_prefixed();
+        let len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        assert_eq!(len + 4, buf.len());
+
+        let decoded = Record::deserialize_size_prefixed(&buf).unwrap();
+        assert_eq!(decoded.uuid, record.uuid);
+        assert_eq!(decoded.position, record.position);
+        assert_eq!(decoded.flex, record.flex);
+        assert_eq!(decoded.version, record.version);
+        assert_eq!(decoded.tags, record.tags);
+    }
 }
+// endregion
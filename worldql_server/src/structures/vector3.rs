@@ -24,6 +24,15 @@ impl Vector3 {
     pub fn zero() -> Self {
         Self::new(0.0, 0.0, 0.0)
     }
+
+    #[inline]
+    pub fn distance_squared(&self, other: &Vector3) -> f64 {
+        let x = self.x - other.x;
+        let y = self.y - other.y;
+        let z = self.z - other.z;
+
+        x * x + y * y + z * z
+    }
 }
 
 // region: Display Trait
@@ -14,6 +14,9 @@ pub struct AreaMap {
     map: AHashMap<CubeArea, AHashSet<Uuid>>,
     subscribed_peers: AHashSet<Uuid>,
     empty_set: AHashSet<Uuid>,
+
+    /// Subscriptions waiting on an initial sync, with the number of syncs in flight
+    pending_syncs: AHashMap<(Uuid, CubeArea), usize>,
 }
 
 impl AreaMap {
@@ -25,9 +28,32 @@ impl AreaMap {
             map: AHashMap::new(),
             subscribed_peers: AHashSet::new(),
             empty_set: AHashSet::new(),
+
+            pending_syncs: AHashMap::new(),
         }
     }
 
+    /// Returns the name of the world this map holds subscriptions for.
+    #[cfg(feature = "http")]
+    #[inline]
+    pub fn world_name(&self) -> &str {
+        &self.world_name
+    }
+
+    /// Returns the number of areas with at least one subscribed peer.
+    #[cfg(any(feature = "http", test))]
+    #[inline]
+    pub fn area_count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns the number of peers subscribed to any area in this world.
+    #[cfg(any(feature = "http", test))]
+    #[inline]
+    pub fn peer_count(&self) -> usize {
+        self.subscribed_peers.len()
+    }
+
     /// Returns `true` if the [`crate::transport::Peer`] corresponding to the given UUID
     /// is subscribed to the given area.
     pub fn is_peer_subscribed(&self, uuid: &Uuid, cube: impl ToCubeArea) -> bool {
@@ -66,6 +92,15 @@ impl AreaMap {
         self.subscribed_peers.iter().copied()
     }
 
+    /// Returns an iterator of every [`CubeArea`] the given peer is subscribed to.
+    #[cfg(any(feature = "http", test))]
+    pub fn get_peer_areas<'a>(&'a self, uuid: &'a Uuid) -> impl Iterator<Item = CubeArea> + 'a {
+        self.map
+            .iter()
+            .filter(move |(_, peers)| peers.contains(uuid))
+            .map(|(cube, _)| *cube)
+    }
+
     /// If the subscription was added, `true` is returned.
     ///
     /// If the subscription was already present, `false` is returned
@@ -84,9 +119,44 @@ impl AreaMap {
         entry.insert(uuid)
     }
 
+    /// Mark a subscription as waiting on an initial sync.
+    ///
+    /// Record changes in the area are not sent to the peer until the sync is finished,
+    /// as they are already included in it.
+    pub fn add_pending_sync(&mut self, uuid: Uuid, cube: impl ToCubeArea) {
+        let cube = cube.to_cube_area(self.cube_size);
+        *self.pending_syncs.entry((uuid, cube)).or_default() += 1;
+    }
+
+    /// Returns `true` if the given peer is waiting on an initial sync of the given area.
+    #[inline]
+    pub fn is_sync_pending(&self, uuid: &Uuid, cube: impl ToCubeArea) -> bool {
+        let cube = cube.to_cube_area(self.cube_size);
+        self.pending_syncs.contains_key(&(*uuid, cube))
+    }
+
+    /// Returns whether the sync was still pending.
+    ///
+    /// Syncs are no longer pending once the peer unsubscribes from the area.
+    pub fn finish_pending_sync(&mut self, uuid: &Uuid, cube: impl ToCubeArea) -> bool {
+        let key = (*uuid, cube.to_cube_area(self.cube_size));
+        let count = match self.pending_syncs.get_mut(&key) {
+            None => return false,
+            Some(count) => count,
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            self.pending_syncs.remove(&key);
+        }
+
+        true
+    }
+
     /// Returns whether the subscription was removed.
     pub fn remove_subscription(&mut self, uuid: &Uuid, cube: impl ToCubeArea) -> bool {
         let cube = cube.to_cube_area(self.cube_size);
+        self.pending_syncs.remove(&(*uuid, cube));
 
         // Early return if no subscriptions are present
         if !self.map.contains_key(&cube) {
@@ -123,6 +193,7 @@ impl AreaMap {
     /// Used in the event of a disconnect.
     pub fn remove_peer(&mut self, uuid: &Uuid) -> bool {
         self.subscribed_peers.remove(uuid);
+        self.pending_syncs.retain(|(peer, _), _| peer != uuid);
 
         let mut removed = false;
         for peers in self.map.values_mut() {
@@ -131,6 +202,9 @@ impl AreaMap {
             }
         }
 
+        // Remove any areas left without subscriptions
+        self.map.retain(|_, peers| !peers.is_empty());
+
         removed
     }
 }
@@ -252,4 +326,69 @@ mod tests {
         assert!(!map.is_peer_subscribed_any(&uuid_1));
         assert!(!map.is_peer_subscribed_any(&uuid_2));
     }
+
+    #[test]
+    fn peer_areas() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+
+        let cube_1 = CubeArea::new(0, 0, 0);
+        let cube_2 = CubeArea::new(16, 16, 16);
+        let mut map = AreaMap::new(16, "world".into());
+
+        // No areas yet
+        assert_eq!(map.area_count(), 0);
+        assert_eq!(map.get_peer_areas(&uuid_1).count(), 0);
+
+        // uuid_1 subscribed to both, uuid_2 to one
+        map.add_subscription(uuid_1, cube_1);
+        map.add_subscription(uuid_1, cube_2);
+        map.add_subscription(uuid_2, cube_2);
+        assert_eq!(map.area_count(), 2);
+        assert_eq!(map.peer_count(), 2);
+        assert_eq!(map.get_peer_areas(&uuid_1).count(), 2);
+        assert_eq!(
+            map.get_peer_areas(&uuid_2).collect::<Vec<_>>(),
+            vec![cube_2]
+        );
+
+        // Removing uuid_1 drops the now empty area
+        map.remove_peer(&uuid_1);
+        assert_eq!(map.area_count(), 1);
+        assert_eq!(map.peer_count(), 1);
+        assert_eq!(map.get_peer_areas(&uuid_1).count(), 0);
+    }
+
+    #[test]
+    fn pending_syncs() {
+        let uuid = Uuid::new_v4();
+        let cube = CubeArea::new(16, 16, 16);
+        let mut map = AreaMap::new(16, "world".into());
+
+        // Not pending until marked
+        map.add_subscription(uuid, cube);
+        assert!(!map.is_sync_pending(&uuid, cube));
+        assert!(!map.finish_pending_sync(&uuid, cube));
+
+        // Pending until every sync has finished
+        map.add_pending_sync(uuid, cube);
+        map.add_pending_sync(uuid, cube);
+        assert!(map.is_sync_pending(&uuid, Vector3::new(6.3, 1.0, 10.5)));
+        assert!(map.finish_pending_sync(&uuid, cube));
+        assert!(map.is_sync_pending(&uuid, cube));
+        assert!(map.finish_pending_sync(&uuid, cube));
+        assert!(!map.is_sync_pending(&uuid, cube));
+
+        // Unsubscribing cancels pending syncs
+        map.add_pending_sync(uuid, cube);
+        map.remove_subscription(&uuid, cube);
+        assert!(!map.is_sync_pending(&uuid, cube));
+        assert!(!map.finish_pending_sync(&uuid, cube));
+
+        // As does disconnecting
+        map.add_subscription(uuid, cube);
+        map.add_pending_sync(uuid, cube);
+        map.remove_peer(&uuid);
+        assert!(!map.is_sync_pending(&uuid, cube));
+    }
 }
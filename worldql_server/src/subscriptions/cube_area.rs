@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use derive_getters::Getters;
 
-use crate::structures::Vector3;
+use crate::structures::{Area, Vector3};
 
 // region: CubeArea
 #[derive(Debug, Default, Getters, Clone, Copy, PartialEq, Eq, Hash)]
@@ -54,6 +54,30 @@ impl CubeArea {
 
         Self::new(x, y, z)
     }
+
+    /// Returns a box [`Area`] covering this cube.
+    ///
+    /// Cube borders are shared with their neighbours, positions in the returned area
+    /// should be checked with [`ToCubeArea`] to exclude them.
+    pub fn to_area(self, size: u16) -> Area {
+        let bounds = |coord: i64| {
+            let (min, max) = match coord > 0 {
+                true => (coord - i64::from(size), coord),
+                false => (coord, coord + i64::from(size)),
+            };
+
+            (min as f64, max as f64)
+        };
+
+        let (min_x, max_x) = bounds(self.x);
+        let (min_y, max_y) = bounds(self.y);
+        let (min_z, max_z) = bounds(self.z);
+
+        Area::new_box(
+            Vector3::new(min_x, min_y, min_z),
+            Vector3::new(max_x, max_y, max_z),
+        )
+    }
 }
 // endregion
 
@@ -174,5 +198,25 @@ mod tests {
         test_from_vector3!((25.0, -13.2, -0.1), (30, -20, -10), 10);
     }
     // endregion
+
+    #[test]
+    fn to_area() {
+        let positions = [
+            Vector3::new(0.0, 10.0, 9.5),
+            Vector3::new(-0.1, -10.0, -19.9),
+            Vector3::new(25.0, -13.2, 0.0),
+        ];
+
+        for position in positions {
+            let cube = CubeArea::from_vector3(position, 10);
+            assert!(cube.to_area(10).contains(&position));
+        }
+
+        let expected = Area::new_box(
+            Vector3::new(0.0, -10.0, -20.0),
+            Vector3::new(10.0, 0.0, -10.0),
+        );
+        assert_eq!(CubeArea::new(10, -10, -20).to_area(10), expected);
+    }
 }
 // endregion
@@ -1,7 +1,11 @@
 mod area_map;
 mod cube_area;
+mod query;
 mod world_map;
 
 pub use area_map::AreaMap;
 pub use cube_area::{CubeArea, ToCubeArea};
+pub use query::SubscriptionQuery;
+#[cfg(feature = "http")]
+pub use query::WorldSummary;
 pub use world_map::WorldMap;
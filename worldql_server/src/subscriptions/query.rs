@@ -0,0 +1,25 @@
+#[cfg(feature = "http")]
+use {super::CubeArea, tokio::sync::oneshot, uuid::Uuid};
+
+/// Read-only queries against the subscription state, which is owned by the
+/// processing thread.
+///
+/// Each query carries a [`oneshot::Sender`] that the reply is sent back on.
+#[derive(Debug)]
+pub enum SubscriptionQuery {
+    /// Summarise every world that currently has subscriptions.
+    #[cfg(feature = "http")]
+    Worlds(oneshot::Sender<Vec<WorldSummary>>),
+
+    /// List every area a peer is subscribed to, grouped by world name.
+    #[cfg(feature = "http")]
+    PeerSubscriptions(Uuid, oneshot::Sender<Vec<(String, Vec<CubeArea>)>>),
+}
+
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct WorldSummary {
+    pub world_name: String,
+    pub area_count: usize,
+    pub peer_count: usize,
+}
@@ -35,6 +35,13 @@ impl WorldMap {
         })
     }
 
+    /// Returns an iterator of every [`AreaMap`] in this map.
+    #[cfg(feature = "http")]
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &AreaMap> + '_ {
+        self.map.values()
+    }
+
     /// Completely removes a [`crate::transport::Peer`] from the map.
     ///
     /// Used in the event of a disconnect.
@@ -0,0 +1,330 @@
+use axum::extract::{Extension, Path, TypedHeader};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use flume::Sender;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::http_rest::{check_auth, AppError};
+use crate::database::{DatabaseError, ThreadRecordStore, WorldStats};
+use crate::subscriptions::SubscriptionQuery;
+use crate::transport::{Peer, ThreadPeerMap};
+
+type AuthHeader = Option<TypedHeader<Authorization<Bearer>>>;
+
+/// Admin routes, nested under `/admin`.
+///
+/// All routes require the HTTP auth token to be set.
+pub(super) fn router() -> Router {
+    Router::new()
+        .route("/peers", get(get_peers))
+        .route("/peers/:uuid", get(get_peer).delete(delete_peer))
+        .route("/peers/:uuid/subscriptions", get(get_peer_subscriptions))
+        .route("/worlds", get(get_worlds))
+        .route("/store/worlds", get(get_stored_worlds))
+        .route("/store/worlds/:world_name", delete(delete_world))
+        .route("/store/worlds/:world_name/copy", post(post_copy_world))
+        .route("/store/worlds/:world_name/rename", post(post_rename_world))
+}
+
+// region: Request Structs
+#[derive(Debug, Deserialize)]
+struct WorldTarget {
+    to: String,
+}
+// endregion
+
+// region: Response Structs
+#[derive(Debug, Serialize)]
+struct PeerInfo {
+    uuid: Uuid,
+    addr: String,
+    transport: String,
+    identity: Option<String>,
+    admin: bool,
+    connected_secs: u64,
+    last_heartbeat_secs: u64,
+}
+
+impl From<&Peer> for PeerInfo {
+    fn from(peer: &Peer) -> Self {
+        let identity = peer.identity().as_ref();
+        Self {
+            uuid: *peer.uuid(),
+            addr: peer.addr().to_string(),
+            transport: peer.connection().to_string(),
+            identity: identity.map(|identity| identity.name.clone()),
+            admin: identity.map_or(false, |identity| identity.admin),
+            connected_secs: peer.connected_at().elapsed().as_secs(),
+            last_heartbeat_secs: peer.last_heartbeat().elapsed().as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AreaInfo {
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionInfo {
+    world_name: String,
+    areas: Vec<AreaInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorldInfo {
+    world_name: String,
+    area_count: usize,
+    peer_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct StoredWorldInfo {
+    world_name: String,
+    table_count: u64,
+    record_count: u64,
+}
+
+impl From<WorldStats> for StoredWorldInfo {
+    fn from(stats: WorldStats) -> Self {
+        Self {
+            world_name: stats.world_name,
+            table_count: stats.table_count,
+            record_count: stats.record_count,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CopyInfo {
+    copied: u64,
+}
+// endregion
+
+// region: Handlers
+async fn get_peers(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(peer_map): Extension<ThreadPeerMap>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let peers = {
+        let map = peer_map.read().await;
+        map.iter().map(PeerInfo::from).collect::<Vec<_>>()
+    };
+
+    Ok(Json(peers).into_response())
+}
+
+async fn get_peer(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(peer_map): Extension<ThreadPeerMap>,
+    Path(uuid): Path<String>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let uuid = match Uuid::parse_str(&uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let map = peer_map.read().await;
+    match map.get(&uuid) {
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(peer) => Ok(Json(PeerInfo::from(peer)).into_response()),
+    }
+}
+
+async fn delete_peer(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(peer_map): Extension<ThreadPeerMap>,
+    Path(uuid): Path<String>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let uuid = match Uuid::parse_str(&uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let mut map = peer_map.write().await;
+    match map.kick(&uuid).await {
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(_) => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+async fn get_peer_subscriptions(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(sub_query_tx): Extension<Sender<SubscriptionQuery>>,
+    Path(uuid): Path<String>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let uuid = match Uuid::parse_str(&uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let query = SubscriptionQuery::PeerSubscriptions(uuid, reply_tx);
+    sub_query_tx.send_async(query).await?;
+
+    let subscriptions = reply_rx
+        .await?
+        .into_iter()
+        .map(|(world_name, areas)| SubscriptionInfo {
+            world_name,
+            areas: areas
+                .into_iter()
+                .map(|area| AreaInfo {
+                    x: *area.x(),
+                    y: *area.y(),
+                    z: *area.z(),
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(subscriptions).into_response())
+}
+
+async fn get_worlds(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(sub_query_tx): Extension<Sender<SubscriptionQuery>>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    sub_query_tx
+        .send_async(SubscriptionQuery::Worlds(reply_tx))
+        .await?;
+
+    let worlds = reply_rx
+        .await?
+        .into_iter()
+        .map(|world| WorldInfo {
+            world_name: world.world_name,
+            area_count: world.area_count,
+            peer_count: world.peer_count,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(worlds).into_response())
+}
+
+/// Returns every world in the record store, unlike `/worlds` which only includes
+/// worlds with subscribed peers.
+async fn get_stored_worlds(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let worlds = store
+        .list_worlds()
+        .await?
+        .into_iter()
+        .map(StoredWorldInfo::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(worlds).into_response())
+}
+
+async fn delete_world(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    Path(world_name): Path<String>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    match store.drop_world(&world_name).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Ok(false) => Ok(StatusCode::NOT_FOUND.into_response()),
+        Err(error) => match world_error_status(&error) {
+            Some(status) => Ok(status.into_response()),
+            None => Err(error.into()),
+        },
+    }
+}
+
+async fn post_copy_world(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    Path(world_name): Path<String>,
+    Json(target): Json<WorldTarget>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    match store.copy_world(&world_name, &target.to).await {
+        Ok(copied) => Ok(Json(CopyInfo { copied }).into_response()),
+        Err(error) => match world_error_status(&error) {
+            Some(status) => Ok(status.into_response()),
+            None => Err(error.into()),
+        },
+    }
+}
+
+async fn post_rename_world(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    Path(world_name): Path<String>,
+    Json(target): Json<WorldTarget>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    match store.rename_world(&world_name, &target.to).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(error) => match world_error_status(&error) {
+            Some(status) => Ok(status.into_response()),
+            None => Err(error.into()),
+        },
+    }
+}
+
+/// Returns the status for errors caused by a world management request, or [`None`] for
+/// any other errors.
+fn world_error_status(error: &DatabaseError) -> Option<StatusCode> {
+    match error {
+        DatabaseError::InvalidWorldName(_) => Some(StatusCode::BAD_REQUEST),
+        DatabaseError::WorldNotFound(_) => Some(StatusCode::NOT_FOUND),
+        DatabaseError::WorldExists(_) => Some(StatusCode::CONFLICT),
+        _ => None,
+    }
+}
+// endregion
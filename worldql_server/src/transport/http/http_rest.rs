@@ -5,7 +5,7 @@ use axum::headers::authorization::Bearer;
 use axum::headers::Authorization;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{AddExtensionLayer, Json, Router};
 use color_eyre::Result;
 use flume::Sender;
@@ -14,10 +14,20 @@ use thiserror::Error;
 use tracing::info;
 use uuid::Uuid;
 
+use super::{admin, records};
+use crate::database::{DatabaseError, ThreadRecordStore};
 use crate::structures::{Instruction, Message, Replication};
+use crate::subscriptions::SubscriptionQuery;
+use crate::transport::ThreadPeerMap;
+use crate::utils::ThreadHealth;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_http_server(
+    peer_map: ThreadPeerMap,
+    store: ThreadRecordStore,
     msg_tx: Sender<Message>,
+    sub_query_tx: Sender<SubscriptionQuery>,
+    health: ThreadHealth,
     host: IpAddr,
     port: u16,
     auth_token: Option<String>,
@@ -27,7 +37,20 @@ pub async fn start_http_server(
 
     let app = Router::new()
         .route("/global_message", post(post_global_message))
+        .route("/healthz", get(get_healthz))
+        .route("/readyz", get(get_readyz))
+        .nest("/admin", admin::router())
+        .nest("/records", records::router());
+
+    #[cfg(feature = "metrics")]
+    let app = app.route("/metrics", get(get_metrics));
+
+    let app = app
         .layer(AddExtensionLayer::new(auth_token))
+        .layer(AddExtensionLayer::new(health))
+        .layer(AddExtensionLayer::new(peer_map))
+        .layer(AddExtensionLayer::new(store))
+        .layer(AddExtensionLayer::new(sub_query_tx))
         .layer(AddExtensionLayer::new(msg_tx));
 
     axum::Server::bind(&addr)
@@ -60,9 +83,18 @@ impl From<PartialGlobalMessage> for Message {
 }
 
 #[derive(Debug, Error)]
-enum AppError {
+pub(super) enum AppError {
     #[error(transparent)]
     SendError(#[from] flume::SendError<Message>),
+
+    #[error(transparent)]
+    SubscriptionQuery(#[from] flume::SendError<SubscriptionQuery>),
+
+    #[error(transparent)]
+    QueryReply(#[from] tokio::sync::oneshot::error::RecvError),
+
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
 }
 
 impl IntoResponse for AppError {
@@ -82,24 +114,73 @@ async fn post_global_message(
     Json(partial_message): Json<PartialGlobalMessage>,
     authorization: Option<TypedHeader<Authorization<Bearer>>>,
 ) -> Result<impl IntoResponse, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, false) {
+        return Ok(status);
+    }
+
+    // Send message to other clients
+    let message: Message = partial_message.into();
+    msg_tx.send_async(message).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_healthz() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+async fn get_readyz(Extension(health): Extension<ThreadHealth>) -> impl IntoResponse {
+    let status = match health.is_ready() {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(health.components()))
+}
+
+#[cfg(feature = "metrics")]
+async fn get_metrics(
+    Extension(auth_token): Extension<Option<String>>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> axum::response::Response {
+    if let Err(status) = check_auth(auth_token, authorization, false) {
+        return status.into_response();
+    }
+
+    let (content_type, body) = crate::metrics::encode_metrics();
+    let mut headers = axum::http::HeaderMap::new();
+    if let Ok(value) = content_type.parse() {
+        headers.insert(axum::http::header::CONTENT_TYPE, value);
+    }
+
+    (headers, body).into_response()
+}
+
+/// Check a request's bearer token against the configured auth token.
+///
+/// If `required` is `true` then requests are rejected when no auth token is configured.
+pub(super) fn check_auth(
+    auth_token: Option<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    required: bool,
+) -> Result<(), StatusCode> {
     match (auth_token, authorization) {
-        // No auth token requested, always allow
-        (None, _) => (),
+        // No auth token requested, allow unless required
+        (None, _) => match required {
+            true => Err(StatusCode::FORBIDDEN),
+            false => Ok(()),
+        },
 
         // Auth token requested but not given
-        (Some(_), None) => return Ok(StatusCode::UNAUTHORIZED),
+        (Some(_), None) => Err(StatusCode::UNAUTHORIZED),
 
         // Auth token requested and given
         (Some(token), Some(TypedHeader(Authorization(bearer)))) => {
             if token != bearer.token() {
-                return Ok(StatusCode::UNAUTHORIZED);
+                return Err(StatusCode::UNAUTHORIZED);
             }
+
+            Ok(())
         }
     }
-
-    // Send message to other clients
-    let message: Message = partial_message.into();
-    msg_tx.send_async(message).await?;
-
-    Ok(StatusCode::NO_CONTENT)
 }
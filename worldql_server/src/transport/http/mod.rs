@@ -1,5 +1,9 @@
 #[cfg(feature = "http")]
+mod admin;
+#[cfg(feature = "http")]
 mod http_rest;
+#[cfg(feature = "http")]
+mod records;
 #[cfg(feature = "websocket")]
 mod websocket;
 
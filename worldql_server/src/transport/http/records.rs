@@ -0,0 +1,229 @@
+use axum::extract::{Extension, Path, Query, TypedHeader};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::http_rest::{check_auth, AppError};
+use crate::database::{DataFilter, DatabaseError, HistoryEntry, ThreadRecordStore};
+use crate::structures::{Area, Record, Vector3};
+use crate::utils::{epoch_millis, is_valid_tag, to_epoch_millis};
+
+type AuthHeader = Option<TypedHeader<Authorization<Bearer>>>;
+
+/// Maximum number of history entries returned by default
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Record routes, nested under `/records`.
+pub(super) fn router() -> Router {
+    Router::new()
+        .route("/query", post(post_query))
+        .route("/:world_name/:uuid/history", get(get_history))
+}
+
+// region: Request and Response Structs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl From<Point> for Vector3 {
+    fn from(point: Point) -> Self {
+        Vector3::new(point.x, point.y, point.z)
+    }
+}
+
+impl From<Vector3> for Point {
+    fn from(vector: Vector3) -> Self {
+        Self {
+            x: *vector.x(),
+            y: *vector.y(),
+            z: *vector.z(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AreaParams {
+    Box { min: Point, max: Point },
+    Sphere { center: Point, radius: f64 },
+}
+
+impl From<AreaParams> for Area {
+    fn from(params: AreaParams) -> Self {
+        match params {
+            AreaParams::Box { min, max } => Area::new_box(min.into(), max.into()),
+            AreaParams::Sphere { center, radius } => Area::new_sphere(center.into(), radius),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AreaQuery {
+    world_name: String,
+    #[serde(flatten)]
+    area: AreaParams,
+
+    /// Only return records modified after this time, in epoch milliseconds
+    after: Option<u64>,
+
+    /// Only return records with data matching this filter, see [`DataFilter`]
+    filter: Option<String>,
+
+    /// Only return records tagged with this tag
+    tag: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RecordInfo {
+    uuid: Uuid,
+    world_name: String,
+    position: Option<Point>,
+    data: Option<String>,
+    flex: Option<Vec<u8>>,
+
+    /// Epoch milliseconds
+    expires_at: Option<u64>,
+    version: Option<u64>,
+    owner: Option<String>,
+    writers: Option<Vec<String>>,
+    tags: Vec<String>,
+}
+
+impl From<Record> for RecordInfo {
+    fn from(record: Record) -> Self {
+        Self {
+            uuid: record.uuid,
+            world_name: record.world_name,
+            position: record.position.map(Point::from),
+            data: record.data,
+            flex: record.flex.map(|flex| flex.to_vec()),
+            expires_at: record.expires_at.as_ref().map(to_epoch_millis),
+            version: record.version,
+            owner: record.owner,
+            writers: record.writers,
+            tags: record.tags,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryInfo {
+    /// Epoch milliseconds
+    changed_at: u64,
+    operation: &'static str,
+    peer: Option<Uuid>,
+    old: Option<RecordInfo>,
+    new: Option<RecordInfo>,
+}
+
+impl From<HistoryEntry> for HistoryInfo {
+    fn from(entry: HistoryEntry) -> Self {
+        Self {
+            changed_at: to_epoch_millis(&entry.changed_at),
+            operation: entry.operation.as_str(),
+            peer: entry.peer,
+            old: entry.old.map(RecordInfo::from),
+            new: entry.new.map(RecordInfo::from),
+        }
+    }
+}
+// endregion
+
+// region: Handlers
+async fn post_query(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    Json(query): Json<AreaQuery>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, false) {
+        return Ok(status.into_response());
+    }
+
+    let after = match query.after.map(epoch_millis) {
+        None => None,
+        Some(None) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+        Some(after) => after,
+    };
+
+    let filter = match query.filter.as_deref().map(str::parse::<DataFilter>) {
+        None => DataFilter::default(),
+        Some(Ok(filter)) => filter,
+        Some(Err(_)) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let area = query.area.into();
+    let result = match query.tag.as_deref() {
+        None => {
+            store
+                .get_records_in_area(&query.world_name, area, after, &filter)
+                .await
+        }
+
+        Some(tag) if is_valid_tag(tag) => {
+            store
+                .get_records_with_tag(&query.world_name, tag, Some(area), after, &filter)
+                .await
+        }
+
+        Some(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let records = match result {
+        Ok(records) => records,
+        Err(DatabaseError::InvalidWorldName(_)) => {
+            return Ok(StatusCode::BAD_REQUEST.into_response())
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let records = records
+        .into_iter()
+        .map(RecordInfo::from)
+        .collect::<Vec<_>>();
+    Ok(Json(records).into_response())
+}
+
+/// Returns the history of a record, newest first.
+///
+/// Requires the HTTP auth token to be set, as history includes deleted records.
+async fn get_history(
+    Extension(auth_token): Extension<Option<String>>,
+    Extension(store): Extension<ThreadRecordStore>,
+    Path((world_name, uuid)): Path<(String, Uuid)>,
+    Query(query): Query<HistoryQuery>,
+    authorization: AuthHeader,
+) -> Result<Response, AppError> {
+    if let Err(status) = check_auth(auth_token, authorization, true) {
+        return Ok(status.into_response());
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    let entries = match store.get_record_history(&world_name, uuid, limit).await {
+        Ok(entries) => entries,
+        Err(DatabaseError::InvalidWorldName(_)) => {
+            return Ok(StatusCode::BAD_REQUEST.into_response())
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let entries = entries
+        .into_iter()
+        .map(HistoryInfo::from)
+        .collect::<Vec<_>>();
+    Ok(Json(entries).into_response())
+}
+// endregion
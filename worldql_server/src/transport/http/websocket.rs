@@ -9,10 +9,13 @@ use uuid::Uuid;
 
 use crate::structures::{Instruction, Message};
 use crate::transport::{Peer, ThreadPeerMap};
+use crate::utils::{health, ThreadHealth, ThreadPeerAuth};
 
 pub async fn start_websocket_server(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<Message>,
+    peer_auth: ThreadPeerAuth,
+    health: ThreadHealth,
     ws_host: IpAddr,
     ws_port: u16,
 ) -> Result<()> {
@@ -20,6 +23,12 @@ pub async fn start_websocket_server(
     let listener = TcpListener::bind(&addr).await?;
     info!("WebSocket Server listening on {}", addr);
 
+    // Listener is bound, mark as not ready again if this thread exits
+    health.set_ready(health::WEBSOCKET, true);
+    scopeguard::defer! {
+        health.set_ready(health::WEBSOCKET, false);
+    }
+
     while let Ok((stream, _)) = listener.accept().await {
         let addr = stream.peer_addr()?;
         debug!("websocket peer address: {}", addr);
@@ -27,6 +36,7 @@ pub async fn start_websocket_server(
         tokio::spawn(handle_connection(
             peer_map.clone(),
             msg_tx.clone(),
+            peer_auth.clone(),
             addr,
             stream,
         ));
@@ -38,6 +48,7 @@ pub async fn start_websocket_server(
 async fn handle_connection(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<Message>,
+    peer_auth: ThreadPeerAuth,
     addr: SocketAddr,
     raw_stream: TcpStream,
 ) -> Result<()> {
@@ -78,6 +89,14 @@ async fn handle_connection(
                 return Ok(());
             }
 
+            match peer_auth.authenticate(message.flex.as_deref()) {
+                Ok(identity) => peer.set_identity(identity),
+                Err(error) => {
+                    debug!("peer {} handshake rejected: {}", &addr, error);
+                    return Ok(());
+                }
+            }
+
             // Only lock for as long as we need
             {
                 let mut map = peer_map.write().await;
@@ -20,6 +20,7 @@ use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
 use crate::structures::Message;
+use crate::utils::PeerIdentity;
 
 #[cfg(feature = "websocket")]
 type WebSocketConnection = SplitSink<WebSocketStream<TcpStream>, WsMessage>;
@@ -34,45 +35,91 @@ pub struct Peer {
     addr: SocketAddr,
     uuid: Uuid,
     connection: PeerConnection,
+
+    /// Identity authenticated during the handshake, [`None`] for anonymous peers
+    identity: Option<PeerIdentity>,
+
+    #[cfg(feature = "http")]
+    connected_at: Instant,
+    last_heartbeat: Instant,
 }
 
 impl Peer {
     #[cfg(feature = "websocket")]
     pub fn new_ws(addr: SocketAddr, uuid: Uuid, ws_conn: WebSocketConnection) -> Self {
+        let now = Instant::now();
         Self {
             addr,
             uuid,
             connection: PeerConnection::WebSocket(ws_conn),
+            identity: None,
+
+            #[cfg(feature = "http")]
+            connected_at: now,
+            last_heartbeat: now,
         }
     }
 
     #[cfg(feature = "zeromq")]
     pub fn new_zmq(addr: SocketAddr, uuid: Uuid, zmq_tx: ZmqConnection) -> Self {
+        let now = Instant::now();
         Self {
             addr,
             uuid,
-            connection: PeerConnection::ZeroMQ((zmq_tx, Instant::now())),
+            connection: PeerConnection::ZeroMQ(zmq_tx),
+            identity: None,
+
+            #[cfg(feature = "http")]
+            connected_at: now,
+            last_heartbeat: now,
         }
     }
 
+    /// Create a [`Peer`] whose outgoing messages are sent to a channel.
+    #[cfg(test)]
+    pub fn new_test(uuid: Uuid) -> (Self, flume::Receiver<Bytes>) {
+        let (tx, rx) = flume::unbounded();
+        let now = Instant::now();
+
+        let peer = Self {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            uuid,
+            connection: PeerConnection::Test(tx),
+            identity: None,
+
+            #[cfg(feature = "http")]
+            connected_at: now,
+            last_heartbeat: now,
+        };
+
+        (peer, rx)
+    }
+
     /// Returns `true` if the duration between the last recieved heartbeat is greater than `max_duration`
     pub fn is_stale(&self, now: &Instant, max_duration: &Duration) -> bool {
         match self.connection {
             #[cfg(feature = "websocket")]
             PeerConnection::WebSocket(_) => false,
             #[cfg(feature = "zeromq")]
-            PeerConnection::ZeroMQ((_, last_heartbeat)) => {
-                let duration = *now - last_heartbeat;
+            PeerConnection::ZeroMQ(_) => {
+                let duration = *now - self.last_heartbeat;
                 duration > *max_duration
             }
+            #[cfg(test)]
+            PeerConnection::Test(_) => false,
         }
     }
 
+    /// Set the identity this peer authenticated as.
+    #[inline]
+    pub fn set_identity(&mut self, identity: Option<PeerIdentity>) {
+        self.identity = identity
+    }
+
     /// Update the Last Received [`Instant`] to the current time.
-    #[cfg(feature = "zeromq")]
     #[inline]
     pub fn update_last_heartbeat(&mut self) {
-        self.connection.update_last_heartbeat()
+        self.last_heartbeat = Instant::now()
     }
 
     /// Send a [`Message`] to this peer.
@@ -86,6 +133,13 @@ impl Peer {
     pub async fn send_raw(&mut self, bytes: Bytes) -> Result<(), SendError> {
         self.connection.send_raw(self.uuid, bytes).await
     }
+
+    /// Close the underlying connection to this peer.
+    #[cfg(feature = "http")]
+    #[inline]
+    pub async fn close(&mut self) -> Result<(), SendError> {
+        self.connection.close().await
+    }
 }
 
 impl PartialEq for Peer {
@@ -110,21 +164,12 @@ pub enum PeerConnection {
     #[cfg(feature = "websocket")]
     WebSocket(WebSocketConnection),
     #[cfg(feature = "zeromq")]
-    ZeroMQ((ZmqConnection, Instant)),
+    ZeroMQ(ZmqConnection),
+    #[cfg(test)]
+    Test(flume::Sender<Bytes>),
 }
 
 impl PeerConnection {
-    /// Update the Last Received [`Instant`] to the current time.
-    #[cfg(feature = "zeromq")]
-    #[inline]
-    fn update_last_heartbeat(&mut self) {
-        #[cfg(feature = "zeromq")]
-        if let PeerConnection::ZeroMQ((_, last_recv)) = self {
-            // Set the last received instant to now
-            *last_recv = Instant::now()
-        }
-    }
-
     /// Send a [`Message`] to this connection.
     #[inline]
     async fn send(&mut self, uuid: Uuid, message: Message) -> Result<(), SendError> {
@@ -146,13 +191,40 @@ impl PeerConnection {
                 Ok(())
             }
             #[cfg(feature = "zeromq")]
-            PeerConnection::ZeroMQ((tx, _)) => {
+            PeerConnection::ZeroMQ(tx) => {
                 tx.send_async((bytes, uuid)).await?;
 
+                Ok(())
+            }
+            #[cfg(test)]
+            PeerConnection::Test(tx) => {
+                tx.send_async(bytes).await?;
+
                 Ok(())
             }
         }
     }
+
+    /// Close this connection.
+    ///
+    /// ZeroMQ peers have no persistent connection to close, they are dropped once
+    /// removed from the [`crate::transport::PeerMap`].
+    #[cfg(feature = "http")]
+    #[inline]
+    async fn close(&mut self) -> Result<(), SendError> {
+        match self {
+            #[cfg(feature = "websocket")]
+            PeerConnection::WebSocket(conn) => {
+                conn.close().await?;
+
+                Ok(())
+            }
+            #[cfg(feature = "zeromq")]
+            PeerConnection::ZeroMQ(_) => Ok(()),
+            #[cfg(test)]
+            PeerConnection::Test(_) => Ok(()),
+        }
+    }
 }
 
 impl Display for PeerConnection {
@@ -162,6 +234,8 @@ impl Display for PeerConnection {
             PeerConnection::WebSocket(_) => write!(f, "WebSocket"),
             #[cfg(feature = "zeromq")]
             PeerConnection::ZeroMQ(_) => write!(f, "ZeroMQ"),
+            #[cfg(test)]
+            PeerConnection::Test(_) => write!(f, "Test"),
         }
     }
 }
@@ -175,4 +249,8 @@ pub enum SendError {
     #[cfg(feature = "zeromq")]
     #[error(transparent)]
     ZmqError(#[from] flume::SendError<ZmqOutgoingPair>),
+
+    #[cfg(test)]
+    #[error(transparent)]
+    TestChannel(#[from] flume::SendError<Bytes>),
 }
@@ -28,6 +28,9 @@ macro_rules! broadcast_to {
             jobs.push(peer.send_raw(bytes.clone()));
         }
 
+        #[cfg(feature = "metrics")]
+        crate::metrics::BROADCAST_FANOUT.observe(jobs.len() as f64);
+
         for result in futures_util::future::join_all(jobs).await {
             if let Err(error) = result {
                 // TODO: Remove peers that error
@@ -78,6 +81,13 @@ impl PeerMap {
         self.map.keys().copied()
     }
 
+    /// Returns an iterator of references to each contained [`Peer`].
+    #[cfg(feature = "http")]
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Peer> + '_ {
+        self.map.values()
+    }
+
     /// Returns an iterator of [`Uuid`] items for each [`Peer`] that is considered stale.
     #[inline]
     pub fn stale_peers_iter(&self, max_duration: Duration) -> impl Iterator<Item = Uuid> + '_ {
@@ -101,8 +111,20 @@ impl PeerMap {
         debug!("inserting peer {} into map", &peer);
         info!("[{}] {} Peer Connected", &peer.addr(), &peer.connection());
 
+        #[cfg(feature = "metrics")]
+        crate::metrics::PEERS
+            .with_label_values(&[&peer.connection().to_string()])
+            .inc();
+
         let existing = self.map.insert(uuid, peer);
 
+        #[cfg(feature = "metrics")]
+        if let Some(existing) = &existing {
+            crate::metrics::PEERS
+                .with_label_values(&[&existing.connection().to_string()])
+                .dec();
+        }
+
         let message = Message {
             instruction: Instruction::PeerConnect,
             parameter: Some(uuid.to_string()),
@@ -126,6 +148,11 @@ impl PeerMap {
             debug!("removed peer {} from map", &peer);
             info!("[{}] {} Peer Disconnected", peer.addr(), peer.connection());
 
+            #[cfg(feature = "metrics")]
+            crate::metrics::PEERS
+                .with_label_values(&[&peer.connection().to_string()])
+                .dec();
+
             let message = Message {
                 instruction: Instruction::PeerDisconnect,
                 parameter: Some(uuid.to_string()),
@@ -139,6 +166,20 @@ impl PeerMap {
         let _ = self.on_remove.send(*uuid);
         result
     }
+
+    /// Forcibly disconnects a [`Peer`], removing it from the map and closing its
+    /// connection.
+    #[cfg(feature = "http")]
+    pub async fn kick(&mut self, uuid: &Uuid) -> Option<Peer> {
+        let mut peer = self.remove(uuid).await?;
+        info!("[{}] {} Peer Kicked", peer.addr(), peer.connection());
+
+        if let Err(error) = peer.close().await {
+            debug!("error closing kicked peer {}: {:?}", uuid, error);
+        }
+
+        Some(peer)
+    }
     // endregion
 
     // region: Broadcast Functions
@@ -7,11 +7,13 @@ use tracing::{debug, info};
 
 use crate::structures::{Instruction, Message};
 use crate::transport::ThreadPeerMap;
+use crate::utils::{health, ThreadHealth};
 
 pub async fn start_zeromq_incoming(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<Message>,
     handshake_tx: Sender<Message>,
+    health: ThreadHealth,
     server_host: IpAddr,
     server_port: u16,
     ctx: tmq::Context,
@@ -23,6 +25,12 @@ pub async fn start_zeromq_incoming(
         server_host, server_port
     );
 
+    // Socket is bound, mark as not ready again if this thread exits
+    health.set_ready(health::ZEROMQ, true);
+    scopeguard::defer! {
+        health.set_ready(health::ZEROMQ, false);
+    }
+
     loop {
         let msg = pull_socket.next().await;
         match msg {
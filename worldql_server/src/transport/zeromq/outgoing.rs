@@ -11,20 +11,30 @@ use uuid::Uuid;
 
 use crate::structures::{Instruction, Message};
 use crate::transport::{Peer, ThreadPeerMap, ZmqOutgoingPair};
+use crate::utils::{health, PeerAuth, ThreadHealth, ThreadPeerAuth};
 
 type SocketMap = AHashMap<Uuid, Push>;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_zeromq_outgoing(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<ZmqOutgoingPair>,
     msg_rx: Receiver<ZmqOutgoingPair>,
     handshake_rx: Receiver<Message>,
+    peer_auth: ThreadPeerAuth,
+    health: ThreadHealth,
     ctx: tmq::Context,
     timeout_secs: u8,
 ) -> Result<()> {
     let mut sockets: SocketMap = AHashMap::new();
     info!("Started ZeroMQ PUSH Manager");
 
+    // Mark as not ready again if this thread exits
+    health.set_ready(health::ZEROMQ_OUTGOING, true);
+    scopeguard::defer! {
+        health.set_ready(health::ZEROMQ_OUTGOING, false);
+    }
+
     let duration = Duration::from_secs(u64::from(timeout_secs));
     let mut interval = time::interval(duration);
     interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
@@ -33,12 +43,17 @@ pub async fn start_zeromq_outgoing(
         tokio::select! {
             // Handle outgoing Message Bytes
             Ok(pair) = msg_rx.recv_async() => {
+                #[cfg(feature = "metrics")]
+                crate::metrics::QUEUE_DEPTH
+                    .with_label_values(&["zeromq_outgoing"])
+                    .set(msg_rx.len() as i64);
+
                 handle_message(&peer_map, &mut sockets, pair).await?
             },
 
             // Handle incoming Handshake Messages
             Ok(message) = handshake_rx.recv_async() => {
-                handle_handshake(&peer_map, msg_tx.clone(), &ctx, &mut sockets, message).await?
+                handle_handshake(&peer_map, &peer_auth, msg_tx.clone(), &ctx, &mut sockets, message).await?
             },
 
             // Repeating interval, check peers which haven't sent
@@ -80,6 +95,7 @@ async fn handle_message(
 
 async fn handle_handshake(
     peer_map: &ThreadPeerMap,
+    peer_auth: &PeerAuth,
     msg_tx: Sender<ZmqOutgoingPair>,
     ctx: &tmq::Context,
     sockets: &mut SocketMap,
@@ -103,6 +119,15 @@ async fn handle_handshake(
         }
     };
 
+    let identity = match peer_auth.authenticate(message.flex.as_deref()) {
+        Ok(identity) => identity,
+        Err(error) => {
+            // Invalid token, drop handshake message
+            debug!("zeromq peer {} handshake rejected: {}", &parameter, error);
+            return Ok(());
+        }
+    };
+
     let endpoint = format!("tcp://{}", &parameter);
     debug!("zeromq peer address: {}", endpoint);
 
@@ -120,7 +145,8 @@ async fn handle_handshake(
     // Add peer to PeerMap and SocketMap
     {
         let mut map = peer_map.write().await;
-        let peer = Peer::new_zmq(addr, message.sender_uuid, msg_tx);
+        let mut peer = Peer::new_zmq(addr, message.sender_uuid, msg_tx);
+        peer.set_identity(identity);
 
         sockets.insert(message.sender_uuid, socket);
         map.insert(message.sender_uuid, peer).await;
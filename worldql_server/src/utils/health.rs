@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+// region: Component Names
+pub const POSTGRES: &str = "postgres";
+#[cfg(feature = "websocket")]
+pub const WEBSOCKET: &str = "websocket";
+#[cfg(feature = "zeromq")]
+pub const ZEROMQ: &str = "zeromq";
+#[cfg(feature = "zeromq")]
+pub const ZEROMQ_OUTGOING: &str = "zeromq_outgoing";
+// endregion
+
+pub type ThreadHealth = Arc<Health>;
+
+/// Tracks the readiness of each server component.
+///
+/// The server is only considered ready once every registered component is ready.
+#[derive(Debug)]
+pub struct Health {
+    components: Mutex<BTreeMap<&'static str, bool>>,
+}
+
+impl Health {
+    /// Create a new [`Health`] with every component initially not ready.
+    pub fn new(components: &[&'static str]) -> Self {
+        let components = components
+            .iter()
+            .map(|component| (*component, false))
+            .collect();
+
+        Self {
+            components: Mutex::new(components),
+        }
+    }
+
+    /// Mark a component as ready or not ready.
+    pub fn set_ready(&self, component: &'static str, ready: bool) {
+        let mut components = self.components.lock().unwrap();
+        components.insert(component, ready);
+    }
+
+    /// Returns `true` if every registered component is ready.
+    #[cfg(any(feature = "http", test))]
+    pub fn is_ready(&self) -> bool {
+        let components = self.components.lock().unwrap();
+        components.values().all(|ready| *ready)
+    }
+
+    /// Returns a snapshot of each component's readiness.
+    #[cfg(any(feature = "http", test))]
+    pub fn components(&self) -> BTreeMap<&'static str, bool> {
+        let components = self.components.lock().unwrap();
+        components.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness() {
+        let health = Health::new(&["a", "b"]);
+        assert!(!health.is_ready());
+
+        // Only one component ready
+        health.set_ready("a", true);
+        assert!(!health.is_ready());
+
+        // Both components ready
+        health.set_ready("b", true);
+        assert!(health.is_ready());
+
+        // Component failed
+        health.set_ready("a", false);
+        assert!(!health.is_ready());
+        assert_eq!(health.components().get("a"), Some(&false));
+        assert_eq!(health.components().get("b"), Some(&true));
+    }
+}
@@ -1,8 +1,14 @@
+pub mod health;
+mod peer_auth;
 mod round;
+mod tags;
 mod time;
 mod trace_packet;
 mod world_names;
 
+pub use health::{Health, ThreadHealth};
+pub use peer_auth::{is_valid_identity, PeerAuth, PeerIdentity, ThreadPeerAuth};
 pub use round::round_by_multiple;
-pub use time::{parse_epoch_millis, ParseEpochError};
+pub use tags::is_valid_tag;
+pub use time::{epoch_millis, parse_epoch_millis, to_epoch_millis, ParseEpochError};
 pub use world_names::{sanitize_world_name, SanitizeError, GLOBAL_WORLD};
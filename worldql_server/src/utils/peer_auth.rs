@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use ahash::{AHashMap, AHashSet};
+use thiserror::Error;
+
+pub type ThreadPeerAuth = Arc<PeerAuth>;
+
+// Max Length
+const MAX_IDENTITY_LENGTH: usize = 64;
+
+/// Returns `true` if `identity` is non-empty, at most 64 chars, and only contains
+/// ASCII alphanumerics or any of `_-.@`
+pub fn is_valid_identity(identity: &str) -> bool {
+    !identity.is_empty()
+        && identity.len() <= MAX_IDENTITY_LENGTH
+        && identity
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '@'))
+}
+
+// region: PeerIdentity Struct
+/// Identity a peer authenticated as during its handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    pub name: String,
+
+    /// Admin peers may write any record, for backend services
+    pub admin: bool,
+}
+// endregion
+
+// region: PeerAuth Struct
+/// Maps the tokens peers send in their handshake to the identity they authenticate as.
+#[derive(Debug, Default)]
+pub struct PeerAuth {
+    tokens: AHashMap<String, String>,
+    admins: AHashSet<String>,
+}
+
+impl PeerAuth {
+    /// Create from `(identity, token)` pairs and the identities that are admins.
+    pub fn new(tokens: Vec<(String, String)>, admins: Vec<String>) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|(identity, token)| (token, identity))
+                .collect(),
+
+            admins: admins.into_iter().collect(),
+        }
+    }
+
+    /// Authenticate a handshake, taking the token sent as its `flex` field.
+    ///
+    /// Handshakes without a token are anonymous and return [`None`].
+    pub fn authenticate(&self, token: Option<&[u8]>) -> Result<Option<PeerIdentity>, AuthError> {
+        let token = match token {
+            None => return Ok(None),
+            Some(token) => std::str::from_utf8(token).map_err(|_| AuthError::InvalidToken)?,
+        };
+
+        let name = self.tokens.get(token).ok_or(AuthError::InvalidToken)?;
+        let identity = PeerIdentity {
+            name: name.clone(),
+            admin: self.admins.contains(name),
+        };
+
+        Ok(Some(identity))
+    }
+}
+// endregion
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("invalid peer token")]
+    InvalidToken,
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_identities() {
+        assert!(is_valid_identity("server-1"));
+        assert!(is_valid_identity("player.42@eu_west"));
+        assert!(!is_valid_identity(""));
+        assert!(!is_valid_identity("a,b"));
+        assert!(!is_valid_identity("a b"));
+        assert!(!is_valid_identity(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn authenticate() {
+        let auth = PeerAuth::new(
+            vec![
+                ("game".into(), "secret".into()),
+                ("backend".into(), "hunter2".into()),
+            ],
+            vec!["backend".into()],
+        );
+
+        assert_eq!(auth.authenticate(None).unwrap(), None);
+        assert!(auth.authenticate(Some(b"wrong")).is_err());
+        assert!(auth.authenticate(Some(&[0xff])).is_err());
+
+        let identity = auth.authenticate(Some(b"secret")).unwrap().unwrap();
+        assert_eq!(identity.name, "game");
+        assert!(!identity.admin);
+
+        let identity = auth.authenticate(Some(b"hunter2")).unwrap().unwrap();
+        assert_eq!(identity.name, "backend");
+        assert!(identity.admin);
+    }
+}
+// endregion
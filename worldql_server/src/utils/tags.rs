@@ -0,0 +1,12 @@
+// Max Length
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Returns `true` if `tag` is non-empty, at most 64 chars, and only contains ASCII
+/// alphanumerics or any of `_-.:`
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= MAX_TAG_LENGTH
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
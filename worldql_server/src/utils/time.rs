@@ -5,14 +5,20 @@ use thiserror::Error;
 
 pub fn parse_epoch_millis(timestamp: &str) -> Result<NaiveDateTime, ParseEpochError> {
     let ts = timestamp.parse::<u64>()?;
+    epoch_millis(ts).ok_or(ParseEpochError::OutOfRangeError)
+}
 
+/// Convert milliseconds since the unix epoch, returning [`None`] if out of range.
+pub fn epoch_millis(ts: u64) -> Option<NaiveDateTime> {
     let secs = (ts / 1000) as i64;
     let nsecs = ((ts % 1000) * 1_000_000) as u32;
 
-    match NaiveDateTime::from_timestamp_opt(secs, nsecs) {
-        None => Err(ParseEpochError::OutOfRangeError),
-        Some(ts) => Ok(ts),
-    }
+    NaiveDateTime::from_timestamp_opt(secs, nsecs)
+}
+
+/// Convert to milliseconds since the unix epoch, clamping times before it to `0`.
+pub fn to_epoch_millis(time: &NaiveDateTime) -> u64 {
+    time.timestamp_millis().max(0) as u64
 }
 
 #[derive(Debug, Error)]